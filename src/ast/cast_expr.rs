@@ -0,0 +1,78 @@
+use crate::errors::SemanticError;
+use crate::semantic_analysis::{Analysis, SymbolTable};
+use crate::{ast::*, token::Span};
+use anyhow::{anyhow, Error, Result};
+use log::{debug, warn};
+
+/// `expr as ty`
+/// Converts between the numeric types and `bool`
+#[derive(Debug)]
+pub struct CastExpression {
+    pub expr: Box<Expression>,
+    pub ty: Type,
+    pub span: Span,
+}
+
+impl PrettyPrint for CastExpression {
+    // format: "Cast ty\n" + expression
+    fn pretty_print(&self, indent: usize) -> String {
+        format!(
+            "{:indent$}Cast {}\n{}",
+            "",
+            self.ty.pretty_print(0),
+            self.expr.pretty_print(indent + 1),
+            indent = indent * 4
+        )
+    }
+}
+
+impl CastExpression {
+    pub fn get_type(&self, _table: &SymbolTable) -> Result<Type> {
+        Ok(self.ty.clone())
+    }
+
+    /// Only primitives may be cast, and only to other primitives
+    fn is_valid_cast(from: &Type, to: &Type) -> bool {
+        matches!((from, to), (Type::Primitive(_), Type::Primitive(_)))
+    }
+
+    pub fn idents_used(&self) -> Vec<Ident> {
+        self.expr.idents_used()
+    }
+}
+
+impl Analysis for CastExpression {
+    fn analyze(&self, table: &mut SymbolTable) -> Vec<Error> {
+        debug!("Analyzing cast: {:?}", self);
+
+        let mut errors = self.expr.analyze(table);
+        if let Err(e) = table.check_type(&self.ty) {
+            errors.push(e);
+        }
+        if !errors.is_empty() {
+            return errors;
+        }
+
+        let from = match self.expr.get_type(table) {
+            Ok(ty) => ty,
+            Err(e) => {
+                errors.push(e);
+                return errors;
+            }
+        };
+
+        if !Self::is_valid_cast(&from, &self.ty) {
+            warn!("Invalid cast from {} to {}", from, self.ty);
+            errors.push(anyhow!(SemanticError::InvalidCast {
+                from_type: from,
+                from_span: self.expr.span(),
+                to_type: self.ty.clone(),
+                to_span: self.ty.span(),
+            }));
+        }
+
+        debug!("Cast analysis errors: {:?}", errors);
+
+        errors
+    }
+}
@@ -0,0 +1,259 @@
+use crate::errors::SemanticError;
+use crate::semantic_analysis::definite_assignment::check_definite_assignment;
+use crate::semantic_analysis::{Analysis, ScopeKind, SymbolTable};
+use crate::{ast::*, token::Span};
+use anyhow::{anyhow, Error};
+use log::debug;
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+/// `|params| -> ty { body }`, a function value that may use the variables
+/// of the enclosing functions. A trailing expression without `;` is returned.
+#[derive(Debug)]
+pub struct Closure {
+    pub parameters: Vec<Parameter>,
+    pub ty: Type,
+    pub block: Block,
+    /// Variables of the enclosing functions used by the body, in order of first use,
+    /// filled in during analysis
+    pub captures: RefCell<Vec<Capture>>,
+    pub span: Span,
+}
+
+/// A variable captured by a closure
+#[derive(Debug, Clone)]
+pub struct Capture {
+    pub ident: Ident,
+    /// Assigned to or mutably borrowed in the body, so the closure refers to
+    /// the variable itself. Otherwise it copies the value when created.
+    pub by_ref: bool,
+}
+
+impl PrettyPrint for Closure {
+    // format: Closure |parameters| -> ty [captures] { block }
+    fn pretty_print(&self, indent: usize) -> String {
+        let captures = self.captures.borrow();
+        let mut s = format!(
+            "{:indent$}Closure |{}| -> {}{} {{\n",
+            "",
+            self.parameters
+                .iter()
+                .map(|p| p.pretty_print(indent))
+                .collect::<Vec<String>>()
+                .join(", "),
+            self.ty.pretty_print(indent),
+            if captures.is_empty() {
+                String::new()
+            } else {
+                format!(
+                    " captures [{}]",
+                    captures
+                        .iter()
+                        .map(|c| format!("{}{}", if c.by_ref { "&mut " } else { "" }, c.ident))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                )
+            },
+            indent = indent * 4
+        );
+        s.push_str(&self.block.pretty_print(indent + 1));
+        s.push_str(&format!("{:indent$}}}\n", "", indent = indent * 4));
+        s
+    }
+}
+
+impl Closure {
+    /// The closure's type, a function type like any other function value
+    pub fn get_type(&self) -> Type {
+        Type::Function(FunctionType {
+            params: self.parameters.iter().map(|p| p.ty.clone()).collect(),
+            ret_ty: Box::new(self.ty.clone()),
+            span: self.span.clone(),
+        })
+    }
+
+    /// Variables captured by the closure, as uses by the enclosing function
+    pub fn idents_used(&self) -> Vec<Ident> {
+        self.captures
+            .borrow()
+            .iter()
+            .map(|c| c.ident.clone())
+            .collect()
+    }
+}
+
+impl Analysis for Closure {
+    fn analyze(&self, table: &mut SymbolTable) -> Vec<Error> {
+        debug!("Analyzing closure: {:?}", self.span);
+        let mut errors = Vec::new();
+
+        // untyped literals returned take the return type
+        self.block.coerce_returns(&self.ty);
+
+        table.enter(ScopeKind::Closure(self.ty.clone()), self.span.clone());
+        let types = self.parameters.iter().map(|p| &p.ty);
+        for ty in types.chain(std::iter::once(&self.ty)) {
+            if let Err(e) = table.check_type(ty) {
+                errors.push(e);
+            }
+        }
+        for param in &self.parameters {
+            if let Err(e) = table.add_param(param) {
+                errors.push(e);
+            }
+        }
+
+        errors.extend(self.block.analyze(table));
+        table.exit();
+        errors.extend(self.block.check_control_flow(&self.span));
+        errors.extend(check_definite_assignment(&self.block, table));
+
+        // nested closures were analysed with the body, so their captures are known
+        let captures = find_captures(self, table);
+        debug!("Closure captures: {:?}", captures);
+
+        // may run any number of times, so can't be what assigns a `let x: T;` once
+        for capture in captures.iter().filter(|c| c.by_ref) {
+            match table.get_var(&capture.ident) {
+                Some(var) if var.deferred && !var.mutable => {
+                    errors.push(anyhow!(SemanticError::ImmutableVariable(
+                        capture.ident.clone(),
+                        var.kind,
+                        capture.ident.span.clone(),
+                        var.span.clone()
+                    )))
+                }
+                _ => {}
+            }
+        }
+        *self.captures.borrow_mut() = captures;
+
+        debug!("Closure analysis errors: {:?}", errors);
+
+        errors
+    }
+}
+
+/// Find the variables of the enclosing functions a closure uses
+fn find_captures(closure: &Closure, table: &SymbolTable) -> Vec<Capture> {
+    let params = closure
+        .parameters
+        .iter()
+        .map(|p| p.ident.ident.clone())
+        .collect();
+
+    let mut finder = CaptureFinder {
+        table,
+        scopes: vec![params],
+        captures: Vec::new(),
+    };
+    finder.block(&closure.block);
+
+    finder.captures
+}
+
+/// Walks a closure body, tracking the variables it declares itself
+struct CaptureFinder<'t> {
+    /// The table the closure is created in
+    table: &'t SymbolTable<'t>,
+    /// Names declared in the body, innermost scope last
+    scopes: Vec<HashSet<String>>,
+    captures: Vec<Capture>,
+}
+
+impl CaptureFinder<'_> {
+    fn block(&mut self, block: &Block) {
+        self.scopes.push(HashSet::new());
+        for statement in &block.statements {
+            self.statement(statement);
+        }
+        self.scopes.pop();
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Expression(e) => self.expression(e),
+            Statement::VariableDecl(v) => {
+                // the initializer still sees any outer variable of the same name
+                if let Some(e) = &v.expression {
+                    self.expression(e);
+                }
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.insert(v.ident.ident.clone());
+                }
+            }
+            Statement::Assignment(a) => {
+                match a.target.strip_parens() {
+                    Expression::Primary(PrimaryExpression::Ident(i)) => self.use_var(i, true),
+                    target => self.expression(target),
+                }
+                self.expression(&a.expression);
+            }
+            Statement::Flow(f) => {
+                self.expression(&f.condition);
+                self.block(&f.if_block);
+                if let Some(else_block) = &f.else_block {
+                    self.block(else_block);
+                }
+            }
+            Statement::Return(e) => {
+                if let Some(e) = e {
+                    self.expression(e);
+                }
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::Primary(p) => match p {
+                PrimaryExpression::Literal(_) => {}
+                PrimaryExpression::Ident(i) => self.use_var(i, false),
+                PrimaryExpression::Parenthesized(e) => self.expression(e),
+                PrimaryExpression::FunctionCall(i, args) => {
+                    // the callee may be a variable holding a function
+                    self.use_var(i, false);
+                    for arg in args {
+                        self.expression(arg);
+                    }
+                }
+                // a nested closure needs everything it captures from this one's environment
+                PrimaryExpression::Closure(c) => {
+                    for capture in c.captures.borrow().iter() {
+                        self.use_var(&capture.ident, capture.by_ref);
+                    }
+                }
+            },
+            Expression::Unary(UnaryExpression {
+                kind: UnaryExpressionKind::MutableReference(e),
+                ..
+            }) => match e.strip_parens() {
+                Expression::Primary(PrimaryExpression::Ident(i)) => self.use_var(i, true),
+                e => self.expression(e),
+            },
+            Expression::Unary(u) => self.expression(u.operand()),
+            Expression::Binary(b) => {
+                self.expression(&b.lhs);
+                self.expression(&b.rhs);
+            }
+            Expression::Cast(c) => self.expression(&c.expr),
+        }
+    }
+
+    /// Record a use of a variable, captured if it isn't declared in the body
+    fn use_var(&mut self, ident: &Ident, mutated: bool) {
+        if self.scopes.iter().any(|scope| scope.contains(&ident.ident))
+            || !self.table.is_local(ident)
+        {
+            return;
+        }
+
+        match self.captures.iter_mut().find(|c| c.ident == *ident) {
+            Some(capture) => capture.by_ref |= mutated,
+            None => self.captures.push(Capture {
+                ident: ident.clone(),
+                by_ref: mutated,
+            }),
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use crate::ast::*;
 use crate::semantic_analysis::{Analysis, SymbolTable};
+use crate::token::Span;
 use anyhow::Error;
 
 pub fn pretty_print_ast(ast: &AST) {
@@ -20,6 +21,21 @@ pub struct Program {
 #[derive(Debug)]
 pub enum Item {
     FunctionDecl(FunctionDecl),
+    ModDecl(ModDecl),
+    GlobalDecl(GlobalDecl),
+    TraitDecl(TraitDecl),
+    ImplDecl(ImplDecl),
+}
+
+/// `mod name;`
+/// Declares the module in `name.pyl`, next to the main file.
+/// Its functions are called as `name::function()`
+#[derive(Debug)]
+pub struct ModDecl {
+    /// Text of the `///` comments above the declaration
+    pub doc: Option<String>,
+    pub ident: Ident,
+    pub span: Span,
 }
 
 impl PrettyPrint for AST {
@@ -43,7 +59,34 @@ impl PrettyPrint for Item {
     fn pretty_print(&self, indent: usize) -> String {
         match self {
             Item::FunctionDecl(f) => f.pretty_print(indent),
+            Item::ModDecl(m) => m.pretty_print(indent),
+            Item::GlobalDecl(g) => g.pretty_print(indent),
+            Item::TraitDecl(t) => t.pretty_print(indent),
+            Item::ImplDecl(i) => i.pretty_print(indent),
+        }
+    }
+}
+
+impl PrettyPrint for ModDecl {
+    // format: [/// doc\n] ModDecl ident
+    fn pretty_print(&self, indent: usize) -> String {
+        let mut s = String::new();
+        for line in self.doc.iter().flat_map(|doc| doc.lines()) {
+            s.push_str(&format!(
+                "{:indent$}/// {}\n",
+                "",
+                line,
+                indent = indent * 4
+            ));
         }
+
+        s.push_str(&format!(
+            "{:indent$}ModDecl {}\n",
+            "",
+            self.ident.ident,
+            indent = indent * 4
+        ));
+        s
     }
 }
 
@@ -70,7 +113,15 @@ impl Analysis for AST {
 impl Analysis for Item {
     fn analyze(&self, table: &mut SymbolTable) -> Vec<Error> {
         match self {
+            // `const fn`s are analysed along with globals, see `analyse_module`
+            Item::FunctionDecl(f) if f.constant => vec![],
             Item::FunctionDecl(f) => f.analyze(table),
+            // modules are resolved before analysis, see `analyse_module`
+            Item::ModDecl(_) => vec![],
+            // globals are analysed before any function, see `analyse_module`
+            Item::GlobalDecl(_) => vec![],
+            Item::TraitDecl(t) => t.analyze(table),
+            Item::ImplDecl(i) => i.analyze(table),
         }
     }
 }
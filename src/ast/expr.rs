@@ -1,7 +1,7 @@
-use std::collections::HashMap;
+use std::collections::HashSet;
 
-use crate::errors::Warning;
-use crate::semantic_analysis::{Analysis, SymbolTable};
+use crate::errors::{SemanticError, Warning};
+use crate::semantic_analysis::{Analysis, Cfg, ScopeKind, SymbolTable};
 use crate::{ast::*, token::Span};
 use anyhow::{anyhow, Error, Result};
 use log::{debug, warn};
@@ -11,6 +11,7 @@ pub enum Expression {
     Primary(PrimaryExpression),
     Unary(UnaryExpression),
     Binary(BinaryExpression),
+    Cast(CastExpression),
 }
 
 #[derive(Debug)]
@@ -46,6 +47,7 @@ impl PrettyPrint for Expression {
             Expression::Primary(p) => p.pretty_print(indent),
             Expression::Unary(u) => u.pretty_print(indent),
             Expression::Binary(b) => b.pretty_print(indent),
+            Expression::Cast(c) => c.pretty_print(indent),
         }
     }
 }
@@ -56,6 +58,7 @@ impl ASTSpan for Expression {
             Expression::Primary(p) => p.span(),
             Expression::Unary(u) => u.span.clone(),
             Expression::Binary(b) => b.span.clone(),
+            Expression::Cast(c) => c.span.clone(),
         }
     }
 }
@@ -64,14 +67,15 @@ impl Analysis for Block {
     fn analyze(&self, table: &mut SymbolTable) -> Vec<Error> {
         debug!("Analyzing block: {self:?}, table: {table:?}");
         let mut errors = Vec::new();
-        let mut new_table = SymbolTable::child(table);
 
+        table.enter(ScopeKind::Block, self.span.clone());
         for statement in &self.statements {
             debug!("Analyzing statement: {statement:?}");
-            errors.extend(statement.analyze(&mut new_table));
+            errors.extend(statement.analyze(table));
         }
+        table.exit();
 
-        errors.extend(self.check_dead_unreachable(&table).0);
+        errors.extend(self.check_unused_variables().0);
 
         debug!("Block analysis complete, errors: {errors:?}");
 
@@ -79,108 +83,108 @@ impl Analysis for Block {
     }
 }
 
+/// Variables declared in a block, in order, and whether each is used
+/// A shadowed binding is tracked apart from the one shadowing it
+#[derive(Default)]
+struct Bindings {
+    declared: Vec<(Ident, bool)>,
+    /// Variables used in the block but declared outside it
+    outer: HashSet<Ident>,
+}
+
+impl Bindings {
+    fn declare(&mut self, ident: &Ident) {
+        self.declared.push((ident.clone(), false));
+    }
+
+    /// Mark the latest binding of each name as used
+    fn use_all<'i>(&mut self, idents: impl IntoIterator<Item = &'i Ident>) {
+        for ident in idents {
+            match self.declared.iter_mut().rev().find(|(i, _)| i == ident) {
+                Some((_, used)) => *used = true,
+                None => {
+                    self.outer.insert(ident.clone());
+                }
+            }
+        }
+    }
+}
+
 impl Block {
-    /// Check for dead and unreachable code
-    /// Returns errors and map of declared variables
-    fn check_dead_unreachable(&self, table: &SymbolTable) -> (Vec<Error>, HashMap<Ident, bool>) {
-        debug!("Checking for dead and unreachable in block: {self:?}, table: {table:?}");
+    /// Give untyped integer literals in return statements the return type
+    pub fn coerce_returns(&self, ty: &Type) {
+        for statement in &self.statements {
+            match statement {
+                Statement::Return(Some(expr)) => expr.coerce_int(ty),
+                Statement::Flow(flow) => {
+                    flow.if_block.coerce_returns(ty);
+                    if let Some(else_block) = &flow.else_block {
+                        else_block.coerce_returns(ty);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Check for unused variables, ignoring code after a `return`
+    /// Returns errors and the variables used by the block but declared outside it
+    fn check_unused_variables(&self) -> (Vec<Error>, HashSet<Ident>) {
+        debug!("Checking for unused variables in block: {self:?}");
         let mut errors = Vec::new();
-        let mut tmp_my_table = SymbolTable::child(table);
 
-        // map of variables declared in this scope, and whether they are used
-        let mut declared_vars: HashMap<Ident, bool> = HashMap::new();
+        // variables declared in this scope, tracked per binding rather than per name
+        let mut bindings = Bindings::default();
 
-        let mut early_return = false;
-        for (cur_idx, statement) in self.statements.iter().enumerate() {
+        for statement in &self.statements {
             match statement {
                 Statement::Return(expr) => {
-                    if early_return {
-                        continue;
-                    }
-
-                    early_return = cur_idx + 1 != self.statements.len();
                     let idents_used = expr.as_ref().map(|e| e.idents_used()).unwrap_or_default();
+                    bindings.use_all(&idents_used);
 
-                    for ident in &idents_used {
-                        if let Some(declared) = declared_vars.get_mut(&ident) {
-                            *declared = true;
-                        }
-                    }
-
-                    debug!("Found return statement at {cur_idx}, early_return: {early_return}, idents_used: {idents_used:?}, stmt: {statement:?}");
+                    debug!(
+                        "Found return statement, idents_used: {idents_used:?}, stmt: {statement:?}"
+                    );
+                    break;
                 }
                 Statement::VariableDecl(v) => {
-                    if early_return {
-                        continue;
+                    // the initializer uses any binding this one shadows
+                    if let Some(e) = &v.expression {
+                        bindings.use_all(&e.idents_used());
                     }
-
-                    if let Err(e) = tmp_my_table.add_var(v) {
-                        warn!("Error adding variable to table: {:?}, {:?}", statement, e);
+                    bindings.declare(&v.ident);
+                }
+                Statement::Assignment(a) => {
+                    // assigning to a variable is not a use, but reading through it is
+                    let mut idents_used = a.expression.idents_used();
+                    if !matches!(
+                        a.target.strip_parens(),
+                        Expression::Primary(PrimaryExpression::Ident(_))
+                    ) {
+                        idents_used.extend(a.target.idents_used());
                     }
-                    declared_vars.insert(v.ident.clone(), false);
 
-                    // expression may use the variable
-                    for ident in &v.expression.idents_used() {
-                        if let Some(declared) = declared_vars.get_mut(&ident) {
-                            *declared = true;
-                        }
-                    }
+                    bindings.use_all(&idents_used);
                 }
+                Statement::Expression(expr) => bindings.use_all(&expr.idents_used()),
                 Statement::Flow(flow) => {
-                    if early_return {
-                        continue;
-                    }
-
-                    let (_, if_guaranteed) = flow.if_block.get_return_stmts(&mut tmp_my_table);
+                    bindings.use_all(&flow.condition.idents_used());
 
                     // block may use a variable in this scope
-                    for (ident, used) in &flow.if_block.check_dead_unreachable(&tmp_my_table).1 {
-                        if let Some(declared) = declared_vars.get_mut(&ident) {
-                            *declared |= *used;
-                        }
-                    }
+                    // its own warnings are reported when it is analysed
+                    let (_, if_used) = flow.if_block.check_unused_variables();
+                    bindings.use_all(&if_used);
 
                     if let Some(else_block) = &flow.else_block {
-                        let (_, else_guaranteed) = else_block.get_return_stmts(&mut tmp_my_table);
-                        for (ident, used) in &else_block.check_dead_unreachable(&tmp_my_table).1 {
-                            if let Some(declared) = declared_vars.get_mut(&ident) {
-                                *declared |= *used;
-                            }
-                        }
-
-                        // in the case where both blocks have a return statement
-                        // we can guarantee a return
-                        if if_guaranteed && else_guaranteed {
-                            early_return = true;
-                            debug!("Found early return");
-                            break;
-                        }
+                        let (_, else_used) = else_block.check_unused_variables();
+                        bindings.use_all(&else_used);
                     }
                 }
-                _ => {}
             }
         }
 
-        if early_return {
-            // get span of unreachable code
-            let span = Span::combine(
-                &self
-                    .statements
-                    .first()
-                    .map(|s| s.span())
-                    .unwrap_or(self.span.clone()),
-                &self
-                    .statements
-                    .last()
-                    .map(|s| s.span())
-                    .unwrap_or(self.span.clone()),
-            );
-
-            errors.push(anyhow!(Warning::UnreachableCode(span)));
-        };
-
-        for (ident, declared) in &declared_vars {
-            if !declared && !ident.ident.starts_with("_") {
+        for (ident, used) in &bindings.declared {
+            if !used && !ident.ident.starts_with('_') {
                 warn!("Unused variable: {:?}", ident);
                 errors.push(anyhow!(Warning::UnusedVariable(
                     ident.clone(),
@@ -189,75 +193,35 @@ impl Block {
             }
         }
 
-        debug!("Dead and unreachable check complete, errors: {errors:?}");
+        debug!("Unused variable check complete, errors: {errors:?}");
 
-        (errors, declared_vars)
+        (errors, bindings.outer)
     }
 
-    /// Explore for all return statements
-    /// Return:
-    /// 1. The return value **types**
-    /// 2. Whether or not it guarantees a return regardless of the path
-    ///
-    /// Invalid return statements will be ignored
-    pub fn get_return_stmts(&self, table: &mut SymbolTable) -> (Vec<Type>, bool) {
-        let mut return_stmts_types = Vec::new();
-        let mut guaranteed_return = true;
-        let mut tmp_my_table = SymbolTable::child(table);
-        let mut early_return = false;
-
-        for statement in &self.statements {
-            match statement {
-                Statement::Return(stmt) => {
-                    debug!("Getting return statement type, table: {table:?}");
-                    if let Some(expr) = stmt {
-                        if let Ok(ty) = expr.get_type(&tmp_my_table) {
-                            return_stmts_types.push(ty.clone());
-                        }
-                    }
-                    debug!("Found return statement: {:?}", statement);
-                    guaranteed_return = true;
-                    early_return = true;
-                    break;
-                }
-                Statement::VariableDecl(v) => {
-                    if let Err(e) = tmp_my_table.add_var(v) {
-                        warn!("Error adding variable to table: {:?}, {:?}", statement, e);
-                    }
-                }
-                Statement::Flow(flow) => {
-                    let (returns, if_guaranteed) =
-                        flow.if_block.get_return_stmts(&mut tmp_my_table);
-                    debug!(
-                        "If block return values (guaranteed {if_guaranteed}): {:?}",
-                        &returns
-                    );
-                    return_stmts_types.extend(returns);
-                    guaranteed_return &= if_guaranteed;
-
-                    if let Some(else_block) = &flow.else_block {
-                        let (returns, else_guaranteed) =
-                            else_block.get_return_stmts(&mut tmp_my_table);
-                        debug!(
-                            "Else block return values (guaranteed {else_guaranteed}): {:?}",
-                            &returns
-                        );
-                        return_stmts_types.extend(returns);
-                        guaranteed_return &= else_guaranteed;
-
-                        // in the case where both blocks have a return statement
-                        // we can guarantee a return
-                        if if_guaranteed && else_guaranteed {
-                            early_return = true;
-                            break;
-                        }
-                    }
-                }
-                _ => {}
+    /// Check the control flow of the body of a function or closure: every
+    /// path returns, and no code is unreachable.
+    /// The type of each value returned is checked by its `return` statement
+    /// `span` is the whole function, for a missing return
+    pub fn check_control_flow(&self, span: &Span) -> Vec<Error> {
+        let mut errors = Vec::new();
+        let cfg = Cfg::build(self);
+
+        if cfg.falls_through() {
+            warn!("Body does not have a guaranteed return statement");
+            if cfg.has_return() {
+                errors.push(anyhow!(SemanticError::ReturnNotGuaranteed(span.clone())));
+            } else {
+                errors.push(anyhow!(SemanticError::MissingReturnStatement(span.clone())));
             }
         }
 
-        (return_stmts_types, guaranteed_return && early_return)
+        for span in cfg.unreachable_code() {
+            errors.push(anyhow!(Warning::UnreachableCode(span)));
+        }
+
+        debug!("Control flow analysis errors: {:?}", errors);
+
+        errors
     }
 }
 
@@ -267,6 +231,7 @@ impl Analysis for Expression {
             Expression::Primary(p) => p.analyze(table),
             Expression::Unary(u) => u.analyze(table),
             Expression::Binary(b) => b.analyze(table),
+            Expression::Cast(c) => c.analyze(table),
         }
     }
 }
@@ -277,6 +242,7 @@ impl Expression {
             Expression::Primary(p) => p.get_type(table),
             Expression::Unary(u) => u.get_type(table),
             Expression::Binary(b) => b.get_type(table),
+            Expression::Cast(c) => c.get_type(table),
         }
     }
 
@@ -285,6 +251,108 @@ impl Expression {
             Expression::Primary(p) => p.idents_used(),
             Expression::Unary(u) => u.idents_used(),
             Expression::Binary(b) => b.idents_used(),
+            Expression::Cast(c) => c.idents_used(),
+        }
+    }
+
+    /// Is this an unsuffixed integer literal, or arithmetic on only those?
+    /// Its type comes from the context, defaulting to `int`
+    pub fn is_untyped_int(&self) -> bool {
+        match self {
+            Expression::Primary(PrimaryExpression::Literal(l)) => l.is_untyped_int(),
+            Expression::Primary(PrimaryExpression::Parenthesized(e)) => e.is_untyped_int(),
+            Expression::Unary(UnaryExpression {
+                kind: UnaryExpressionKind::Negation(e),
+                ..
+            }) => e.is_untyped_int(),
+            Expression::Binary(b) => {
+                b.op.kind.is_arithmetic() && b.lhs.is_untyped_int() && b.rhs.is_untyped_int()
+            }
+            _ => false,
+        }
+    }
+
+    /// Give an untyped integer expression the integer type its context expects.
+    /// Anything else is left alone, and checked against the type as usual.
+    pub fn coerce_int(&self, ty: &Type) {
+        let kind = match ty {
+            Type::Primitive(p) if p.kind.is_integer() => p.kind,
+            _ => return,
+        };
+
+        if self.is_untyped_int() {
+            debug!("Coercing {:?} to {:?}", self, kind);
+            self.set_int_type(kind);
+        }
+    }
+
+    fn set_int_type(&self, kind: PrimitiveKind) {
+        match self {
+            Expression::Primary(PrimaryExpression::Literal(l)) => l.contextual_ty.set(Some(kind)),
+            Expression::Primary(PrimaryExpression::Parenthesized(e)) => e.set_int_type(kind),
+            Expression::Unary(u) => u.operand().set_int_type(kind),
+            Expression::Binary(b) => {
+                b.lhs.set_int_type(kind);
+                b.rhs.set_int_type(kind);
+            }
+            _ => {}
+        }
+    }
+
+    /// Strip any parentheses surrounding the expression
+    pub fn strip_parens(&self) -> &Expression {
+        match self {
+            Expression::Primary(PrimaryExpression::Parenthesized(e)) => e.strip_parens(),
+            _ => self,
+        }
+    }
+
+    /// Is the expression a place that can be assigned to or borrowed?
+    /// Places are variables and dereferences.
+    pub fn is_place(&self) -> bool {
+        matches!(
+            self.strip_parens(),
+            Expression::Primary(PrimaryExpression::Ident(_))
+                | Expression::Unary(UnaryExpression {
+                    kind: UnaryExpressionKind::Dereference(_),
+                    ..
+                })
+        )
+    }
+
+    /// Check the expression may be mutated through.
+    /// Variables must be declared `mut`, and dereferences must be of a `&mut`.
+    /// Expressions which are not places are temporaries, and always pass.
+    pub fn check_mutable_place(&self, table: &SymbolTable) -> Result<()> {
+        match self.strip_parens() {
+            Expression::Primary(PrimaryExpression::Ident(i)) => match table.get_var(i) {
+                Some(var) if !var.mutable => Err(anyhow!(SemanticError::ImmutableVariable(
+                    i.clone(),
+                    var.kind,
+                    i.span.clone(),
+                    var.span.clone()
+                ))),
+                Some(_) => Ok(()),
+                None => Err(anyhow!(SemanticError::VariableNotDeclared(
+                    i.clone(),
+                    i.span.clone()
+                ))),
+            },
+            Expression::Unary(UnaryExpression {
+                kind: UnaryExpressionKind::Dereference(e),
+                span,
+            }) => {
+                let ty = e.get_type(table)?;
+                match ty.referenced() {
+                    Some(r) if r.mutable => Ok(()),
+                    _ => Err(anyhow!(SemanticError::MutationThroughSharedReference {
+                        found_type: ty.clone(),
+                        found_span: e.span(),
+                        span: span.clone(),
+                    })),
+                }
+            }
+            _ => Ok(()),
         }
     }
 }
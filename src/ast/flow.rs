@@ -1,8 +1,9 @@
-use crate::errors::SemanticError;
-use crate::semantic_analysis::{Analysis, SymbolTable};
+use crate::errors::{SemanticError, Warning};
+use crate::semantic_analysis::{const_eval, Analysis, ConstValue, SymbolTable, VarKind};
 use crate::{ast::*, token::Span};
-use anyhow::{anyhow, Error};
+use anyhow::{anyhow, Error, Result};
 use log::{debug, warn};
+use std::cell::RefCell;
 
 #[derive(Debug)]
 pub enum Statement {
@@ -10,12 +11,36 @@ pub enum Statement {
     VariableDecl(VariableDecl),
     Flow(FlowStatement),
     Return(Option<Box<Expression>>),
+    Assignment(Assignment),
 }
 
 #[derive(Debug)]
 pub struct VariableDecl {
     pub ident: Ident,
-    pub ty: Type,
+    pub mutable: bool,
+    /// Explicit annotation, if any
+    pub ty: Option<Type>,
+    /// Type inferred from the initializer, filled in during analysis
+    pub inferred_ty: RefCell<Option<Type>>,
+    /// The initializer, or `None` for `let x: T;`, assigned later
+    pub expression: Option<Expression>,
+    pub span: Span,
+}
+
+impl VariableDecl {
+    /// The variable's type: the annotation, or the inferred type once analysed
+    pub fn var_type(&self) -> Option<Type> {
+        self.ty
+            .clone()
+            .or_else(|| self.inferred_ty.borrow().clone())
+    }
+}
+
+/// `target = expression;`
+/// The target must be a place: a variable or a dereference
+#[derive(Debug)]
+pub struct Assignment {
+    pub target: Expression,
     pub expression: Expression,
     pub span: Span,
 }
@@ -42,24 +67,47 @@ impl PrettyPrint for Statement {
                     .unwrap_or_else(|| "".to_string()),
                 indent = indent * 4
             ),
+            Statement::Assignment(a) => a.pretty_print(indent),
         }
     }
 }
 
 impl PrettyPrint for VariableDecl {
-    // format: "VariableDecl ident: ty =\n" + expression
+    // format: "VariableDecl [mut ]ident: ty [(inferred)] [=\n + expression]"
     fn pretty_print(&self, indent: usize) -> String {
+        let ty = match (&self.ty, self.inferred_ty.borrow().as_ref()) {
+            (Some(ty), _) => ty.pretty_print(0),
+            (None, Some(ty)) => format!("{} (inferred)", ty.pretty_print(0)),
+            (None, None) => "_".to_string(),
+        };
+
+        let expression = match &self.expression {
+            Some(e) => format!(" =\n{}", e.pretty_print(indent + 1)),
+            None => "\n".to_string(),
+        };
+
         format!(
-            "{:indent$}VariableDecl {}:{} =\n{}",
+            "{:indent$}VariableDecl {}{}:{}{}",
             "",
+            if self.mutable { "mut " } else { "" },
             self.ident.ident,
-            self.ty.pretty_print(0),
-            self.expression.pretty_print(indent + 1),
+            ty,
+            expression,
             indent = indent * 4
         )
     }
 }
 
+impl PrettyPrint for Assignment {
+    // format: "Assignment\n" + target + expression
+    fn pretty_print(&self, indent: usize) -> String {
+        let mut s = format!("{:indent$}Assignment\n", "", indent = indent * 4);
+        s.push_str(&self.target.pretty_print(indent + 1));
+        s.push_str(&self.expression.pretty_print(indent + 1));
+        s
+    }
+}
+
 impl PrettyPrint for FlowStatement {
     // format: "FlowStatement\ncond=\n" + condition + "if=\n" + if_block + "else=\n" + else_block
     fn pretty_print(&self, indent: usize) -> String {
@@ -80,6 +128,7 @@ impl ASTSpan for Statement {
             Statement::VariableDecl(v) => v.span.clone(),
             Statement::Flow(f) => f.span.clone(),
             Statement::Return(e) => e.as_ref().map(|e| e.span()).unwrap_or_default(),
+            Statement::Assignment(a) => a.span.clone(),
         }
     }
 }
@@ -90,56 +139,273 @@ impl Analysis for Statement {
             Statement::Expression(e) => e.analyze(table),
             Statement::VariableDecl(v) => v.analyze(table),
             Statement::Flow(f) => f.analyze(table),
-            Statement::Return(e) => e.as_ref().map_or_else(|| vec![], |e| e.analyze(table)),
+            Statement::Return(e) => e
+                .as_ref()
+                .map_or_else(Vec::new, |e| analyze_return(e, table)),
+            Statement::Assignment(a) => a.analyze(table),
         }
     }
 }
 
+/// Check a returned value has the return type of the function or closure
+/// it is returned from
+fn analyze_return(expression: &Expression, table: &mut SymbolTable) -> Vec<Error> {
+    debug!("Analyzing return statement: {:?}", expression);
+
+    // the value reports its own errors, so don't repeat them below
+    let mut errors = expression.analyze(table);
+    let expr_failed = !errors.is_empty();
+
+    let expected = match table.return_type() {
+        Some(ty) => ty.clone(),
+        None => return errors,
+    };
+    match expression.get_type(table) {
+        Ok(found) if found != expected => {
+            warn!(
+                "Incompatible return type: {:?}, expected {:?}",
+                found, expected
+            );
+            errors.push(anyhow!(SemanticError::IncompatibleReturnType {
+                expected_span: expected.span(),
+                expected_type: expected,
+                found_span: found.span(),
+                found_type: found,
+            }));
+        }
+        Ok(_) => (),
+        Err(e) if !expr_failed => errors.push(e),
+        Err(_) => (),
+    }
+
+    if let Err(e) = check_returned_borrow(expression, table) {
+        warn!("Returned reference outlives its value: {:?}", expression);
+        errors.push(e);
+    }
+
+    errors
+}
+
+/// A returned reference must outlive the function, so it can't borrow a local,
+/// a parameter or a temporary of it. There are no lifetimes to follow a
+/// reference elsewhere, so only a borrow in the `return` itself is caught
+fn check_returned_borrow(expression: &Expression, table: &SymbolTable) -> Result<()> {
+    let borrowed = match expression.strip_parens() {
+        Expression::Unary(UnaryExpression {
+            kind: UnaryExpressionKind::Reference(e) | UnaryExpressionKind::MutableReference(e),
+            ..
+        }) => e.strip_parens(),
+        _ => return Ok(()),
+    };
+
+    match borrowed {
+        // points wherever the dereferenced reference does
+        Expression::Unary(UnaryExpression {
+            kind: UnaryExpressionKind::Dereference(_),
+            ..
+        }) => Ok(()),
+        Expression::Primary(PrimaryExpression::Ident(i)) => match table.get_var(i) {
+            Some(var) if var.kind == VarKind::Static => Ok(()),
+            Some(var) if matches!(var.kind, VarKind::Let | VarKind::Param) => {
+                Err(anyhow!(SemanticError::ReturnsLocalReference(
+                    i.clone(),
+                    expression.span(),
+                    var.span.clone()
+                )))
+            }
+            // constants and functions are copied into a temporary to borrow them
+            _ => Err(anyhow!(SemanticError::ReturnsTemporaryReference(
+                expression.span()
+            ))),
+        },
+        _ => Err(anyhow!(SemanticError::ReturnsTemporaryReference(
+            expression.span()
+        ))),
+    }
+}
+
 impl Analysis for VariableDecl {
     fn analyze(&self, table: &mut SymbolTable) -> Vec<Error> {
         debug!("Analyzing variable declaration: {:?}", self.ident.ident);
         let mut errors = Vec::new();
 
-        match table.add_var(&self) {
-            Ok(_) => (),
-            Err(e) => errors.push(e),
+        if let Some(annotated) = &self.ty {
+            if let Err(e) = table.check_type(annotated) {
+                errors.push(e);
+            }
+        }
+
+        // `let x: T;` has nothing to check until it is assigned
+        let expression = match &self.expression {
+            Some(expression) => expression,
+            None => {
+                if let Err(e) = table.add_var(self) {
+                    errors.push(e);
+                }
+                return errors;
+            }
         };
 
-        debug!("Checking expression type: {:?}", self.expression);
-        // check if expression type matches variable type
-        match self.expression.get_type(table) {
-            Ok(ty) => {
-                if ty != self.ty {
+        if let Some(annotated) = &self.ty {
+            expression.coerce_int(annotated);
+        }
+
+        // the initialiser reports its own errors, so don't repeat them below
+        let expr_errors = expression.analyze(table);
+        let expr_failed = !expr_errors.is_empty();
+        errors.extend(expr_errors);
+
+        debug!("Checking expression type: {:?}", expression);
+        let expr_ty = match expression.get_type(table) {
+            Ok(ty) => Some(ty),
+            Err(e) => {
+                warn!("Error getting expression type: {:?}", self.ident.ident);
+                if !expr_failed {
+                    errors.push(e);
+                }
+                None
+            }
+        };
+
+        match (&self.ty, expr_ty) {
+            // check if expression type matches variable type
+            (Some(annotated), Some(ty)) => {
+                if &ty != annotated {
                     warn!(
                         "Variable type does not match expression type: {:?}",
                         self.ident.ident
                     );
-                    errors.push(anyhow!(SemanticError::TypesDoNotMatch {
-                        expected_type: self.ty.clone(),
-                        expected_span: self.ty.span(),
-                        found_type: ty,
-                        found_span: self.expression.span(),
-                    }));
+                    errors.push(anyhow!(SemanticError::type_mismatch(
+                        annotated.clone(),
+                        annotated.span(),
+                        ty,
+                        expression.span(),
+                    )));
                 }
             }
+            (None, Some(ty)) => {
+                debug!("Inferred type of {}: {:?}", self.ident.ident, ty);
+                *self.inferred_ty.borrow_mut() = Some(ty);
+            }
+            // nothing to infer from, so the variable can't be declared
+            (None, None) => return errors,
+            (Some(_), None) => (),
+        }
+
+        // declared after the initialiser, which can't refer to the variable itself
+        if let Err(e) = table.add_var(self) {
+            errors.push(e);
+        }
+
+        debug!("Variable declaration analysis errors: {:?}", errors);
+
+        errors
+    }
+}
+
+impl Analysis for Assignment {
+    fn analyze(&self, table: &mut SymbolTable) -> Vec<Error> {
+        debug!("Analyzing assignment: {:?}", self);
+        let mut errors = Vec::new();
+
+        if !self.target.is_place() {
+            warn!("Invalid assignment target: {:?}", self.target);
+            errors.push(anyhow!(SemanticError::InvalidAssignmentTarget(
+                self.target.span()
+            )));
+            return errors;
+        }
+
+        // resolves the variables of the target, like those of any other expression
+        let target_errors = self.target.analyze(table);
+        if !target_errors.is_empty() {
+            return target_errors;
+        }
+
+        let target_ty = match self.target.get_type(table) {
+            Ok(ty) => ty,
             Err(e) => {
-                warn!("Error getting expression type: {:?}", self.ident.ident);
                 errors.push(e);
                 return errors;
             }
         };
 
-        debug!("Variable declaration analysis errors: {:?}", errors);
+        // assigning an immutable `let x: T;` more than once is caught by
+        // the definite assignment check instead
+        let deferred = match self.target.strip_parens() {
+            Expression::Primary(PrimaryExpression::Ident(i)) => {
+                table.get_var(i).is_some_and(|v| v.deferred)
+            }
+            _ => false,
+        };
+        if !deferred {
+            if let Err(e) = self.target.check_mutable_place(table) {
+                warn!("Assignment to immutable place: {:?}", self.target);
+                errors.push(e);
+            }
+        }
+
+        self.expression.coerce_int(&target_ty);
+
+        let expr_errors = self.expression.analyze(table);
+        let expr_failed = !expr_errors.is_empty();
+        errors.extend(expr_errors);
+
+        // check if expression type matches the target type
+        match self.expression.get_type(table) {
+            Ok(ty) => {
+                if ty != target_ty {
+                    errors.push(anyhow!(SemanticError::type_mismatch(
+                        target_ty,
+                        self.target.span(),
+                        ty,
+                        self.expression.span(),
+                    )));
+                }
+            }
+            Err(e) if !expr_failed => errors.push(e),
+            Err(_) => (),
+        }
+
+        debug!("Assignment analysis errors: {:?}", errors);
 
         errors
     }
 }
 
+impl FlowStatement {
+    /// Warn about conditions known at compile time, other than a plain `true` or `false`
+    /// Conditions that would always overflow or divide by zero are errors
+    fn check_constant_condition(&self, table: &SymbolTable) -> Option<Error> {
+        if let Expression::Primary(PrimaryExpression::Literal(_)) = self.condition.strip_parens() {
+            return None;
+        }
+
+        match const_eval::eval(&self.condition, table) {
+            Ok(ConstValue::Bool(value)) => {
+                warn!("Condition is always {}: {:?}", value, self.condition);
+                Some(anyhow!(Warning::ConstantCondition(
+                    value,
+                    self.condition.span()
+                )))
+            }
+            Ok(_) => None,
+            Err(e) if const_eval::is_not_constant(&e) => None,
+            Err(e) => Some(e),
+        }
+    }
+}
+
 impl Analysis for FlowStatement {
     fn analyze(&self, table: &mut SymbolTable) -> Vec<Error> {
         debug!("Analyzing flow statement: {:?}", self);
         let mut errors = Vec::new();
 
+        let condition_errors = self.condition.analyze(table);
+        if !condition_errors.is_empty() {
+            return condition_errors;
+        }
+
         // check if condition is a boolean
         debug!("Checking condition type: {:?}", self.condition);
         match self.condition.get_type(table) {
@@ -154,6 +420,8 @@ impl Analysis for FlowStatement {
                         found_type: ty,
                         found_span: self.condition.span(),
                     }));
+                } else {
+                    errors.extend(self.check_constant_condition(table));
                 }
             }
             Err(e) => {
@@ -1,18 +1,46 @@
 use crate::errors::SemanticError;
-use crate::semantic_analysis::{Analysis, SymbolTable};
+use crate::semantic_analysis::definite_assignment::check_definite_assignment;
+use crate::semantic_analysis::{Analysis, ScopeKind, SymbolTable};
 use crate::{ast::*, token::Span};
 use anyhow::{anyhow, Error};
 use log::{debug, warn};
 
 #[derive(Debug)]
 pub struct FunctionDecl {
+    /// Text of the `///` comments above the function
+    pub doc: Option<String>,
+    /// Declared with `pub`, so other modules may use it
+    pub public: bool,
+    /// Declared `const fn`, so it may be called at compile time
+    pub constant: bool,
+    /// Set by an `#[inline]` or `#[inline(never)]` attribute
+    pub inline: Option<Inline>,
     pub ident: Ident,
+    /// Type parameters of a generic function, e.g. `T` in `fn max<T>`
+    /// Each call instantiates them with the types of its arguments
+    pub type_params: Vec<TypeParam>,
     pub parameters: Vec<Parameter>,
     pub ty: Type,
     pub block: Block,
     pub span: Span,
 }
 
+/// Whether calls to a function should be inlined, overriding the size heuristic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Inline {
+    /// `#[inline]`: inline whenever the call is not recursive
+    Always,
+    /// `#[inline(never)]`
+    Never,
+}
+
+/// A type parameter, and the traits its type must implement, e.g. `T: Shape + Named`
+#[derive(Debug, Clone)]
+pub struct TypeParam {
+    pub ident: Ident,
+    pub bounds: Vec<Ident>,
+}
+
 #[derive(Debug)]
 pub struct Parameter {
     pub ident: Ident,
@@ -21,12 +49,40 @@ pub struct Parameter {
 }
 
 impl PrettyPrint for FunctionDecl {
-    // format: FuncDecl ident(parameters) -> ty { block }
+    // format: [/// doc\n] [#[inline]\n] FuncDecl [pub] [const] ident[<type params>](parameters) -> ty { block }
     fn pretty_print(&self, indent: usize) -> String {
-        let mut s = format!(
-            "{:indent$}FuncDecl {}({}) -> {} {{\n",
+        let mut s = String::new();
+        for line in self.doc.iter().flat_map(|doc| doc.lines()) {
+            s.push_str(&format!(
+                "{:indent$}/// {}\n",
+                "",
+                line,
+                indent = indent * 4
+            ));
+        }
+
+        if let Some(inline) = self.inline {
+            s.push_str(&format!("{:indent$}{}\n", "", inline, indent = indent * 4));
+        }
+
+        s.push_str(&format!(
+            "{:indent$}FuncDecl {}{}{}{}({}) -> {} {{\n",
             "",
+            if self.public { "pub " } else { "" },
+            if self.constant { "const " } else { "" },
             self.ident.ident,
+            if self.type_params.is_empty() {
+                String::new()
+            } else {
+                format!(
+                    "<{}>",
+                    self.type_params
+                        .iter()
+                        .map(|p| p.pretty_print(0))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                )
+            },
             self.parameters
                 .iter()
                 .map(|p| p.pretty_print(indent))
@@ -34,13 +90,41 @@ impl PrettyPrint for FunctionDecl {
                 .join(", "),
             self.ty.pretty_print(indent),
             indent = indent * 4
-        );
+        ));
         s.push_str(&self.block.pretty_print(indent + 1));
         s.push_str(&format!("{:indent$}}}\n", "", indent = indent * 4));
         s
     }
 }
 
+impl std::fmt::Display for Inline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Inline::Always => write!(f, "#[inline]"),
+            Inline::Never => write!(f, "#[inline(never)]"),
+        }
+    }
+}
+
+impl PrettyPrint for TypeParam {
+    // format: ident[: bound + bound]
+    fn pretty_print(&self, _indent: usize) -> String {
+        if self.bounds.is_empty() {
+            return self.ident.ident.clone();
+        }
+
+        format!(
+            "{}: {}",
+            self.ident.ident,
+            self.bounds
+                .iter()
+                .map(|b| b.ident.clone())
+                .collect::<Vec<String>>()
+                .join(" + ")
+        )
+    }
+}
+
 impl PrettyPrint for Parameter {
     // format: ident: ty
     fn pretty_print(&self, _indent: usize) -> String {
@@ -49,67 +133,57 @@ impl PrettyPrint for Parameter {
 }
 
 impl FunctionDecl {
-    fn analyze_return_stmt(&self, table: &mut SymbolTable) -> Vec<Error> {
+    fn analyze_control_flow(&self) -> Vec<Error> {
         debug!(
-            "Analyzing return statements for function: {:?}",
+            "Analyzing control flow for function: {:?}",
             self.ident.ident
         );
-        let mut errors = Vec::new();
+        self.block.check_control_flow(&self.span)
+    }
 
-        // multiple return statements
+    /// Declare the type parameters, and check the signature only uses declared ones
+    fn analyze_type_params(&self, table: &mut SymbolTable) -> Vec<Error> {
         debug!(
-            "Getting return statements for function: {:?}",
+            "Analyzing type parameters for function: {:?}",
             self.ident.ident
         );
-        let (return_values, guaranteed_return) = self.block.get_return_stmts(table);
-        debug!(
-            "Return values: {:?}, guaranteed return: {:?}",
-            return_values, guaranteed_return
-        );
+        let mut errors = Vec::new();
 
-        if !guaranteed_return {
-            warn!(
-                "Function does not have a guaranteed return statement: {:?}",
-                self.ident.ident
-            );
-            if return_values.len() == 0 {
-                errors.push(anyhow!(SemanticError::MissingReturnStatement(
-                    self.span.clone()
-                )));
-            } else {
-                errors.push(anyhow!(SemanticError::ReturnNotGuaranteed(
-                    self.span.clone()
-                )));
-            }
-        } else {
-            for ty in return_values {
-                if ty != self.ty {
-                    warn!(
-                        "Incompatible return type for function: {:?}",
-                        self.ident.ident
-                    );
-                    errors.push(anyhow!(SemanticError::IncompatibleReturnType {
-                        expected_type: self.ty.clone(),
-                        expected_span: self.ty.span(),
-                        found_type: ty.clone(),
-                        found_span: ty.span(),
-                    }));
+        for param in &self.type_params {
+            for bound in &param.bounds {
+                if table.get_trait(&bound.ident).is_none() {
+                    warn!("Trait not declared: {}", bound.ident);
+                    errors.push(anyhow!(SemanticError::TraitNotDeclared(
+                        bound.clone(),
+                        bound.span.clone()
+                    )));
                 }
             }
+
+            if let Err(e) = table.add_type_param(param) {
+                errors.push(e);
+            }
+        }
+
+        let types = self.parameters.iter().map(|p| &p.ty);
+        for ty in types.chain(std::iter::once(&self.ty)) {
+            if let Err(e) = table.check_type(ty) {
+                errors.push(e);
+            }
         }
 
-        debug!("Return statement analysis errors: {:?}", errors);
+        debug!("Type parameter analysis errors: {:?}", errors);
 
         errors
     }
 
-    /// Analyze parameters, and build a new symbol table for the next block
-    fn analyze_parameters(&self, new_table: &mut SymbolTable) -> Vec<Error> {
+    /// Analyze parameters, declaring them in the function's scope
+    fn analyze_parameters(&self, table: &mut SymbolTable) -> Vec<Error> {
         debug!("Analyzing parameters for function: {:?}", self.ident.ident);
         let mut errors = Vec::new();
 
         for param in &self.parameters {
-            if let Err(e) = new_table.add_param(param) {
+            if let Err(e) = table.add_param(param) {
                 errors.push(e);
             }
         }
@@ -125,14 +199,20 @@ impl Analysis for FunctionDecl {
         debug!("Analyzing function declaration: {:?}", self.ident.ident);
         let mut errors = Vec::new();
 
-        errors.extend(self.analyze_return_stmt(table));
+        // untyped literals returned take the return type
+        self.block.coerce_returns(&self.ty);
+        errors.extend(self.analyze_control_flow());
 
-        let mut new_table = SymbolTable::child(table);
-        let param_errors = self.analyze_parameters(&mut new_table);
+        table.enter(ScopeKind::Function(self.ty.clone()), self.span.clone());
+        errors.extend(self.analyze_type_params(table));
+        let param_errors = self.analyze_parameters(table);
         errors.extend(param_errors);
 
         // analyze the block
-        errors.extend(self.block.analyze(&mut new_table));
+        errors.extend(self.block.analyze(table));
+        table.exit();
+        // needs the captures of the closures, found with the block
+        errors.extend(check_definite_assignment(&self.block, table));
 
         debug!("Function declaration analysis errors: {:?}", errors);
 
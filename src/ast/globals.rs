@@ -0,0 +1,115 @@
+use crate::errors::SemanticError;
+use crate::semantic_analysis::{const_eval, Analysis, ConstValue, SymbolTable};
+use crate::{ast::*, token::Span};
+use anyhow::{anyhow, Error};
+use log::{debug, warn};
+use std::cell::Cell;
+
+/// `const NAME: type = expr;` or `static [mut] NAME: type = expr;`
+/// Both are visible from every function of their module.
+/// The initializer must be a constant expression.
+#[derive(Debug)]
+pub struct GlobalDecl {
+    /// Text of the `///` comments above the declaration
+    pub doc: Option<String>,
+    pub kind: GlobalKind,
+    /// Declared `static mut`
+    pub mutable: bool,
+    pub ident: Ident,
+    pub ty: Type,
+    pub expression: Expression,
+    /// Value of the initializer, filled in during analysis
+    pub value: Cell<Option<ConstValue>>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GlobalKind {
+    /// Inlined at every use, it has no address of its own
+    Const,
+    /// A single location in the data section
+    Static,
+}
+
+impl PrettyPrint for GlobalDecl {
+    // format: [/// doc\n] GlobalDecl const|static [mut ]ident: ty =\n + expression
+    fn pretty_print(&self, indent: usize) -> String {
+        let mut s = String::new();
+        for line in self.doc.iter().flat_map(|doc| doc.lines()) {
+            s.push_str(&format!(
+                "{:indent$}/// {}\n",
+                "",
+                line,
+                indent = indent * 4
+            ));
+        }
+
+        s.push_str(&format!(
+            "{:indent$}GlobalDecl {} {}{}: {} =\n{}",
+            "",
+            self.kind,
+            if self.mutable { "mut " } else { "" },
+            self.ident.ident,
+            self.ty.pretty_print(0),
+            self.expression.pretty_print(indent + 1),
+            indent = indent * 4
+        ));
+        s
+    }
+}
+
+impl std::fmt::Display for GlobalKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GlobalKind::Const => write!(f, "const"),
+            GlobalKind::Static => write!(f, "static"),
+        }
+    }
+}
+
+impl Analysis for GlobalDecl {
+    fn analyze(&self, table: &mut SymbolTable) -> Vec<Error> {
+        debug!("Analyzing global declaration: {:?}", self.ident.ident);
+
+        self.expression.coerce_int(&self.ty);
+
+        // the initializer reports its own errors, so don't evaluate it
+        let mut errors = self.expression.analyze(table);
+        if let Err(e) = table.check_type(&self.ty) {
+            errors.push(e);
+        }
+        if errors.is_empty() {
+            match self.expression.get_type(table) {
+                Ok(ty) if ty != self.ty => {
+                    warn!(
+                        "Global type does not match expression type: {:?}",
+                        self.ident.ident
+                    );
+                    errors.push(anyhow!(SemanticError::type_mismatch(
+                        self.ty.clone(),
+                        self.ty.span(),
+                        ty,
+                        self.expression.span(),
+                    )));
+                }
+                Ok(_) => match const_eval::eval(&self.expression, table) {
+                    Ok(value) => {
+                        debug!("Value of {}: {:?}", self.ident.ident, value);
+                        self.value.set(Some(value));
+                    }
+                    Err(e) => errors.push(e),
+                },
+                Err(e) => errors.push(e),
+            }
+        }
+
+        // declared even if the initializer is invalid, to avoid follow-up errors
+        if let Err(e) = table.add_global(self) {
+            errors.push(e);
+        }
+
+        debug!("Global declaration analysis errors: {:?}", errors);
+
+        errors
+    }
+}
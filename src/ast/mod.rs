@@ -6,22 +6,30 @@
 //! We can use this to generate the IR (Intermediate Representation) for the
 //! language, which is then used to generate the final machine code.
 
+mod cast_expr;
+mod closures;
 mod core;
 mod expr;
 mod flow;
 mod functions;
+mod globals;
 mod nary_expr;
 mod ops;
 mod prim_expr;
+mod traits;
 mod types;
 mod utils;
 
+pub use self::cast_expr::*;
+pub use self::closures::*;
 pub use self::core::*;
 pub use self::expr::*;
 pub use self::flow::*;
 pub use self::functions::*;
+pub use self::globals::*;
 pub use self::nary_expr::*;
 pub use self::ops::*;
 pub use self::prim_expr::*;
+pub use self::traits::*;
 pub use self::types::*;
 pub use self::utils::*;
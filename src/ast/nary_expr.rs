@@ -14,6 +14,9 @@ pub struct UnaryExpression {
 pub enum UnaryExpressionKind {
     Negation(Box<Expression>),
     Not(Box<Expression>),
+    Reference(Box<Expression>),
+    MutableReference(Box<Expression>),
+    Dereference(Box<Expression>),
 }
 
 #[derive(Debug)]
@@ -39,6 +42,24 @@ impl PrettyPrint for UnaryExpression {
                 e.pretty_print(indent + 1),
                 indent = indent * 4
             ),
+            UnaryExpressionKind::Reference(e) => format!(
+                "{:indent$}Reference\n{}\n",
+                "",
+                e.pretty_print(indent + 1),
+                indent = indent * 4
+            ),
+            UnaryExpressionKind::MutableReference(e) => format!(
+                "{:indent$}MutableReference\n{}\n",
+                "",
+                e.pretty_print(indent + 1),
+                indent = indent * 4
+            ),
+            UnaryExpressionKind::Dereference(e) => format!(
+                "{:indent$}Dereference\n{}\n",
+                "",
+                e.pretty_print(indent + 1),
+                indent = indent * 4
+            ),
         }
     }
 }
@@ -64,7 +85,7 @@ impl UnaryExpression {
         debug!("Analyzing Negation: {:?}, table: {:?}", self, table);
         let mut errors = Vec::new();
 
-        let expr: &Box<Expression> = match &self.kind {
+        let expr: &Expression = match &self.kind {
             UnaryExpressionKind::Negation(e) => e,
             _ => unreachable!(),
         };
@@ -80,13 +101,20 @@ impl UnaryExpression {
 
         match expr_type {
             Type::Primitive(ref prim_ty) => match prim_ty.kind {
-                PrimitiveKind::Int | PrimitiveKind::Float => (),
+                kind if kind.is_signed() || kind == PrimitiveKind::Float => (),
                 _ => errors.push(anyhow!(SemanticError::UnsupportedUnaryOperation {
                     operator: "Negation".to_string(),
                     operand_type: expr_type.clone(),
                     span: self.span.clone(),
                 })),
             },
+            Type::Reference(_) | Type::Function(_) | Type::Param(_) => {
+                errors.push(anyhow!(SemanticError::UnsupportedUnaryOperation {
+                    operator: "Negation".to_string(),
+                    operand_type: expr_type.clone(),
+                    span: self.span.clone(),
+                }))
+            }
         }
 
         debug!("Negation analysis errors: {:?}", errors);
@@ -98,7 +126,7 @@ impl UnaryExpression {
         debug!("Analyzing Not: {:?}, table: {:?}", self, table);
         let mut errors = Vec::new();
 
-        let expr: &Box<Expression> = match &self.kind {
+        let expr: &Expression = match &self.kind {
             UnaryExpressionKind::Not(e) => e,
             _ => unreachable!(),
         };
@@ -121,39 +149,126 @@ impl UnaryExpression {
                     span: self.span.clone(),
                 })),
             },
+            Type::Reference(_) | Type::Function(_) | Type::Param(_) => {
+                errors.push(anyhow!(SemanticError::UnsupportedUnaryOperation {
+                    operator: "Not".to_string(),
+                    operand_type: expr_type.clone(),
+                    span: self.span.clone(),
+                }))
+            }
         }
 
         debug!("Not analysis errors: {:?}", errors);
         errors
     }
 
+    /// `&mut` may only borrow a `mut` binding, or a place behind a `&mut`.
+    /// Temporaries may always be borrowed.
+    fn analyze_mutable_reference(&self, table: &SymbolTable) -> Vec<Error> {
+        debug!("Analyzing MutableReference: {:?}, table: {:?}", self, table);
+        let mut errors = Vec::new();
+
+        let expr: &Expression = match &self.kind {
+            UnaryExpressionKind::MutableReference(e) => e,
+            _ => unreachable!(),
+        };
+
+        if let Err(e) = expr.get_type(table) {
+            errors.push(e);
+            return errors;
+        }
+
+        if let Err(e) = expr.check_mutable_place(table) {
+            warn!("Mutable borrow of immutable place: {:?}", expr);
+            errors.push(e);
+        }
+
+        debug!("MutableReference analysis errors: {:?}", errors);
+        errors
+    }
+
+    fn analyze_dereference(&self, table: &SymbolTable) -> Vec<Error> {
+        debug!("Analyzing Dereference: {:?}, table: {:?}", self, table);
+        let mut errors = Vec::new();
+
+        if let Err(e) = self.get_type(table) {
+            errors.push(e);
+        }
+
+        debug!("Dereference analysis errors: {:?}", errors);
+        errors
+    }
+
     pub fn get_type(&self, table: &SymbolTable) -> Result<Type> {
         debug!(
             "Getting type for UnaryExpression: {:?}, table: {:?}",
             self, table
         );
-        let expr = match &self.kind {
-            UnaryExpressionKind::Negation(e) => e,
-            UnaryExpressionKind::Not(e) => e,
-        };
-
-        expr.get_type(table)
+        match &self.kind {
+            UnaryExpressionKind::Negation(e) | UnaryExpressionKind::Not(e) => e.get_type(table),
+            UnaryExpressionKind::Reference(e) => Ok(Type::Reference(ReferenceType {
+                inner: Box::new(e.get_type(table)?),
+                mutable: false,
+                span: self.span.clone(),
+            })),
+            UnaryExpressionKind::MutableReference(e) => Ok(Type::Reference(ReferenceType {
+                inner: Box::new(e.get_type(table)?),
+                mutable: true,
+                span: self.span.clone(),
+            })),
+            UnaryExpressionKind::Dereference(e) => {
+                let ty = e.get_type(table)?;
+                match ty.referenced() {
+                    Some(r) => Ok(*r.inner.clone()),
+                    None => Err(anyhow!(SemanticError::UnsupportedUnaryOperation {
+                        operator: "Dereference".to_string(),
+                        operand_type: ty,
+                        span: self.span.clone(),
+                    })),
+                }
+            }
+        }
     }
 
-    pub fn idents_used(&self) -> Vec<Ident> {
+    /// Get the operand of the expression
+    pub fn operand(&self) -> &Expression {
         match &self.kind {
-            UnaryExpressionKind::Negation(e) => e.idents_used(),
-            UnaryExpressionKind::Not(e) => e.idents_used(),
+            UnaryExpressionKind::Negation(e)
+            | UnaryExpressionKind::Not(e)
+            | UnaryExpressionKind::Reference(e)
+            | UnaryExpressionKind::MutableReference(e)
+            | UnaryExpressionKind::Dereference(e) => e,
         }
     }
+
+    pub fn idents_used(&self) -> Vec<Ident> {
+        self.operand().idents_used()
+    }
 }
 
 impl Analysis for UnaryExpression {
     fn analyze(&self, table: &mut SymbolTable) -> Vec<Error> {
-        // no need to analyze the expression, the expression itself is analyzed
+        // a negated literal is range checked as a negative value
+        let operand_errors = match (&self.kind, self.operand().strip_parens()) {
+            (
+                UnaryExpressionKind::Negation(_),
+                Expression::Primary(PrimaryExpression::Literal(l)),
+            ) => l.check_range(true).err().into_iter().collect(),
+            _ => self.operand().analyze(table),
+        };
+        if !operand_errors.is_empty() {
+            return operand_errors;
+        }
+
         match &self.kind {
             UnaryExpressionKind::Negation(_) => self.analyze_negation(table),
             UnaryExpressionKind::Not(_) => self.analyze_not(table),
+            UnaryExpressionKind::Reference(e) => match e.get_type(table) {
+                Ok(_) => vec![],
+                Err(e) => vec![e],
+            },
+            UnaryExpressionKind::MutableReference(_) => self.analyze_mutable_reference(table),
+            UnaryExpressionKind::Dereference(_) => self.analyze_dereference(table),
         }
     }
 }
@@ -161,8 +276,29 @@ impl Analysis for UnaryExpression {
 impl Analysis for BinaryExpression {
     fn analyze(&self, table: &mut SymbolTable) -> Vec<Error> {
         debug!("Analyzing BinaryExpression: {:?}, table: {:?}", self, table);
+
+        // an untyped literal on one side takes the type of the other
+        match (self.lhs.is_untyped_int(), self.rhs.is_untyped_int()) {
+            (true, false) => {
+                if let Ok(ty) = self.rhs.get_type(table) {
+                    self.lhs.coerce_int(&ty);
+                }
+            }
+            (false, true) => {
+                if let Ok(ty) = self.lhs.get_type(table) {
+                    self.rhs.coerce_int(&ty);
+                }
+            }
+            _ => (),
+        }
+
+        let mut errors = self.lhs.analyze(table);
+        errors.extend(self.rhs.analyze(table));
+        if !errors.is_empty() {
+            return errors;
+        }
+
         // validate both sides are same type
-        let mut errors = Vec::new();
 
         let lhs_type = match self.lhs.get_type(table) {
             Ok(t) => t,
@@ -187,12 +323,12 @@ impl Analysis for BinaryExpression {
                 "Types do not match: lhs: {:?}, rhs: {:?}",
                 lhs_type, rhs_type
             );
-            errors.push(anyhow!(SemanticError::TypesDoNotMatch {
-                expected_type: lhs_type.clone(),
-                expected_span: self.lhs.span(),
-                found_type: rhs_type.clone(),
-                found_span: self.rhs.span(),
-            }));
+            errors.push(anyhow!(SemanticError::type_mismatch(
+                lhs_type.clone(),
+                self.lhs.span(),
+                rhs_type.clone(),
+                self.rhs.span(),
+            )));
         };
 
         // check if either side is not a valid type
@@ -232,46 +368,53 @@ impl BinaryExpression {
             "Getting type for BinaryExpression: {:?}, table: {:?}",
             self, table
         );
-        let lhs_type = self.lhs.get_type(table)?;
-        let rhs_type = self.rhs.get_type(table)?;
+        let mut lhs_type = self.lhs.get_type(table)?;
+        let mut rhs_type = self.rhs.get_type(table)?;
+
+        // an untyped literal takes the type of the other side,
+        // even before analysis has recorded it
+        if self.lhs.is_untyped_int() && rhs_type.is_integer() {
+            lhs_type = rhs_type.clone();
+        } else if self.rhs.is_untyped_int() && lhs_type.is_integer() {
+            rhs_type = lhs_type.clone();
+        }
 
         if lhs_type != rhs_type {
             warn!(
                 "Types do not match: lhs: {:?}, rhs: {:?}",
                 lhs_type, rhs_type
             );
-            return Err(anyhow!(SemanticError::TypesDoNotMatch {
-                expected_type: lhs_type,
-                expected_span: self.lhs.span(),
-                found_type: rhs_type,
-                found_span: self.rhs.span(),
-            }));
+            return Err(anyhow!(SemanticError::type_mismatch(
+                lhs_type,
+                self.lhs.span(),
+                rhs_type,
+                self.rhs.span(),
+            )));
         }
 
         debug!("BinaryExpression type success: {:?}", lhs_type);
 
         // if comparison, return bool
-        match self.op.kind {
-            BinaryOperatorKind::Equal
-            | BinaryOperatorKind::NotEqual
-            | BinaryOperatorKind::LessThan
-            | BinaryOperatorKind::LessThanOrEqual
-            | BinaryOperatorKind::GreaterThan
-            | BinaryOperatorKind::GreaterThanOrEqual => Ok(Type::Primitive(PrimitiveType {
+        if self.op.kind.is_comparison() {
+            Ok(Type::Primitive(PrimitiveType {
                 kind: PrimitiveKind::Bool,
                 span: self.span.clone(),
-            })),
-            _ => Ok(lhs_type),
+            }))
+        } else {
+            Ok(lhs_type)
         }
     }
 
+    /// `&&` and `||` only take `bool`s, which are otherwise only compared
     fn is_valid_type(&self, ty: &Type) -> bool {
+        let op = &self.op.kind;
         match ty {
-            Type::Primitive(ref prim_ty) => match prim_ty.kind {
-                PrimitiveKind::Int | PrimitiveKind::Float | PrimitiveKind::Bool => true,
-                #[allow(unreachable_patterns)]
-                _ => false,
-            },
+            Type::Primitive(p) if op.is_logical() => p.kind == PrimitiveKind::Bool,
+            Type::Primitive(p) => op.is_comparison() || p.kind != PrimitiveKind::Bool,
+            // a type parameter may stand for any primitive, `bool` included,
+            // and instances are not re-checked, so it can only be compared
+            Type::Param(_) => op.is_comparison(),
+            Type::Reference(_) | Type::Function(_) => false,
         }
     }
 
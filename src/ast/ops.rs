@@ -6,7 +6,7 @@ pub struct BinaryOperator {
     pub span: Span,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum BinaryOperatorKind {
     Add,
     Subtract,
@@ -33,6 +33,8 @@ pub struct UnaryOperator {
 pub enum UnaryOperatorKind {
     Negate,
     Not,
+    Reference,
+    Dereference,
 }
 
 impl BinaryOperator {
@@ -42,6 +44,36 @@ impl BinaryOperator {
 }
 
 impl BinaryOperatorKind {
+    /// `+ - * / %`
+    pub fn is_arithmetic(&self) -> bool {
+        matches!(
+            self,
+            BinaryOperatorKind::Add
+                | BinaryOperatorKind::Subtract
+                | BinaryOperatorKind::Multiply
+                | BinaryOperatorKind::Divide
+                | BinaryOperatorKind::Modulus
+        )
+    }
+
+    /// `&& ||`, which only take `bool`s
+    pub fn is_logical(&self) -> bool {
+        matches!(self, BinaryOperatorKind::And | BinaryOperatorKind::Or)
+    }
+
+    /// `== != < <= > >=`
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            BinaryOperatorKind::Equal
+                | BinaryOperatorKind::NotEqual
+                | BinaryOperatorKind::LessThan
+                | BinaryOperatorKind::LessThanOrEqual
+                | BinaryOperatorKind::GreaterThan
+                | BinaryOperatorKind::GreaterThanOrEqual
+        )
+    }
+
     /// Get the precedence of the operator
     pub fn precedence(&self) -> u8 {
         match self {
@@ -65,6 +97,8 @@ impl PrettyPrint for UnaryOperatorKind {
         match self {
             UnaryOperatorKind::Negate => "Negate ".to_string(),
             UnaryOperatorKind::Not => "Not ".to_string(),
+            UnaryOperatorKind::Reference => "Reference ".to_string(),
+            UnaryOperatorKind::Dereference => "Dereference ".to_string(),
         }
     }
 }
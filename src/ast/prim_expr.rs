@@ -1,13 +1,20 @@
 use crate::errors::SemanticError;
-use crate::semantic_analysis::{Analysis, SymbolTable};
+use crate::semantic_analysis::{Analysis, FuncSymbol, SymbolId, SymbolTable};
 use crate::{ast::*, token::Span};
 use anyhow::{anyhow, Error, Result};
 use log::warn;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::OnceLock;
 
 #[derive(Debug, Clone)]
 pub struct Ident {
     pub ident: String,
     pub span: Span,
+    /// The variable it declares or refers to, filled in during analysis.
+    /// Unset for other names, like functions and types. Not a `Cell`, as
+    /// errors hold idents and must be `Sync`
+    pub symbol: OnceLock<SymbolId>,
 }
 
 #[derive(Debug)]
@@ -16,18 +23,24 @@ pub enum PrimaryExpression {
     Ident(Ident),
     Parenthesized(Box<Expression>),
     FunctionCall(Ident, Vec<Expression>),
+    Closure(Closure),
 }
 
 #[derive(Debug)]
 pub struct Literal {
     pub kind: LiteralKind,
+    /// Integer type suffix, e.g. `5u8`
+    pub suffix: Option<PrimitiveKind>,
+    /// Integer type given by the context of an unsuffixed literal,
+    /// filled in during analysis
+    pub contextual_ty: Cell<Option<PrimitiveKind>>,
     pub span: Span,
 }
 
 #[derive(Debug)]
 pub enum LiteralKind {
-    Int(i32),
-    Float(f32),
+    Int(u64),
+    Float(f64),
     Bool(bool),
 }
 
@@ -54,6 +67,7 @@ impl PrettyPrint for PrimaryExpression {
                     .join(", "),
                 indent = indent * 4
             ),
+            PrimaryExpression::Closure(c) => c.pretty_print(indent),
         }
     }
 }
@@ -61,13 +75,37 @@ impl PrettyPrint for PrimaryExpression {
 impl PrettyPrint for Literal {
     fn pretty_print(&self, indent: usize) -> String {
         match self.kind {
-            LiteralKind::Int(i) => format!("{:indent$}Int {}\n", "", i, indent = indent * 4),
+            LiteralKind::Int(i) => format!(
+                "{:indent$}Int {}{}\n",
+                "",
+                i,
+                self.suffix
+                    .or(self.contextual_ty.get())
+                    .map(|kind| format!(" ({})", Type::primitive(kind)))
+                    .unwrap_or_default(),
+                indent = indent * 4
+            ),
             LiteralKind::Float(f) => format!("{:indent$}Float {}\n", "", f, indent = indent * 4),
             LiteralKind::Bool(b) => format!("{:indent$}Bool {}\n", "", b, indent = indent * 4),
         }
     }
 }
 
+impl Ident {
+    pub fn new(ident: String, span: Span) -> Self {
+        Ident {
+            ident,
+            span,
+            symbol: OnceLock::new(),
+        }
+    }
+
+    /// Split a qualified path like `math::add` into the module and the name
+    pub fn split_path(&self) -> Option<(&str, &str)> {
+        self.ident.split_once("::")
+    }
+}
+
 impl PrettyPrint for Ident {
     fn pretty_print(&self, _indent: usize) -> String {
         self.ident.clone()
@@ -101,11 +139,214 @@ impl ASTSpan for PrimaryExpression {
             PrimaryExpression::Ident(i) => i.span.clone(),
             PrimaryExpression::Parenthesized(p) => p.span(),
             PrimaryExpression::FunctionCall(i, _) => i.span.clone(),
+            PrimaryExpression::Closure(c) => c.span.clone(),
         }
     }
 }
 
 impl PrimaryExpression {
+    /// Resolve the signature of a called ident
+    /// Variables of function type shadow declared functions
+    pub fn callee_type(ident: &Ident, table: &SymbolTable) -> Result<FunctionType> {
+        if let Some(var) = table.get_var(ident) {
+            return match var.ty.function() {
+                Some(f) => Ok(f.clone()),
+                None => Err(anyhow!(SemanticError::NotCallable {
+                    ident: ident.clone(),
+                    found_type: var.ty.clone(),
+                    call_span: ident.span.clone(),
+                    decl_span: var.span.clone(),
+                })),
+            };
+        }
+
+        match table.get_fn(ident) {
+            Some(func) => {
+                Self::check_visible(ident, table)?;
+                match func.ty() {
+                    Type::Function(f) => Ok(f),
+                    _ => unreachable!("function symbol type is always a function"),
+                }
+            }
+            None => Err(Self::undeclared_fn(ident, table)),
+        }
+    }
+
+    /// The signature of a call, with the callee instantiated if it is generic
+    pub fn call_type(
+        ident: &Ident,
+        args: &[Expression],
+        table: &SymbolTable,
+    ) -> Result<FunctionType> {
+        let func = Self::callee_type(ident, table)?;
+
+        match Self::generic_callee(ident, table) {
+            Some(generic) if generic.params.len() == args.len() => {
+                Self::instantiate(ident, generic, args, table)
+            }
+            _ => Ok(func),
+        }
+    }
+
+    /// The generic function called by an ident, if any
+    /// Variables of function type shadow it, and are never generic
+    fn generic_callee<'t>(ident: &Ident, table: &'t SymbolTable) -> Option<&'t FuncSymbol> {
+        if table.get_var(ident).is_some() {
+            return None;
+        }
+
+        table.get_fn(ident).filter(|func| func.is_generic())
+    }
+
+    /// Instantiate a generic function for a call, inferring each type
+    /// parameter from the types of the arguments
+    ///
+    /// Untyped integer literals are matched last, so they take the type
+    /// the other arguments give a parameter, and `int` otherwise
+    pub fn instantiate(
+        ident: &Ident,
+        func: &FuncSymbol,
+        args: &[Expression],
+        table: &SymbolTable,
+    ) -> Result<FunctionType> {
+        let mut bindings = HashMap::new();
+
+        let (untyped, typed): (Vec<_>, Vec<_>) = func
+            .params
+            .iter()
+            .zip(args)
+            .partition(|(_, arg)| arg.is_untyped_int());
+
+        for (param_ty, arg) in typed.into_iter().chain(untyped) {
+            let expected = param_ty.substitute(&bindings);
+            if arg.is_untyped_int() && expected.is_integer() {
+                continue;
+            }
+
+            let arg_ty = arg.get_type(table)?;
+            if !param_ty.unify(&arg_ty, &mut bindings) {
+                warn!(
+                    "Generic function argument type mismatch: {:?}, {:?}",
+                    expected, arg_ty
+                );
+                return Err(anyhow!(SemanticError::type_mismatch(
+                    expected,
+                    param_ty.span(),
+                    arg_ty,
+                    arg.span(),
+                )));
+            }
+
+            // anything bound by this argument is new, as earlier bindings were valid
+            let invalid = bindings
+                .iter()
+                .find(|(_, ty)| !matches!(ty, Type::Primitive(_) | Type::Param(_)));
+            if let Some((param, ty)) = invalid {
+                warn!("Invalid type argument for {}: {}", param, ty);
+                return Err(anyhow!(SemanticError::InvalidTypeArgument {
+                    param: param.clone(),
+                    ty: ty.clone(),
+                    span: arg.span(),
+                }));
+            }
+        }
+
+        for param in &func.type_params {
+            let ty = match bindings.get(&param.ident.ident) {
+                Some(ty) => ty,
+                None => {
+                    warn!("Type parameter not inferred: {} of {}", param.ident, ident);
+                    return Err(anyhow!(SemanticError::TypeParamNotInferred {
+                        param: param.ident.ident.clone(),
+                        ident: ident.clone(),
+                        call_span: ident.span.clone(),
+                        decl_span: func.ident_span.clone(),
+                    }));
+                }
+            };
+
+            for bound in &param.bounds {
+                if !Self::satisfies(ident, ty, &bound.ident, table) {
+                    warn!("Unsatisfied bound of {}: {}: {}", ident, ty, bound);
+                    return Err(anyhow!(SemanticError::UnsatisfiedBound {
+                        trait_name: bound.ident.clone(),
+                        ty: ty.clone(),
+                        span: ident.span.clone(),
+                    }));
+                }
+            }
+        }
+
+        log::debug!("Instantiated {} with {:?}", ident, bindings);
+
+        Ok(FunctionType {
+            params: func
+                .params
+                .iter()
+                .map(|p| p.substitute(&bindings))
+                .collect(),
+            ret_ty: Box::new(func.ret_ty.substitute(&bindings)),
+            span: func.sig_span.clone(),
+        })
+    }
+
+    /// Does a type implement a trait bounding a type parameter of the callee?
+    /// The bounds of another module's functions name that module's traits
+    fn satisfies(ident: &Ident, ty: &Type, trait_name: &str, table: &SymbolTable) -> bool {
+        match ident
+            .split_path()
+            .and_then(|(module, _)| table.get_module(module))
+        {
+            Some(module) => module.implements(ty, trait_name),
+            None => table.implements(ty, trait_name),
+        }
+    }
+
+    /// Functions of other modules may only be used if they are `pub`
+    fn check_visible(ident: &Ident, table: &SymbolTable) -> Result<()> {
+        let (module, name) = match ident.split_path() {
+            Some((module, name)) => match table.get_module(module) {
+                Some(module) => (module, name),
+                None => return Ok(()),
+            },
+            None => return Ok(()),
+        };
+
+        match module.functions.get(name) {
+            Some(func) if !func.public => {
+                warn!("Private function used: {}", ident);
+                Err(anyhow!(SemanticError::PrivateItem {
+                    ident: ident.clone(),
+                    span: ident.span.clone(),
+                    decl_file: module.file.clone(),
+                    decl_span: module.decl_spans[name].clone(),
+                }))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The error for an ident that names no function
+    /// Paths into undeclared modules report the module
+    fn undeclared_fn(ident: &Ident, table: &SymbolTable) -> Error {
+        match ident.split_path() {
+            Some((trait_name, method)) if table.get_trait(trait_name).is_some() => {
+                anyhow!(SemanticError::NotATraitMethod {
+                    method: Ident::new(method.to_string(), ident.span.clone()),
+                    trait_ident: Ident::new(trait_name.to_string(), ident.span.clone()),
+                    span: ident.span.clone(),
+                })
+            }
+            Some((module, _)) if table.get_module(module).is_none() => anyhow!(
+                SemanticError::ModuleNotDeclared(module.to_string(), ident.span.clone())
+            ),
+            _ => anyhow!(SemanticError::FunctionNotDeclared(
+                ident.clone(),
+                ident.span.clone()
+            )),
+        }
+    }
+
     fn analyze_fn_call(&self, table: &mut SymbolTable) -> Vec<Error> {
         log::debug!("Analyzing function call: {:?}, table: {:?}", self, table);
         let mut errors = Vec::new();
@@ -116,57 +357,91 @@ impl PrimaryExpression {
         };
 
         // can we find the function in the symbol table?
-        if let Some(func) = table.get_fn(&ident) {
-            // check if the number of arguments match
-            if func.params.len() != args.len() {
-                warn!(
-                    "Function call argument count mismatch: {:?}, {:?}",
-                    func, args
-                );
+        let mut func = match Self::callee_type(ident, table) {
+            Ok(func) => func,
+            Err(e) => {
+                errors.push(e);
+                return errors;
+            }
+        };
 
-                // get the span of arguments supplied
-                let call_span = Span::combine(
-                    &args.first().map(|a| a.span()).unwrap_or(ident.span.clone()),
-                    &args.last().map(|a| a.span()).unwrap_or(ident.span.clone()),
-                );
+        // check if the number of arguments match
+        if func.params.len() != args.len() {
+            warn!(
+                "Function call argument count mismatch: {:?}, {:?}",
+                func, args
+            );
 
-                errors.push(anyhow!(SemanticError::ArgumentCountMismatch {
-                    expected: func.params.len(),
-                    found: args.len(),
-                    call_span,
-                    decl_span: func.sig_span.clone(),
-                }));
-            } else {
-                // check if the types of the arguments match
-                for (param_ty, arg) in func.params.iter().zip(args) {
-                    log::debug!("Checking param {:?} against arg {:?}", param_ty, arg);
-                    let arg_ty: Type = match arg.get_type(table) {
-                        Ok(ty) => ty,
-                        Err(e) => {
-                            errors.push(e);
-                            continue;
-                        }
-                    };
-
-                    if *param_ty != arg_ty {
-                        warn!(
-                            "Function call argument type mismatch: {:?}, {:?}",
-                            param_ty, arg_ty
-                        );
-                        errors.push(anyhow!(SemanticError::TypesDoNotMatch {
-                            expected_type: param_ty.clone(),
-                            expected_span: param_ty.span(),
-                            found_type: arg_ty,
-                            found_span: arg.span(),
-                        }));
+            // get the span of arguments supplied
+            let call_span = Span::combine(
+                &args.first().map(|a| a.span()).unwrap_or(ident.span.clone()),
+                &args.last().map(|a| a.span()).unwrap_or(ident.span.clone()),
+            );
+
+            errors.push(anyhow!(SemanticError::ArgumentCountMismatch {
+                expected: func.params.len(),
+                found: args.len(),
+                call_span,
+                decl_span: func.span.clone(),
+            }));
+        } else {
+            // a generic function is instantiated from the argument types, so those are
+            // analysed first, except untyped literals that take the instantiated types
+            let mut analyzed = vec![false; args.len()];
+            if let Some(generic) = Self::generic_callee(ident, table).cloned() {
+                for (arg, analyzed) in args.iter().zip(analyzed.iter_mut()) {
+                    if !arg.is_untyped_int() {
+                        errors.extend(arg.analyze(table));
+                        *analyzed = true;
                     }
                 }
+                if !errors.is_empty() {
+                    return errors;
+                }
+
+                func = match Self::instantiate(ident, &generic, args, table) {
+                    Ok(func) => func,
+                    Err(e) => {
+                        errors.push(e);
+                        return errors;
+                    }
+                };
+            }
+
+            // check if the types of the arguments match
+            for ((param_ty, arg), analyzed) in func.params.iter().zip(args).zip(analyzed) {
+                log::debug!("Checking param {:?} against arg {:?}", param_ty, arg);
+                arg.coerce_int(param_ty);
+
+                if !analyzed {
+                    let arg_errors = arg.analyze(table);
+                    if !arg_errors.is_empty() {
+                        errors.extend(arg_errors);
+                        continue;
+                    }
+                }
+
+                let arg_ty: Type = match arg.get_type(table) {
+                    Ok(ty) => ty,
+                    Err(e) => {
+                        errors.push(e);
+                        continue;
+                    }
+                };
+
+                if *param_ty != arg_ty {
+                    warn!(
+                        "Function call argument type mismatch: {:?}, {:?}",
+                        param_ty, arg_ty
+                    );
+                    errors.push(anyhow!(SemanticError::type_mismatch(
+                        param_ty.clone(),
+                        param_ty.span(),
+                        arg_ty,
+                        arg.span(),
+                    )));
+                }
             }
-        } else {
-            errors.push(anyhow!(SemanticError::FunctionNotDeclared(
-                ident.clone(),
-                ident.span.clone()
-            )));
         }
 
         log::debug!("Function call analysis errors: {:?}", errors);
@@ -178,8 +453,21 @@ impl PrimaryExpression {
         match self {
             PrimaryExpression::Literal(l) => Ok(l.get_type()),
             PrimaryExpression::Ident(i) => {
-                if let Some(var) = table.get_var(&i) {
+                if let Some(var) = table.get_var(i) {
                     Ok(var.ty.clone())
+                } else if let Some(func) = table.get_fn(i) {
+                    // functions may be used as values, once their types are known
+                    Self::check_visible(i, table)?;
+                    if func.is_generic() {
+                        warn!("Generic function used as a value: {}", i);
+                        return Err(anyhow!(SemanticError::GenericFunctionValue(
+                            i.clone(),
+                            i.span.clone()
+                        )));
+                    }
+                    Ok(func.ty())
+                } else if i.split_path().is_some() {
+                    Err(Self::undeclared_fn(i, table))
                 } else {
                     log::debug!(
                         "During type-retrieval variable not declared: {:?} for table {:?}",
@@ -193,16 +481,10 @@ impl PrimaryExpression {
                 }
             }
             PrimaryExpression::Parenthesized(p) => p.get_type(table),
-            PrimaryExpression::FunctionCall(i, _) => {
-                if let Some(func) = table.get_fn(&i) {
-                    Ok(func.ret_ty.clone())
-                } else {
-                    Err(anyhow!(SemanticError::FunctionNotDeclared(
-                        i.clone(),
-                        i.span.clone()
-                    )))
-                }
+            PrimaryExpression::FunctionCall(i, args) => {
+                Ok(*Self::call_type(i, args, table)?.ret_ty)
             }
+            PrimaryExpression::Closure(c) => Ok(c.get_type()),
         }
     }
 
@@ -211,39 +493,81 @@ impl PrimaryExpression {
             PrimaryExpression::Literal(_) => vec![],
             PrimaryExpression::Ident(i) => vec![i.clone()],
             PrimaryExpression::Parenthesized(p) => p.idents_used(),
-            PrimaryExpression::FunctionCall(_, args) => {
-                args.iter().flat_map(|a| a.idents_used()).collect()
+            PrimaryExpression::FunctionCall(i, args) => {
+                // the callee may be a variable holding a function
+                let mut idents = vec![i.clone()];
+                idents.extend(args.iter().flat_map(|a| a.idents_used()));
+                idents
             }
+            PrimaryExpression::Closure(c) => c.idents_used(),
         }
     }
 }
 
 impl Analysis for PrimaryExpression {
-    fn analyze(&self, _table: &mut SymbolTable) -> Vec<Error> {
+    fn analyze(&self, table: &mut SymbolTable) -> Vec<Error> {
         match self {
-            PrimaryExpression::Literal(_) => vec![],
-            PrimaryExpression::Ident(_) => vec![],
-            PrimaryExpression::Parenthesized(p) => p.analyze(_table),
-            PrimaryExpression::FunctionCall(_, _) => self.analyze_fn_call(_table),
+            PrimaryExpression::Literal(l) => match l.check_range(false) {
+                Ok(_) => vec![],
+                Err(e) => vec![e],
+            },
+            PrimaryExpression::Ident(i) => {
+                table.resolve(i);
+                vec![]
+            }
+            PrimaryExpression::Parenthesized(p) => p.analyze(table),
+            PrimaryExpression::FunctionCall(i, _) => {
+                // the callee may be a variable holding a function
+                table.resolve(i);
+                self.analyze_fn_call(table)
+            }
+            PrimaryExpression::Closure(c) => c.analyze(table),
         }
     }
 }
 
 impl Literal {
-    fn get_type(&self) -> Type {
-        match self.kind {
-            LiteralKind::Int(_) => Type::Primitive(PrimitiveType {
-                kind: PrimitiveKind::Int,
-                span: self.span.clone(),
-            }),
-            LiteralKind::Float(_) => Type::Primitive(PrimitiveType {
-                kind: PrimitiveKind::Float,
-                span: self.span.clone(),
-            }),
-            LiteralKind::Bool(_) => Type::Primitive(PrimitiveType {
-                kind: PrimitiveKind::Bool,
-                span: self.span.clone(),
-            }),
+    pub fn get_type(&self) -> Type {
+        let kind = match self.kind {
+            LiteralKind::Int(_) => self
+                .suffix
+                .or(self.contextual_ty.get())
+                .unwrap_or(PrimitiveKind::I64),
+            LiteralKind::Float(_) => PrimitiveKind::Float,
+            LiteralKind::Bool(_) => PrimitiveKind::Bool,
+        };
+
+        Type::Primitive(PrimitiveType {
+            kind,
+            span: self.span.clone(),
+        })
+    }
+
+    /// Is this an integer literal without a suffix?
+    /// Its type comes from the context, defaulting to `int`
+    pub fn is_untyped_int(&self) -> bool {
+        matches!(self.kind, LiteralKind::Int(_)) && self.suffix.is_none()
+    }
+
+    /// Check an integer literal fits in its type, negated if it is the operand of `-`
+    pub fn check_range(&self, negated: bool) -> Result<()> {
+        let value = match self.kind {
+            LiteralKind::Int(i) if negated => -(i as i128),
+            LiteralKind::Int(i) => i as i128,
+            _ => return Ok(()),
+        };
+
+        let ty = self.get_type();
+        match ty {
+            Type::Primitive(ref p) if !p.kind.fits(value) => {
+                warn!("Literal {} out of range for {}", value, ty);
+                Err(anyhow!(SemanticError::LiteralOutOfRange {
+                    value,
+                    ty,
+                    span: self.span.clone(),
+                }))
+            }
+            _ => Ok(()),
         }
     }
 }
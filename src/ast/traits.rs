@@ -0,0 +1,252 @@
+use crate::errors::SemanticError;
+use crate::semantic_analysis::{Analysis, ScopeKind, SymbolTable};
+use crate::{ast::*, token::Span};
+use anyhow::{anyhow, Error};
+use log::{debug, warn};
+use std::collections::HashMap;
+
+/// The type parameter of every trait method, standing for the implementing type
+pub const SELF_TYPE: &str = "Self";
+
+/// `trait Name { fn method(params) -> ty; }`
+/// Methods are called through the trait, as `Name::method(args)`,
+/// and dispatched statically on the type the arguments give `Self`.
+/// Traits are only visible in their own module
+#[derive(Debug)]
+pub struct TraitDecl {
+    /// Text of the `///` comments above the declaration
+    pub doc: Option<String>,
+    pub ident: Ident,
+    pub methods: Vec<MethodDecl>,
+    pub span: Span,
+}
+
+/// A method signature of a trait, without a body
+#[derive(Debug)]
+pub struct MethodDecl {
+    pub ident: Ident,
+    pub parameters: Vec<Parameter>,
+    pub ty: Type,
+    pub span: Span,
+}
+
+/// `impl Trait for type { fn method(params) -> ty { ... } }`
+/// Methods are written with the implementing type in place of `Self`
+#[derive(Debug)]
+pub struct ImplDecl {
+    /// Text of the `///` comments above the declaration
+    pub doc: Option<String>,
+    pub trait_ident: Ident,
+    pub ty: Type,
+    pub methods: Vec<FunctionDecl>,
+    pub span: Span,
+}
+
+impl PrettyPrint for TraitDecl {
+    // format: [/// doc\n] TraitDecl ident { methods }
+    fn pretty_print(&self, indent: usize) -> String {
+        let mut s = String::new();
+        for line in self.doc.iter().flat_map(|doc| doc.lines()) {
+            s.push_str(&format!(
+                "{:indent$}/// {}\n",
+                "",
+                line,
+                indent = indent * 4
+            ));
+        }
+
+        s.push_str(&format!(
+            "{:indent$}TraitDecl {} {{\n",
+            "",
+            self.ident.ident,
+            indent = indent * 4
+        ));
+        for method in &self.methods {
+            s.push_str(&method.pretty_print(indent + 1));
+        }
+        s.push_str(&format!("{:indent$}}}\n", "", indent = indent * 4));
+        s
+    }
+}
+
+impl PrettyPrint for MethodDecl {
+    // format: MethodDecl ident(parameters) -> ty
+    fn pretty_print(&self, indent: usize) -> String {
+        format!(
+            "{:indent$}MethodDecl {}({}) -> {}\n",
+            "",
+            self.ident.ident,
+            self.parameters
+                .iter()
+                .map(|p| p.pretty_print(indent))
+                .collect::<Vec<String>>()
+                .join(", "),
+            self.ty.pretty_print(indent),
+            indent = indent * 4
+        )
+    }
+}
+
+impl PrettyPrint for ImplDecl {
+    // format: [/// doc\n] ImplDecl trait for ty { methods }
+    fn pretty_print(&self, indent: usize) -> String {
+        let mut s = String::new();
+        for line in self.doc.iter().flat_map(|doc| doc.lines()) {
+            s.push_str(&format!(
+                "{:indent$}/// {}\n",
+                "",
+                line,
+                indent = indent * 4
+            ));
+        }
+
+        s.push_str(&format!(
+            "{:indent$}ImplDecl {} for {} {{\n",
+            "",
+            self.trait_ident.ident,
+            self.ty.pretty_print(0),
+            indent = indent * 4
+        ));
+        for method in &self.methods {
+            s.push_str(&method.pretty_print(indent + 1));
+        }
+        s.push_str(&format!("{:indent$}}}\n", "", indent = indent * 4));
+        s
+    }
+}
+
+/// The type of a function with the given signature
+fn signature_type(parameters: &[Parameter], ty: &Type, span: &Span) -> Type {
+    Type::Function(FunctionType {
+        params: parameters.iter().map(|p| p.ty.clone()).collect(),
+        ret_ty: Box::new(ty.clone()),
+        span: span.clone(),
+    })
+}
+
+impl MethodDecl {
+    /// The method's type, still generic over `Self`
+    pub fn signature(&self) -> Type {
+        signature_type(&self.parameters, &self.ty, &self.span)
+    }
+}
+
+impl Analysis for TraitDecl {
+    fn analyze(&self, table: &mut SymbolTable) -> Vec<Error> {
+        debug!("Analyzing trait declaration: {:?}", self.ident.ident);
+        let mut errors = Vec::new();
+
+        // `Self` is in scope for the signatures, and implements the trait
+        table.enter(ScopeKind::Trait, self.span.clone());
+        let self_param = TypeParam {
+            ident: Ident::new(SELF_TYPE.to_string(), self.ident.span.clone()),
+            bounds: vec![self.ident.clone()],
+        };
+        if let Err(e) = table.add_type_param(&self_param) {
+            errors.push(e);
+        }
+
+        let mut declared: HashMap<&str, &Span> = HashMap::new();
+        for method in &self.methods {
+            if let Some(existing) = declared.insert(&method.ident.ident, &method.span) {
+                warn!("Trait method already declared: {}", method.ident);
+                errors.push(anyhow!(SemanticError::FunctionAlreadyDeclared(
+                    method.ident.clone(),
+                    method.span.clone(),
+                    existing.clone()
+                )));
+            }
+
+            let types = method.parameters.iter().map(|p| &p.ty);
+            for ty in types.chain(std::iter::once(&method.ty)) {
+                if let Err(e) = table.check_type(ty) {
+                    errors.push(e);
+                }
+            }
+        }
+        table.exit();
+
+        debug!("Trait declaration analysis errors: {:?}", errors);
+
+        errors
+    }
+}
+
+impl Analysis for ImplDecl {
+    /// The impl itself is registered before any function is analysed,
+    /// see `analyse_module`, so this checks its methods against the trait
+    fn analyze(&self, table: &mut SymbolTable) -> Vec<Error> {
+        debug!(
+            "Analyzing impl of {} for {}",
+            self.trait_ident.ident, self.ty
+        );
+        let mut errors = Vec::new();
+
+        // an undeclared trait was reported when the impl was registered
+        let declared = match table.get_trait(&self.trait_ident.ident) {
+            Some(t) => t.methods.clone(),
+            None => vec![],
+        };
+        let bindings = HashMap::from([(SELF_TYPE.to_string(), self.ty.clone())]);
+
+        let mut implemented: HashMap<&str, &Span> = HashMap::new();
+        for method in &self.methods {
+            if let Some(existing) = implemented.insert(&method.ident.ident, &method.span) {
+                warn!("Method already implemented: {}", method.ident);
+                errors.push(anyhow!(SemanticError::FunctionAlreadyDeclared(
+                    method.ident.clone(),
+                    method.span.clone(),
+                    existing.clone()
+                )));
+            }
+
+            match declared
+                .iter()
+                .find(|(name, _)| *name == method.ident.ident)
+            {
+                Some((_, decl)) => {
+                    let expected = decl.ty().substitute(&bindings);
+                    let found = signature_type(&method.parameters, &method.ty, &method.span);
+                    if !method.type_params.is_empty() || found != expected {
+                        warn!("Method does not match its trait: {}", method.ident);
+                        errors.push(anyhow!(SemanticError::IncompatibleMethod {
+                            method: method.ident.clone(),
+                            expected,
+                            found,
+                            span: method.span.clone(),
+                            decl_span: decl.span.clone(),
+                        }));
+                    }
+                }
+                None if table.get_trait(&self.trait_ident.ident).is_some() => {
+                    warn!("Method not in trait: {}", method.ident);
+                    errors.push(anyhow!(SemanticError::NotATraitMethod {
+                        method: method.ident.clone(),
+                        trait_ident: self.trait_ident.clone(),
+                        span: method.ident.span.clone(),
+                    }));
+                }
+                None => {}
+            }
+
+            errors.extend(method.analyze(table));
+        }
+
+        for (name, decl) in &declared {
+            if !implemented.contains_key(name.as_str()) {
+                warn!("Method not implemented: {}", name);
+                errors.push(anyhow!(SemanticError::MissingTraitMethod {
+                    method: name.clone(),
+                    trait_ident: self.trait_ident.clone(),
+                    ty: self.ty.clone(),
+                    span: Span::combine(&self.trait_ident.span, &self.ty.span()),
+                    decl_span: decl.span.clone(),
+                }));
+            }
+        }
+
+        debug!("Impl analysis errors: {:?}", errors);
+
+        errors
+    }
+}
@@ -1,8 +1,12 @@
 use crate::{ast::*, token::Span};
+use std::collections::HashMap;
 
 #[derive(Debug, Clone)]
 pub enum Type {
     Primitive(PrimitiveType),
+    Reference(ReferenceType),
+    Function(FunctionType),
+    Param(ParamType),
 }
 
 #[derive(Debug, Clone)]
@@ -11,17 +15,287 @@ pub struct PrimitiveType {
     pub span: Span,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PrimitiveKind {
-    Int,
+    I8,
+    I16,
+    I32,
+    /// Also spelled `int`
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
     Float,
     Bool,
 }
 
+/// `&T` or `&mut T`
+#[derive(Debug, Clone)]
+pub struct ReferenceType {
+    pub inner: Box<Type>,
+    pub mutable: bool,
+    pub span: Span,
+}
+
+/// `fn(params) -> ret`
+#[derive(Debug, Clone)]
+pub struct FunctionType {
+    pub params: Vec<Type>,
+    pub ret_ty: Box<Type>,
+    pub span: Span,
+}
+
+/// A type parameter of a generic function, e.g. `T`
+/// Replaced by a concrete type in every instantiation
+#[derive(Debug, Clone)]
+pub struct ParamType {
+    pub name: String,
+    pub span: Span,
+}
+
+impl Type {
+    /// A primitive type with no source location
+    pub fn primitive(kind: PrimitiveKind) -> Type {
+        Type::Primitive(PrimitiveType {
+            kind,
+            span: Span::default(),
+        })
+    }
+
+    /// Get the referenced type, if this is a reference
+    pub fn referenced(&self) -> Option<&ReferenceType> {
+        match self {
+            Type::Reference(r) => Some(r),
+            _ => None,
+        }
+    }
+
+    /// Get the function signature, if this is a function type
+    pub fn function(&self) -> Option<&FunctionType> {
+        match self {
+            Type::Function(f) => Some(f),
+            _ => None,
+        }
+    }
+
+    /// Is this a primitive of the given kind?
+    pub fn is_primitive(&self, kind: PrimitiveKind) -> bool {
+        match self {
+            Type::Primitive(p) => p.kind == kind,
+            _ => false,
+        }
+    }
+}
+
+impl PrimitiveKind {
+    /// The integer kind named by a type keyword or literal suffix, e.g. `u8`
+    pub fn from_int_suffix(suffix: &str) -> Option<PrimitiveKind> {
+        match suffix {
+            "i8" => Some(PrimitiveKind::I8),
+            "i16" => Some(PrimitiveKind::I16),
+            "i32" => Some(PrimitiveKind::I32),
+            "i64" => Some(PrimitiveKind::I64),
+            "u8" => Some(PrimitiveKind::U8),
+            "u16" => Some(PrimitiveKind::U16),
+            "u32" => Some(PrimitiveKind::U32),
+            "u64" => Some(PrimitiveKind::U64),
+            _ => None,
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.bits().is_some() && *self != PrimitiveKind::Bool
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(
+            self,
+            PrimitiveKind::I8 | PrimitiveKind::I16 | PrimitiveKind::I32 | PrimitiveKind::I64
+        )
+    }
+
+    /// Integers and floats
+    pub fn is_numeric(&self) -> bool {
+        self.is_integer() || *self == PrimitiveKind::Float
+    }
+
+    /// Width of integer kinds, including `bool`
+    pub fn bits(&self) -> Option<u32> {
+        match self {
+            PrimitiveKind::I8 | PrimitiveKind::U8 | PrimitiveKind::Bool => Some(8),
+            PrimitiveKind::I16 | PrimitiveKind::U16 => Some(16),
+            PrimitiveKind::I32 | PrimitiveKind::U32 => Some(32),
+            PrimitiveKind::I64 | PrimitiveKind::U64 => Some(64),
+            PrimitiveKind::Float => None,
+        }
+    }
+
+    /// Can an integer kind hold the value?
+    pub fn fits(&self, value: i128) -> bool {
+        let bits = match self.bits() {
+            Some(bits) if self.is_integer() => bits,
+            _ => return false,
+        };
+
+        if self.is_signed() {
+            let max = (1i128 << (bits - 1)) - 1;
+            (-max - 1..=max).contains(&value)
+        } else {
+            (0..1i128 << bits).contains(&value)
+        }
+    }
+
+    /// Wrap a value to an integer kind's width, as two's complement
+    pub fn wrap(&self, value: i128) -> i128 {
+        let bits = match self.bits() {
+            Some(bits) if self.is_integer() => bits,
+            _ => return value,
+        };
+
+        if self.is_signed() {
+            let shift = 128 - bits;
+            (value << shift) >> shift
+        } else {
+            value & ((1i128 << bits) - 1)
+        }
+    }
+
+    /// Convert a float to an integer kind, saturating at its bounds
+    /// NaN converts to zero
+    pub fn saturate(&self, value: f64) -> i128 {
+        let bits = match self.bits() {
+            Some(bits) if self.is_integer() => bits,
+            _ => return 0,
+        };
+
+        let (min, max) = if self.is_signed() {
+            (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1)
+        } else {
+            (0, (1i128 << bits) - 1)
+        };
+
+        if value.is_nan() {
+            0
+        } else {
+            (value as i128).clamp(min, max)
+        }
+    }
+}
+
+impl Type {
+    /// Is this an integer type of any width?
+    pub fn is_integer(&self) -> bool {
+        match self {
+            Type::Primitive(p) => p.kind.is_integer(),
+            _ => false,
+        }
+    }
+
+    /// Is this an integer or float type?
+    pub fn is_numeric(&self) -> bool {
+        match self {
+            Type::Primitive(p) => p.kind.is_numeric(),
+            _ => false,
+        }
+    }
+
+    /// Replace the type parameters bound in `bindings`, leaving the others
+    pub fn substitute(&self, bindings: &HashMap<String, Type>) -> Type {
+        match self {
+            Type::Primitive(_) => self.clone(),
+            Type::Reference(r) => Type::Reference(ReferenceType {
+                inner: Box::new(r.inner.substitute(bindings)),
+                mutable: r.mutable,
+                span: r.span.clone(),
+            }),
+            Type::Function(f) => Type::Function(FunctionType {
+                params: f.params.iter().map(|p| p.substitute(bindings)).collect(),
+                ret_ty: Box::new(f.ret_ty.substitute(bindings)),
+                span: f.span.clone(),
+            }),
+            Type::Param(p) => match bindings.get(&p.name) {
+                Some(ty) => ty.clone(),
+                None => self.clone(),
+            },
+        }
+    }
+
+    /// Match a declared type against a concrete one, binding its type parameters
+    /// Returns false if they do not match, or a parameter is bound twice differently
+    pub fn unify(&self, ty: &Type, bindings: &mut HashMap<String, Type>) -> bool {
+        match (self, ty) {
+            (Type::Param(p), ty) => match bindings.get(&p.name) {
+                Some(bound) => bound == ty,
+                None => {
+                    bindings.insert(p.name.clone(), ty.clone());
+                    true
+                }
+            },
+            (Type::Reference(r1), Type::Reference(r2)) => {
+                r1.mutable == r2.mutable && r1.inner.unify(&r2.inner, bindings)
+            }
+            (Type::Function(f1), Type::Function(f2)) => {
+                f1.params.len() == f2.params.len()
+                    && f1
+                        .params
+                        .iter()
+                        .zip(&f2.params)
+                        .all(|(p1, p2)| p1.unify(p2, bindings))
+                    && f1.ret_ty.unify(&f2.ret_ty, bindings)
+            }
+            (declared, ty) => declared == ty,
+        }
+    }
+
+    /// Every type parameter mentioned in the type
+    pub fn params(&self) -> Vec<&ParamType> {
+        match self {
+            Type::Primitive(_) => vec![],
+            Type::Reference(r) => r.inner.params(),
+            Type::Function(f) => f
+                .params
+                .iter()
+                .chain(std::iter::once(f.ret_ty.as_ref()))
+                .flat_map(|p| p.params())
+                .collect(),
+            Type::Param(p) => vec![p],
+        }
+    }
+
+    /// The same type, with every part located at `span`
+    /// Used for types declared in another file
+    pub fn with_span(&self, span: &Span) -> Type {
+        match self {
+            Type::Primitive(p) => Type::Primitive(PrimitiveType {
+                kind: p.kind,
+                span: span.clone(),
+            }),
+            Type::Reference(r) => Type::Reference(ReferenceType {
+                inner: Box::new(r.inner.with_span(span)),
+                mutable: r.mutable,
+                span: span.clone(),
+            }),
+            Type::Function(f) => Type::Function(FunctionType {
+                params: f.params.iter().map(|p| p.with_span(span)).collect(),
+                ret_ty: Box::new(f.ret_ty.with_span(span)),
+                span: span.clone(),
+            }),
+            Type::Param(p) => Type::Param(ParamType {
+                name: p.name.clone(),
+                span: span.clone(),
+            }),
+        }
+    }
+}
+
 impl PrettyPrint for Type {
     fn pretty_print(&self, _indent: usize) -> String {
         match self {
             Type::Primitive(p) => p.pretty_print(0),
+            Type::Reference(r) => r.pretty_print(0),
+            Type::Function(f) => f.pretty_print(0),
+            Type::Param(p) => p.name.clone(),
         }
     }
 }
@@ -29,17 +303,51 @@ impl PrettyPrint for Type {
 impl PrettyPrint for PrimitiveType {
     fn pretty_print(&self, _indent: usize) -> String {
         match self.kind {
-            PrimitiveKind::Int => "int".to_string(),
+            PrimitiveKind::I8 => "i8".to_string(),
+            PrimitiveKind::I16 => "i16".to_string(),
+            PrimitiveKind::I32 => "i32".to_string(),
+            PrimitiveKind::I64 => "int".to_string(),
+            PrimitiveKind::U8 => "u8".to_string(),
+            PrimitiveKind::U16 => "u16".to_string(),
+            PrimitiveKind::U32 => "u32".to_string(),
+            PrimitiveKind::U64 => "u64".to_string(),
             PrimitiveKind::Float => "float".to_string(),
             PrimitiveKind::Bool => "bool".to_string(),
         }
     }
 }
 
+impl PrettyPrint for ReferenceType {
+    fn pretty_print(&self, _indent: usize) -> String {
+        if self.mutable {
+            format!("&mut {}", self.inner.pretty_print(0))
+        } else {
+            format!("&{}", self.inner.pretty_print(0))
+        }
+    }
+}
+
+impl PrettyPrint for FunctionType {
+    fn pretty_print(&self, _indent: usize) -> String {
+        format!(
+            "fn({}) -> {}",
+            self.params
+                .iter()
+                .map(|p| p.pretty_print(0))
+                .collect::<Vec<String>>()
+                .join(", "),
+            self.ret_ty.pretty_print(0)
+        )
+    }
+}
+
 impl ASTSpan for Type {
     fn span(&self) -> Span {
         match self {
             Type::Primitive(p) => p.span.clone(),
+            Type::Reference(r) => r.span.clone(),
+            Type::Function(f) => f.span.clone(),
+            Type::Param(p) => p.span.clone(),
         }
     }
 }
@@ -54,6 +362,10 @@ impl PartialEq for Type {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (Type::Primitive(p1), Type::Primitive(p2)) => p1 == p2,
+            (Type::Reference(r1), Type::Reference(r2)) => r1 == r2,
+            (Type::Function(f1), Type::Function(f2)) => f1 == f2,
+            (Type::Param(p1), Type::Param(p2)) => p1.name == p2.name,
+            _ => false,
         }
     }
 }
@@ -63,3 +375,15 @@ impl PartialEq for PrimitiveType {
         self.kind == other.kind
     }
 }
+
+impl PartialEq for ReferenceType {
+    fn eq(&self, other: &Self) -> bool {
+        self.mutable == other.mutable && self.inner == other.inner
+    }
+}
+
+impl PartialEq for FunctionType {
+    fn eq(&self, other: &Self) -> bool {
+        self.params == other.params && self.ret_ty == other.ret_ty
+    }
+}
@@ -0,0 +1,140 @@
+//! Closure conversion.
+//!
+//! Every function value is a pointer to a closure object: the address of
+//! code taking the object as a hidden first argument, followed by one
+//! pointer-sized field per captured variable. A field holds the variable's
+//! value, or the address of its heap cell if the closure captures it by
+//! reference, so the closure may outlive the function that created it.
+//!
+//! Named functions used as values get a static object without captures,
+//! whose code is a thunk dropping the object and calling the function.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+
+use cranelift::codegen::ir::{types, AbiParam, InstBuilder, Signature, UserFuncName};
+use cranelift::codegen::Context;
+use cranelift::frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_module::{DataDescription, DataId, FuncId, Module};
+use cranelift_object::ObjectModule;
+
+/// Offset of a captured variable in a closure object
+pub fn capture_offset(index: usize, pointer_type: types::Type) -> i32 {
+    ((index + 1) * pointer_type.bytes() as usize) as i32
+}
+
+/// Size of a closure object with `captures` captured variables
+pub fn object_size(captures: usize, pointer_type: types::Type) -> i64 {
+    ((captures + 1) * pointer_type.bytes() as usize) as i64
+}
+
+/// The signature of a closure's code: `sig` with the object as first parameter
+pub fn closure_signature(sig: &Signature, pointer_type: types::Type) -> Signature {
+    let mut sig = sig.clone();
+    sig.params.insert(0, AbiParam::new(pointer_type));
+    sig
+}
+
+/// The closure objects of named functions used as values
+#[derive(Default)]
+pub struct FunctionValues {
+    /// Object of each function, by the function's id
+    objects: HashMap<FuncId, DataId>,
+    /// Thunks declared but not defined yet
+    thunks: Vec<Thunk>,
+}
+
+/// Code calling a named function through a closure object
+struct Thunk {
+    id: FuncId,
+    target: FuncId,
+    /// Signature of the target
+    sig: Signature,
+}
+
+impl FunctionValues {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the closure object of a function with signature `sig`,
+    /// declaring it and its thunk on first use
+    pub fn object(
+        &mut self,
+        module: &mut ObjectModule,
+        target: FuncId,
+        sig: &Signature,
+    ) -> Result<DataId> {
+        if let Some(id) = self.objects.get(&target) {
+            return Ok(*id);
+        }
+
+        let pointer_type = module.target_config().pointer_type();
+        let thunk = module.declare_anonymous_function(&closure_signature(sig, pointer_type))?;
+
+        let id = module.declare_anonymous_data(false, false)?;
+        let mut data = DataDescription::new();
+        // not zero-initialised, which would place it in `.bss` without its relocation
+        data.define(vec![0; object_size(0, pointer_type) as usize].into_boxed_slice());
+        data.set_align(pointer_type.bytes() as u64);
+        let thunk_ref = module.declare_func_in_data(thunk, &mut data);
+        data.write_function_addr(0, thunk_ref);
+        module.define_data(id, &data)?;
+
+        self.objects.insert(target, id);
+        self.thunks.push(Thunk {
+            id: thunk,
+            target,
+            sig: sig.clone(),
+        });
+
+        Ok(id)
+    }
+
+    /// Define the bodies of every thunk that was declared
+    pub fn define(
+        &self,
+        module: &mut ObjectModule,
+        ctx: &mut Context,
+        builder_ctx: &mut FunctionBuilderContext,
+    ) -> Result<()> {
+        for thunk in &self.thunks {
+            define_thunk(thunk, module, ctx, builder_ctx)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// `return target(args...)`, ignoring the closure object
+fn define_thunk(
+    thunk: &Thunk,
+    module: &mut ObjectModule,
+    ctx: &mut Context,
+    builder_ctx: &mut FunctionBuilderContext,
+) -> Result<()> {
+    let pointer_type = module.target_config().pointer_type();
+    ctx.func.signature = closure_signature(&thunk.sig, pointer_type);
+    ctx.func.name = UserFuncName::user(0, thunk.id.as_u32());
+
+    let mut builder = FunctionBuilder::new(&mut ctx.func, builder_ctx);
+    let entry = builder.create_block();
+    builder.append_block_params_for_function_params(entry);
+    builder.switch_to_block(entry);
+    builder.seal_block(entry);
+
+    let args = builder.block_params(entry)[1..].to_vec();
+    let target = module.declare_func_in_func(thunk.target, builder.func);
+    let call = builder.ins().call(target, &args);
+    let results = builder.inst_results(call).to_vec();
+    builder.ins().return_(&results);
+    builder.finalize();
+
+    module
+        .define_function(thunk.id, ctx)
+        .map_err(|e| anyhow!("Failed to define thunk: {:?}", e))?;
+    module.clear_context(ctx);
+
+    Ok(())
+}
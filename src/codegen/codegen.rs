@@ -1,61 +1,1712 @@
 //! Handles the main code-generation logic.
+//!
+//! Translates the MIR of the program into Cranelift IR, one function at a
+//! time, and collects the compiled functions into an object file.
 
-use crate::ast::*;
-use anyhow::{Result, Error, anyhow};
+use std::collections::HashMap;
 
-use cranelift::{codegen::{
-    ir::{types::I64, AbiParam, Function, Signature, UserFuncName, self},
-    isa::CallConv,
-}, prelude::{InstBuilder, Block, types::{I8, self}, MemFlags, Variable, EntityRef, Value, IntCC}};
-use cranelift::frontend::{FunctionBuilder, FunctionBuilderContext};
-use cranelift_module::Module;
+use crate::files::{Files, SourceFile};
+use crate::hir::{Static, UnaryOp};
+use crate::mir::{self, BlockId, InstKind, Terminator, VarId};
+use crate::token::Span;
+use anyhow::{anyhow, Result};
+
+use cranelift::codegen::ir::{
+    condcodes::FloatCC, types, AbiParam, Block, Endianness, InstBuilder, MemFlags, Signature,
+    StackSlot, StackSlotData, StackSlotKind, TrapCode, UserFuncName, Value,
+};
+use cranelift::codegen::{settings, settings::Configurable, Context};
+use cranelift::frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift::prelude::{EntityRef, IntCC};
+use cranelift_module::{default_libcall_names, DataDescription, DataId, FuncId, Linkage, Module};
 use cranelift_object::{ObjectBuilder, ObjectModule};
 
-use crate::ast;
+use crate::ast::{self, BinaryOperatorKind, FunctionType, PrimitiveKind};
+use crate::semantic_analysis::ConstValue;
+
+use super::closures::{self, FunctionValues};
+use super::runtime::Runtime;
+
+/// Options for code generation
+#[derive(Debug, Clone)]
+pub struct CodegenOptions {
+    /// Print the Cranelift IR of each function
+    pub print_ir: bool,
+    /// Print the machine code of each function
+    pub print_asm: bool,
+    /// Panic on integer overflow and division by zero
+    pub checked_arith: bool,
+    /// How much Cranelift optimises
+    pub opt_level: settings::OptLevel,
+    /// Run Cranelift's verifier over each function
+    pub verify: bool,
+    /// Keep every variable in a stack slot, not only those whose address is taken
+    pub stack_locals: bool,
+}
+
+/// Generates an object file from the MIR of a program
+pub struct CodeGenerator {
+    module: ObjectModule,
+    ctx: Context,
+    builder_ctx: FunctionBuilderContext,
+    /// All declared functions, by symbol name
+    functions: HashMap<String, FunctionInfo>,
+    /// All declared statics, by qualified name
+    statics: HashMap<String, DataId>,
+    /// Support routines used by the program
+    runtime: Runtime,
+    /// Closure objects of named functions used as values
+    fn_values: FunctionValues,
+    options: CodegenOptions,
+}
+
+/// A declared function
+struct FunctionInfo {
+    id: FuncId,
+    /// Signature without the closure object of closures
+    sig: Signature,
+}
+
+/// Where a variable lives
+#[derive(Debug, Clone, Copy)]
+enum Storage {
+    /// An SSA variable
+    Var(Variable),
+    /// A stack slot, for variables whose address is taken, or every variable at `-O0`
+    Stack(StackSlot),
+}
+
+/// Translates a single function body
+struct FunctionTranslator<'a> {
+    builder: FunctionBuilder<'a>,
+    module: &'a mut ObjectModule,
+    functions: &'a HashMap<String, FunctionInfo>,
+    statics: &'a HashMap<String, DataId>,
+    func: &'a mir::Function,
+    pointer_type: types::Type,
+    /// The Cranelift block of each MIR block
+    blocks: Vec<Block>,
+    /// The Cranelift value of each MIR value defined so far
+    values: HashMap<mir::Value, Value>,
+    /// Storage of each variable
+    vars: Vec<Storage>,
+    runtime: &'a mut Runtime,
+    fn_values: &'a mut FunctionValues,
+    /// Emit overflow and divisor checks
+    checked_arith: bool,
+    /// Keep every variable in a stack slot
+    stack_locals: bool,
+    /// The file being compiled, for panic locations
+    file: &'a SourceFile,
+}
 
-fn to_primitive_kind(ty: &ast::Type) -> Result<ast::PrimitiveKind> {
+/// Get the Cranelift type used to represent a type
+/// References are pointers
+fn to_cranelift_type(ty: &ast::Type, pointer_type: types::Type) -> types::Type {
     match ty {
-        ast::Type::Primitive(p) => Ok(p.kind.clone()),
-        _ => Err(anyhow!("Expected a primitive type")),
+        ast::Type::Primitive(p) => match p.kind {
+            ast::PrimitiveKind::Float => types::F32,
+            kind => types::Type::int_with_byte_size(kind.bits().unwrap() as u16 / 8).unwrap(),
+        },
+        ast::Type::Reference(_) | ast::Type::Function(_) => pointer_type,
+        ast::Type::Param(p) => unreachable!("type parameter `{}` was not instantiated", p.name),
+    }
+}
+
+/// The object file symbol of a function or static
+/// Names are prefixed so they can't clash with the C library routines the
+/// runtime imports, except `main`, which the C runtime calls
+fn symbol_name(name: &str) -> String {
+    match name {
+        "main" => name.to_string(),
+        _ => format!("pyl::{}", name),
+    }
+}
+
+impl CodeGenerator {
+    /// Create a code generator targeting the host machine
+    pub fn new(name: &str, options: CodegenOptions) -> Result<Self> {
+        let mut flag_builder = settings::builder();
+        // executables are linked as position-independent by default
+        flag_builder.set("is_pic", "true")?;
+        flag_builder.set(
+            "opt_level",
+            match options.opt_level {
+                settings::OptLevel::None => "none",
+                settings::OptLevel::Speed => "speed",
+                settings::OptLevel::SpeedAndSize => "speed_and_size",
+            },
+        )?;
+        flag_builder.set(
+            "enable_verifier",
+            if options.verify { "true" } else { "false" },
+        )?;
+
+        let isa_builder = cranelift_native::builder().map_err(|e| anyhow!(e))?;
+        let isa = isa_builder.finish(settings::Flags::new(flag_builder))?;
+
+        let builder = ObjectBuilder::new(isa, name, default_libcall_names())?;
+        let module = ObjectModule::new(builder);
+
+        Ok(Self {
+            ctx: module.make_context(),
+            module,
+            builder_ctx: FunctionBuilderContext::new(),
+            functions: HashMap::new(),
+            statics: HashMap::new(),
+            runtime: Runtime::new(),
+            fn_values: FunctionValues::new(),
+            options,
+        })
+    }
+
+    /// Declare every function and static, so they can be used in any order
+    pub fn declare_program(&mut self, program: &mir::Program) -> Result<()> {
+        for func in &program.functions {
+            self.declare_function(func)?;
+        }
+
+        for global in &program.statics {
+            self.declare_static(global)?;
+        }
+
+        Ok(())
+    }
+
+    /// Compile every function of a declared program
+    /// `files` holds the source of each function, used to locate runtime panics
+    pub fn compile_program(&mut self, program: &mir::Program, files: &Files) -> Result<()> {
+        for func in &program.functions {
+            let file = files
+                .get_file(&func.file)
+                .ok_or_else(|| anyhow!("Unknown file: {}", func.file))?;
+            self.define_function(func, file)?;
+        }
+
+        Ok(())
+    }
+
+    /// Finish code generation and produce the object file
+    pub fn finish(mut self) -> Result<Vec<u8>> {
+        self.fn_values
+            .define(&mut self.module, &mut self.ctx, &mut self.builder_ctx)?;
+        self.runtime
+            .define(&mut self.module, &mut self.ctx, &mut self.builder_ctx)?;
+
+        let product = self.module.finish();
+        Ok(product.emit()?)
+    }
+
+    /// The signature of a function, without the closure object of closures
+    fn signature(&self, func: &mir::Function) -> Signature {
+        let pointer_type = self.module.target_config().pointer_type();
+        let mut sig = self.module.make_signature();
+
+        for ty in &func.params {
+            sig.params
+                .push(AbiParam::new(to_cranelift_type(ty, pointer_type)));
+        }
+        sig.returns
+            .push(AbiParam::new(to_cranelift_type(&func.ret_ty, pointer_type)));
+
+        sig
+    }
+
+    /// The signature a function is defined with
+    fn full_signature(&self, func: &mir::Function) -> Signature {
+        let sig = self.signature(func);
+        match func.closure {
+            true => closures::closure_signature(&sig, self.module.target_config().pointer_type()),
+            false => sig,
+        }
+    }
+
+    /// Declare a function under its symbol name
+    fn declare_function(&mut self, func: &mir::Function) -> Result<()> {
+        // only `main` needs to be visible to the linker
+        let linkage = if func.name == "main" {
+            Linkage::Export
+        } else {
+            Linkage::Local
+        };
+
+        let id = self.module.declare_function(
+            &symbol_name(&func.name),
+            linkage,
+            &self.full_signature(func),
+        )?;
+
+        let sig = self.signature(func);
+        self.functions
+            .insert(func.name.clone(), FunctionInfo { id, sig });
+
+        Ok(())
+    }
+
+    /// Declare a static, placing its value in the data section
+    fn declare_static(&mut self, global: &Static) -> Result<()> {
+        // only `static mut` needs to be writable
+        let id = self.module.declare_data(
+            &symbol_name(&global.name),
+            Linkage::Local,
+            global.mutable,
+            false,
+        )?;
+
+        let bytes = self.value_bytes(global.value, &global.ty);
+        let mut data = DataDescription::new();
+        data.set_align(bytes.len() as u64);
+        data.define(bytes.into_boxed_slice());
+        self.module.define_data(id, &data)?;
+
+        self.statics.insert(global.name.clone(), id);
+
+        Ok(())
+    }
+
+    /// The representation of a value in memory, in the target's byte order
+    fn value_bytes(&self, value: ConstValue, ty: &ast::Type) -> Vec<u8> {
+        let pointer_type = self.module.target_config().pointer_type();
+        let size = to_cranelift_type(ty, pointer_type).bytes() as usize;
+
+        let bits = match value {
+            ConstValue::Int(v, _) => v as u64,
+            ConstValue::Float(f) => (f as f32).to_bits() as u64,
+            ConstValue::Bool(b) => b as u64,
+        };
+
+        match self.module.isa().endianness() {
+            Endianness::Little => bits.to_le_bytes()[..size].to_vec(),
+            Endianness::Big => bits.to_be_bytes()[8 - size..].to_vec(),
+        }
+    }
+
+    /// Translate a declared function and define it
+    fn define_function(&mut self, func: &mir::Function, file: &SourceFile) -> Result<()> {
+        let id = self.functions[&func.name].id;
+        self.ctx.func.signature = self.full_signature(func);
+        self.ctx.func.name = UserFuncName::user(0, id.as_u32());
+
+        let pointer_type = self.module.target_config().pointer_type();
+        let builder = FunctionBuilder::new(&mut self.ctx.func, &mut self.builder_ctx);
+
+        let mut translator = FunctionTranslator {
+            builder,
+            module: &mut self.module,
+            functions: &self.functions,
+            statics: &self.statics,
+            func,
+            pointer_type,
+            blocks: Vec::new(),
+            values: HashMap::new(),
+            vars: Vec::new(),
+            runtime: &mut self.runtime,
+            fn_values: &mut self.fn_values,
+            checked_arith: self.options.checked_arith,
+            stack_locals: self.options.stack_locals,
+            file,
+        };
+
+        translator.translate_function()?;
+        translator.builder.finalize();
+
+        if self.options.print_ir {
+            println!("{}", self.ctx.func.display());
+        }
+
+        if self.options.print_asm {
+            self.ctx.set_disasm(true);
+        }
+
+        self.module
+            .define_function(id, &mut self.ctx)
+            .map_err(|e| anyhow!("Failed to define function `{}`: {:?}", func.name, e))?;
+
+        if self.options.print_asm {
+            if let Some(vcode) = self.ctx.compiled_code().and_then(|c| c.vcode.as_ref()) {
+                println!("{}", vcode);
+            }
+        }
+
+        self.module.clear_context(&mut self.ctx);
+
+        Ok(())
     }
 }
 
-/// Only supports primitive types for now
-fn to_cranelift_type(ty: &ast::Type) -> Result<types::Type> {
-    match to_primitive_kind(ty)? {
-        ast::PrimitiveKind::Int => Ok(I64),
-        ast::PrimitiveKind::Bool => Ok(I8),
-        ast::PrimitiveKind::Float => unimplemented!(),
+impl<'a> FunctionTranslator<'a> {
+    fn translate_function(&mut self) -> Result<()> {
+        for _ in &self.func.blocks {
+            self.blocks.push(self.builder.create_block());
+        }
+        self.builder
+            .append_block_params_for_function_params(self.blocks[0]);
+
+        for (i, var) in self.func.vars.iter().enumerate() {
+            let ty = to_cranelift_type(&var.ty, self.pointer_type);
+            let storage = if var.addressed || self.stack_locals {
+                Storage::Stack(self.builder.create_sized_stack_slot(StackSlotData::new(
+                    StackSlotKind::ExplicitSlot,
+                    ty.bytes(),
+                )))
+            } else {
+                let variable = Variable::new(i);
+                self.builder.declare_var(variable, ty);
+                Storage::Var(variable)
+            };
+            self.vars.push(storage);
+        }
+
+        for (i, block) in self.func.blocks.iter().enumerate() {
+            self.builder.switch_to_block(self.blocks[i]);
+            for inst in &block.insts {
+                let value = self.translate_inst(&inst.kind, inst.result, &inst.span)?;
+                if let (Some(result), Some(value)) = (inst.result, value) {
+                    self.values.insert(result, value);
+                }
+            }
+            self.translate_terminator(&block.terminator)?;
+        }
+
+        // variables are read across blocks, so blocks are only sealed
+        // once every predecessor is known
+        self.builder.seal_all_blocks();
+
+        Ok(())
+    }
+
+    fn value(&self, value: mir::Value) -> Result<Value> {
+        self.values
+            .get(&value)
+            .copied()
+            .ok_or_else(|| anyhow!("Value {} used before it is defined", value))
+    }
+
+    fn values(&self, values: &[mir::Value]) -> Result<Vec<Value>> {
+        values.iter().map(|v| self.value(*v)).collect()
+    }
+
+    /// The Cranelift type of a value
+    fn cl_type(&self, value: mir::Value) -> types::Type {
+        to_cranelift_type(self.func.value_type(value), self.pointer_type)
+    }
+
+    fn function(&self, name: &str) -> Result<&'a FunctionInfo> {
+        let functions: &'a HashMap<String, FunctionInfo> = self.functions;
+        functions
+            .get(name)
+            .ok_or_else(|| anyhow!("Function `{}` not found during codegen", name))
+    }
+
+    /// Get the address of a static's data
+    fn static_addr(&mut self, name: &str) -> Result<Value> {
+        let id = *self
+            .statics
+            .get(name)
+            .ok_or_else(|| anyhow!("Static `{}` not found during codegen", name))?;
+        let data = self.module.declare_data_in_func(id, self.builder.func);
+        Ok(self.builder.ins().global_value(self.pointer_type, data))
+    }
+
+    /// Build the Cranelift signature for a function type
+    fn function_signature(&self, ty: &FunctionType) -> Signature {
+        let mut sig = self.module.make_signature();
+        for param in &ty.params {
+            sig.params
+                .push(AbiParam::new(to_cranelift_type(param, self.pointer_type)));
+        }
+        sig.returns.push(AbiParam::new(to_cranelift_type(
+            &ty.ret_ty,
+            self.pointer_type,
+        )));
+        sig
+    }
+
+    /// Translate an instruction, returning the value it defines
+    fn translate_inst(
+        &mut self,
+        kind: &InstKind,
+        result: Option<mir::Value>,
+        span: &Span,
+    ) -> Result<Option<Value>> {
+        let value = match kind {
+            InstKind::Param(i) => {
+                // closures are passed their object first
+                let index = i + self.func.closure as usize;
+                self.builder.block_params(self.blocks[0])[index]
+            }
+            InstKind::Capture(i) => {
+                let object = self.builder.block_params(self.blocks[0])[0];
+                let ty = self.cl_type(result.expect("captures define a value"));
+                let offset = closures::capture_offset(*i, self.pointer_type);
+                self.builder
+                    .ins()
+                    .load(ty, MemFlags::trusted(), object, offset)
+            }
+            InstKind::Const(c) => {
+                let ty = self.cl_type(result.expect("constants define a value"));
+                self.translate_const(*c, ty)
+            }
+            InstKind::Read(var) => self.read_var(*var),
+            InstKind::Write(var, v) => {
+                let value = self.value(*v)?;
+                match self.vars[var.0] {
+                    Storage::Var(variable) => self.builder.def_var(variable, value),
+                    Storage::Stack(slot) => {
+                        self.builder.ins().stack_store(value, slot, 0);
+                    }
+                }
+                return Ok(None);
+            }
+            InstKind::VarAddr(var) => match self.vars[var.0] {
+                Storage::Stack(slot) => self.builder.ins().stack_addr(self.pointer_type, slot, 0),
+                Storage::Var(_) => return Err(anyhow!("Variable {} is not addressable", var)),
+            },
+            InstKind::StaticAddr(name) => self.static_addr(name)?,
+            InstKind::Load(ptr) => {
+                let ty = self.cl_type(result.expect("loads define a value"));
+                let ptr = self.value(*ptr)?;
+                self.builder.ins().load(ty, MemFlags::trusted(), ptr, 0)
+            }
+            InstKind::Store(ptr, v) => {
+                let ptr = self.value(*ptr)?;
+                let value = self.value(*v)?;
+                self.builder.ins().store(MemFlags::trusted(), value, ptr, 0);
+                return Ok(None);
+            }
+            InstKind::Unary(op, v) => {
+                let is_float = self.func.value_type(*v).is_primitive(PrimitiveKind::Float);
+                let value = self.value(*v)?;
+                match op {
+                    UnaryOp::Neg if is_float => self.builder.ins().fneg(value),
+                    UnaryOp::Neg if self.checked_arith => self.translate_negation(value, span)?,
+                    UnaryOp::Neg => self.builder.ins().ineg(value),
+                    UnaryOp::Not => self.builder.ins().icmp_imm(IntCC::Equal, value, 0),
+                }
+            }
+            InstKind::Binary(op, lhs, rhs) => self.translate_binary(*op, *lhs, *rhs, span)?,
+            InstKind::Cast(v) => {
+                let to_type = self.func.value_type(result.expect("casts define a value"));
+                self.translate_cast(*v, to_type)?
+            }
+            InstKind::Call(name, args) => {
+                let args = self.values(args)?;
+                let id = self.function(name)?.id;
+                let func_ref = self.module.declare_func_in_func(id, self.builder.func);
+                let call = self.builder.ins().call(func_ref, &args);
+                self.builder.inst_results(call)[0]
+            }
+            // a function value calls the code at the start of its closure object
+            InstKind::CallIndirect(f, args) => {
+                let func_ty = match self.func.value_type(*f) {
+                    ast::Type::Function(func_ty) => func_ty,
+                    ty => return Err(anyhow!("Cannot call {}", ty)),
+                };
+                let sig = closures::closure_signature(
+                    &self.function_signature(func_ty),
+                    self.pointer_type,
+                );
+                let sig_ref = self.builder.import_signature(sig);
+                let object = self.value(*f)?;
+                let code =
+                    self.builder
+                        .ins()
+                        .load(self.pointer_type, MemFlags::trusted(), object, 0);
+
+                let mut args = self.values(args)?;
+                args.insert(0, object);
+                let call = self.builder.ins().call_indirect(sig_ref, code, &args);
+                self.builder.inst_results(call)[0]
+            }
+            InstKind::FunctionValue(name) => {
+                let func = self.function(name)?;
+                let object = self.fn_values.object(self.module, func.id, &func.sig)?;
+                let data = self.module.declare_data_in_func(object, self.builder.func);
+                self.builder.ins().global_value(self.pointer_type, data)
+            }
+            InstKind::Closure(name, captures) => self.translate_closure(name, captures)?,
+            InstKind::Alloc => {
+                let result = result.expect("allocations define a value");
+                let ty = match self.func.value_type(result) {
+                    ast::Type::Reference(r) => to_cranelift_type(&r.inner, self.pointer_type),
+                    ty => return Err(anyhow!("Cannot allocate a cell for {}", ty)),
+                };
+                self.translate_alloc(ty.bytes() as i64)?
+            }
+        };
+
+        Ok(Some(value))
+    }
+
+    fn translate_terminator(&mut self, terminator: &Terminator) -> Result<()> {
+        match terminator {
+            Terminator::Jump(b) => {
+                let block = self.block(*b);
+                self.builder.ins().jump(block, &[]);
+            }
+            Terminator::Branch(c, then, els) => {
+                let condition = self.value(*c)?;
+                let (then, els) = (self.block(*then), self.block(*els));
+                self.builder.ins().brif(condition, then, &[], els, &[]);
+            }
+            Terminator::Return(Some(v)) => {
+                let value = self.value(*v)?;
+                self.builder.ins().return_(&[value]);
+            }
+            Terminator::Return(None) => {
+                self.builder.ins().return_(&[]);
+            }
+            Terminator::Unreachable => {
+                self.builder.ins().trap(TrapCode::UnreachableCodeReached);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn block(&self, id: BlockId) -> Block {
+        self.blocks[id.0]
+    }
+
+    fn read_var(&mut self, var: VarId) -> Value {
+        match self.vars[var.0] {
+            Storage::Var(variable) => self.builder.use_var(variable),
+            Storage::Stack(slot) => {
+                let ty = to_cranelift_type(&self.func.var(var).ty, self.pointer_type);
+                self.builder.ins().stack_load(ty, slot, 0)
+            }
+        }
+    }
+
+    /// Allocate `size` bytes on the heap
+    fn translate_alloc(&mut self, size: i64) -> Result<Value> {
+        let alloc = self.runtime.alloc_fn(self.module)?;
+        let alloc = self.module.declare_func_in_func(alloc, self.builder.func);
+        let size = self.builder.ins().iconst(types::I64, size);
+        let call = self.builder.ins().call(alloc, &[size]);
+        Ok(self.builder.inst_results(call)[0])
+    }
+
+    /// Allocate a closure object, holding the closure's code and its captures
+    fn translate_closure(&mut self, name: &str, captures: &[mir::Value]) -> Result<Value> {
+        let size = closures::object_size(captures.len(), self.pointer_type);
+        let object = self.translate_alloc(size)?;
+
+        let code = self.function(name)?.id;
+        let code = self.module.declare_func_in_func(code, self.builder.func);
+        let code = self.builder.ins().func_addr(self.pointer_type, code);
+        self.builder
+            .ins()
+            .store(MemFlags::trusted(), code, object, 0);
+
+        for (index, capture) in captures.iter().enumerate() {
+            let value = self.value(*capture)?;
+            let offset = closures::capture_offset(index, self.pointer_type);
+            self.builder
+                .ins()
+                .store(MemFlags::trusted(), value, object, offset);
+        }
+
+        Ok(object)
+    }
+
+    /// A constant of Cranelift type `ty`
+    fn translate_const(&mut self, value: ConstValue, ty: types::Type) -> Value {
+        match value {
+            ConstValue::Int(v, _) => {
+                // immediates are the zero-extended bit patterns of the narrow type
+                let imm = (v as u64) & (u64::MAX >> (64 - ty.bits()));
+                self.builder.ins().iconst(ty, imm as i64)
+            }
+            ConstValue::Float(f) => self.builder.ins().f32const(f as f32),
+            ConstValue::Bool(b) => self.builder.ins().iconst(types::I8, b as i64),
+        }
+    }
+
+    fn translate_binary(
+        &mut self,
+        op: BinaryOperatorKind,
+        lhs: mir::Value,
+        rhs: mir::Value,
+        span: &Span,
+    ) -> Result<Value> {
+        let kind = match self.func.value_type(lhs) {
+            ast::Type::Primitive(p) => p.kind,
+            // references and functions compare as unsigned addresses
+            _ => PrimitiveKind::U64,
+        };
+        let is_float = kind == PrimitiveKind::Float;
+        let signed = kind.is_signed();
+
+        let lhs = self.value(lhs)?;
+        let rhs = self.value(rhs)?;
+
+        if self.checked_arith && kind.is_integer() {
+            match op {
+                BinaryOperatorKind::Add
+                | BinaryOperatorKind::Subtract
+                | BinaryOperatorKind::Multiply => {
+                    return self.translate_overflowing(op, span, signed, lhs, rhs)
+                }
+                BinaryOperatorKind::Divide | BinaryOperatorKind::Modulus => {
+                    self.check_divisor(op, span, signed, lhs, rhs)?
+                }
+                _ => {}
+            }
+        }
+
+        let ins = self.builder.ins();
+        let value = match (op, is_float) {
+            (BinaryOperatorKind::Add, false) => ins.iadd(lhs, rhs),
+            (BinaryOperatorKind::Add, true) => ins.fadd(lhs, rhs),
+            (BinaryOperatorKind::Subtract, false) => ins.isub(lhs, rhs),
+            (BinaryOperatorKind::Subtract, true) => ins.fsub(lhs, rhs),
+            (BinaryOperatorKind::Multiply, false) => ins.imul(lhs, rhs),
+            (BinaryOperatorKind::Multiply, true) => ins.fmul(lhs, rhs),
+            (BinaryOperatorKind::Divide, false) if signed => ins.sdiv(lhs, rhs),
+            (BinaryOperatorKind::Divide, false) => ins.udiv(lhs, rhs),
+            (BinaryOperatorKind::Divide, true) => ins.fdiv(lhs, rhs),
+            (BinaryOperatorKind::Modulus, false) if signed => ins.srem(lhs, rhs),
+            (BinaryOperatorKind::Modulus, false) => ins.urem(lhs, rhs),
+            // truncated like the integer remainder, so it takes the sign of `lhs`
+            (BinaryOperatorKind::Modulus, true) => {
+                let quotient = ins.fdiv(lhs, rhs);
+                let quotient = self.builder.ins().trunc(quotient);
+                let multiple = self.builder.ins().fmul(quotient, rhs);
+                self.builder.ins().fsub(lhs, multiple)
+            }
+            (BinaryOperatorKind::And | BinaryOperatorKind::Or, _) => {
+                return Err(anyhow!("`{}` should have been lowered to branches", op))
+            }
+            (op, false) => ins.icmp(int_cc(&op, signed), lhs, rhs),
+            (op, true) => ins.fcmp(float_cc(&op), lhs, rhs),
+        };
+
+        Ok(value)
+    }
+
+    /// Integer `+`, `-` or `*` that panics instead of wrapping
+    fn translate_overflowing(
+        &mut self,
+        op: BinaryOperatorKind,
+        span: &Span,
+        signed: bool,
+        lhs: Value,
+        rhs: Value,
+    ) -> Result<Value> {
+        let ins = self.builder.ins();
+        let ((value, overflow), verb) = match (op, signed) {
+            (BinaryOperatorKind::Add, true) => (ins.sadd_overflow(lhs, rhs), "add"),
+            (BinaryOperatorKind::Add, false) => (ins.uadd_overflow(lhs, rhs), "add"),
+            (BinaryOperatorKind::Subtract, true) => (ins.ssub_overflow(lhs, rhs), "subtract"),
+            (BinaryOperatorKind::Subtract, false) => (ins.usub_overflow(lhs, rhs), "subtract"),
+            (BinaryOperatorKind::Multiply, true) => (ins.smul_overflow(lhs, rhs), "multiply"),
+            (BinaryOperatorKind::Multiply, false) => (ins.umul_overflow(lhs, rhs), "multiply"),
+            (op, _) => unreachable!("not an overflowing operator: {:?}", op),
+        };
+
+        let msg = format!("attempt to {} with overflow", verb);
+        self.panic_if(overflow, span, &msg)?;
+
+        Ok(value)
+    }
+
+    /// Integer negation that panics on `-MIN` instead of wrapping,
+    /// as `0 - value` does
+    fn translate_negation(&mut self, value: Value, span: &Span) -> Result<Value> {
+        let ty = self.builder.func.dfg.value_type(value);
+        let zero = self.builder.ins().iconst(ty, 0);
+        let (value, overflow) = self.builder.ins().ssub_overflow(zero, value);
+        self.panic_if(overflow, span, "attempt to negate with overflow")?;
+
+        Ok(value)
+    }
+
+    /// Panic if the divisor is zero, or for signed types if the result
+    /// overflows (`MIN / -1`), rather than letting the hardware trap
+    fn check_divisor(
+        &mut self,
+        op: BinaryOperatorKind,
+        span: &Span,
+        signed: bool,
+        lhs: Value,
+        rhs: Value,
+    ) -> Result<()> {
+        let verb = match op {
+            BinaryOperatorKind::Divide => "divide",
+            _ => "calculate the remainder",
+        };
+
+        let is_zero = self.builder.ins().icmp_imm(IntCC::Equal, rhs, 0);
+        self.panic_if(is_zero, span, &format!("attempt to {} by zero", verb))?;
+
+        if signed {
+            // immediates are the zero-extended bit patterns of the narrow type
+            let bits = self.builder.func.dfg.value_type(lhs).bits();
+            let min = (1u64 << (bits - 1)) as i64;
+            let minus_one = (u64::MAX >> (64 - bits)) as i64;
+
+            let is_min = self.builder.ins().icmp_imm(IntCC::Equal, lhs, min);
+            let is_minus_one = self.builder.ins().icmp_imm(IntCC::Equal, rhs, minus_one);
+            let overflow = self.builder.ins().band(is_min, is_minus_one);
+            let msg = format!("attempt to {} with overflow", verb);
+            self.panic_if(overflow, span, &msg)?;
+        }
+
+        Ok(())
+    }
+
+    /// Call the panic routine if `condition` is true
+    /// The message is prefixed with the file, line and column of `span`
+    fn panic_if(&mut self, condition: Value, span: &Span, msg: &str) -> Result<()> {
+        let panic_block = self.builder.create_block();
+        let ok_block = self.builder.create_block();
+        self.builder
+            .ins()
+            .brif(condition, panic_block, &[], ok_block, &[]);
+
+        self.builder.switch_to_block(panic_block);
+        self.builder.seal_block(panic_block);
+        self.builder.set_cold_block(panic_block);
+
+        let (line, col) = self.file.location(span.start);
+        let text = format!("panic at {}:{}:{}: {}\n", self.file.id, line, col, msg);
+        let data = self.runtime.message(self.module, &text)?;
+        let panic = self.runtime.panic_fn(self.module)?;
+
+        let data = self.module.declare_data_in_func(data, self.builder.func);
+        let ptr = self.builder.ins().global_value(self.pointer_type, data);
+        let len = self.builder.ins().iconst(types::I64, text.len() as i64);
+        let panic = self.module.declare_func_in_func(panic, self.builder.func);
+        self.builder.ins().call(panic, &[ptr, len]);
+        self.builder.ins().trap(TrapCode::UnreachableCodeReached);
+
+        self.builder.switch_to_block(ok_block);
+        self.builder.seal_block(ok_block);
+
+        Ok(())
+    }
+
+    /// Convert between numeric types and `bool`
+    /// Integers are sign or zero extended by the source's signedness,
+    /// and floats convert to integers saturating, as in Rust
+    fn translate_cast(&mut self, value: mir::Value, to_type: &ast::Type) -> Result<Value> {
+        let from_type = self.func.value_type(value);
+        let from = match from_type {
+            ast::Type::Primitive(p) => p.kind,
+            ty => return Err(anyhow!("Cannot cast {}", ty)),
+        };
+        let to = match to_type {
+            ast::Type::Primitive(p) => p.kind,
+            ty => return Err(anyhow!("Cannot cast to {}", ty)),
+        };
+
+        let from_ty = to_cranelift_type(from_type, self.pointer_type);
+        let value = self.value(value)?;
+        let to_ty = to_cranelift_type(to_type, self.pointer_type);
+
+        let value = match (from, to) {
+            (from, to) if from == to => value,
+            // anything non-zero is true
+            (PrimitiveKind::Float, PrimitiveKind::Bool) => {
+                let zero = self.builder.ins().f32const(0.0);
+                self.builder.ins().fcmp(FloatCC::NotEqual, value, zero)
+            }
+            (_, PrimitiveKind::Bool) => self.builder.ins().icmp_imm(IntCC::NotEqual, value, 0),
+            (PrimitiveKind::Float, to) if to_ty.bits() < 32 => {
+                self.translate_narrowing_float_cast(value, to, to_ty)
+            }
+            (PrimitiveKind::Float, to) if to.is_signed() => {
+                self.builder.ins().fcvt_to_sint_sat(to_ty, value)
+            }
+            (PrimitiveKind::Float, _) => self.builder.ins().fcvt_to_uint_sat(to_ty, value),
+            (from, PrimitiveKind::Float) if from.is_signed() => {
+                self.builder.ins().fcvt_from_sint(to_ty, value)
+            }
+            (_, PrimitiveKind::Float) => self.builder.ins().fcvt_from_uint(to_ty, value),
+            _ if to_ty.bits() < from_ty.bits() => self.builder.ins().ireduce(to_ty, value),
+            _ if to_ty.bits() == from_ty.bits() => value,
+            (from, _) if from.is_signed() => self.builder.ins().sextend(to_ty, value),
+            _ => self.builder.ins().uextend(to_ty, value),
+        };
+
+        Ok(value)
+    }
+
+    /// Convert a float to an 8 or 16-bit integer, saturating at its bounds.
+    /// Cranelift only converts to 32 and 64 bits, so convert to 32 and clamp
+    fn translate_narrowing_float_cast(
+        &mut self,
+        value: Value,
+        to: PrimitiveKind,
+        to_ty: types::Type,
+    ) -> Value {
+        // immediates are the zero-extended bit patterns of the 32-bit values
+        let min = to.saturate(f64::NEG_INFINITY) as u32 as i64;
+        let max = to.saturate(f64::INFINITY) as u32 as i64;
+
+        let min = self.builder.ins().iconst(types::I32, min);
+        let max = self.builder.ins().iconst(types::I32, max);
+        let clamped = if to.is_signed() {
+            let wide = self.builder.ins().fcvt_to_sint_sat(types::I32, value);
+            let wide = self.builder.ins().smax(wide, min);
+            self.builder.ins().smin(wide, max)
+        } else {
+            let wide = self.builder.ins().fcvt_to_uint_sat(types::I32, value);
+            self.builder.ins().umin(wide, max)
+        };
+
+        self.builder.ins().ireduce(to_ty, clamped)
     }
 }
 
-/// Compiles a function declaration into a Cranelift IR function
-pub fn compile_function(funcDecl: &ast::FunctionDecl, builder: &mut FunctionBuilder) -> Result<Signature> {
-    let mut sig = Signature::new(CallConv::SystemV);
+fn int_cc(op: &BinaryOperatorKind, signed: bool) -> IntCC {
+    match (op, signed) {
+        (BinaryOperatorKind::Equal, _) => IntCC::Equal,
+        (BinaryOperatorKind::NotEqual, _) => IntCC::NotEqual,
+        (BinaryOperatorKind::LessThan, true) => IntCC::SignedLessThan,
+        (BinaryOperatorKind::LessThan, false) => IntCC::UnsignedLessThan,
+        (BinaryOperatorKind::LessThanOrEqual, true) => IntCC::SignedLessThanOrEqual,
+        (BinaryOperatorKind::LessThanOrEqual, false) => IntCC::UnsignedLessThanOrEqual,
+        (BinaryOperatorKind::GreaterThan, true) => IntCC::SignedGreaterThan,
+        (BinaryOperatorKind::GreaterThan, false) => IntCC::UnsignedGreaterThan,
+        (BinaryOperatorKind::GreaterThanOrEqual, true) => IntCC::SignedGreaterThanOrEqual,
+        (BinaryOperatorKind::GreaterThanOrEqual, false) => IntCC::UnsignedGreaterThanOrEqual,
+        _ => unreachable!("not a comparison: {:?}", op),
+    }
+}
 
-    // Parameters
-    for param in funcDecl.parameters.iter() {
-        let param_ty = to_cranelift_type(&param.ty)?;
-        sig.params.push(AbiParam::new(param_ty));
+fn float_cc(op: &BinaryOperatorKind) -> FloatCC {
+    match op {
+        BinaryOperatorKind::Equal => FloatCC::Equal,
+        BinaryOperatorKind::NotEqual => FloatCC::NotEqual,
+        BinaryOperatorKind::LessThan => FloatCC::LessThan,
+        BinaryOperatorKind::LessThanOrEqual => FloatCC::LessThanOrEqual,
+        BinaryOperatorKind::GreaterThan => FloatCC::GreaterThan,
+        BinaryOperatorKind::GreaterThanOrEqual => FloatCC::GreaterThanOrEqual,
+        _ => unreachable!("not a comparison: {:?}", op),
     }
+}
 
-    // Return type
-    let return_ty = to_cranelift_type(&funcDecl.ty)?;
-    sig.returns.push(AbiParam::new(return_ty));
+#[cfg(test)]
+mod tests {
+    use crate::frontend::{Compiler, CompilerOptions, OptLevel};
+    use anyhow::Result;
+    use std::path::PathBuf;
+    use test_log::test;
 
-    // Build function
-    let entry_block = builder.create_block();
-    builder.switch_to_block(entry_block);
-    builder.seal_block(entry_block);
-    builder.append_block_params_for_function_params(entry_block);
+    /// Compile and run a program, returning its exit code
+    fn run(name: &str, src: &str) -> i32 {
+        run_with(name, &[(name, src)], false).0
+    }
+
+    /// Compile a program with checked arithmetic and run it,
+    /// returning its exit code and stderr
+    fn run_checked(name: &str, src: &str) -> (i32, String) {
+        run_with(name, &[(name, src)], true)
+    }
 
-    // Variables
-    let mut variables = Vec::new();
-    for (param) in funcDecl.parameters.iter() {
-        let ty = to_cranelift_type(&param.ty)?;
-        let var = Variable::new(variables.len());
-        builder.declare_var(var, ty);
+    /// Compile with optimisations and run a program, returning its exit code and stderr
+    fn run_optimised(name: &str, src: &str) -> (i32, String) {
+        let options = CompilerOptions {
+            opt_level: OptLevel::O1,
+            ..options(true)
+        };
+        execute(&compile_with(name, &[(name, src)], options).expect("Failed to compile"))
     }
 
-    Ok(sig)
-}
\ No newline at end of file
+    fn options(checked_arith: bool) -> CompilerOptions {
+        CompilerOptions {
+            verbose: false,
+            print_tokens: false,
+            print_ast: false,
+            print_ir: true,
+            print_asm: false,
+            checked_arith,
+            emit: Default::default(),
+            opt_level: Default::default(),
+            warn_non_tail_recursion: false,
+        }
+    }
+
+    /// Compile a program from `(path, source)` files, the main file first
+    fn compile(name: &str, files: &[(&str, &str)], checked_arith: bool) -> Result<PathBuf> {
+        compile_with(name, files, options(checked_arith))
+    }
+
+    fn compile_with(
+        name: &str,
+        files: &[(&str, &str)],
+        options: CompilerOptions,
+    ) -> Result<PathBuf> {
+        let mut compiler = Compiler::new(options);
+        for (path, src) in files {
+            compiler.add_source(path.to_string(), src.to_string());
+        }
+
+        let dst = std::env::temp_dir().join(format!("{}_{}.exe", name, std::process::id()));
+        compiler.compile(&dst)?;
+
+        Ok(dst)
+    }
+
+    fn run_with(name: &str, files: &[(&str, &str)], checked_arith: bool) -> (i32, String) {
+        execute(&compile(name, files, checked_arith).expect("Failed to compile"))
+    }
+
+    /// Run a compiled executable and delete it, returning its exit code and stderr
+    fn execute(dst: &PathBuf) -> (i32, String) {
+        let output = std::process::Command::new(dst)
+            .output()
+            .expect("Failed to run executable");
+        std::fs::remove_file(dst).unwrap();
+
+        (
+            output.status.code().expect("Executable was killed"),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )
+    }
+
+    #[test]
+    fn arithmetic() {
+        let src = "fn main() -> int { let a: int = 5 + 2 * 3; return a - 1; }";
+        assert_eq!(run("arithmetic", src), 10);
+    }
+
+    #[test]
+    fn branches() {
+        let src = r#"fn main() -> int {
+            if max(3, 7) == 7 {
+                return 1;
+            } else {
+                return 2;
+            }
+        }
+
+        fn max(a: int, b: int) -> int {
+            if a > b {
+                return a;
+            }
+            return b;
+        }"#;
+        assert_eq!(run("branches", src), 1);
+    }
+
+    #[test]
+    fn references() {
+        let src = r#"fn bump(p: &mut int, by: int) -> int {
+            *p = *p + by;
+            return *p;
+        }
+
+        fn read(p: &int) -> int {
+            return *p;
+        }
+
+        fn main() -> int {
+            let mut x: int = 5;
+            let r: &mut int = &mut x;
+            bump(r, 10);
+            return bump(&mut x, 1) + read(&x);
+        }"#;
+        assert_eq!(run("references", src), 32);
+    }
+
+    #[test]
+    fn function_pointers() {
+        let src = r#"fn add(a: int, b: int) -> int {
+            return a + b;
+        }
+
+        fn mul(a: int, b: int) -> int {
+            return a * b;
+        }
+
+        fn apply(f: fn(int, int) -> int, a: int, b: int) -> int {
+            return f(a, b);
+        }
+
+        fn main() -> int {
+            let mut g: fn(int, int) -> int = add;
+            let x: int = apply(g, 2, 3);
+            g = mul;
+            return x + g(2, 10);
+        }"#;
+        assert_eq!(run("function_pointers", src), 25);
+    }
+
+    #[test]
+    fn sized_integers() {
+        let src = r#"fn main() -> int {
+            let a: u8 = 250;
+            let wrapped = a + 10;
+            let neg: i8 = -128;
+            let quot = (neg as int) / 3;
+            let big = 200u8 > 100;
+            return wrapped as int + quot + big as int + half(9) as int;
+        }
+
+        fn half(v: u16) -> u16 {
+            return v / 2;
+        }"#;
+        // 4 - 42 + 1 + 4
+        assert_eq!(run("sized_integers", src), 256 - 33);
+    }
+
+    #[test]
+    fn casts() {
+        let src = r#"fn main() -> int {
+            let f: float = 2.9;
+            let back: u16 = 65535;
+            let n = -1 as u8;
+            return f as int + (back as i16 == -1) as int + n as int;
+        }"#;
+        // 2 + 1 + 255
+        assert_eq!(run("casts", src), 258 % 256);
+
+        // floats saturate at the bounds of narrow integers
+        let narrow = r#"fn main() -> int {
+            let f = 1.5;
+            let big = 1e9;
+            let small = -1e9;
+            let mut failed = 0;
+            if (f as u8) as int != 1 || (f as i8) as int != 1 {
+                failed = failed + 1;
+            }
+            if (f as u16) as int != 1 || (-f as i16) as int != -1 {
+                failed = failed + 2;
+            }
+            if (big as u8) as int != 255 || (big as i8) as int != 127 {
+                failed = failed + 4;
+            }
+            if (big as u16) as int != 65535 || (big as i16) as int != 32767 {
+                failed = failed + 8;
+            }
+            if (small as u8) as int != 0 || (small as i8) as int != -128 {
+                failed = failed + 16;
+            }
+            if (small as u16) as int != 0 || (small as i16) as int != -32768 {
+                failed = failed + 32;
+            }
+            return failed;
+        }"#;
+        assert_eq!(run("narrow_float_casts", narrow), 0);
+    }
+
+    #[test]
+    fn literal_formats() {
+        let src = r#"fn main() -> int {
+            let big: u64 = 18_446_744_073_709_551_615;
+            let wide = 3_000_000_000;
+            return (big / 0x1_0000_0000_0000_00) as int + wide / 1_000_000_000 + 0o10;
+        }"#;
+        // 255 + 3 + 8
+        assert_eq!(run("literal_formats", src), 266 % 256);
+    }
+
+    #[test]
+    fn checked_arithmetic() {
+        let src = r#"fn main() -> int {
+            let a: i8 = -100;
+            let b: u32 = 7;
+            return (a - 27) as int + (b * 3 / 2 % 4) as int;
+        }"#;
+        // -127 + 2
+        assert_eq!(
+            run_checked("checked_arithmetic", src),
+            (256 - 125, String::new())
+        );
+    }
+
+    #[test]
+    fn overflow_panics() {
+        let src = r#"fn main() -> int {
+            let a: u8 = 250;
+            return add(a, 10) as int;
+        }
+
+        fn add(a: u8, b: u8) -> u8 {
+            return a + b;
+        }"#;
+        let (code, stderr) = run_checked("overflow_panics", src);
+        assert_eq!(code, 101);
+        assert_eq!(
+            stderr,
+            "panic at overflow_panics:7:20: attempt to add with overflow\n"
+        );
+    }
+
+    #[test]
+    fn negation_overflow_panics() {
+        let src = r#"fn main() -> int {
+            let a: i8 = -100;
+            return (-a) as int;
+        }"#;
+        assert_eq!(run_checked("negation", src), (100, String::new()));
+
+        let min = r#"fn main() -> int {
+            let min = -9223372036854775807 - 1;
+            return -min;
+        }"#;
+        let (code, stderr) = run_checked("negation_overflow", min);
+        assert_eq!(code, 101);
+        assert_eq!(
+            stderr,
+            "panic at negation_overflow:3:20: attempt to negate with overflow\n"
+        );
+
+        let narrow = r#"fn main() -> int {
+            let min: i8 = -127 - 1;
+            let neg: i8 = -min;
+            return neg as int;
+        }"#;
+        let (code, stderr) = run_checked("narrow_negation_overflow", narrow);
+        assert_eq!(code, 101);
+        assert_eq!(
+            stderr,
+            "panic at narrow_negation_overflow:3:27: attempt to negate with overflow\n"
+        );
+
+        // the panic routine still calls the C library's `write` and `exit`
+        let shadowed = r#"fn write(n: int) -> int { return n; }
+        fn exit(n: int) -> int { return n; }
+
+        fn main() -> int {
+            let min: i8 = -127 - 1;
+            return write(exit((-min) as int));
+        }"#;
+        let (code, stderr) = run_checked("panic_names", shadowed);
+        assert_eq!(code, 101);
+        assert_eq!(
+            stderr,
+            "panic at panic_names:6:32: attempt to negate with overflow\n"
+        );
+    }
+
+    #[test]
+    fn division_panics() {
+        let by_zero = r#"fn main() -> int {
+            let zero = 0;
+            return 10 / zero;
+        }"#;
+        let (code, stderr) = run_checked("division_by_zero", by_zero);
+        assert_eq!(code, 101);
+        assert_eq!(
+            stderr,
+            "panic at division_by_zero:3:20: attempt to divide by zero\n"
+        );
+
+        let min = r#"fn main() -> int {
+            let min: i16 = -32768;
+            let neg: i16 = -1;
+            return (min % neg) as int;
+        }"#;
+        let (code, stderr) = run_checked("remainder_overflow", min);
+        assert_eq!(code, 101);
+        assert_eq!(
+            stderr,
+            "panic at remainder_overflow:4:21: attempt to calculate the remainder with overflow\n"
+        );
+    }
+
+    #[test]
+    fn constant_folding() {
+        let src = r#"fn main() -> int {
+            let a: int = 5 + 2;
+            let verbose: bool = a > 10;
+            if verbose {
+                return 0;
+            }
+            let b: u8 = (a * 40) as u8;
+            return b as int + 1;
+        }"#;
+        assert_eq!(run_optimised("constant_folding", src), (25, String::new()));
+
+        // still panics at runtime, rather than folding to a wrapped value
+        let overflow = r#"fn main() -> int {
+            let a: i8 = 100;
+            return (a + a) as int;
+        }"#;
+        let (code, stderr) = run_optimised("folded_overflow", overflow);
+        assert_eq!(code, 101);
+        assert_eq!(
+            stderr,
+            "panic at folded_overflow:3:21: attempt to add with overflow\n"
+        );
+    }
+
+    #[test]
+    fn opt_levels() {
+        let src = r#"fn add(a: int, b: int) -> int { return a + b; }
+
+        fn main() -> int {
+            let mut total: int = 0;
+            let x: int = add(2, 3);
+            let p: &mut int = &mut total;
+            *p = add(x, 1);
+            return total;
+        }"#;
+
+        for (name, opt_level) in [
+            ("opt_0", OptLevel::O0),
+            ("opt_1", OptLevel::O1),
+            ("opt_2", OptLevel::O2),
+            ("opt_s", OptLevel::Os),
+        ] {
+            let options = CompilerOptions {
+                opt_level,
+                ..options(true)
+            };
+            let dst = compile_with(name, &[(name, src)], options).expect("Failed to compile");
+            assert_eq!(execute(&dst), (6, String::new()), "at {:?}", opt_level);
+        }
+
+        assert_eq!("s".parse(), Ok(OptLevel::Os));
+        assert!("3".parse::<OptLevel>().is_err());
+    }
+
+    #[test]
+    fn tail_calls() {
+        // deep enough to overflow the stack, unless the calls reuse their frame
+        let src = r#"fn count(n: int, acc: int) -> int {
+            if n == 0 {
+                return acc;
+            }
+            let next: int = n - 1;
+            return count(next, acc + 1);
+        }
+
+        fn main() -> int {
+            return count(10000000, 0) - 9999958;
+        }"#;
+        assert_eq!(run("tail_calls", src), 42);
+        assert_eq!(
+            run_optimised("tail_calls_optimised", src),
+            (42, String::new())
+        );
+    }
+
+    #[test]
+    fn deferred_initialization() {
+        let src = r#"fn pick(flag: bool) -> int {
+            let x: int;
+            if flag {
+                x = 10;
+            } else {
+                x = 20;
+            }
+            let mut y: int;
+            y = 1;
+            y = y + x;
+            let add = |n: int| -> int { return n + x; };
+            return add(y);
+        }
+
+        fn main() -> int {
+            return pick(true) + pick(false);
+        }"#;
+        assert_eq!(run("deferred_initialization", src), 62);
+        assert_eq!(
+            run_optimised("deferred_initialization_optimised", src),
+            (62, String::new())
+        );
+    }
+
+    #[test]
+    fn inlining() {
+        let src = r#"fn abs(x: int) -> int {
+            if x < 0 {
+                return -x;
+            }
+            return x;
+        }
+
+        fn fact(n: int) -> int {
+            if n < 2 {
+                return 1;
+            }
+            return n * fact(n - 1);
+        }
+
+        fn main() -> int {
+            let mut total: int = abs(3 - 10);
+            if total > 5 {
+                total = total + abs(total);
+            }
+            return total + fact(4);
+        }"#;
+        assert_eq!(run_optimised("inlining", src), (38, String::new()));
+
+        // an inlined panic still reports where it happened
+        let overflow = r#"fn add(a: i8, b: i8) -> i8 { return a + b; }
+
+        fn main() -> int {
+            return add(100, 100) as int;
+        }"#;
+        let (code, stderr) = run_optimised("inlined_overflow", overflow);
+        assert_eq!(code, 101);
+        assert_eq!(
+            stderr,
+            "panic at inlined_overflow:1:37: attempt to add with overflow\n"
+        );
+    }
+
+    #[test]
+    fn modules() {
+        let main = r#"mod math;
+        mod shapes;
+
+        fn main() -> int {
+            return math::add(1, 2) + shapes::square(3) + add(100);
+        }
+
+        fn add(x: int) -> int {
+            return x;
+        }"#;
+        let math = r#"pub fn add(a: int, b: int) -> int {
+            return a + b;
+        }
+
+        pub fn mul(a: int, b: int) -> int {
+            return a * b;
+        }"#;
+        let shapes = r#"mod math;
+
+        pub fn square(x: int) -> int {
+            return apply(math::mul, x);
+        }
+
+        fn apply(f: fn(int, int) -> int, x: int) -> int {
+            return f(x, x);
+        }"#;
+
+        let files = [
+            ("modules", main),
+            ("math.pyl", math),
+            ("shapes.pyl", shapes),
+        ];
+        // 3 + 9 + 100
+        assert_eq!(run_with("modules", &files, false).0, 112);
+    }
+
+    #[test]
+    fn globals() {
+        let main = r#"mod counter;
+
+        const BASE: u8 = 250;
+        const WRAPPED: u8 = (BASE as int + 10) as u8;
+        const SCALE: float = 1.5;
+        static mut TOTAL: int = BASE as int;
+
+        fn bump(by: int) -> int {
+            TOTAL = TOTAL + by;
+            return TOTAL;
+        }
+
+        fn read(p: &int) -> int {
+            return *p;
+        }
+
+        fn main() -> int {
+            let shared = &mut TOTAL;
+            *shared = *shared - 250;
+            bump(WRAPPED as int);
+            bump((SCALE * 2.0) as int);
+            return read(&TOTAL) + (*&BASE) as int - 250 + counter::next();
+        }"#;
+        let counter = r#"static mut COUNT: i8 = -1;
+
+        pub fn next() -> int {
+            COUNT = COUNT + 1;
+            COUNT = COUNT + 1;
+            return COUNT as int;
+        }"#;
+
+        // 260 wraps to 4, plus 3, and the counter counts up twice from -1
+        let files = [("globals", main), ("counter.pyl", counter)];
+        assert_eq!(run_with("globals", &files, false).0, 8);
+    }
+
+    #[test]
+    fn short_circuit() {
+        let src = r#"static mut CALLS: int = 0;
+
+        fn check(result: bool) -> bool {
+            CALLS = CALLS + 1;
+            return result;
+        }
+
+        fn boom() -> bool {
+            let zero = 0;
+            return 1 / zero == 0;
+        }
+
+        fn main() -> int {
+            if false && boom() {
+                return 1;
+            }
+            let a = check(false) && check(true);
+            let b = check(true) || check(false);
+            let c = check(true) && (check(false) || check(true));
+            if a || !b || !c {
+                return 2;
+            }
+            return CALLS;
+        }"#;
+        // only the right-hand sides that decide the result are called
+        assert_eq!(run_checked("short_circuit", src), (5, String::new()));
+        assert_eq!(
+            run_optimised("short_circuit_optimised", src),
+            (5, String::new())
+        );
+    }
+
+    #[test]
+    fn float_remainder() {
+        let src = r#"const FOLDED: float = -7.5 % 2.0;
+
+        fn rem(a: float, b: float) -> float {
+            return a % b;
+        }
+
+        fn main() -> int {
+            // 1.5 and -1.5, taking the sign of the left-hand side
+            let m = 7.5 % 2.0;
+            if rem(-7.5, 2.0) != FOLDED {
+                return 1;
+            }
+            return ((m + rem(7.5, -2.0)) * 10.0 - FOLDED * 2.0) as int;
+        }"#;
+        assert_eq!(run("float_remainder", src), 33);
+        assert_eq!(
+            run_optimised("float_remainder_optimised", src),
+            (33, String::new())
+        );
+    }
+
+    #[test]
+    fn const_fns() {
+        let src = r#"const fn cube(x: int) -> int {
+            let squared = x * x;
+            return squared * x;
+        }
+
+        const CUBED: int = cube(3);
+
+        fn main() -> int {
+            let x = 2;
+            return CUBED + cube(x);
+        }"#;
+        // evaluated at compile time and called at runtime alike
+        assert_eq!(run("const_fns", src), 35);
+    }
+
+    #[test]
+    fn generics() {
+        let main = r#"mod util;
+
+        fn max<T>(a: T, b: T) -> T {
+            if a > b {
+                return a;
+            }
+            return b;
+        }
+
+        fn clamp<T>(x: T, lo: T, hi: T) -> T {
+            return max(lo, util::min(x, hi));
+        }
+
+        fn main() -> int {
+            let small: u8 = 200;
+            let f = max(1.5, 2.75);
+            let b = clamp(small, 10, 100) as int;
+            return max(3, -4) + b + (f * 4.0) as int + util::min(small, 250) as int;
+        }"#;
+        let util = r#"pub fn min<T>(a: T, b: T) -> T {
+            if a < b {
+                return a;
+            }
+            return b;
+        }"#;
+
+        // 3 + 100 + 11 + 200, wrapped to the exit code
+        let files = [("generics", main), ("util.pyl", util)];
+        assert_eq!(run_with("generics", &files, false).0, 314 % 256);
+    }
+
+    #[test]
+    fn traits() {
+        let main = r#"mod shapes;
+
+        trait Size {
+            fn size(s: Self) -> int;
+            fn double(s: Self) -> Self;
+        }
+
+        impl Size for int {
+            fn size(s: int) -> int { return s; }
+            fn double(s: int) -> int { return s * 2; }
+        }
+
+        impl Size for bool {
+            fn size(s: bool) -> int {
+                if s {
+                    return 1;
+                }
+                return 0;
+            }
+            fn double(s: bool) -> bool { return s; }
+        }
+
+        fn total<T: Size>(a: T, b: T) -> int {
+            return Size::size(Size::double(a)) + Size::size(b);
+        }
+
+        fn main() -> int {
+            let square: u8 = 4;
+            return total(5, 7) + total(true, false) + shapes::sides(square);
+        }"#;
+        let shapes = r#"trait Sides {
+            fn sides(s: Self) -> int;
+        }
+
+        impl Sides for u8 {
+            fn sides(s: u8) -> int { return s as int * 10; }
+        }
+
+        pub fn sides<T: Sides>(s: T) -> int {
+            return Sides::sides(s);
+        }"#;
+
+        // 17 + 1 + 40
+        let files = [("traits", main), ("shapes.pyl", shapes)];
+        assert_eq!(run_with("traits", &files, false).0, 58);
+    }
+
+    #[test]
+    fn closures() {
+        let src = r#"fn make_adder(n: int) -> fn(int) -> int {
+            return |x: int| -> int { x + n };
+        }
+
+        fn apply<T>(f: fn(T) -> T, x: T) -> T {
+            return f(x);
+        }
+
+        fn double(x: int) -> int { return x * 2; }
+
+        fn main() -> int {
+            let add5 = make_adder(5);
+            let mut count = 0;
+            let bump = |by: int| -> int {
+                count = count + by;
+                count
+            };
+            bump(3);
+            bump(4);
+
+            let scale: u8 = 3;
+            let triple = |x: u8| -> u8 {
+                let inner = || -> u8 { x * scale };
+                return inner();
+            };
+
+            // 15 + 7 + 14 + 12 + 21
+            return add5(10) + count + apply(double, 7) + triple(4) as int + apply(|x: int| -> int { x + count + 14 }, 0);
+        }"#;
+
+        assert_eq!(run("closures", src), 69);
+    }
+
+    #[test]
+    fn escaping_closures() {
+        let src = r#"fn mk(k: int) -> fn(int) -> int {
+            let mut n = k;
+            return |z: int| -> int { n = n + z; return n; };
+        }
+
+        fn counter(start: int, skip: int) -> fn() -> int {
+            if skip > 0 {
+                return counter(start + 1, skip - 1);
+            }
+            let mut next: int;
+            next = start;
+            return || -> int { next = next + 1; next };
+        }
+
+        fn main() -> int {
+            let f = mk(5);
+            let g = mk(100);
+            let a = f(1);
+            let b = g(1);
+            let c = f(2);
+            let d = counter(10, 3);
+            d();
+            // 6 + 101 + 8 + 15, each closure keeping its own variable
+            return a + b + c + d();
+        }"#;
+
+        assert_eq!(run("escaping_closures", src), 130);
+    }
+
+    #[test]
+    fn runtime_names() {
+        // the heap cell of `n` still comes from the C library's `malloc`
+        let src = r#"fn malloc(n: int) -> int { return 0; }
+
+        fn main() -> int {
+            let mut n = 1;
+            let f = || -> int { n = n + 1; n };
+            return f() + malloc(0);
+        }"#;
+
+        assert_eq!(run("runtime_names", src), 2);
+    }
+
+    #[test]
+    fn shadowing() {
+        let src = r#"fn main() -> int {
+            let x = 2;
+            let get = || -> int { x };
+            let x: u8 = 40;
+            let mut x = x as int + 1;
+
+            let mut y = 0;
+            if x > 0 {
+                let x = x * 2;
+                y = x;
+            }
+            x = x + 1;
+
+            // 42 + 2 + 82
+            return x + get() + y;
+        }"#;
+
+        assert_eq!(run("shadowing", src), 126);
+    }
+
+    #[test]
+    fn cyclic_modules() {
+        let files = [
+            (
+                "cyclic_modules",
+                "mod a;\nfn main() -> int { return a::f(); }",
+            ),
+            ("a.pyl", "mod b;\nfn f() -> int { return b::g(); }"),
+            ("b.pyl", "mod a;\nfn g() -> int { return 1; }"),
+        ];
+        assert!(compile("cyclic_modules", &files, false).is_err());
+
+        let missing = [(
+            "missing_module",
+            "mod nope;\nfn main() -> int { return 0; }",
+        )];
+        assert!(compile("missing_module", &missing, false).is_err());
+
+        let private = [
+            ("private_fn", "mod a;\nfn main() -> int { return a::f(); }"),
+            ("a.pyl", "fn f() -> int { return 1; }"),
+        ];
+        assert!(compile("private_fn", &private, false).is_err());
+    }
+}
@@ -1,7 +1,8 @@
-//! Unimplemented
+//! Lowers an analysed AST into an object file using Cranelift.
 
-// mod traits;
-// mod codegen;
+mod closures;
+#[allow(clippy::module_inception)]
+mod codegen;
+mod runtime;
 
-// pub use self::traits::*;
-// pub use self::codegen::*;
\ No newline at end of file
+pub use self::codegen::*;
@@ -0,0 +1,166 @@
+//! Runtime support routines called from generated code.
+//!
+//! Routines are declared the first time a function needs them, and
+//! only the ones that were used get a body in the object file.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+
+use cranelift::codegen::ir::{types, AbiParam, InstBuilder, TrapCode, UserFuncName};
+use cranelift::codegen::Context;
+use cranelift::frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_module::{DataDescription, DataId, FuncId, Linkage, Module};
+use cranelift_object::ObjectModule;
+
+/// Exit code of a program that panicked, as in Rust
+pub const PANIC_EXIT_CODE: i64 = 101;
+
+/// Symbol name of the panic routine
+const PANIC_FN: &str = "__pyl_panic";
+
+/// The C library's allocator, for closure environments
+const ALLOC_FN: &str = "malloc";
+
+/// The runtime routines used by a program
+#[derive(Debug, Default)]
+pub struct Runtime {
+    /// `__pyl_panic(msg: *u8, len: i64) -> !`
+    panic: Option<FuncId>,
+    /// `malloc(size: i64) -> *u8`, imported
+    alloc: Option<FuncId>,
+    /// Panic messages already in the object file
+    messages: HashMap<String, DataId>,
+}
+
+impl Runtime {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the panic routine, declaring it on first use
+    ///
+    /// It prints `len` bytes of `msg` to stderr and exits with [`PANIC_EXIT_CODE`]
+    pub fn panic_fn(&mut self, module: &mut ObjectModule) -> Result<FuncId> {
+        if let Some(id) = self.panic {
+            return Ok(id);
+        }
+
+        let pointer_type = module.target_config().pointer_type();
+        let mut sig = module.make_signature();
+        sig.params.push(AbiParam::new(pointer_type));
+        sig.params.push(AbiParam::new(types::I64));
+
+        let id = module.declare_function(PANIC_FN, Linkage::Local, &sig)?;
+        self.panic = Some(id);
+
+        Ok(id)
+    }
+
+    /// Get the allocator, declaring it on first use
+    ///
+    /// Memory is never freed, as nothing tracks how long a closure or captured variable lives
+    pub fn alloc_fn(&mut self, module: &mut ObjectModule) -> Result<FuncId> {
+        if let Some(id) = self.alloc {
+            return Ok(id);
+        }
+
+        let pointer_type = module.target_config().pointer_type();
+        let mut sig = module.make_signature();
+        sig.params.push(AbiParam::new(types::I64));
+        sig.returns.push(AbiParam::new(pointer_type));
+
+        let id = module.declare_function(ALLOC_FN, Linkage::Import, &sig)?;
+        self.alloc = Some(id);
+
+        Ok(id)
+    }
+
+    /// Store a message for the panic routine in read-only data
+    /// Identical messages share the same data
+    pub fn message(&mut self, module: &mut ObjectModule, text: &str) -> Result<DataId> {
+        if let Some(id) = self.messages.get(text) {
+            return Ok(*id);
+        }
+
+        let id = module.declare_anonymous_data(false, false)?;
+
+        let mut data = DataDescription::new();
+        data.define(text.as_bytes().into());
+        module.define_data(id, &data)?;
+
+        self.messages.insert(text.to_string(), id);
+
+        Ok(id)
+    }
+
+    /// Define the bodies of every routine that was declared
+    pub fn define(
+        &self,
+        module: &mut ObjectModule,
+        ctx: &mut Context,
+        builder_ctx: &mut FunctionBuilderContext,
+    ) -> Result<()> {
+        if let Some(id) = self.panic {
+            define_panic(id, module, ctx, builder_ctx)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// `write(2, msg, len); exit(101);`, using the C library
+fn define_panic(
+    id: FuncId,
+    module: &mut ObjectModule,
+    ctx: &mut Context,
+    builder_ctx: &mut FunctionBuilderContext,
+) -> Result<()> {
+    let pointer_type = module.target_config().pointer_type();
+
+    let mut write_sig = module.make_signature();
+    write_sig.params.push(AbiParam::new(types::I32));
+    write_sig.params.push(AbiParam::new(pointer_type));
+    write_sig.params.push(AbiParam::new(types::I64));
+    write_sig.returns.push(AbiParam::new(types::I64));
+    let write = module.declare_function("write", Linkage::Import, &write_sig)?;
+
+    let mut exit_sig = module.make_signature();
+    exit_sig.params.push(AbiParam::new(types::I32));
+    let exit = module.declare_function("exit", Linkage::Import, &exit_sig)?;
+
+    let mut sig = module.make_signature();
+    sig.params.push(AbiParam::new(pointer_type));
+    sig.params.push(AbiParam::new(types::I64));
+
+    ctx.func.signature = sig;
+    ctx.func.name = UserFuncName::user(0, id.as_u32());
+
+    let mut builder = FunctionBuilder::new(&mut ctx.func, builder_ctx);
+    let entry = builder.create_block();
+    builder.append_block_params_for_function_params(entry);
+    builder.switch_to_block(entry);
+    builder.seal_block(entry);
+
+    let params = builder.block_params(entry).to_vec();
+    let (msg, len) = (params[0], params[1]);
+
+    let write = module.declare_func_in_func(write, builder.func);
+    let stderr = builder.ins().iconst(types::I32, 2);
+    builder.ins().call(write, &[stderr, msg, len]);
+
+    let exit = module.declare_func_in_func(exit, builder.func);
+    let code = builder.ins().iconst(types::I32, PANIC_EXIT_CODE);
+    builder.ins().call(exit, &[code]);
+
+    // `exit` does not return
+    builder.ins().trap(TrapCode::UnreachableCodeReached);
+    builder.finalize();
+
+    module
+        .define_function(id, ctx)
+        .map_err(|e| anyhow!("Failed to define `{}`: {:?}", PANIC_FN, e))?;
+    module.clear_context(ctx);
+
+    Ok(())
+}
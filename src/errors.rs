@@ -3,6 +3,8 @@
 
 use crate::ast::{Ident, Type};
 use crate::files::Files;
+use crate::semantic_analysis::const_eval::MAX_CALL_DEPTH;
+use crate::semantic_analysis::VarKind;
 use crate::token::{ReportableSpan, Span, TokenKind};
 use anyhow::{anyhow, Result};
 use ariadne::{Color, Label, Report, ReportKind};
@@ -46,6 +48,18 @@ pub enum LangError {
     // Invalid literal
     #[error("Invalid literal: `{0}`")]
     InvalidLiteral(String, Span),
+    // A block comment was not closed before the end of the file
+    #[error("Unterminated block comment")]
+    UnterminatedComment(Span),
+    // Only `#[inline]` and `#[inline(never)]` are known
+    #[error("Unknown attribute: `{0}`")]
+    UnknownAttribute(String, Span),
+    // An attribute before an item other than a function
+    #[error("Attributes are only allowed on functions")]
+    MisplacedAttribute(Span),
+    // `let x;`, with neither a type nor a value to infer one from
+    #[error("Type annotation needed for a variable declared without a value")]
+    MissingTypeAnnotation(Span),
 }
 
 /// Errors for Semantic Analysis
@@ -118,6 +132,183 @@ pub enum SemanticError {
         operand_type: Type,
         span: Span,
     },
+
+    /// How the variable was declared, and 2 spans for the mutation and the declaration
+    #[error("Cannot mutate immutable variable `{0}`")]
+    ImmutableVariable(Ident, VarKind, Span, Span),
+
+    /// 2 spans for the read, and the variable declaration
+    #[error("Use of possibly-uninitialized variable `{0}`")]
+    PossiblyUninitialized(Ident, Span, Span),
+
+    #[error("Cannot mutate through a shared reference")]
+    MutationThroughSharedReference {
+        found_type: Type,
+        found_span: Span, // reference
+        span: Span,       // mutation
+    },
+
+    #[error("Invalid left-hand side of assignment")]
+    InvalidAssignmentTarget(Span),
+
+    /// 2 spans for the returned reference, and the variable declaration
+    #[error("Cannot return a reference to local variable `{0}`")]
+    ReturnsLocalReference(Ident, Span, Span),
+
+    /// Span for the returned reference
+    #[error("Cannot return a reference to a temporary value")]
+    ReturnsTemporaryReference(Span),
+
+    /// Span for the call, and the variable declaration
+    #[error("`{ident}` is not a function")]
+    NotCallable {
+        ident: Ident,
+        found_type: Type,
+        call_span: Span,
+        decl_span: Span,
+    },
+
+    /// Numeric types are never converted implicitly
+    #[error("Implicit conversion from `{found_type}` to `{expected_type}`")]
+    ImplicitConversion {
+        expected_type: Type,
+        expected_span: Span,
+        found_type: Type,
+        found_span: Span,
+    },
+
+    #[error("Cannot cast `{from_type}` as `{to_type}`")]
+    InvalidCast {
+        from_type: Type,
+        from_span: Span,
+        to_type: Type,
+        to_span: Span,
+    },
+
+    #[error("Literal out of range for `{ty}`")]
+    LiteralOutOfRange { value: i128, ty: Type, span: Span },
+
+    /// Span of the `mod` item, and the path that was searched
+    #[error("Module `{0}` not found")]
+    ModuleNotFound(Ident, String, Span),
+
+    /// 2 spans for the module declaration, and the existing declaration
+    #[error("Module `{0}` already declared")]
+    ModuleAlreadyDeclared(Ident, Span, Span),
+
+    #[error("Module `{0}` has not been declared")]
+    ModuleNotDeclared(String, Span),
+
+    /// Span of the use, and the declaration in the other module's file
+    #[error("`{ident}` is private")]
+    PrivateItem {
+        ident: Ident,
+        span: Span,
+        decl_file: String,
+        decl_span: Span,
+    },
+
+    /// Span of the `mod` item that closes the cycle
+    #[error("Module `{module}` depends on itself")]
+    CyclicModule {
+        module: Ident,
+        /// e.g. `a -> b -> a`
+        cycle: String,
+        span: Span,
+    },
+
+    /// 2 spans for the global declaration, and the function
+    #[error("`{0}` is already declared as a function")]
+    NameAlreadyDeclared(Ident, Span, Span),
+
+    /// Span of the part that cannot be evaluated
+    #[error("Expression is not constant")]
+    NotConstant(Span),
+
+    /// Span of the operation, as in the runtime panic message
+    #[error("Constant evaluation overflowed")]
+    ConstOverflow { verb: String, ty: Type, span: Span },
+
+    #[error("Constant evaluation divided by zero")]
+    ConstDivisionByZero { verb: String, span: Span },
+
+    /// Span of the call that went too deep
+    #[error("Constant evaluation exceeded the recursion limit")]
+    ConstRecursionLimit(Span),
+
+    /// A type name that is not a type parameter in scope
+    #[error("Type `{0}` is not declared")]
+    TypeNotDeclared(String, Span),
+
+    /// 2 spans for the redeclaration, and the first declaration
+    #[error("Type parameter `{0}` is already declared")]
+    TypeParamAlreadyDeclared(Ident, Span, Span),
+
+    /// A type parameter that no argument determines
+    #[error("Cannot infer type parameter `{param}` of `{ident}`")]
+    TypeParamNotInferred {
+        param: String,
+        ident: Ident,
+        call_span: Span,
+        decl_span: Span,
+    },
+
+    /// Span of the argument the type was inferred from
+    #[error("Type parameter `{param}` cannot be `{ty}`")]
+    InvalidTypeArgument { param: String, ty: Type, span: Span },
+
+    #[error("Generic function `{0}` cannot be used as a value")]
+    GenericFunctionValue(Ident, Span),
+
+    #[error("Trait `{0}` is not declared")]
+    TraitNotDeclared(Ident, Span),
+
+    /// 2 spans for the redeclaration, and the first declaration
+    #[error("Trait `{0}` is already declared")]
+    TraitAlreadyDeclared(Ident, Span, Span),
+
+    #[error("Trait `{trait_ident}` is already implemented for `{ty}`")]
+    ConflictingImpl {
+        trait_ident: Ident,
+        ty: Type,
+        span: Span,
+        existing: Span,
+    },
+
+    /// Span of the impl header, and of the method in the trait
+    #[error("Method `{method}` of `{trait_ident}` is not implemented for `{ty}`")]
+    MissingTraitMethod {
+        method: String,
+        trait_ident: Ident,
+        ty: Type,
+        span: Span,
+        decl_span: Span,
+    },
+
+    #[error("Method `{method}` is not a member of trait `{trait_ident}`")]
+    NotATraitMethod {
+        method: Ident,
+        trait_ident: Ident,
+        span: Span,
+    },
+
+    /// The types are of the whole method, with `Self` replaced in `expected`
+    #[error("Method `{method}` does not match its declaration in the trait")]
+    IncompatibleMethod {
+        method: Ident,
+        expected: Type,
+        found: Type,
+        span: Span,
+        decl_span: Span,
+    },
+
+    /// Span of the call whose type arguments do not satisfy the bound
+    #[error("Trait `{trait_name}` is not implemented for `{ty}`")]
+    UnsatisfiedBound {
+        trait_name: String,
+        ty: Type,
+        span: Span,
+    },
 }
 
 #[derive(Debug, Error)]
@@ -130,6 +321,14 @@ pub enum Warning {
 
     #[error("Unreachable code")]
     UnreachableCode(Span),
+
+    /// Span of the condition
+    #[error("Condition is always `{0}`")]
+    ConstantCondition(bool, Span),
+
+    /// The function, and the span of its call to itself
+    #[error("Recursive call is not in tail position")]
+    NonTailRecursion(Ident, Span),
 }
 pub struct ErrorReporter<'a> {
     files: &'a mut Files,
@@ -137,7 +336,7 @@ pub struct ErrorReporter<'a> {
 
 impl LangError {
     /// Produces a diagnostic for the error
-    pub fn diagnostic(&self, file: String) -> Report<ReportableSpan> {
+    pub fn diagnostic(&self, file: String) -> Report<'_, ReportableSpan> {
         let span = self.span(file);
 
         let label = Label::new(span.clone())
@@ -160,6 +359,10 @@ impl LangError {
                 LangError::ExpectedAnyToken { span, .. } => span,
                 LangError::UnexpectedEOF(span) => span,
                 LangError::InvalidLiteral(_, span) => span,
+                LangError::UnterminatedComment(span) => span,
+                LangError::UnknownAttribute(_, span) => span,
+                LangError::MisplacedAttribute(span) => span,
+                LangError::MissingTypeAnnotation(span) => span,
             },
         )
     }
@@ -177,7 +380,31 @@ impl LangError {
 }
 
 impl SemanticError {
-    pub fn diagnostic(&self, file: String) -> Report<ReportableSpan> {
+    /// A type mismatch, which is an implicit conversion between numeric types
+    pub fn type_mismatch(
+        expected_type: Type,
+        expected_span: Span,
+        found_type: Type,
+        found_span: Span,
+    ) -> SemanticError {
+        if expected_type.is_numeric() && found_type.is_numeric() {
+            SemanticError::ImplicitConversion {
+                expected_type,
+                expected_span,
+                found_type,
+                found_span,
+            }
+        } else {
+            SemanticError::TypesDoNotMatch {
+                expected_type,
+                expected_span,
+                found_type,
+                found_span,
+            }
+        }
+    }
+
+    pub fn diagnostic(&self, file: String) -> Report<'_, ReportableSpan> {
         let span = self.first_span(&file);
 
         let labels = self.get_labels(&file);
@@ -194,9 +421,9 @@ impl SemanticError {
     }
 
     /// First span of error
-    pub fn first_span(&self, file: &String) -> ReportableSpan {
+    pub fn first_span(&self, file: &str) -> ReportableSpan {
         ReportableSpan::new(
-            file.clone(),
+            file.to_string(),
             match self {
                 SemanticError::MissingMainFunction(span) => span,
                 SemanticError::FunctionAlreadyDeclared(_, span, _) => span,
@@ -212,44 +439,76 @@ impl SemanticError {
                 SemanticError::ArgumentCountMismatch { call_span, .. } => call_span,
                 SemanticError::UnsupportedUnaryOperation { span, .. } => span,
                 SemanticError::UnsupportedBinaryOperation { span, .. } => span,
+                SemanticError::ImmutableVariable(_, _, span, _) => span,
+                SemanticError::PossiblyUninitialized(_, span, _) => span,
+                SemanticError::MutationThroughSharedReference { span, .. } => span,
+                SemanticError::InvalidAssignmentTarget(span) => span,
+                SemanticError::ReturnsLocalReference(_, span, _) => span,
+                SemanticError::ReturnsTemporaryReference(span) => span,
+                SemanticError::NotCallable { call_span, .. } => call_span,
+                SemanticError::ImplicitConversion { found_span, .. } => found_span,
+                SemanticError::InvalidCast { from_span, .. } => from_span,
+                SemanticError::LiteralOutOfRange { span, .. } => span,
+                SemanticError::ModuleNotFound(_, _, span) => span,
+                SemanticError::ModuleAlreadyDeclared(_, span, _) => span,
+                SemanticError::ModuleNotDeclared(_, span) => span,
+                SemanticError::CyclicModule { span, .. } => span,
+                SemanticError::PrivateItem { span, .. } => span,
+                SemanticError::NameAlreadyDeclared(_, span, _) => span,
+                SemanticError::NotConstant(span) => span,
+                SemanticError::ConstOverflow { span, .. } => span,
+                SemanticError::ConstDivisionByZero { span, .. } => span,
+                SemanticError::ConstRecursionLimit(span) => span,
+                SemanticError::TypeNotDeclared(_, span) => span,
+                SemanticError::TypeParamAlreadyDeclared(_, span, _) => span,
+                SemanticError::TypeParamNotInferred { call_span, .. } => call_span,
+                SemanticError::InvalidTypeArgument { span, .. } => span,
+                SemanticError::GenericFunctionValue(_, span) => span,
+                SemanticError::TraitNotDeclared(_, span) => span,
+                SemanticError::TraitAlreadyDeclared(_, span, _) => span,
+                SemanticError::ConflictingImpl { span, .. } => span,
+                SemanticError::MissingTraitMethod { span, .. } => span,
+                SemanticError::NotATraitMethod { span, .. } => span,
+                SemanticError::IncompatibleMethod { span, .. } => span,
+                SemanticError::UnsatisfiedBound { span, .. } => span,
             },
         )
     }
 
-    fn get_labels(&self, file: &String) -> Vec<Label<ReportableSpan>> {
+    fn get_labels(&self, file: &str) -> Vec<Label<ReportableSpan>> {
         match self {
             SemanticError::MissingMainFunction(ref span) => {
-                vec![Label::new(ReportableSpan::new(file.clone(), span))
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
                     .with_message("No main function declared")
                     .with_color(PRIM_COLOR)]
             }
             SemanticError::FunctionAlreadyDeclared(ref name, ref span, ref existing) => {
                 vec![
-                    Label::new(ReportableSpan::new(file.clone(), span))
+                    Label::new(ReportableSpan::new(file.to_string(), span))
                         .with_message(format!("Tried to declare {name} here"))
                         .with_color(PRIM_COLOR),
-                    Label::new(ReportableSpan::new(file.clone(), existing))
+                    Label::new(ReportableSpan::new(file.to_string(), existing))
                         .with_message("Already declared here")
                         .with_color(SEC_COLOR),
                 ]
             }
             SemanticError::VariableAlreadyDeclared(ref name, ref span, ref existing) => {
                 vec![
-                    Label::new(ReportableSpan::new(file.clone(), span))
+                    Label::new(ReportableSpan::new(file.to_string(), span))
                         .with_message(format!("Variable `{}` already declared", name))
                         .with_color(PRIM_COLOR),
-                    Label::new(ReportableSpan::new(file.clone(), existing))
+                    Label::new(ReportableSpan::new(file.to_string(), existing))
                         .with_message("First declared here")
                         .with_color(SEC_COLOR),
                 ]
             }
             SemanticError::MainMustReturnInt(ref span) => {
-                vec![Label::new(ReportableSpan::new(file.clone(), span))
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
                     .with_message("Does not return an integer literal")
                     .with_color(PRIM_COLOR)]
             }
             SemanticError::MissingReturnStatement(ref span) => {
-                vec![Label::new(ReportableSpan::new(file.clone(), span))
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
                     .with_message("Missing return statement")
                     .with_color(PRIM_COLOR)]
             }
@@ -260,16 +519,16 @@ impl SemanticError {
                 found_type,
             } => {
                 vec![
-                    Label::new(ReportableSpan::new(file.clone(), found_span))
+                    Label::new(ReportableSpan::new(file.to_string(), found_span))
                         .with_message(format!("found {found_type} instead of {expected_type}"))
                         .with_color(PRIM_COLOR),
-                    Label::new(ReportableSpan::new(file.clone(), expected_span))
+                    Label::new(ReportableSpan::new(file.to_string(), expected_span))
                         .with_message(format!("expected {expected_type} return type"))
                         .with_color(SEC_COLOR),
                 ]
             }
             SemanticError::ReturnNotGuaranteed(ref span) => {
-                vec![Label::new(ReportableSpan::new(file.clone(), span))
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
                     .with_message("Return not guaranteed in all branches")
                     .with_color(PRIM_COLOR)]
             }
@@ -280,12 +539,12 @@ impl SemanticError {
                 found_span,
             } => {
                 vec![
-                    Label::new(ReportableSpan::new(file.clone(), found_span))
+                    Label::new(ReportableSpan::new(file.to_string(), found_span))
                         .with_message(format!(
                             "found type {found_type} instead of {expected_type}"
                         ))
                         .with_color(PRIM_COLOR),
-                    Label::new(ReportableSpan::new(file.clone(), expected_span))
+                    Label::new(ReportableSpan::new(file.to_string(), expected_span))
                         .with_message(format!("expected type {expected_type}"))
                         .with_color(SEC_COLOR),
                 ]
@@ -294,17 +553,19 @@ impl SemanticError {
                 found_type,
                 found_span,
             } => {
-                vec![Label::new(ReportableSpan::new(file.clone(), found_span))
-                    .with_message(format!("evaluates to {found_type}"))
-                    .with_color(PRIM_COLOR)]
+                vec![
+                    Label::new(ReportableSpan::new(file.to_string(), found_span))
+                        .with_message(format!("evaluates to {found_type}"))
+                        .with_color(PRIM_COLOR),
+                ]
             }
             SemanticError::FunctionNotDeclared(ref name, ref span) => {
-                vec![Label::new(ReportableSpan::new(file.clone(), span))
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
                     .with_message(format!("Function `{}` has not been declared yet", name))
                     .with_color(PRIM_COLOR)]
             }
             SemanticError::VariableNotDeclared(ref name, ref span) => {
-                vec![Label::new(ReportableSpan::new(file.clone(), span))
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
                     .with_message(format!("Variable `{}` has not been declared yet", name))
                     .with_color(PRIM_COLOR)]
             }
@@ -315,10 +576,10 @@ impl SemanticError {
                 decl_span,
             } => {
                 vec![
-                    Label::new(ReportableSpan::new(file.clone(), call_span))
+                    Label::new(ReportableSpan::new(file.to_string(), call_span))
                         .with_message(format!("got {found} arguments"))
                         .with_color(PRIM_COLOR),
-                    Label::new(ReportableSpan::new(file.clone(), decl_span))
+                    Label::new(ReportableSpan::new(file.to_string(), decl_span))
                         .with_message(format!("expected {expected} arguments"))
                         .with_color(SEC_COLOR),
                 ]
@@ -329,10 +590,10 @@ impl SemanticError {
                 span,
             } => {
                 vec![
-                    Label::new(ReportableSpan::new(file.clone(), span))
+                    Label::new(ReportableSpan::new(file.to_string(), span))
                         .with_message("unsupported unary operation for {operator}")
                         .with_color(PRIM_COLOR),
-                    Label::new(ReportableSpan::new(file.clone(), span))
+                    Label::new(ReportableSpan::new(file.to_string(), span))
                         .with_message(format!("can't apply {operator} to {operand_type}"))
                         .with_color(SEC_COLOR),
                 ]
@@ -343,14 +604,306 @@ impl SemanticError {
                 span,
             } => {
                 vec![
-                    Label::new(ReportableSpan::new(file.clone(), span))
+                    Label::new(ReportableSpan::new(file.to_string(), span))
                         .with_message("unsupported binary operation for {operator}")
                         .with_color(PRIM_COLOR),
-                    Label::new(ReportableSpan::new(file.clone(), span))
+                    Label::new(ReportableSpan::new(file.to_string(), span))
                         .with_message(format!("can't apply {operator} to {operand_type}"))
                         .with_color(SEC_COLOR),
                 ]
             }
+            SemanticError::ImmutableVariable(ref name, _, ref span, ref decl) => {
+                vec![
+                    Label::new(ReportableSpan::new(file.to_string(), span))
+                        .with_message(format!("`{name}` is mutated here"))
+                        .with_color(PRIM_COLOR),
+                    Label::new(ReportableSpan::new(file.to_string(), decl))
+                        .with_message("declared immutable here")
+                        .with_color(SEC_COLOR),
+                ]
+            }
+            SemanticError::PossiblyUninitialized(ref name, ref span, ref decl) => {
+                vec![
+                    Label::new(ReportableSpan::new(file.to_string(), span))
+                        .with_message(format!(
+                            "`{name}` is read here, but may not be assigned yet"
+                        ))
+                        .with_color(PRIM_COLOR),
+                    Label::new(ReportableSpan::new(file.to_string(), decl))
+                        .with_message("declared without a value here")
+                        .with_color(SEC_COLOR),
+                ]
+            }
+            SemanticError::MutationThroughSharedReference {
+                found_type,
+                found_span,
+                span,
+            } => {
+                vec![
+                    Label::new(ReportableSpan::new(file.to_string(), span))
+                        .with_message("mutated here")
+                        .with_color(PRIM_COLOR),
+                    Label::new(ReportableSpan::new(file.to_string(), found_span))
+                        .with_message(format!("this is a {found_type}"))
+                        .with_color(SEC_COLOR),
+                ]
+            }
+            SemanticError::InvalidAssignmentTarget(ref span) => {
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
+                    .with_message("cannot assign to this expression")
+                    .with_color(PRIM_COLOR)]
+            }
+            SemanticError::ReturnsLocalReference(ref name, ref span, ref decl) => {
+                vec![
+                    Label::new(ReportableSpan::new(file.to_string(), span))
+                        .with_message(format!("returns a reference to `{name}`"))
+                        .with_color(PRIM_COLOR),
+                    Label::new(ReportableSpan::new(file.to_string(), decl))
+                        .with_message("which only lives until the function returns")
+                        .with_color(SEC_COLOR),
+                ]
+            }
+            SemanticError::ReturnsTemporaryReference(ref span) => {
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
+                    .with_message("returns a reference to a value of the current function")
+                    .with_color(PRIM_COLOR)]
+            }
+            SemanticError::NotCallable {
+                ident,
+                found_type,
+                call_span,
+                decl_span,
+            } => {
+                vec![
+                    Label::new(ReportableSpan::new(file.to_string(), call_span))
+                        .with_message(format!("`{ident}` is called here"))
+                        .with_color(PRIM_COLOR),
+                    Label::new(ReportableSpan::new(file.to_string(), decl_span))
+                        .with_message(format!("`{ident}` is a {found_type}"))
+                        .with_color(SEC_COLOR),
+                ]
+            }
+            SemanticError::ImplicitConversion {
+                expected_type,
+                expected_span,
+                found_type,
+                found_span,
+            } => {
+                vec![
+                    Label::new(ReportableSpan::new(file.to_string(), found_span))
+                        .with_message(format!("this is a {found_type}"))
+                        .with_color(PRIM_COLOR),
+                    Label::new(ReportableSpan::new(file.to_string(), expected_span))
+                        .with_message(format!("expected {expected_type}"))
+                        .with_color(SEC_COLOR),
+                ]
+            }
+            SemanticError::InvalidCast {
+                from_type,
+                from_span,
+                to_type,
+                to_span,
+            } => {
+                vec![
+                    Label::new(ReportableSpan::new(file.to_string(), from_span))
+                        .with_message(format!("this is a {from_type}"))
+                        .with_color(PRIM_COLOR),
+                    Label::new(ReportableSpan::new(file.to_string(), to_span))
+                        .with_message(format!("cannot be converted to {to_type}"))
+                        .with_color(SEC_COLOR),
+                ]
+            }
+            SemanticError::LiteralOutOfRange { value, ty, span } => {
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
+                    .with_message(format!("{value} does not fit in {ty}"))
+                    .with_color(PRIM_COLOR)]
+            }
+            SemanticError::ModuleNotFound(_, ref path, ref span) => {
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
+                    .with_message(format!("could not find `{path}`"))
+                    .with_color(PRIM_COLOR)]
+            }
+            SemanticError::ModuleAlreadyDeclared(ref name, ref span, ref existing) => {
+                vec![
+                    Label::new(ReportableSpan::new(file.to_string(), span))
+                        .with_message(format!("Tried to declare {name} here"))
+                        .with_color(PRIM_COLOR),
+                    Label::new(ReportableSpan::new(file.to_string(), existing))
+                        .with_message("Already declared here")
+                        .with_color(SEC_COLOR),
+                ]
+            }
+            SemanticError::ModuleNotDeclared(ref name, ref span) => {
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
+                    .with_message(format!("Module `{}` has not been declared", name))
+                    .with_color(PRIM_COLOR)]
+            }
+            SemanticError::CyclicModule { cycle, span, .. } => {
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
+                    .with_message(format!("cycle: {cycle}"))
+                    .with_color(PRIM_COLOR)]
+            }
+            SemanticError::PrivateItem {
+                ident,
+                span,
+                decl_file,
+                decl_span,
+            } => {
+                vec![
+                    Label::new(ReportableSpan::new(file.to_string(), span))
+                        .with_message(format!("`{ident}` is used here"))
+                        .with_color(PRIM_COLOR),
+                    Label::new(ReportableSpan::new(decl_file.clone(), decl_span))
+                        .with_message("declared private here")
+                        .with_color(SEC_COLOR),
+                ]
+            }
+            SemanticError::NameAlreadyDeclared(ref name, ref span, ref func) => {
+                vec![
+                    Label::new(ReportableSpan::new(file.to_string(), span))
+                        .with_message(format!("`{}` declared here", name))
+                        .with_color(PRIM_COLOR),
+                    Label::new(ReportableSpan::new(file.to_string(), func))
+                        .with_message("function declared here")
+                        .with_color(SEC_COLOR),
+                ]
+            }
+            SemanticError::NotConstant(ref span) => {
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
+                    .with_message("cannot be evaluated at compile time")
+                    .with_color(PRIM_COLOR)]
+            }
+            SemanticError::ConstOverflow { verb, ty, span } => {
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
+                    .with_message(format!("attempt to {verb} with overflow of `{ty}`"))
+                    .with_color(PRIM_COLOR)]
+            }
+            SemanticError::ConstDivisionByZero { verb, span } => {
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
+                    .with_message(format!("attempt to {verb} by zero"))
+                    .with_color(PRIM_COLOR)]
+            }
+            SemanticError::ConstRecursionLimit(ref span) => {
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
+                    .with_message(format!(
+                        "more than {MAX_CALL_DEPTH} nested calls of `const fn`s"
+                    ))
+                    .with_color(PRIM_COLOR)]
+            }
+            SemanticError::TypeNotDeclared(_, ref span) => {
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
+                    .with_message("not a type parameter of the function")
+                    .with_color(PRIM_COLOR)]
+            }
+            SemanticError::TypeParamAlreadyDeclared(_, ref span, ref existing) => {
+                vec![
+                    Label::new(ReportableSpan::new(file.to_string(), span))
+                        .with_message("redeclared here")
+                        .with_color(PRIM_COLOR),
+                    Label::new(ReportableSpan::new(file.to_string(), existing))
+                        .with_message("first declared here")
+                        .with_color(SEC_COLOR),
+                ]
+            }
+            SemanticError::TypeParamNotInferred {
+                ident,
+                call_span,
+                decl_span,
+                ..
+            } => {
+                vec![
+                    Label::new(ReportableSpan::new(file.to_string(), call_span))
+                        .with_message(format!("`{ident}` is called here"))
+                        .with_color(PRIM_COLOR),
+                    Label::new(ReportableSpan::new(file.to_string(), decl_span))
+                        .with_message(format!("`{ident}` is declared here"))
+                        .with_color(SEC_COLOR),
+                ]
+            }
+            SemanticError::InvalidTypeArgument { param, ty, span } => {
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
+                    .with_message(format!(
+                        "`{param}` is inferred as `{ty}` from this argument"
+                    ))
+                    .with_color(PRIM_COLOR)]
+            }
+            SemanticError::GenericFunctionValue(_, ref span) => {
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
+                    .with_message("used as a value here")
+                    .with_color(PRIM_COLOR)]
+            }
+            SemanticError::TraitNotDeclared(_, ref span) => {
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
+                    .with_message("not a trait of this module")
+                    .with_color(PRIM_COLOR)]
+            }
+            SemanticError::TraitAlreadyDeclared(_, ref span, ref existing) => {
+                vec![
+                    Label::new(ReportableSpan::new(file.to_string(), span))
+                        .with_message("redeclared here")
+                        .with_color(PRIM_COLOR),
+                    Label::new(ReportableSpan::new(file.to_string(), existing))
+                        .with_message("first declared here")
+                        .with_color(SEC_COLOR),
+                ]
+            }
+            SemanticError::ConflictingImpl { span, existing, .. } => {
+                vec![
+                    Label::new(ReportableSpan::new(file.to_string(), span))
+                        .with_message("conflicting implementation")
+                        .with_color(PRIM_COLOR),
+                    Label::new(ReportableSpan::new(file.to_string(), existing))
+                        .with_message("first implemented here")
+                        .with_color(SEC_COLOR),
+                ]
+            }
+            SemanticError::MissingTraitMethod {
+                method,
+                span,
+                decl_span,
+                ..
+            } => {
+                vec![
+                    Label::new(ReportableSpan::new(file.to_string(), span))
+                        .with_message(format!("missing `{method}`"))
+                        .with_color(PRIM_COLOR),
+                    Label::new(ReportableSpan::new(file.to_string(), decl_span))
+                        .with_message(format!("`{method}` is declared here"))
+                        .with_color(SEC_COLOR),
+                ]
+            }
+            SemanticError::NotATraitMethod {
+                trait_ident, span, ..
+            } => {
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
+                    .with_message(format!("not declared in `{trait_ident}`"))
+                    .with_color(PRIM_COLOR)]
+            }
+            SemanticError::IncompatibleMethod {
+                expected,
+                found,
+                span,
+                decl_span,
+                ..
+            } => {
+                vec![
+                    Label::new(ReportableSpan::new(file.to_string(), span))
+                        .with_message(format!("has type `{found}`"))
+                        .with_color(PRIM_COLOR),
+                    Label::new(ReportableSpan::new(file.to_string(), decl_span))
+                        .with_message(format!("expected `{expected}` from this declaration"))
+                        .with_color(SEC_COLOR),
+                ]
+            }
+            SemanticError::UnsatisfiedBound {
+                trait_name,
+                ty,
+                span,
+            } => {
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
+                    .with_message(format!("requires `{ty}: {trait_name}`"))
+                    .with_color(PRIM_COLOR)]
+            }
         }
     }
 
@@ -366,13 +919,94 @@ impl SemanticError {
             SemanticError::ReturnNotGuaranteed(_) => {
                 Some("make sure all possible paths return a value".to_string())
             }
+            SemanticError::ImmutableVariable(ident, kind, _, _) => Some(match kind {
+                VarKind::Let => format!("consider making it mutable: `let mut {ident}`"),
+                VarKind::Param => {
+                    format!("consider shadowing it with a mutable copy: `let mut {ident} = {ident};`")
+                }
+                VarKind::Const => {
+                    format!("a `const` can never be mutated, consider `static mut {ident}` instead")
+                }
+                VarKind::Static => format!("consider making it mutable: `static mut {ident}`"),
+            }),
+            SemanticError::PossiblyUninitialized(ident, _, _) => Some(format!(
+                "assign `{ident}` on every path before reading it, or give it an initial value"
+            )),
+            SemanticError::MutationThroughSharedReference { .. } => {
+                Some("consider taking a `&mut` reference instead".to_string())
+            }
+            SemanticError::ReturnsLocalReference(..)
+            | SemanticError::ReturnsTemporaryReference(_) => {
+                Some("consider returning the value itself instead".to_string())
+            }
+            SemanticError::ImplicitConversion { expected_type, .. } => Some(format!(
+                "convert explicitly with `as`: `expr as {expected_type}`"
+            )),
+            SemanticError::InvalidCast { .. } => {
+                Some("only numeric types and `bool` can be cast".to_string())
+            }
+            SemanticError::ModuleNotFound(name, _, _) => {
+                Some(format!("create `{name}.pyl` next to the main file"))
+            }
+            SemanticError::ModuleNotDeclared(name, _) => {
+                Some(format!("consider declaring it: `mod {name};`"))
+            }
+            SemanticError::PrivateItem { ident, .. } => {
+                let name = ident
+                    .split_path()
+                    .map_or(ident.ident.as_str(), |(_, name)| name);
+                Some(format!("consider making it public: `pub fn {name}`"))
+            }
+            SemanticError::CyclicModule { .. } => {
+                Some("move the shared functions into a separate module".to_string())
+            }
+            SemanticError::NameAlreadyDeclared(..) => {
+                Some("globals and functions share names, consider renaming one".to_string())
+            }
+            SemanticError::NotConstant(_) => Some(
+                "constants may only use literals, operators, casts, other constants and `const fn`s"
+                    .to_string(),
+            ),
+            SemanticError::ConstOverflow { ty, .. } => {
+                Some(format!("the result does not fit in `{ty}`"))
+            }
+            SemanticError::ConstRecursionLimit(_) => {
+                Some("make sure the recursion reaches its base case".to_string())
+            }
+            SemanticError::TypeNotDeclared(name, _) => Some(format!(
+                "declare it after the function name: `fn name<{name}>`"
+            )),
+            SemanticError::TypeParamNotInferred { param, .. } => Some(format!(
+                "use `{param}` in a parameter type, so the arguments determine it"
+            )),
+            SemanticError::InvalidTypeArgument { .. } => {
+                Some("type parameters stand for primitive types".to_string())
+            }
+            SemanticError::ConflictingImpl { .. } => {
+                Some("a type implements each trait at most once".to_string())
+            }
+            SemanticError::IncompatibleMethod { .. } => {
+                Some("write the implementing type in place of `Self`".to_string())
+            }
+            SemanticError::UnsatisfiedBound {
+                trait_name,
+                ty: Type::Param(p),
+                ..
+            } => Some(format!("consider adding a bound: `{}: {trait_name}`", p.name)),
+            SemanticError::UnsatisfiedBound { trait_name, ty, .. } => {
+                Some(format!("consider implementing it: `impl {trait_name} for {ty}`"))
+            }
+            SemanticError::GenericFunctionValue(..) => Some(
+                "call it instead, so its type parameters are inferred from the arguments"
+                    .to_string(),
+            ),
             _ => None,
         }
     }
 }
 
 impl Warning {
-    pub fn diagnostic(&self, file: String) -> Report<ReportableSpan> {
+    pub fn diagnostic(&self, file: String) -> Report<'_, ReportableSpan> {
         let span = self.first_span(&file);
 
         let labels = self.get_labels(&file);
@@ -393,39 +1027,62 @@ impl Warning {
             Warning::UnusedVariable(ident, _) => Some(format!(
                 "if intended, prefix with an underscore: `_{ident}`"
             )),
+            Warning::ConstantCondition(true, _) => {
+                Some("the `else` branch is never taken".to_string())
+            }
+            Warning::ConstantCondition(false, _) => {
+                Some("the `if` branch is never taken".to_string())
+            }
+            Warning::NonTailRecursion(..) => Some(
+                "return the call's result directly, passing any work left as extra \
+                 parameters, so it reuses the stack frame"
+                    .to_string(),
+            ),
             _ => None,
         }
     }
 
     /// First span of error
-    pub fn first_span(&self, file: &String) -> ReportableSpan {
+    pub fn first_span(&self, file: &str) -> ReportableSpan {
         ReportableSpan::new(
-            file.clone(),
+            file.to_string(),
             match self {
                 Warning::UnusedVariable(_, span) => span,
                 Warning::UnusedFunction(_, span) => span,
                 Warning::UnreachableCode(span) => span,
+                Warning::ConstantCondition(_, span) => span,
+                Warning::NonTailRecursion(_, span) => span,
             },
         )
     }
 
-    fn get_labels(&self, file: &String) -> Vec<Label<ReportableSpan>> {
+    fn get_labels(&self, file: &str) -> Vec<Label<ReportableSpan>> {
         match self {
             Warning::UnusedVariable(ref name, ref span) => {
-                vec![Label::new(ReportableSpan::new(file.clone(), span))
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
                     .with_message(format!("{name} is never used"))
                     .with_color(TERT_COLOR)]
             }
             Warning::UnusedFunction(ref name, ref span) => {
-                vec![Label::new(ReportableSpan::new(file.clone(), span))
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
                     .with_message(format!("{name} is never called"))
                     .with_color(TERT_COLOR)]
             }
             Warning::UnreachableCode(ref span) => {
-                vec![Label::new(ReportableSpan::new(file.clone(), span))
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
                     .with_message("code is unreachable")
                     .with_color(TERT_COLOR)]
             }
+            Warning::ConstantCondition(value, ref span) => {
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
+                    .with_message(format!("always evaluates to `{value}`"))
+                    .with_color(TERT_COLOR)]
+            }
+            Warning::NonTailRecursion(ref name, ref span) => {
+                vec![Label::new(ReportableSpan::new(file.to_string(), span))
+                    .with_message(format!("each call to {name} grows the stack"))
+                    .with_color(TERT_COLOR)]
+            }
         }
     }
 }
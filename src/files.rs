@@ -33,6 +33,14 @@ impl SourceFile {
             source: Source::from(contents),
         }
     }
+
+    /// Get the 1-based line and column of a byte offset
+    pub fn location(&self, offset: usize) -> (usize, usize) {
+        match self.source.get_byte_line(offset) {
+            Some((_, line, col)) => (line + 1, col + 1),
+            None => (self.source.lines().len(), 1),
+        }
+    }
 }
 
 impl Files {
@@ -50,11 +58,17 @@ impl Files {
     }
 
     /// Get a file from the collection
-    fn get_file(&self, path: &str) -> Option<&SourceFile> {
+    pub fn get_file(&self, path: &str) -> Option<&SourceFile> {
         self.files.get(path)
     }
 }
 
+impl Default for Files {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ariadne::Cache<String> for Files {
     type Storage = String;
 
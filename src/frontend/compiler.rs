@@ -1,17 +1,27 @@
 //! Frontend compiler
 //! Called through args
 
-use anyhow::{Error, Result};
+use anyhow::{anyhow, Error, Result};
 use ariadne::Cache;
+use cranelift::codegen::settings;
 use log::debug;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
 
-use crate::ast::AST;
-use crate::errors::{ErrorReporter, SemanticError};
+use crate::ast::{Item, ModDecl, AST};
+use crate::codegen::{CodeGenerator, CodegenOptions};
+use crate::errors::{ErrorReporter, SemanticError, Warning};
 use crate::files::Files;
+use crate::hir::{self, lower_module};
 use crate::lexer;
+use crate::mir::{
+    self, ConstantFolding, DeadCodeElimination, Inliner, PassManager, RemoveUnreachableBlocks,
+    INLINE_THRESHOLD,
+};
 use crate::parser::Parser;
-use crate::semantic_analysis::analyse;
+use crate::semantic_analysis::{analyse_module, ModuleSymbol, SymbolTable};
 use crate::token::Token;
 
 pub struct Compiler {
@@ -20,12 +30,112 @@ pub struct Compiler {
     options: CompilerOptions,
 }
 
+/// A parsed source file of the program
+struct Module {
+    /// Name given by `mod name;`, `None` for the main file
+    name: Option<String>,
+    ast: AST,
+}
+
+impl Module {
+    /// Name used in diagnostics
+    fn display_name(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => Path::new(&self.ast.file_id)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| self.ast.file_id.clone()),
+        }
+    }
+}
+
 pub struct CompilerOptions {
     pub verbose: bool,
     pub print_tokens: bool,
     pub print_ast: bool,
     pub print_ir: bool,
     pub print_asm: bool,
+    /// Panic on integer overflow and division by zero at runtime
+    pub checked_arith: bool,
+    /// What to produce
+    pub emit: Emit,
+    /// How much to optimise
+    pub opt_level: OptLevel,
+    /// Warn about functions calling themselves outside tail position
+    pub warn_non_tail_recursion: bool,
+}
+
+/// What the compiler produces, chosen with `--emit`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Emit {
+    /// A linked executable
+    #[default]
+    Executable,
+    /// The MIR of the program, printed instead of generating code
+    Mir,
+}
+
+impl FromStr for Emit {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "exe" => Ok(Emit::Executable),
+            "mir" => Ok(Emit::Mir),
+            _ => Err(format!("unknown output `{}`, expected `exe` or `mir`", s)),
+        }
+    }
+}
+
+/// How much to optimise, chosen with `-O`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OptLevel {
+    /// No optimisation, and every local kept in a stack slot for debuggers
+    #[default]
+    O0,
+    O1,
+    /// Inline larger functions, and skip Cranelift's verifier
+    O2,
+    /// Optimise for size, only inlining the smallest functions
+    Os,
+}
+
+impl OptLevel {
+    /// Largest function inlined, or `None` to not inline at all
+    fn inline_threshold(self) -> Option<usize> {
+        match self {
+            OptLevel::O0 => None,
+            OptLevel::O1 => Some(INLINE_THRESHOLD),
+            OptLevel::O2 => Some(INLINE_THRESHOLD * 2),
+            OptLevel::Os => Some(INLINE_THRESHOLD / 4),
+        }
+    }
+
+    fn cranelift(self) -> settings::OptLevel {
+        match self {
+            OptLevel::O0 => settings::OptLevel::None,
+            OptLevel::O1 | OptLevel::O2 => settings::OptLevel::Speed,
+            OptLevel::Os => settings::OptLevel::SpeedAndSize,
+        }
+    }
+}
+
+impl FromStr for OptLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(OptLevel::O0),
+            "1" => Ok(OptLevel::O1),
+            "2" => Ok(OptLevel::O2),
+            "s" => Ok(OptLevel::Os),
+            _ => Err(format!(
+                "unknown optimisation level `{}`, expected `0`, `1`, `2` or `s`",
+                s
+            )),
+        }
+    }
 }
 
 impl Compiler {
@@ -45,7 +155,7 @@ impl Compiler {
         }
 
         let content = std::fs::read_to_string(&path)
-            .expect(format!("Unable to read file: {}", path).as_str());
+            .unwrap_or_else(|_| panic!("Unable to read file: {}", path));
 
         self.add_source(path, content);
     }
@@ -59,55 +169,210 @@ impl Compiler {
         }
     }
 
-    pub fn compile<P>(&mut self, _dst: P) -> Result<()>
+    pub fn compile<P>(&mut self, dst: P) -> Result<()>
     where
         P: AsRef<Path>,
     {
-        // Track files to compile
-        let mut files_to_compile = vec![self.main_file.clone()];
+        let modules = self.load_modules()?;
+
+        // modules are analysed after the modules they declare
+        let mut symbols = HashMap::new();
+        let mut lowered = Vec::new();
+        // keep stdout to the MIR alone when emitting it
+        let banners = self.options.emit != Emit::Mir;
+        for i in self.module_order(&modules)? {
+            let module = &modules[i];
+            if banners {
+                println!("Compiling: {}", &module.ast.file_id);
+            }
+
+            debug!("Analysing: {}", &module.ast.file_id);
+            let table = self.analyse_ast(&module.ast, module.name.is_none(), &symbols)?;
+            if banners {
+                println!("Compiled");
+            }
+
+            debug!("Lowering: {}", &module.ast.file_id);
+            lowered.push(lower_module(&module.ast, module.name.as_deref(), &table)?);
+
+            if let Some(name) = &module.name {
+                symbols.insert(name.clone(), ModuleSymbol::new(&module.ast));
+            }
+        }
 
-        // Loop until all files are compiled
-        while let Some(file_path) = files_to_compile.pop() {
-            println!("Compiling: {}", &file_path);
-            self.compile_file(file_path)?;
-            println!("Compiled");
+        for (module, func) in hir::unused_functions(&lowered) {
+            let warning = anyhow!(Warning::UnusedFunction(
+                func.ident.clone(),
+                func.ident.span.clone()
+            ));
+            self.report_errors(&[warning], &module.file);
         }
 
+        if self.options.warn_non_tail_recursion {
+            self.check_tail_recursion(&lowered);
+        }
+
+        debug!("Lowering to MIR");
+        let stack_locals = self.options.opt_level == OptLevel::O0;
+        let mut program = mir::lower_program(&lowered, stack_locals)?;
+        self.passes().run(&mut program)?;
+
+        if self.options.emit == Emit::Mir {
+            print!("{}", program);
+            return Ok(());
+        }
+
+        debug!("Generating code");
+        let object = self.generate(&program)?;
+
+        debug!("Linking: {}", dst.as_ref().display());
+        link(&object, dst.as_ref())?;
+
         Ok(())
     }
 
-    fn compile_file(&mut self, file_path: String) -> Result<()> {
+    /// Parse the main file and every module it declares, transitively
+    fn load_modules(&mut self) -> Result<Vec<Module>> {
+        // Track files to compile
+        let mut files_to_compile = vec![(self.main_file.clone(), None)];
+        let mut seen = HashSet::from([self.main_file.clone()]);
+        let mut modules = Vec::new();
+
+        // Loop until all files are parsed
+        while let Some((file_path, name)) = files_to_compile.pop() {
+            let ast = self.parse_file(file_path)?;
+
+            for decl in mod_decls(&ast) {
+                let path = module_path(&self.main_file, &decl.ident.ident);
+                if !seen.insert(path.clone()) {
+                    continue;
+                }
+
+                if !self.load_source(&path) {
+                    let err = anyhow!(SemanticError::ModuleNotFound(
+                        decl.ident.clone(),
+                        path,
+                        decl.span.clone()
+                    ));
+                    self.report_errors(&[err], &ast.file_id);
+                    return Err(anyhow!("Failed to load module `{}`", decl.ident));
+                }
+
+                files_to_compile.push((path, Some(decl.ident.ident.clone())));
+            }
+
+            modules.push(Module { name, ast });
+        }
+
+        Ok(modules)
+    }
+
+    /// Make a module's source available, reading it from disk if it was not added
+    fn load_source(&mut self, path: &str) -> bool {
+        if self.files.get_file(path).is_some() {
+            return true;
+        }
+
+        match std::fs::read_to_string(path) {
+            Ok(content) => {
+                self.files.add_file(path.to_string(), content);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Order the modules so each comes after every module it declares
+    /// Returns indices into `modules`, and reports a cycle at the `mod` item closing it
+    fn module_order(&mut self, modules: &[Module]) -> Result<Vec<usize>> {
+        let mut order = Vec::new();
+        let mut visited = vec![false; modules.len()];
+
+        // the main file is always first
+        if let Err((i, err)) = visit_module(0, modules, &mut visited, &mut vec![], &mut order) {
+            self.report_errors(&[err], &modules[i].ast.file_id);
+            return Err(anyhow!("Failed to order modules"));
+        }
+
+        Ok(order)
+    }
+
+    fn parse_file(&mut self, file_path: String) -> Result<AST> {
         debug!("Tokenizing: {}", &file_path);
         let tokens = self.lex_file(file_path.clone())?;
 
         debug!("Parsing: {}", &file_path);
-        let program = self.parse_tokens(tokens, file_path.clone())?;
+        self.parse_tokens(tokens, file_path)
+    }
 
-        debug!("Analysing: {}", &file_path);
-        self.analyse_ast(&program, file_path.clone())?;
+    /// Warn about each call a function makes to itself outside tail position,
+    /// which can't reuse its stack frame
+    fn check_tail_recursion(&mut self, modules: &[hir::Module]) {
+        for module in modules {
+            for func in &module.functions {
+                let warnings: Vec<Error> = hir::non_tail_recursion(func)
+                    .into_iter()
+                    .map(|call| {
+                        anyhow!(Warning::NonTailRecursion(
+                            func.ident.clone(),
+                            call.span.clone()
+                        ))
+                    })
+                    .collect();
+                self.report_errors(&warnings, &module.file);
+            }
+        }
+    }
 
-        Ok(())
+    /// The passes run over the MIR before codegen
+    fn passes(&self) -> PassManager {
+        let mut passes = PassManager::new();
+        let optimise = self.options.opt_level != OptLevel::O0;
+        if let Some(threshold) = self.options.opt_level.inline_threshold() {
+            passes.add(Inliner { threshold });
+        }
+        if optimise {
+            passes.add(ConstantFolding);
+        }
+        passes.add(RemoveUnreachableBlocks);
+        if optimise {
+            passes.add(DeadCodeElimination);
+        }
+        passes
     }
 
-    fn parse_tokens<'f>(&mut self, stream: Vec<Token>, file_id: String) -> Result<AST> {
+    /// Generate an object file for the program
+    fn generate(&mut self, program: &mir::Program) -> Result<Vec<u8>> {
+        let options = CodegenOptions {
+            print_ir: self.options.print_ir,
+            print_asm: self.options.print_asm,
+            checked_arith: self.options.checked_arith,
+            opt_level: self.options.opt_level.cranelift(),
+            verify: matches!(self.options.opt_level, OptLevel::O0 | OptLevel::O1),
+            stack_locals: self.options.opt_level == OptLevel::O0,
+        };
+
+        let mut generator = CodeGenerator::new(&self.main_file, options)?;
+        generator.declare_program(program)?;
+        generator.compile_program(program, &self.files)?;
+
+        generator.finish()
+    }
+
+    fn parse_tokens(&mut self, stream: Vec<Token>, file_id: String) -> Result<AST> {
         let mut parser = Parser::new(stream);
         let ast = match parser.parse(file_id.clone()) {
             Ok(program) => program,
             Err(err) => {
-                self.report_errors(&vec![err], &file_id);
+                self.report_errors(&[err], &file_id);
                 return Err(anyhow::anyhow!("Failed to parse file"));
             }
         };
 
-        if self.options.print_ast {
-            // println!("{:#?}", ast);
-            println!("{}", ast);
-        }
-
         Ok(ast)
     }
 
-    fn lex_file<'f>(&mut self, file_id: String) -> Result<Vec<Token>> {
+    fn lex_file(&mut self, file_id: String) -> Result<Vec<Token>> {
         let src = self.files.fetch(&file_id).unwrap();
 
         // load source into string
@@ -131,17 +396,27 @@ impl Compiler {
         }
     }
 
-    fn analyse_ast(&mut self, ast: &AST, file_id: String) -> Result<()> {
+    /// Analyse a module, returning its symbol table for lowering
+    fn analyse_ast<'a>(
+        &mut self,
+        ast: &'a AST,
+        is_main: bool,
+        modules: &HashMap<String, ModuleSymbol>,
+    ) -> Result<SymbolTable<'a>> {
+        let file_id = ast.file_id.clone();
+
         // let errors = analyse(ast);
         // split into errors & warnings by checking downcastref
-        let (errors, warnings): (Vec<Error>, Vec<Error>) =
-            analyse(ast).into_iter().partition(|e| {
-                if e.downcast_ref::<SemanticError>().is_some() {
-                    true
-                } else {
-                    false
-                }
-            });
+        let (table, errors) = analyse_module(ast, is_main, modules);
+        let (errors, warnings): (Vec<Error>, Vec<Error>) = errors
+            .into_iter()
+            .partition(|e| e.downcast_ref::<SemanticError>().is_some());
+
+        // printed after analysis so inferred types are filled in
+        if self.options.print_ast {
+            // println!("{:#?}", ast);
+            println!("{}", ast);
+        }
 
         self.report_errors(&errors, &file_id);
         self.report_errors(&warnings, &file_id);
@@ -150,20 +425,104 @@ impl Compiler {
             return Err(anyhow::anyhow!("Failed to analyse file"));
         }
 
-        Ok(())
+        Ok(table)
     }
 
-    fn report_errors(&mut self, errors: &Vec<Error>, file_id: &String) {
+    fn report_errors(&mut self, errors: &[Error], file_id: &str) {
         let mut reporter = ErrorReporter::new(&mut self.files);
 
         for err in errors {
             reporter
-                .report(file_id.clone(), &err)
+                .report(file_id.to_string(), err)
                 .expect("Failed to report error");
         }
     }
 }
 
+fn mod_decls(ast: &AST) -> impl Iterator<Item = &ModDecl> {
+    ast.program.items.iter().filter_map(|item| match item {
+        Item::ModDecl(m) => Some(m),
+        _ => None,
+    })
+}
+
+/// `mod name;` refers to `name.pyl` next to the main file
+fn module_path(main_file: &str, name: &str) -> String {
+    Path::new(main_file)
+        .with_file_name(format!("{}.pyl", name))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Depth-first search from module `i`, pushing modules to `order` after
+/// the modules they declare. `stack` holds the path from the main file
+fn visit_module(
+    i: usize,
+    modules: &[Module],
+    visited: &mut [bool],
+    stack: &mut Vec<usize>,
+    order: &mut Vec<usize>,
+) -> std::result::Result<(), (usize, Error)> {
+    visited[i] = true;
+    stack.push(i);
+
+    for decl in mod_decls(&modules[i].ast) {
+        let path = module_path(&modules[0].ast.file_id, &decl.ident.ident);
+        let j = modules
+            .iter()
+            .position(|m| m.ast.file_id == path)
+            .expect("declared modules are loaded");
+
+        if let Some(start) = stack.iter().position(|&k| k == j) {
+            let cycle = stack[start..]
+                .iter()
+                .chain([&j])
+                .map(|&k| modules[k].display_name())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+
+            return Err((
+                i,
+                anyhow!(SemanticError::CyclicModule {
+                    module: decl.ident.clone(),
+                    cycle,
+                    span: decl.span.clone(),
+                }),
+            ));
+        }
+
+        if !visited[j] {
+            visit_module(j, modules, visited, stack, order)?;
+        }
+    }
+
+    stack.pop();
+    order.push(i);
+
+    Ok(())
+}
+
+/// Link an object file into an executable using the system C compiler
+fn link(object: &[u8], dst: &Path) -> Result<()> {
+    let object_path = dst.with_extension("o");
+    std::fs::write(&object_path, object)?;
+
+    let status = Command::new("cc")
+        .arg(&object_path)
+        .arg("-o")
+        .arg(dst)
+        .status()
+        .map_err(|e| anyhow!("Failed to run linker `cc`: {}", e));
+
+    std::fs::remove_file(&object_path)?;
+
+    if !status?.success() {
+        return Err(anyhow!("Failed to link {}", dst.display()));
+    }
+
+    Ok(())
+}
+
 pub fn default_output_file(input_file: &str) -> String {
     // replace suffix
     let mut output_file = input_file.to_string();
@@ -1,2 +1,2 @@
 pub mod compiler;
-pub use compiler::{Compiler, CompilerOptions};
+pub use compiler::{Compiler, CompilerOptions, Emit, OptLevel};
@@ -0,0 +1,191 @@
+//! Finds the functions a program calls, starting from `main`.
+//!
+//! A function is used if `main` may call it, directly or through other
+//! functions, closures and function values, or if a `const` or `static`
+//! initializer calls it at compile time.
+
+use std::collections::HashSet;
+
+use log::debug;
+
+use super::*;
+
+/// Functions never used, and the module declaring each
+/// Impl methods are left out, as they may exist only to implement their trait,
+/// and so are functions named with a leading underscore
+pub fn unused_functions(modules: &[Module]) -> Vec<(&Module, &Function)> {
+    let used = used_functions(modules);
+
+    modules
+        .iter()
+        .flat_map(|m| m.functions.iter().map(move |f| (m, f)))
+        .filter(|(_, f)| {
+            !used.contains(f.name.as_str()) && !f.method && !f.ident.ident.starts_with('_')
+        })
+        .collect()
+}
+
+/// Qualified names of the functions used
+pub fn used_functions(modules: &[Module]) -> HashSet<&str> {
+    let functions: Vec<&Function> = modules.iter().flat_map(|m| &m.functions).collect();
+
+    let mut queue: Vec<&str> = vec!["main"];
+    queue.extend(
+        modules
+            .iter()
+            .flat_map(|m| m.const_calls.iter().map(String::as_str)),
+    );
+
+    let mut used = HashSet::new();
+    while let Some(name) = queue.pop() {
+        if !used.insert(name) {
+            continue;
+        }
+
+        let func = match functions.iter().find(|f| f.name == name) {
+            Some(func) => func,
+            None => continue,
+        };
+
+        let bodies = std::iter::once(&func.body).chain(func.closures.iter().map(|c| &c.body));
+        for body in bodies {
+            visit_block(body, &mut |expr| match &expr.kind {
+                ExprKind::Function(name) | ExprKind::Call(Callee::Function(name, _), _) => {
+                    queue.push(name)
+                }
+                ExprKind::Call(callee @ Callee::Method { .. }, _) => {
+                    queue.extend(
+                        functions
+                            .iter()
+                            .filter(|f| implements(f, callee))
+                            .map(|f| f.name.as_str()),
+                    );
+                }
+                _ => {}
+            });
+        }
+    }
+
+    debug!("Functions used: {:?}", used);
+    used
+}
+
+/// Could a method call dispatch to a function?
+/// Calls in generic functions may dispatch to any impl of the method
+fn implements(func: &Function, callee: &Callee) -> bool {
+    let (module, trait_name, method, self_ty) = match callee {
+        Callee::Method {
+            module,
+            trait_name,
+            method,
+            self_ty,
+        } => (module.as_deref(), trait_name, method, self_ty),
+        _ => return false,
+    };
+
+    if self_ty.params().is_empty() {
+        return func.name == impl_method_name(module, self_ty, trait_name, method);
+    }
+
+    // `<ty as Trait>::method`, in the module declaring the trait
+    let prefix = qualified_name(module, "<");
+    let suffix = format!(" as {}>::{}", trait_name, method);
+    func.method && func.name.starts_with(&prefix) && func.name.ends_with(&suffix)
+}
+
+/// Call `f` with every expression in a block, outermost first
+pub(super) fn visit_block<'h>(block: &'h Block, f: &mut impl FnMut(&'h Expr)) {
+    for statement in &block.statements {
+        match statement {
+            Statement::Let(_, Some(e)) | Statement::Expr(e) | Statement::Return(Some(e)) => {
+                visit_expr(e, f)
+            }
+            Statement::Assign(place, e) => {
+                visit_expr(place, f);
+                visit_expr(e, f);
+            }
+            Statement::If(condition, then_block, else_block) => {
+                visit_expr(condition, f);
+                visit_block(then_block, f);
+                if let Some(else_block) = else_block {
+                    visit_block(else_block, f);
+                }
+            }
+            Statement::Let(_, None) | Statement::Return(None) => {}
+        }
+    }
+}
+
+fn visit_expr<'h>(expr: &'h Expr, f: &mut impl FnMut(&'h Expr)) {
+    f(expr);
+
+    match &expr.kind {
+        ExprKind::Call(callee, args) => {
+            if let Callee::Value(e) = callee {
+                visit_expr(e, f);
+            }
+            for arg in args {
+                visit_expr(arg, f);
+            }
+        }
+        ExprKind::Unary(_, e) | ExprKind::Ref(e) | ExprKind::Deref(e) | ExprKind::Cast(e) => {
+            visit_expr(e, f)
+        }
+        ExprKind::Binary(_, lhs, rhs) => {
+            visit_expr(lhs, f);
+            visit_expr(rhs, f);
+        }
+        ExprKind::Literal(_)
+        | ExprKind::Local(_)
+        | ExprKind::Static(_)
+        | ExprKind::Function(_)
+        | ExprKind::Closure(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hir::tests::lower;
+
+    #[test]
+    fn unused() {
+        let src = r#"const fn square(x: int) -> int { return x * x; }
+        const FOUR: int = square(2);
+
+        trait Show {
+            fn show(s: Self) -> int;
+        }
+
+        impl Show for int {
+            fn show(s: int) -> int { return s; }
+        }
+
+        impl Show for bool {
+            fn show(s: bool) -> int { return 0; }
+        }
+
+        fn main() -> int {
+            let f = |x: int| -> int { return twice(x); };
+            return apply(f, FOUR) + Show::show(1);
+        }
+
+        fn apply(f: fn(int) -> int, x: int) -> int { return f(x); }
+        fn twice(x: int) -> int { return x * 2; }
+
+        fn never() -> int { return only_never(); }
+        fn only_never() -> int { return 1; }
+        fn _ignored() -> int { return 2; }
+        fn max<T>(a: T, b: T) -> T { return a; }"#;
+
+        let module = lower(src);
+        let modules = [module];
+        let unused: Vec<_> = unused_functions(&modules)
+            .into_iter()
+            .map(|(_, f)| f.name.as_str())
+            .collect();
+
+        // `<bool as Show>::show` is unused too, but methods aren't reported
+        assert_eq!(unused, ["never", "only_never", "max"]);
+    }
+}
@@ -0,0 +1,585 @@
+//! Lowers an analysed AST into HIR.
+//!
+//! Analysis has already checked the program, so names resolve and types
+//! agree; anything that doesn't is reported as an internal error. Variables
+//! are found by the symbol analysis recorded on each ident, rather than by
+//! name, so shadowing needs no handling here.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use log::debug;
+
+use super::*;
+use crate::ast::{self, ASTSpan, Expression, Ident, Item, PrimaryExpression, AST};
+use crate::ast::{
+    Literal, LiteralKind, PrimitiveKind, ReferenceType, UnaryExpressionKind, SELF_TYPE,
+};
+use crate::semantic_analysis::{SymbolId, SymbolTable};
+
+/// Lower an analysed module, with the symbol table analysis left
+pub fn lower_module(ast: &AST, name: Option<&str>, table: &SymbolTable) -> Result<Module> {
+    debug!("Lowering: {}", ast.file_id);
+
+    let mut functions = Vec::new();
+    let mut statics = Vec::new();
+    let mut const_calls = Vec::new();
+    for item in &ast.program.items {
+        if let Item::GlobalDecl(g) = item {
+            calls_in(&g.expression, &mut |ident| {
+                const_calls.push(FunctionLowerer::new(table, name).function_name(ident))
+            });
+        }
+
+        match item {
+            Item::FunctionDecl(f) => {
+                let qualified = qualified_name(name, &f.ident.ident);
+                functions.push(FunctionLowerer::new(table, name).lower(f, qualified, false)?);
+            }
+            Item::ImplDecl(i) => {
+                for method in &i.methods {
+                    let qualified =
+                        impl_method_name(name, &i.ty, &i.trait_ident.ident, &method.ident.ident);
+                    functions
+                        .push(FunctionLowerer::new(table, name).lower(method, qualified, true)?);
+                }
+            }
+            Item::GlobalDecl(g) if g.kind == ast::GlobalKind::Static => {
+                let value = g
+                    .value
+                    .get()
+                    .ok_or_else(|| anyhow!("Static `{}` has no value while lowering", g.ident))?;
+                statics.push(Static {
+                    name: qualified_name(name, &g.ident.ident),
+                    ty: g.ty.clone(),
+                    mutable: g.mutable,
+                    value,
+                });
+            }
+            // `const`s are inlined, and traits have no code of their own
+            Item::GlobalDecl(_) | Item::TraitDecl(_) | Item::ModDecl(_) => {}
+        }
+    }
+
+    Ok(Module {
+        name: name.map(str::to_string),
+        file: ast.file_id.clone(),
+        functions,
+        statics,
+        const_calls,
+    })
+}
+
+/// Call `f` with the callee of every call in an expression
+fn calls_in(expr: &Expression, f: &mut impl FnMut(&Ident)) {
+    match expr {
+        Expression::Primary(PrimaryExpression::FunctionCall(ident, args)) => {
+            f(ident);
+            for arg in args {
+                calls_in(arg, f);
+            }
+        }
+        Expression::Primary(PrimaryExpression::Parenthesized(e)) => calls_in(e, f),
+        Expression::Primary(_) => {}
+        Expression::Unary(u) => calls_in(u.operand(), f),
+        Expression::Binary(b) => {
+            calls_in(&b.lhs, f);
+            calls_in(&b.rhs, f);
+        }
+        Expression::Cast(c) => calls_in(&c.expr, f),
+    }
+}
+
+/// Lower a literal to a constant, negated if it is the operand of `-`
+fn lower_literal(l: &Literal, negated: bool) -> Result<(ExprKind, ast::Type)> {
+    let ty = l.get_type();
+    let value = match (&l.kind, &ty) {
+        (LiteralKind::Int(i), ast::Type::Primitive(p)) if negated => {
+            ConstValue::Int(p.kind.wrap(-(*i as i128)), p.kind)
+        }
+        (LiteralKind::Int(i), ast::Type::Primitive(p)) => {
+            ConstValue::Int(p.kind.wrap(*i as i128), p.kind)
+        }
+        (LiteralKind::Float(f), _) if negated => ConstValue::Float(-*f),
+        (LiteralKind::Float(f), _) => ConstValue::Float(*f),
+        (LiteralKind::Bool(b), _) => ConstValue::Bool(*b),
+        (LiteralKind::Int(_), ty) => return Err(anyhow!("Integer literal of type {}", ty)),
+    };
+    Ok((ExprKind::Literal(value), ty))
+}
+
+/// Lowers one function, numbering the bindings of its body and closures
+struct FunctionLowerer<'t, 'a> {
+    /// The module's symbol table, for its items
+    table: &'t SymbolTable<'a>,
+    /// The module being lowered, `None` for the main module
+    module: Option<&'t str>,
+    bindings: Vec<Binding>,
+    closures: Vec<Closure>,
+    /// The binding of each variable declared in the function and its closures
+    symbols: HashMap<SymbolId, BindingId>,
+}
+
+impl<'t, 'a> FunctionLowerer<'t, 'a> {
+    fn new(table: &'t SymbolTable<'a>, module: Option<&'t str>) -> Self {
+        Self {
+            table,
+            module,
+            bindings: Vec::new(),
+            closures: Vec::new(),
+            symbols: HashMap::new(),
+        }
+    }
+
+    fn lower(mut self, func: &ast::FunctionDecl, name: String, method: bool) -> Result<Function> {
+        debug!("Lowering function: {}", name);
+
+        let params = func
+            .parameters
+            .iter()
+            .map(|p| self.declare(&p.ident, &p.ty, false))
+            .collect::<Result<_>>()?;
+        let body = self.lower_block(&func.block)?;
+
+        Ok(Function {
+            name,
+            ident: func.ident.clone(),
+            method,
+            inline: func.inline,
+            type_params: func
+                .type_params
+                .iter()
+                .map(|p| p.ident.ident.clone())
+                .collect(),
+            params,
+            ret_ty: func.ty.clone(),
+            body,
+            bindings: self.bindings,
+            closures: self.closures,
+            span: func.span.clone(),
+        })
+    }
+
+    /// Add a binding for the variable an ident declares
+    fn declare(&mut self, ident: &Ident, ty: &ast::Type, mutable: bool) -> Result<BindingId> {
+        let symbol = *ident
+            .symbol
+            .get()
+            .ok_or_else(|| anyhow!("Variable `{}` was not declared by analysis", ident))?;
+        let id = BindingId(self.bindings.len());
+        self.bindings.push(Binding {
+            name: ident.ident.clone(),
+            ty: ty.clone(),
+            mutable,
+            addressed: false,
+            boxed: false,
+            span: ident.span.clone(),
+        });
+        self.symbols.insert(symbol, id);
+
+        Ok(id)
+    }
+
+    /// The binding an ident refers to, if it is a variable of the function
+    fn local(&self, ident: &Ident) -> Option<BindingId> {
+        ident
+            .symbol
+            .get()
+            .and_then(|symbol| self.symbols.get(symbol))
+            .copied()
+    }
+
+    fn lookup(&self, ident: &Ident) -> Result<BindingId> {
+        self.local(ident)
+            .ok_or_else(|| anyhow!("Variable `{}` not found while lowering", ident))
+    }
+
+    /// The qualified name of a function, given by name or by path into another module
+    fn function_name(&self, ident: &Ident) -> String {
+        match ident.split_path() {
+            Some(_) => ident.ident.clone(),
+            None => qualified_name(self.module, &ident.ident),
+        }
+    }
+
+    fn lower_block(&mut self, block: &ast::Block) -> Result<Block> {
+        let statements = block
+            .statements
+            .iter()
+            .map(|s| self.lower_statement(s))
+            .collect::<Result<_>>()?;
+
+        Ok(Block { statements })
+    }
+
+    fn lower_statement(&mut self, statement: &ast::Statement) -> Result<Statement> {
+        Ok(match statement {
+            ast::Statement::Expression(e) => Statement::Expr(self.lower_expr(e)?),
+            ast::Statement::VariableDecl(v) => {
+                let value = v
+                    .expression
+                    .as_ref()
+                    .map(|e| self.lower_expr(e))
+                    .transpose()?;
+                let ty = v
+                    .var_type()
+                    .or_else(|| value.as_ref().map(|v| v.ty.clone()))
+                    .ok_or_else(|| anyhow!("Variable `{}` has no type", v.ident))?;
+                Statement::Let(self.declare(&v.ident, &ty, v.mutable)?, value)
+            }
+            ast::Statement::Assignment(a) => {
+                Statement::Assign(self.lower_expr(&a.target)?, self.lower_expr(&a.expression)?)
+            }
+            ast::Statement::Flow(f) => Statement::If(
+                self.lower_expr(&f.condition)?,
+                self.lower_block(&f.if_block)?,
+                f.else_block
+                    .as_ref()
+                    .map(|b| self.lower_block(b))
+                    .transpose()?,
+            ),
+            ast::Statement::Return(e) => {
+                Statement::Return(e.as_ref().map(|e| self.lower_expr(e)).transpose()?)
+            }
+        })
+    }
+
+    fn lower_expr(&mut self, expr: &Expression) -> Result<Expr> {
+        let span = expr.span();
+        let (kind, ty) = match expr {
+            Expression::Primary(p) => match p {
+                PrimaryExpression::Literal(l) => lower_literal(l, false)?,
+                PrimaryExpression::Ident(i) => self.lower_ident(i)?,
+                PrimaryExpression::Parenthesized(e) => return self.lower_expr(e),
+                PrimaryExpression::FunctionCall(i, args) => self.lower_call(i, args)?,
+                PrimaryExpression::Closure(c) => self.lower_closure(c)?,
+            },
+            Expression::Unary(u) => match (&u.kind, u.operand().strip_parens()) {
+                // a negated literal is a negative constant, as the literal
+                // alone may be one past the maximum, e.g. `-128i8`
+                (
+                    UnaryExpressionKind::Negation(_),
+                    Expression::Primary(PrimaryExpression::Literal(l)),
+                ) => lower_literal(l, true)?,
+                (UnaryExpressionKind::Negation(e) | UnaryExpressionKind::Not(e), _) => {
+                    let op = match u.kind {
+                        UnaryExpressionKind::Negation(_) => UnaryOp::Neg,
+                        _ => UnaryOp::Not,
+                    };
+                    let operand = self.lower_expr(e)?;
+                    let ty = operand.ty.clone();
+                    (ExprKind::Unary(op, Box::new(operand)), ty)
+                }
+                (
+                    UnaryExpressionKind::Reference(e) | UnaryExpressionKind::MutableReference(e),
+                    _,
+                ) => {
+                    let place = self.lower_expr(e)?;
+                    if let ExprKind::Local(id) = place.kind {
+                        self.bindings[id.0].addressed = true;
+                    }
+                    let ty = ast::Type::Reference(ReferenceType {
+                        inner: Box::new(place.ty.clone()),
+                        mutable: matches!(u.kind, UnaryExpressionKind::MutableReference(_)),
+                        span: u.span.clone(),
+                    });
+                    (ExprKind::Ref(Box::new(place)), ty)
+                }
+                (UnaryExpressionKind::Dereference(e), _) => {
+                    let ptr = self.lower_expr(e)?;
+                    let ty = match &ptr.ty {
+                        ast::Type::Reference(r) => (*r.inner).clone(),
+                        ty => return Err(anyhow!("Cannot dereference {}", ty)),
+                    };
+                    (ExprKind::Deref(Box::new(ptr)), ty)
+                }
+            },
+            Expression::Binary(b) => {
+                let lhs = self.lower_expr(&b.lhs)?;
+                let rhs = self.lower_expr(&b.rhs)?;
+                let ty = if b.op.kind.is_comparison() {
+                    ast::Type::primitive(PrimitiveKind::Bool)
+                } else {
+                    lhs.ty.clone()
+                };
+                (
+                    ExprKind::Binary(b.op.kind, Box::new(lhs), Box::new(rhs)),
+                    ty,
+                )
+            }
+            Expression::Cast(c) => (
+                ExprKind::Cast(Box::new(self.lower_expr(&c.expr)?)),
+                c.ty.clone(),
+            ),
+        };
+
+        Ok(Expr { kind, ty, span })
+    }
+
+    /// A variable, a global or a function used as a value
+    fn lower_ident(&mut self, ident: &Ident) -> Result<(ExprKind, ast::Type)> {
+        if let Some(id) = self.local(ident) {
+            return Ok((ExprKind::Local(id), self.bindings[id.0].ty.clone()));
+        }
+
+        // any other variable is a global of the module
+        if let Some(symbol) = ident.symbol.get() {
+            let global = self.table.symbol(*symbol);
+            let kind = match global.value {
+                Some(value) => ExprKind::Literal(value),
+                None => ExprKind::Static(qualified_name(self.module, &ident.ident)),
+            };
+            return Ok((kind, global.ty.clone()));
+        }
+
+        let func = self
+            .table
+            .get_fn(ident)
+            .ok_or_else(|| anyhow!("Function `{}` not found while lowering", ident))?;
+        Ok((ExprKind::Function(self.function_name(ident)), func.ty()))
+    }
+
+    /// Resolve what a call calls, inferring the type parameters of generic
+    /// functions and trait methods from the arguments, as analysis did
+    fn lower_call(&mut self, ident: &Ident, args: &[Expression]) -> Result<(ExprKind, ast::Type)> {
+        let args = args
+            .iter()
+            .map(|a| self.lower_expr(a))
+            .collect::<Result<Vec<_>>>()?;
+
+        // locals shadow functions, and are closure objects
+        if let Some(id) = self.local(ident) {
+            let binding = &self.bindings[id.0];
+            let ret_ty = match &binding.ty {
+                ast::Type::Function(f) => (*f.ret_ty).clone(),
+                ty => return Err(anyhow!("Cannot call {}", ty)),
+            };
+            let callee = Expr {
+                kind: ExprKind::Local(id),
+                ty: binding.ty.clone(),
+                span: ident.span.clone(),
+            };
+            return Ok((
+                ExprKind::Call(Callee::Value(Box::new(callee)), args),
+                ret_ty,
+            ));
+        }
+
+        let func = self
+            .table
+            .get_fn(ident)
+            .ok_or_else(|| anyhow!("Function `{}` not found while lowering", ident))?;
+
+        let mut type_args = HashMap::new();
+        for (param, arg) in func.params.iter().zip(&args) {
+            if !param.unify(&arg.ty, &mut type_args) {
+                return Err(anyhow!("Cannot pass {} to `{}`", arg.ty, ident));
+            }
+        }
+        let ret_ty = func.ret_ty.substitute(&type_args);
+
+        let trait_method = ident
+            .split_path()
+            .filter(|(prefix, _)| self.table.get_trait(prefix).is_some());
+        let callee = match trait_method {
+            Some((trait_name, method)) => Callee::Method {
+                module: self.module.map(str::to_string),
+                trait_name: trait_name.to_string(),
+                method: method.to_string(),
+                self_ty: type_args
+                    .remove(SELF_TYPE)
+                    .ok_or_else(|| anyhow!("Cannot infer `{}` for `{}`", SELF_TYPE, ident))?,
+            },
+            None => {
+                let type_args = func
+                    .type_params
+                    .iter()
+                    .map(|p| {
+                        type_args
+                            .remove(&p.ident.ident)
+                            .ok_or_else(|| anyhow!("Cannot infer `{}` for `{}`", p.ident, ident))
+                    })
+                    .collect::<Result<_>>()?;
+                Callee::Function(self.function_name(ident), type_args)
+            }
+        };
+
+        Ok((ExprKind::Call(callee, args), ret_ty))
+    }
+
+    /// Lower a closure's body into the function's closures
+    /// Its captures are bindings of the enclosing bodies
+    fn lower_closure(&mut self, closure: &ast::Closure) -> Result<(ExprKind, ast::Type)> {
+        let mut captures = Vec::new();
+        for capture in closure.captures.borrow().iter() {
+            let binding = self.lookup(&capture.ident)?;
+            if capture.by_ref {
+                self.bindings[binding.0].boxed = true;
+            }
+            captures.push(Capture {
+                binding,
+                by_ref: capture.by_ref,
+            });
+        }
+
+        let params = closure
+            .parameters
+            .iter()
+            .map(|p| self.declare(&p.ident, &p.ty, false))
+            .collect::<Result<_>>()?;
+        let body = self.lower_block(&closure.block)?;
+
+        let id = ClosureId(self.closures.len());
+        self.closures.push(Closure {
+            params,
+            ret_ty: closure.ty.clone(),
+            captures,
+            body,
+            span: closure.span.clone(),
+        });
+
+        Ok((ExprKind::Closure(id), closure.get_type()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hir::tests::lower;
+
+    fn function<'m>(module: &'m Module, name: &str) -> &'m Function {
+        module.functions.iter().find(|f| f.name == name).unwrap()
+    }
+
+    #[test]
+    fn bindings() {
+        let src = r#"const ONE: int = 1;
+
+        fn main() -> int {
+            let x = ONE;
+            let x: u8 = 2;
+            if x > 1 {
+                let x = true;
+                return x as int;
+            }
+            return x as int;
+        }"#;
+        let module = lower(src);
+        let main = function(&module, "main");
+
+        // each `let` of `x` is its own binding
+        let names: Vec<_> = main.bindings.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["x", "x", "x"]);
+
+        let statements = &main.body.statements;
+        match &statements[0] {
+            Statement::Let(BindingId(0), Some(value)) => {
+                assert!(matches!(
+                    value.kind,
+                    ExprKind::Literal(ConstValue::Int(1, PrimitiveKind::I64))
+                ))
+            }
+            s => panic!("Expected the first let, found {:?}", s),
+        }
+        let (then_block, ret) = match (&statements[2], &statements[3]) {
+            (Statement::If(_, then_block, None), Statement::Return(Some(ret))) => (then_block, ret),
+            s => panic!("Expected an if and a return, found {:?}", s),
+        };
+
+        match &then_block.statements[1] {
+            Statement::Return(Some(Expr {
+                kind: ExprKind::Cast(inner),
+                ty,
+                ..
+            })) => {
+                assert!(matches!(inner.kind, ExprKind::Local(BindingId(2))));
+                assert!(inner.ty.is_primitive(PrimitiveKind::Bool));
+                assert!(ty.is_primitive(PrimitiveKind::I64));
+            }
+            s => panic!("Expected a return, found {:?}", s),
+        }
+        match &ret.kind {
+            ExprKind::Cast(inner) => {
+                assert!(matches!(inner.kind, ExprKind::Local(BindingId(1))));
+                assert!(inner.ty.is_primitive(PrimitiveKind::U8));
+            }
+            e => panic!("Expected a cast, found {:?}", e),
+        }
+    }
+
+    #[test]
+    fn calls() {
+        let src = r#"trait Half {
+            fn half(s: Self) -> Self;
+        }
+
+        impl Half for u8 {
+            fn half(s: u8) -> u8 { return s / 2; }
+        }
+
+        fn max<T>(a: T, b: T) -> T {
+            if a > b {
+                return a;
+            }
+            return b;
+        }
+
+        fn main() -> int {
+            let mut n: u8 = 8;
+            let bump = |by: u8| -> u8 {
+                n = n + by;
+                n
+            };
+            bump(1);
+            return max(Half::half(n), 2) as int;
+        }"#;
+        let module = lower(src);
+        assert!(function(&module, "<u8 as Half>::half").params.len() == 1);
+        let main = function(&module, "main");
+
+        // captured by reference, so `n` must live on the heap
+        assert!(main.binding(BindingId(0)).boxed);
+        let closure = main.closure(ClosureId(0));
+        // the initializer is lowered before `bump` is declared
+        assert_eq!(closure.params, [BindingId(1)]);
+        assert!(matches!(
+            closure.captures[..],
+            [Capture {
+                binding: BindingId(0),
+                by_ref: true
+            }]
+        ));
+
+        let ret = match main.body.statements.last() {
+            Some(Statement::Return(Some(Expr {
+                kind: ExprKind::Cast(ret),
+                ..
+            }))) => ret,
+            s => panic!("Expected a return, found {:?}", s),
+        };
+        let args = match &ret.kind {
+            ExprKind::Call(Callee::Function(name, type_args), args) => {
+                assert_eq!(name, "max");
+                assert_eq!(type_args[..], [ast::Type::primitive(PrimitiveKind::U8)]);
+                args
+            }
+            e => panic!("Expected a call of `max`, found {:?}", e),
+        };
+        match &args[0].kind {
+            ExprKind::Call(
+                Callee::Method {
+                    trait_name,
+                    method,
+                    self_ty,
+                    ..
+                },
+                _,
+            ) => {
+                assert_eq!((trait_name.as_str(), method.as_str()), ("Half", "half"));
+                assert!(self_ty.is_primitive(PrimitiveKind::U8));
+            }
+            e => panic!("Expected a call of `Half::half`, found {:?}", e),
+        }
+        assert!(args[1].ty.is_primitive(PrimitiveKind::U8));
+    }
+}
@@ -0,0 +1,233 @@
+//! Declares the HIR, the typed intermediate representation between
+//! semantic analysis and codegen.
+//!
+//! Each module is lowered from its AST once analysis finds no errors.
+//! Every expression carries its resolved type, and every name is resolved:
+//! locals to the binding that declares them, and functions, statics and
+//! trait methods to the item they refer to. `const`s are inlined.
+//!
+//! Generic functions are lowered once, so their types may still mention
+//! type parameters until codegen instantiates them.
+
+mod call_graph;
+mod lower;
+mod tail_calls;
+
+pub use self::call_graph::*;
+pub use self::lower::*;
+pub use self::tail_calls::*;
+
+use crate::ast::{BinaryOperatorKind, Ident, Inline, Type};
+use crate::semantic_analysis::ConstValue;
+use crate::token::Span;
+
+/// The name a function is declared under, e.g. `math::add`
+/// Functions of the main module keep their own name
+pub fn qualified_name(module: Option<&str>, name: &str) -> String {
+    match module {
+        Some(module) => format!("{}::{}", module, name),
+        None => name.to_string(),
+    }
+}
+
+/// The name the method of an impl is declared under, e.g. `<int as Show>::show`
+pub fn impl_method_name(module: Option<&str>, ty: &Type, trait_name: &str, method: &str) -> String {
+    qualified_name(module, &format!("<{} as {}>::{}", ty, trait_name, method))
+}
+
+/// A lowered source file
+#[derive(Debug, Clone)]
+pub struct Module {
+    /// Name given by `mod name;`, `None` for the main module
+    pub name: Option<String>,
+    /// The file it was parsed from
+    pub file: String,
+    /// Functions, including the methods of impls
+    pub functions: Vec<Function>,
+    pub statics: Vec<Static>,
+    /// Functions called while evaluating the initializers of `const`s and `static`s,
+    /// by qualified name
+    pub const_calls: Vec<String>,
+}
+
+/// A function, or a method of an impl
+#[derive(Debug, Clone)]
+pub struct Function {
+    /// Qualified name, see [`qualified_name`] and [`impl_method_name`]
+    pub name: String,
+    /// Name as declared, for diagnostics
+    pub ident: Ident,
+    /// A method of an impl, called through its trait
+    pub method: bool,
+    /// Its `#[inline]` attribute, if any
+    pub inline: Option<Inline>,
+    /// Names of the type parameters, empty unless generic
+    pub type_params: Vec<String>,
+    pub params: Vec<BindingId>,
+    pub ret_ty: Type,
+    pub body: Block,
+    /// Every variable of the function and of its closures, by [`BindingId`]
+    pub bindings: Vec<Binding>,
+    /// Every closure the function creates, including nested ones, by [`ClosureId`]
+    pub closures: Vec<Closure>,
+    pub span: Span,
+}
+
+/// Index of a binding in [`Function::bindings`]
+/// A shadowing `let` is a new binding of the same name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BindingId(pub usize);
+
+/// Index of a closure in [`Function::closures`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClosureId(pub usize);
+
+/// A parameter or `let` variable
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub name: String,
+    pub ty: Type,
+    pub mutable: bool,
+    /// Its address is taken with `&`
+    pub addressed: bool,
+    /// Captured by reference by a closure, which may outlive the function,
+    /// so it lives in a heap cell the closure shares
+    pub boxed: bool,
+    pub span: Span,
+}
+
+/// A closure's body, sharing the bindings of the function creating it
+#[derive(Debug, Clone)]
+pub struct Closure {
+    pub params: Vec<BindingId>,
+    pub ret_ty: Type,
+    /// Variables of the enclosing bodies, in the order of the closure object's fields
+    pub captures: Vec<Capture>,
+    pub body: Block,
+    pub span: Span,
+}
+
+/// A variable captured by a closure
+#[derive(Debug, Clone, Copy)]
+pub struct Capture {
+    pub binding: BindingId,
+    pub by_ref: bool,
+}
+
+/// A `static`, with the value of its initializer
+#[derive(Debug, Clone)]
+pub struct Static {
+    /// Qualified name, as for functions
+    pub name: String,
+    pub ty: Type,
+    pub mutable: bool,
+    pub value: ConstValue,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Block {
+    pub statements: Vec<Statement>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Statement {
+    /// `let`, with no value for `let x: T;`
+    Let(BindingId, Option<Expr>),
+    /// `place = value`, where the place is a local, a static or a dereference
+    Assign(Expr, Expr),
+    If(Expr, Block, Option<Block>),
+    Return(Option<Expr>),
+    Expr(Expr),
+}
+
+/// An expression and its type
+#[derive(Debug, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub ty: Type,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExprKind {
+    /// A literal, or the value of a `const`
+    Literal(ConstValue),
+    Local(BindingId),
+    /// A `static`, by qualified name
+    Static(String),
+    /// A named function used as a value, by qualified name
+    Function(String),
+    Call(Callee, Vec<Expr>),
+    Closure(ClosureId),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOperatorKind, Box<Expr>, Box<Expr>),
+    /// The address of a place, or of a temporary holding any other value
+    Ref(Box<Expr>),
+    Deref(Box<Expr>),
+    /// Conversion to the expression's type
+    Cast(Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+/// What a call calls
+#[derive(Debug, Clone)]
+pub enum Callee {
+    /// A function by qualified name, with the type of each of its type parameters
+    Function(String, Vec<Type>),
+    /// `Trait::method`, dispatched on the type given to `Self`
+    Method {
+        /// Module declaring the trait and its impls
+        module: Option<String>,
+        trait_name: String,
+        method: String,
+        self_ty: Type,
+    },
+    /// A function value, called through its closure object
+    Value(Box<Expr>),
+}
+
+impl Function {
+    pub fn binding(&self, id: BindingId) -> &Binding {
+        &self.bindings[id.0]
+    }
+
+    pub fn closure(&self, id: ClosureId) -> &Closure {
+        &self.closures[id.0]
+    }
+
+    pub fn is_generic(&self) -> bool {
+        !self.type_params.is_empty()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::semantic_analysis::analyse_module;
+    use std::collections::HashMap;
+
+    /// Parse, analyse and lower a single-file program
+    pub fn lower(src: &str) -> Module {
+        let lexer = crate::lexer::Lexer::new(src);
+        let (tokens, errors) = crate::lexer::consume_lexer(lexer);
+        assert!(errors.is_empty());
+
+        let ast = crate::parser::Parser::new(tokens)
+            .parse("test".to_string())
+            .expect("Failed to parse");
+        // warnings don't stop lowering
+        let (table, errors) = analyse_module(&ast, true, &HashMap::new());
+        let errors: Vec<_> = errors
+            .into_iter()
+            .filter(|e| e.downcast_ref::<crate::errors::SemanticError>().is_some())
+            .collect();
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        lower_module(&ast, None, &table).expect("Failed to lower")
+    }
+}
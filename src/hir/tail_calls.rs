@@ -0,0 +1,140 @@
+//! Finds calls in tail position, whose result a `return` returns directly.
+//!
+//! A function calling itself in tail position is lowered into a jump back
+//! to the start of its body, so it runs in constant stack space. Any other
+//! call a function makes to itself grows the stack, and is reported by
+//! `--warn-non-tail-recursion`. Only a function's own body is searched, not
+//! the bodies of its closures.
+
+use super::call_graph::visit_block;
+use super::*;
+
+/// Calls returned directly by a `return`, in the order they appear
+pub fn tail_calls(func: &Function) -> Vec<&Expr> {
+    let mut calls = Vec::new();
+    tail_calls_in(&func.body, &mut calls);
+    calls
+}
+
+fn tail_calls_in<'h>(block: &'h Block, calls: &mut Vec<&'h Expr>) {
+    for statement in &block.statements {
+        match statement {
+            Statement::Return(Some(e)) if matches!(e.kind, ExprKind::Call(..)) => calls.push(e),
+            Statement::If(_, then_block, else_block) => {
+                tail_calls_in(then_block, calls);
+                if let Some(else_block) = else_block {
+                    tail_calls_in(else_block, calls);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Calls a function makes to itself in tail position, which can reuse its
+/// stack frame. References and function values passed as arguments may
+/// point into the frame, so calls passing them are left out
+pub fn self_tail_calls(func: &Function) -> Vec<&Expr> {
+    tail_calls(func)
+        .into_iter()
+        .filter(|e| match &e.kind {
+            ExprKind::Call(callee, args) => {
+                calls_itself(func, callee)
+                    && args
+                        .iter()
+                        .all(|a| !matches!(a.ty, Type::Reference(_) | Type::Function(_)))
+            }
+            _ => false,
+        })
+        .collect()
+}
+
+/// Calls a function makes to itself outside tail position
+pub fn non_tail_recursion(func: &Function) -> Vec<&Expr> {
+    let tail = tail_calls(func);
+
+    let mut calls = Vec::new();
+    visit_block(&func.body, &mut |expr| {
+        if let ExprKind::Call(callee, _) = &expr.kind {
+            if calls_itself(func, callee) && !tail.iter().any(|t| std::ptr::eq(*t, expr)) {
+                calls.push(expr);
+            }
+        }
+    });
+    calls
+}
+
+/// Does a call call the function making it?
+/// Calls through function values, and methods of a generic `Self`, may not
+fn calls_itself(func: &Function, callee: &Callee) -> bool {
+    match callee {
+        Callee::Function(name, _) => *name == func.name,
+        Callee::Method {
+            module,
+            trait_name,
+            method,
+            self_ty,
+        } if self_ty.params().is_empty() => {
+            func.name == impl_method_name(module.as_deref(), self_ty, trait_name, method)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hir::tests::lower;
+
+    #[test]
+    fn recursion() {
+        let src = r#"fn gcd(a: int, b: int) -> int {
+            if b == 0 {
+                return a;
+            }
+            return gcd(b, a % b);
+        }
+
+        fn fact(n: int) -> int {
+            if n < 2 {
+                return 1;
+            }
+            return n * fact(n - 1);
+        }
+
+        fn through(n: int, p: &int) -> int {
+            if n == 0 {
+                return *p;
+            }
+            return through(n - 1, p);
+        }
+
+        fn main() -> int {
+            let x: int = 1;
+            return gcd(fact(4), through(2, &x));
+        }"#;
+
+        let module = lower(src);
+        let function = |name: &str| module.functions.iter().find(|f| f.name == name).unwrap();
+        let (gcd, fact, through, main) = (
+            function("gcd"),
+            function("fact"),
+            function("through"),
+            function("main"),
+        );
+
+        assert_eq!(self_tail_calls(gcd).len(), 1);
+        assert!(non_tail_recursion(gcd).is_empty());
+
+        assert!(self_tail_calls(fact).is_empty());
+        assert_eq!(non_tail_recursion(fact).len(), 1);
+
+        // a tail call, but passing a reference
+        assert_eq!(tail_calls(through).len(), 1);
+        assert!(self_tail_calls(through).is_empty());
+        assert!(non_tail_recursion(through).is_empty());
+
+        assert_eq!(tail_calls(main).len(), 1);
+        assert!(self_tail_calls(main).is_empty());
+    }
+}
@@ -3,6 +3,7 @@
 
 use anyhow::{anyhow, Error, Result};
 
+use crate::ast::PrimitiveKind;
 use crate::errors::LangError;
 use crate::token::{Span, Token, TokenKind};
 
@@ -41,83 +42,202 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    /// Skip comments
-    fn skip_comments(&mut self) {
-        if self.peek() == Some('/') && self.src[self.pos + 1..].chars().next() == Some('/') {
-            while let Some(ch) = self.peek() {
-                if ch == '\n' {
-                    break;
-                } else {
-                    self.advance();
+    /// Skip whitespace and comments, which may be interleaved
+    /// Stops at doc comments, which are tokens
+    fn skip_trivia(&mut self) -> Result<()> {
+        loop {
+            self.skip_whitespace();
+
+            let rest = &self.src[self.pos..];
+            if rest.starts_with("//") && !is_doc_comment(rest) {
+                self.skip_line_comment();
+            } else if rest.starts_with("/*") {
+                self.skip_block_comment()?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Skip a `//` comment, up to the newline
+    fn skip_line_comment(&mut self) {
+        self.advance_while(|ch| ch != '\n');
+    }
+
+    /// Skip a `/* */` comment, which may contain nested block comments
+    fn skip_block_comment(&mut self) -> Result<()> {
+        let start = self.pos;
+        let mut depth = 0;
+
+        loop {
+            let rest = &self.src[self.pos..];
+            if rest.starts_with("/*") {
+                depth += 1;
+                self.pos += 2;
+            } else if rest.starts_with("*/") {
+                depth -= 1;
+                self.pos += 2;
+                if depth == 0 {
+                    return Ok(());
                 }
+            } else if self.peek().is_some() {
+                self.advance();
+            } else {
+                return Err(anyhow!(LangError::UnterminatedComment(Span {
+                    start,
+                    end: start + 2
+                })));
+            }
+        }
+    }
+
+    /// Lex a `///` doc comment, keeping the text after the slashes
+    fn lex_doc_comment(&mut self) -> Token {
+        let start = self.pos;
+        self.advance_while(|ch| ch != '\n');
+        let end = self.pos;
+
+        let text = &self.src[start + 3..end];
+        let text = text.strip_prefix(' ').unwrap_or(text).trim_end();
+
+        Token {
+            kind: TokenKind::DocComment(text.to_string()),
+            span: Span { start, end },
+        }
+    }
+
+    /// Peek at the character after the next
+    fn peek_second(&self) -> Option<char> {
+        self.src[self.pos..].chars().nth(1)
+    }
+
+    /// Advance past characters matching the predicate
+    fn advance_while(&mut self, predicate: impl Fn(char) -> bool) {
+        while let Some(ch) = self.peek() {
+            if predicate(ch) {
+                self.advance();
+            } else {
+                break;
             }
         }
     }
 
     /// Lex a number
-    /// Supports integers and floats
+    /// Supports integers in decimal, hex (`0x`), binary (`0b`) and octal (`0o`),
+    /// floats with an optional exponent (`1.5e-3`), `_` separators,
+    /// and integer type suffixes (`5u8`)
     fn lex_number(&mut self) -> Result<Token> {
         let start = self.pos;
-        let mut has_decimal = false;
-        while let Some(ch) = self.peek() {
-            if ch.is_digit(10) {
+
+        let radix = match (self.peek(), self.peek_second()) {
+            (Some('0'), Some('x')) => 16,
+            (Some('0'), Some('b')) => 2,
+            (Some('0'), Some('o')) => 8,
+            _ => 10,
+        };
+        if radix != 10 {
+            self.advance();
+            self.advance();
+        }
+
+        // binary and octal take any decimal digit, so invalid ones can be reported
+        let digits_start = self.pos;
+        match radix {
+            16 => self.advance_while(|ch| ch.is_ascii_hexdigit() || ch == '_'),
+            _ => self.advance_while(|ch| ch.is_ascii_digit() || ch == '_'),
+        }
+
+        let mut is_float = false;
+        if radix == 10 {
+            if self.peek() == Some('.') {
+                is_float = true;
                 self.advance();
-            } else if ch == '.' {
-                if has_decimal {
+                self.advance_while(|ch| ch.is_ascii_digit() || ch == '_');
+
+                // a second decimal point
+                if self.peek() == Some('.') {
+                    let dot = self.pos;
                     self.advance();
-                    let val = &self.src[self.pos - 1..self.pos];
-
-                    // advance until whitespace
-                    while let Some(ch) = self.peek() {
-                        if ch.is_whitespace() {
-                            break;
-                        } else {
-                            self.advance();
-                        }
-                    }
+                    self.advance_while(|ch| !ch.is_whitespace());
 
                     return Err(anyhow!(LangError::UnexpectedCharacter(
-                        val.to_string(),
+                        ".".to_string(),
                         Span {
-                            start,
-                            end: self.pos
+                            start: dot,
+                            end: dot + 1
                         }
                     )));
-                } else {
-                    has_decimal = true;
+                }
+            }
+
+            // exponent, only if digits follow
+            if let Some('e' | 'E') = self.peek() {
+                let rest = &self.src[self.pos + 1..];
+                let rest = rest.strip_prefix(['+', '-']).unwrap_or(rest);
+                if rest.starts_with(|ch: char| ch.is_ascii_digit()) {
+                    is_float = true;
                     self.advance();
+                    if let Some('+' | '-') = self.peek() {
+                        self.advance();
+                    }
+                    self.advance_while(|ch| ch.is_ascii_digit() || ch == '_');
                 }
-            } else {
-                break;
             }
         }
+        let digits_end = self.pos;
+
+        // integer type suffix
+        self.advance_while(|ch| ch.is_alphanumeric() || ch == '_');
         let end = self.pos;
 
-        let value = &self.src[start..end];
+        let literal = &self.src[start..end];
+        let invalid = |start, end| {
+            anyhow!(LangError::InvalidLiteral(
+                literal.to_string(),
+                Span { start, end }
+            ))
+        };
+
+        let digits = &self.src[digits_start..digits_end];
+        if radix != 10 {
+            if !digits.chars().any(|ch| ch != '_') {
+                // point at where the digits should be
+                return Err(invalid(digits_start, end.max(digits_start + 1)));
+            }
+
+            if let Some(offset) = digits.find(|ch: char| ch != '_' && !ch.is_digit(radix)) {
+                let digit_start = digits_start + offset;
+                return Err(invalid(digit_start, digit_start + 1));
+            }
+        }
 
-        if has_decimal {
-            match value.parse() {
-                Ok(f) => Ok(Token {
-                    kind: TokenKind::FloatLiteral(f),
-                    span: Span { start, end },
-                }),
-                Err(_) => Err(anyhow!(LangError::InvalidLiteral(
-                    value.to_string(),
-                    Span { start, end }
-                ))),
+        let suffix = match &self.src[digits_end..end] {
+            "" => None,
+            suffix => match PrimitiveKind::from_int_suffix(suffix) {
+                Some(kind) if !is_float => Some(kind),
+                _ => return Err(invalid(digits_end, end)),
+            },
+        };
+
+        let digits: String = digits.chars().filter(|&ch| ch != '_').collect();
+        let kind = if is_float {
+            match digits.parse::<f64>() {
+                // `float` is 32 bits, so larger values would become infinity
+                Ok(f) if (f as f32).is_finite() => TokenKind::FloatLiteral(f),
+                _ => return Err(invalid(start, end)),
             }
         } else {
-            match value.parse() {
-                Ok(i) => Ok(Token {
-                    kind: TokenKind::IntLiteral(i),
-                    span: Span { start, end },
-                }),
-                Err(_) => Err(anyhow!(LangError::InvalidLiteral(
-                    value.to_string(),
-                    Span { start, end }
-                ))),
+            match u64::from_str_radix(&digits, radix) {
+                Ok(i) => TokenKind::IntLiteral(i, suffix),
+                // too large for 64 bits
+                Err(_) => return Err(invalid(digits_start, digits_end)),
             }
-        }
+        };
+
+        Ok(Token {
+            kind,
+            span: Span { start, end },
+        })
     }
 
     /// Lex an operator (may or may not be multi-char)
@@ -128,12 +248,12 @@ impl<'a> Lexer<'a> {
         let mut end = self.pos;
 
         if let Some(ch) = self.peek() {
-            match ch {
-                '+' | '-' | '*' | '/' | '%' | '^' | '=' | '<' | '>' | '!' | '&' | '|' => {
-                    self.advance();
-                    end = self.pos;
-                }
-                _ => {}
+            // only join the next character if it forms a known operator,
+            // so `=&x` or `(*p)` lex as separate operators
+            let candidate = &self.src[start..self.pos + ch.len_utf8()];
+            if TokenKind::is_compound_operator(candidate) {
+                self.advance();
+                end = self.pos;
             }
         } else {
             return Err(anyhow!(LangError::UnexpectedEOF(Span { start, end })));
@@ -188,24 +308,31 @@ impl<'a> Iterator for Lexer<'a> {
     type Item = Result<Token>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.skip_whitespace();
-        self.skip_comments();
+        if let Err(e) = self.skip_trivia() {
+            return Some(Err(e));
+        }
+
+        if is_doc_comment(&self.src[self.pos..]) {
+            return Some(Ok(self.lex_doc_comment()));
+        }
 
         if let Some(ch) = self.peek() {
             let token = match ch {
-                '+' | '-' | '*' | '/' | '%' | '^' | '=' | '<' | '>' | '!' | '&' | '|' => {
+                '+' | '-' | '*' | '/' | '%' | '^' | '=' | '<' | '>' | '!' | '&' | '|' | ':' => {
                     self.lex_op()
                 }
                 '0'..='9' => self.lex_number(),
                 'a'..='z' | 'A'..='Z' | '_' => self.lex_word(),
                 '{' => Ok(self.lex_single_char(TokenKind::LBrace)),
                 '}' => Ok(self.lex_single_char(TokenKind::RBrace)),
+                '[' => Ok(self.lex_single_char(TokenKind::LBracket)),
+                ']' => Ok(self.lex_single_char(TokenKind::RBracket)),
+                '#' => Ok(self.lex_single_char(TokenKind::Hash)),
                 '(' => Ok(self.lex_single_char(TokenKind::LParen)),
                 ')' => Ok(self.lex_single_char(TokenKind::RParen)),
                 ';' => Ok(self.lex_single_char(TokenKind::Semicolon)),
                 ',' => Ok(self.lex_single_char(TokenKind::Comma)),
                 '.' => Ok(self.lex_single_char(TokenKind::Dot)),
-                ':' => Ok(self.lex_single_char(TokenKind::Colon)),
                 _ => {
                     // try ident/keyword
                     if ch.is_alphabetic() {
@@ -229,6 +356,11 @@ impl<'a> Iterator for Lexer<'a> {
     }
 }
 
+/// `///` starts a doc comment, but `////` is an ordinary comment
+fn is_doc_comment(src: &str) -> bool {
+    src.starts_with("///") && !src.starts_with("////")
+}
+
 /// Consumes and partitions the Lexer iterator into 2 `vec`s for tokens and errors.
 pub fn consume_lexer(lexer: Lexer) -> (Vec<Token>, Vec<Error>) {
     let mut tokens = Vec::new();
@@ -298,4 +430,155 @@ mod tests {
 
         assert_eq!(tokens.len(), 20);
     }
+
+    #[test]
+    fn lex_int_suffix() {
+        let src = "255u8 as i64 5x";
+        let lexer = Lexer::new(src);
+        let (tokens, errors) = consume_lexer(lexer);
+
+        pretty_print_tokens(&tokens);
+
+        // `5x` is not a valid suffix
+        assert_eq!(errors.len(), 1);
+
+        let kinds: Vec<TokenKind> = tokens.into_iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds[0],
+            TokenKind::IntLiteral(255, Some(PrimitiveKind::U8))
+        );
+        assert_eq!(kinds[1], TokenKind::As);
+        assert_eq!(kinds[2], TokenKind::I64);
+    }
+
+    #[test]
+    fn lex_number_formats() {
+        let src =
+            "0xff 0b1010_1010 0o17 1_000_000 3000000000 1.5e-3 2E10 3.4e38 0x10u8 18446744073709551615";
+        let lexer = Lexer::new(src);
+        let (tokens, errors) = consume_lexer(lexer);
+
+        assert_eq!(errors.len(), 0);
+
+        let kinds: Vec<TokenKind> = tokens.into_iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::IntLiteral(0xff, None),
+                TokenKind::IntLiteral(0b1010_1010, None),
+                TokenKind::IntLiteral(0o17, None),
+                TokenKind::IntLiteral(1_000_000, None),
+                TokenKind::IntLiteral(3_000_000_000, None),
+                TokenKind::FloatLiteral(1.5e-3),
+                TokenKind::FloatLiteral(2e10),
+                TokenKind::FloatLiteral(3.4e38),
+                TokenKind::IntLiteral(0x10, Some(PrimitiveKind::U8)),
+                TokenKind::IntLiteral(u64::MAX, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_literal_spans() {
+        // (source, offending span)
+        let cases = [
+            ("0b1021", 4..5),
+            ("0o78", 3..4),
+            ("0x", 2..3),
+            ("12abc", 2..5),
+            ("1.5u8", 3..5),
+            ("18446744073709551616", 0..20),
+            ("1e300", 0..5),
+            ("1e400", 0..5),
+            ("3.5e38", 0..6),
+        ];
+
+        for (src, expected) in cases {
+            let (_, errors) = consume_lexer(Lexer::new(src));
+            assert_eq!(errors.len(), 1, "{src}");
+
+            match errors[0].downcast_ref::<LangError>() {
+                Some(LangError::InvalidLiteral(literal, span)) => {
+                    assert_eq!(literal, src);
+                    assert_eq!(span.start..span.end, expected, "{src}");
+                }
+                other => panic!("unexpected error for {src}: {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn lex_comments() {
+        let src = "// one
+        // two
+
+        /* block /* nested */ still comment */ let /**/ x
+        //// not a doc comment
+        /// doc line
+        fn";
+        let (tokens, errors) = consume_lexer(Lexer::new(src));
+
+        assert_eq!(errors.len(), 0);
+
+        let kinds: Vec<TokenKind> = tokens.into_iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Let,
+                TokenKind::Ident("x".to_string()),
+                TokenKind::DocComment("doc line".to_string()),
+                TokenKind::Fn,
+            ]
+        );
+    }
+
+    #[test]
+    fn unterminated_comment() {
+        let src = "let x /* outer /* inner */";
+        let (tokens, errors) = consume_lexer(Lexer::new(src));
+
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(errors.len(), 1);
+
+        match errors[0].downcast_ref::<LangError>() {
+            Some(LangError::UnterminatedComment(span)) => assert_eq!(span.start..span.end, 6..8),
+            other => panic!("unexpected error: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn lex_logical_operators() {
+        let src = "a&&b||!c ==-1";
+        let lexer = Lexer::new(src);
+        let (tokens, errors) = consume_lexer(lexer);
+
+        pretty_print_tokens(&tokens);
+
+        assert_eq!(errors.len(), 0);
+
+        let kinds: Vec<TokenKind> = tokens.into_iter().map(|t| t.kind).collect();
+        assert_eq!(kinds[1], TokenKind::AmpersandAmpersand);
+        assert_eq!(kinds[3], TokenKind::PipePipe);
+        assert_eq!(kinds[4], TokenKind::Bang);
+        assert_eq!(kinds[6], TokenKind::EqualsEquals);
+        assert_eq!(kinds[7], TokenKind::Minus);
+    }
+
+    #[test]
+    fn lex_references() {
+        let src = "let r: &mut int =&mut x; *r = *r+1;";
+        let lexer = Lexer::new(src);
+        let (tokens, errors) = consume_lexer(lexer);
+
+        pretty_print_tokens(&tokens);
+
+        assert_eq!(errors.len(), 0);
+
+        let kinds: Vec<TokenKind> = tokens.into_iter().map(|t| t.kind).collect();
+        assert_eq!(kinds[3], TokenKind::Ampersand);
+        assert_eq!(kinds[4], TokenKind::Mut);
+        assert_eq!(kinds[6], TokenKind::Equals);
+        assert_eq!(kinds[7], TokenKind::Ampersand);
+        assert_eq!(kinds[11], TokenKind::Star);
+    }
 }
@@ -18,6 +18,8 @@ pub mod token;
 pub mod parser;
 pub mod ast;
 pub mod semantic_analysis;
+pub mod hir;
+pub mod mir;
 pub mod frontend;
 pub mod codegen;
 
@@ -52,6 +54,33 @@ pub struct Args {
     /// Print ASM?
     #[options(help = "Print ASM")]
     asm: bool,
+
+    /// Build without debug checks
+    #[options(help = "Build without debug checks")]
+    release: bool,
+
+    /// Check arithmetic even in release builds
+    #[options(help = "Panic on integer overflow and division by zero (default unless --release)")]
+    checked_arith: bool,
+
+    /// What to output
+    #[options(no_short, help = "What to output: `exe` (default) or `mir`")]
+    emit: Option<frontend::Emit>,
+
+    /// Optimisation level
+    #[options(
+        short = "O",
+        long = "opt-level",
+        help = "Optimisation level: 0 (default), 1, 2 or s"
+    )]
+    opt_level: Option<frontend::OptLevel>,
+
+    /// Warn about recursion outside tail position
+    #[options(
+        no_short,
+        help = "Warn when a function calls itself outside tail position"
+    )]
+    warn_non_tail_recursion: bool,
 }
 
 fn compile(opts: Args) -> PathBuf {
@@ -61,7 +90,12 @@ fn compile(opts: Args) -> PathBuf {
         print_ast: opts.ast,
         print_ir: opts.ir,
         print_asm: opts.asm,
+        checked_arith: opts.checked_arith || !opts.release,
+        emit: opts.emit.unwrap_or_default(),
+        opt_level: opts.opt_level.unwrap_or_default(),
+        warn_non_tail_recursion: opts.warn_non_tail_recursion,
     };
+    let emit = options.emit;
 
     let mut compiler = frontend::Compiler::new(options);
 
@@ -72,7 +106,10 @@ fn compile(opts: Args) -> PathBuf {
     let out = Path::new(&out_str).to_path_buf();
 
     match compiler.compile(out.clone()) {
-        Ok(_) => println!("Compiled {} to {}", opts.file, out_str),
+        Ok(_) if emit == frontend::Emit::Executable => {
+            println!("Compiled {} to {}", opts.file, out_str)
+        }
+        Ok(_) => {}
         Err(e) => {
             eprintln!("Error: {}", e);
             std::process::exit(1);
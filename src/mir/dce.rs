@@ -0,0 +1,161 @@
+//! Removes code that can never run or whose result is never used.
+//!
+//! Functions are kept if `main` may call them, directly or through other
+//! functions, closures and function values. Within a function, instructions
+//! defining unused values are dropped unless they have an effect. Integer
+//! arithmetic is kept, as it may panic with checked arithmetic.
+
+use std::collections::HashSet;
+
+use anyhow::Result;
+use log::debug;
+
+use super::*;
+
+/// Drops unused functions and instructions
+pub struct DeadCodeElimination;
+
+impl Pass for DeadCodeElimination {
+    fn name(&self) -> &'static str {
+        "dead-code-elimination"
+    }
+
+    fn run(&self, program: &mut Program) -> Result<()> {
+        // first, as unused closures and function values keep no function alive
+        for func in &mut program.functions {
+            remove_dead_insts(func);
+        }
+
+        let reachable = reachable_functions(program);
+        program.functions.retain(|f| {
+            let keep = reachable.contains(&f.name);
+            if !keep {
+                debug!("Removing unused function: {}", f.name);
+            }
+            keep
+        });
+
+        Ok(())
+    }
+}
+
+/// Names of the functions `main` may call
+pub fn reachable_functions(program: &Program) -> HashSet<String> {
+    let mut reachable = HashSet::new();
+    let mut queue = vec!["main".to_string()];
+
+    while let Some(name) = queue.pop() {
+        if reachable.contains(&name) {
+            continue;
+        }
+
+        if let Some(func) = program.functions.iter().find(|f| f.name == name) {
+            for inst in func.blocks.iter().flat_map(|b| &b.insts) {
+                match &inst.kind {
+                    InstKind::Call(callee, _)
+                    | InstKind::FunctionValue(callee)
+                    | InstKind::Closure(callee, _) => queue.push(callee.clone()),
+                    _ => {}
+                }
+            }
+        }
+        reachable.insert(name);
+    }
+
+    reachable
+}
+
+/// Could removing an unused instruction change what the program does?
+fn is_needed(func: &Function, kind: &InstKind) -> bool {
+    match kind {
+        InstKind::Binary(op, lhs, _) => op.is_arithmetic() && func.value_type(*lhs).is_integer(),
+        InstKind::Unary(UnaryOp::Neg, v) => func.value_type(*v).is_integer(),
+        kind => kind.has_effects(),
+    }
+}
+
+/// Remove instructions whose values are unused, until none are left
+fn remove_dead_insts(func: &mut Function) {
+    loop {
+        let mut used = HashSet::new();
+        for block in &func.blocks {
+            for inst in &block.insts {
+                used.extend(inst.kind.operands());
+            }
+            if let Terminator::Branch(c, _, _) | Terminator::Return(Some(c)) = block.terminator {
+                used.insert(c);
+            }
+        }
+
+        let mut removed = false;
+        for i in 0..func.blocks.len() {
+            let insts = std::mem::take(&mut func.blocks[i].insts);
+            let before = insts.len();
+            let kept: Vec<Inst> = insts
+                .into_iter()
+                .filter(|inst| match inst.result {
+                    Some(result) => used.contains(&result) || is_needed(func, &inst.kind),
+                    None => true,
+                })
+                .collect();
+            removed |= kept.len() < before;
+            func.blocks[i].insts = kept;
+        }
+
+        if !removed {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mir::tests::lower;
+
+    #[test]
+    fn removes_unused() {
+        let src = r#"fn main() -> int {
+            let unused: int = double(4) * 2;
+            let f = |x: int| -> int { return x; };
+            let cmp: bool = 1 < 2;
+            return 1;
+        }
+
+        fn double(x: int) -> int { return x * 2; }
+
+        fn never() -> int { return helper(); }
+
+        fn helper() -> int { return 0; }"#;
+
+        let mut program = lower(src);
+        DeadCodeElimination.run(&mut program).unwrap();
+
+        // the call is kept for its effects, and its multiplication may panic
+        let expected = "fn main() -> int {
+bb0:
+    %0: int = const 4
+    %1: int = call double(%0)
+    %2: int = const 2
+    %3: int = mul %1, %2
+    %8: int = const 1
+    return %8
+
+bb1:
+    unreachable
+}
+
+fn double(int) -> int {
+bb0:
+    %0: int = param 0
+    %1: int = const 2
+    %2: int = mul %0, %1
+    return %2
+
+bb1:
+    unreachable
+}
+";
+        assert_eq!(program.to_string(), expected);
+    }
+}
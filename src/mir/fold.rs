@@ -0,0 +1,192 @@
+//! Folds instructions whose operands are constants.
+//!
+//! Immutable `let` bindings are SSA values, so constants propagate through
+//! them as their uses are folded. Branches on a constant become jumps,
+//! leaving the other side for [`RemoveUnreachableBlocks`] to drop.
+//!
+//! Operations that would overflow or divide by zero are left for runtime,
+//! so they still panic or wrap as they would unoptimised.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+
+use super::*;
+use crate::semantic_analysis::const_eval;
+
+/// Replaces instructions on constants with their result
+pub struct ConstantFolding;
+
+impl Pass for ConstantFolding {
+    fn name(&self) -> &'static str {
+        "constant-folding"
+    }
+
+    fn run(&self, program: &mut Program) -> Result<()> {
+        for func in &mut program.functions {
+            fold_function(func);
+        }
+
+        Ok(())
+    }
+}
+
+fn fold_function(func: &mut Function) {
+    let mut constants = HashMap::new();
+
+    // a use may come in an earlier block than its definition, so fold
+    // until nothing changes
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in 0..func.blocks.len() {
+            for i in 0..func.blocks[block].insts.len() {
+                let inst = &func.blocks[block].insts[i];
+                let result = match inst.result {
+                    Some(result) => result,
+                    None => continue,
+                };
+                if let InstKind::Const(c) = inst.kind {
+                    constants.insert(result, c);
+                    continue;
+                }
+
+                if let Some(value) = fold(func, &inst.kind, result, &constants, &inst.span) {
+                    func.blocks[block].insts[i].kind = InstKind::Const(value);
+                    constants.insert(result, value);
+                    changed = true;
+                }
+            }
+
+            let block = &mut func.blocks[block];
+            if let Terminator::Branch(c, then, els) = block.terminator {
+                if let Some(ConstValue::Bool(b)) = constants.get(&c) {
+                    block.terminator = Terminator::Jump(if *b { then } else { els });
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    remove_unused_constants(func);
+}
+
+/// The result of an instruction, if its operands are constants
+fn fold(
+    func: &Function,
+    kind: &InstKind,
+    result: Value,
+    constants: &HashMap<Value, ConstValue>,
+    span: &Span,
+) -> Option<ConstValue> {
+    match kind {
+        InstKind::Unary(UnaryOp::Neg, v) => const_eval::negate(*constants.get(v)?, span).ok(),
+        InstKind::Unary(UnaryOp::Not, v) => match constants.get(v)? {
+            ConstValue::Bool(b) => Some(ConstValue::Bool(!b)),
+            _ => None,
+        },
+        InstKind::Binary(op, lhs, rhs) => {
+            let (lhs, rhs) = (*constants.get(lhs)?, *constants.get(rhs)?);
+            const_eval::binary(op, lhs, rhs, span).ok()
+        }
+        InstKind::Cast(v) => match func.value_type(result) {
+            Type::Primitive(p) => Some(constants.get(v)?.cast(p.kind)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Remove constants left unused once their uses are folded
+fn remove_unused_constants(func: &mut Function) {
+    let mut used = HashSet::new();
+    for block in &func.blocks {
+        for inst in &block.insts {
+            used.extend(inst.kind.operands());
+        }
+        if let Terminator::Branch(c, _, _) | Terminator::Return(Some(c)) = block.terminator {
+            used.insert(c);
+        }
+    }
+
+    for block in &mut func.blocks {
+        block.insts.retain(|inst| match (&inst.kind, inst.result) {
+            (InstKind::Const(_), Some(result)) => used.contains(&result),
+            _ => true,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mir::tests::lower;
+
+    #[test]
+    fn folds_through_lets() {
+        let src = r#"fn main() -> int {
+            let a: int = 5 + 2;
+            let b: int = 6;
+            let c: int = a + b;
+            return -c * 2;
+        }"#;
+
+        let mut program = lower(src);
+        ConstantFolding.run(&mut program).unwrap();
+
+        let expected = "fn main() -> int {
+bb0:
+    %7: int = const -26
+    return %7
+
+bb1:
+    unreachable
+}
+";
+        assert_eq!(program.to_string(), expected);
+    }
+
+    #[test]
+    fn branches() {
+        let src = r#"fn main() -> int {
+            let debug: bool = 1 > 2;
+            if debug {
+                return 1;
+            }
+            return 2;
+        }"#;
+
+        let mut program = lower(src);
+        ConstantFolding.run(&mut program).unwrap();
+        RemoveUnreachableBlocks.run(&mut program).unwrap();
+
+        let expected = "fn main() -> int {
+bb0:
+    jump bb1
+
+bb1:
+    %4: int = const 2
+    return %4
+}
+";
+        assert_eq!(program.to_string(), expected);
+    }
+
+    #[test]
+    fn overflow_left_for_runtime() {
+        let src = r#"fn main() -> int {
+            let a: i8 = 100;
+            let b: i8 = a + a;
+            return b as int;
+        }"#;
+
+        let mut program = lower(src);
+        ConstantFolding.run(&mut program).unwrap();
+
+        let main = &program.functions[0];
+        assert!(main.blocks[0]
+            .insts
+            .iter()
+            .any(|inst| matches!(inst.kind, InstKind::Binary(BinaryOperatorKind::Add, ..))));
+    }
+}
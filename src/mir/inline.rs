@@ -0,0 +1,416 @@
+//! Inlines calls to small functions.
+//!
+//! A call is replaced by a copy of the callee's blocks, placed right after
+//! the block making the call so values are still defined before their uses.
+//! Callees are inlined into first, so a chain of small helpers is flattened
+//! in one run. Calls to recursive functions are never inlined, so neither is
+//! a function into itself.
+//!
+//! `#[inline]` inlines a function whatever its size, and `#[inline(never)]`
+//! keeps every call to it.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use log::debug;
+
+use super::*;
+
+/// Size of the largest function inlined by default, in instructions
+pub const INLINE_THRESHOLD: usize = 16;
+
+/// Replaces calls to small functions with their body
+pub struct Inliner {
+    /// Functions with at most this many instructions are inlined
+    pub threshold: usize,
+}
+
+impl Default for Inliner {
+    fn default() -> Self {
+        Self {
+            threshold: INLINE_THRESHOLD,
+        }
+    }
+}
+
+impl Pass for Inliner {
+    fn name(&self) -> &'static str {
+        "inline"
+    }
+
+    fn run(&self, program: &mut Program) -> Result<()> {
+        let recursive = recursive_functions(program);
+        let mut done = HashSet::new();
+        for i in 0..program.functions.len() {
+            self.inline_calls(program, i, &recursive, &mut done);
+        }
+
+        Ok(())
+    }
+}
+
+impl Inliner {
+    /// Should a call from one function to another be inlined?
+    fn should_inline(
+        &self,
+        caller: &Function,
+        callee: &Function,
+        recursive: &HashSet<String>,
+    ) -> bool {
+        // instructions keep their spans, which panics report against the caller's file
+        if recursive.contains(&callee.name) || callee.closure || callee.file != caller.file {
+            return false;
+        }
+
+        match callee.inline {
+            Some(Inline::Always) => true,
+            Some(Inline::Never) => false,
+            None => size(callee) <= self.threshold,
+        }
+    }
+
+    /// Inline the calls of a function, after inlining the calls of its callees
+    fn inline_calls(
+        &self,
+        program: &mut Program,
+        index: usize,
+        recursive: &HashSet<String>,
+        done: &mut HashSet<usize>,
+    ) {
+        if !done.insert(index) {
+            return;
+        }
+
+        // callees inlined are not recursive, so this always ends
+        let caller = &program.functions[index];
+        let inlined: Vec<usize> = callees(caller)
+            .filter_map(|name| program.functions.iter().position(|f| f.name == name))
+            .filter(|&i| self.should_inline(caller, &program.functions[i], recursive))
+            .collect();
+        for callee in inlined {
+            self.inline_calls(program, callee, recursive, done);
+        }
+
+        let (mut block, mut i) = (0, 0);
+        while block < program.functions[index].blocks.len() {
+            let func = &program.functions[index];
+            let callee = match func.blocks[block].insts.get(i) {
+                Some(inst) => match &inst.kind {
+                    InstKind::Call(name, _) => program
+                        .functions
+                        .iter()
+                        .find(|f| &f.name == name && self.should_inline(func, f, recursive)),
+                    _ => None,
+                },
+                None => {
+                    block += 1;
+                    i = 0;
+                    continue;
+                }
+            };
+
+            match callee {
+                Some(callee) => {
+                    debug!("Inlining {} into {}", callee.name, func.name);
+                    let callee = callee.clone();
+                    // the callee's calls are already inlined, so carry on after it
+                    block =
+                        inline_call(&mut program.functions[index], BlockId(block), i, &callee).0;
+                    i = 0;
+                }
+                None => i += 1,
+            }
+        }
+    }
+}
+
+/// Names of the functions that may call themselves, directly or through
+/// other functions
+pub fn recursive_functions(program: &Program) -> HashSet<String> {
+    let calls: HashMap<&str, Vec<&str>> = program
+        .functions
+        .iter()
+        .map(|f| (f.name.as_str(), callees(f).collect()))
+        .collect();
+
+    calls
+        .keys()
+        .filter(|&&name| {
+            let mut visited = HashSet::new();
+            let mut queue = calls[name].clone();
+            while let Some(callee) = queue.pop() {
+                if callee == name {
+                    return true;
+                }
+                if visited.insert(callee) {
+                    queue.extend(calls.get(callee).into_iter().flatten());
+                }
+            }
+            false
+        })
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// Names of the functions called directly
+fn callees(func: &Function) -> impl Iterator<Item = &str> {
+    func.blocks
+        .iter()
+        .flat_map(|b| &b.insts)
+        .filter_map(|inst| match &inst.kind {
+            InstKind::Call(name, _) => Some(name.as_str()),
+            _ => None,
+        })
+}
+
+/// Number of instructions, not counting parameters
+fn size(func: &Function) -> usize {
+    func.blocks
+        .iter()
+        .flat_map(|b| &b.insts)
+        .filter(|inst| !matches!(inst.kind, InstKind::Param(_)))
+        .count()
+}
+
+/// Replace the call at an index of a block with the body of the callee.
+/// Returns the block the rest of the caller continues in
+fn inline_call(func: &mut Function, block: BlockId, index: usize, callee: &Function) -> BlockId {
+    let call = func.blocks[block.0].insts[index].clone();
+    let args = match call.kind {
+        InstKind::Call(_, args) => args,
+        _ => unreachable!("Only calls are inlined"),
+    };
+
+    // the callee's blocks go right after the call, then the rest of the block
+    let entry = BlockId(block.0 + 1);
+    let after = BlockId(entry.0 + callee.blocks.len());
+    let shift = callee.blocks.len() + 1;
+    for b in &mut func.blocks {
+        b.terminator.map_successors(|b| {
+            if b.0 > block.0 {
+                BlockId(b.0 + shift)
+            } else {
+                b
+            }
+        });
+    }
+
+    let rest = func.blocks[block.0].insts.split_off(index + 1);
+    func.blocks[block.0].insts.pop();
+    let terminator = std::mem::replace(
+        &mut func.blocks[block.0].terminator,
+        Terminator::Jump(entry),
+    );
+
+    let vars: Vec<VarId> = callee
+        .vars
+        .iter()
+        .map(|var| {
+            func.vars.push(var.clone());
+            VarId(func.vars.len() - 1)
+        })
+        .collect();
+
+    let mut values = HashMap::new();
+    let returns = callee
+        .blocks
+        .iter()
+        .filter(|b| matches!(b.terminator, Terminator::Return(_)))
+        .count();
+    // with several returns, each writes its value for the rest of the caller to read
+    let result = match call.result {
+        Some(_) if returns > 1 => {
+            func.vars.push(Var {
+                name: String::new(),
+                ty: callee.ret_ty.clone(),
+                addressed: false,
+            });
+            Some(VarId(func.vars.len() - 1))
+        }
+        _ => None,
+    };
+
+    let mut blocks = Vec::new();
+    for b in &callee.blocks {
+        let mut insts = Vec::new();
+        for inst in &b.insts {
+            if let (InstKind::Param(i), Some(v)) = (&inst.kind, inst.result) {
+                values.insert(v, args[*i]);
+                continue;
+            }
+
+            let result = inst.result.map(|v| {
+                let new = func.new_value(callee.value_type(v).clone());
+                values.insert(v, new);
+                new
+            });
+            let mut kind = match &inst.kind {
+                InstKind::Read(var) => InstKind::Read(vars[var.0]),
+                InstKind::Write(var, v) => InstKind::Write(vars[var.0], *v),
+                InstKind::VarAddr(var) => InstKind::VarAddr(vars[var.0]),
+                kind => kind.clone(),
+            };
+            kind.map_operands(|v| values[&v]);
+            insts.push(Inst {
+                result,
+                kind,
+                span: inst.span.clone(),
+            });
+        }
+
+        let mut terminator = b.terminator.clone();
+        terminator.map_operand(|v| values[&v]);
+        terminator.map_successors(|b| BlockId(entry.0 + b.0));
+        if let Terminator::Return(value) = terminator {
+            if let (Some(var), Some(value)) = (result, value) {
+                insts.push(Inst {
+                    result: None,
+                    kind: InstKind::Write(var, value),
+                    span: call.span.clone(),
+                });
+            }
+            terminator = Terminator::Jump(after);
+        }
+
+        blocks.push(Block { insts, terminator });
+    }
+
+    // the rest of the block, reading the result if there are several returns
+    let mut rest_insts = Vec::new();
+    if let (Some(v), Some(var)) = (call.result, result) {
+        rest_insts.push(Inst {
+            result: Some(v),
+            kind: InstKind::Read(var),
+            span: call.span.clone(),
+        });
+    }
+    rest_insts.extend(rest);
+    blocks.push(Block {
+        insts: rest_insts,
+        terminator,
+    });
+
+    let tail = func.blocks.split_off(entry.0);
+    func.blocks.extend(blocks);
+    func.blocks.extend(tail);
+
+    // otherwise uses of the result become uses of the returned value
+    let returned = callee.blocks.iter().find_map(|b| match b.terminator {
+        Terminator::Return(Some(v)) => Some(values[&v]),
+        _ => None,
+    });
+    if let (Some(v), None, Some(returned)) = (call.result, result, returned) {
+        replace_uses(func, v, returned);
+    }
+
+    after
+}
+
+/// Replace every use of a value with another
+fn replace_uses(func: &mut Function, from: Value, to: Value) {
+    let replace = |v| if v == from { to } else { v };
+    for block in &mut func.blocks {
+        for inst in &mut block.insts {
+            inst.kind.map_operands(replace);
+        }
+        block.terminator.map_operand(replace);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mir::tests::lower;
+
+    #[test]
+    fn inlines_small_functions() {
+        let src = r#"fn add(a: int, b: int) -> int { return a + b; }
+
+        fn main() -> int {
+            return add(add(1, 2), 3);
+        }"#;
+
+        let mut program = lower(src);
+        Inliner::default().run(&mut program).unwrap();
+        ConstantFolding.run(&mut program).unwrap();
+        RemoveUnreachableBlocks.run(&mut program).unwrap();
+
+        let main = program.functions.iter().find(|f| f.name == "main").unwrap();
+        let expected = "fn main() -> int {
+bb0:
+    jump bb1
+
+bb1:
+    jump bb2
+
+bb2:
+    jump bb3
+
+bb3:
+    %6: int = const 6
+    jump bb4
+
+bb4:
+    return %6
+}";
+        assert_eq!(main.to_string(), expected);
+    }
+
+    #[test]
+    fn recursion_and_attributes() {
+        let src = r#"fn fact(n: int) -> int {
+            if n < 2 {
+                return 1;
+            }
+            return n * fact(n - 1);
+        }
+
+        fn even(n: int) -> bool {
+            if n == 0 {
+                return true;
+            }
+            return odd(n - 1);
+        }
+
+        fn odd(n: int) -> bool {
+            if n == 0 {
+                return false;
+            }
+            return even(n - 1);
+        }
+
+        #[inline(never)]
+        fn one() -> int { return 1; }
+
+        #[inline]
+        fn big(x: int) -> int {
+            let a: int = x * 2 + x * 3 + x * 4;
+            let b: int = a * 2 + a * 3 + a * 4;
+            return a + b * 2 + b * 3 + b * 4;
+        }
+
+        fn main() -> int {
+            if even(4) {
+                return fact(5) + one() + big(1);
+            }
+            return 0;
+        }"#;
+
+        let mut program = lower(src);
+        let recursive = recursive_functions(&program);
+        assert!(["fact", "even", "odd"]
+            .iter()
+            .all(|f| recursive.contains(*f)));
+        assert_eq!(recursive.len(), 3);
+
+        Inliner::default().run(&mut program).unwrap();
+        let calls: Vec<&str> = program
+            .functions
+            .iter()
+            .map(|f| (f.name.as_str(), callees(f).collect::<Vec<_>>()))
+            .filter(|(name, _)| *name == "main" || *name == "fact")
+            .flat_map(|(_, calls)| calls)
+            .collect();
+        assert_eq!(calls, ["fact", "even", "fact", "one"]);
+    }
+}
@@ -0,0 +1,804 @@
+//! Lowers the HIR of every module into MIR.
+//!
+//! Generic functions are instantiated for each list of type arguments they
+//! are called with, starting from the functions that aren't generic.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+use log::debug;
+
+use super::*;
+use crate::ast::ReferenceType;
+use crate::hir::{self, impl_method_name, BindingId, Callee, ClosureId, Expr, ExprKind};
+
+/// Lower every module of a program into one MIR program.
+/// With `stack_locals`, every binding is a variable, so codegen can keep it in memory
+pub fn lower_program(modules: &[hir::Module], stack_locals: bool) -> Result<Program> {
+    let mut functions = HashMap::new();
+    let mut queue = Vec::new();
+    for module in modules {
+        for func in &module.functions {
+            functions.insert(func.name.as_str(), (func, module.file.as_str()));
+            if !func.is_generic() {
+                queue.push(Instance {
+                    name: func.name.clone(),
+                    func: func.name.clone(),
+                    type_args: HashMap::new(),
+                });
+            }
+        }
+    }
+
+    let mut lowerer = ProgramLowerer {
+        functions,
+        instances: HashSet::new(),
+        queue,
+        stack_locals,
+        program: Program {
+            functions: Vec::new(),
+            statics: modules.iter().flat_map(|m| m.statics.clone()).collect(),
+        },
+    };
+
+    // in declaration order, then instances in the order they are first called
+    lowerer.queue.reverse();
+    while let Some(instance) = lowerer.queue.pop() {
+        lowerer.lower_instance(instance)?;
+    }
+
+    Ok(lowerer.program)
+}
+
+/// A function to lower, with the type of each of its type parameters
+struct Instance {
+    /// Symbol name, mangled for instances of generic functions
+    name: String,
+    /// Qualified name of the HIR function
+    func: String,
+    type_args: HashMap<String, Type>,
+}
+
+struct ProgramLowerer<'h> {
+    /// Every HIR function, and the file declaring it
+    functions: HashMap<&'h str, (&'h hir::Function, &'h str)>,
+    /// Symbol names of the instances lowered or queued
+    instances: HashSet<String>,
+    /// Functions still to lower, the next last
+    queue: Vec<Instance>,
+    /// Make every binding a variable
+    stack_locals: bool,
+    program: Program,
+}
+
+impl<'h> ProgramLowerer<'h> {
+    /// Lower a function and its closures
+    fn lower_instance(&mut self, instance: Instance) -> Result<()> {
+        debug!("Lowering to MIR: {}", instance.name);
+        let (func, file) = self.functions[instance.func.as_str()];
+
+        let mut builder = FunctionBuilder::new(self, func, file, &instance);
+        builder.lower_function()?;
+        let lowered = builder.finish();
+        self.program.functions.push(lowered);
+
+        for id in 0..func.closures.len() {
+            let mut builder = FunctionBuilder::new(self, func, file, &instance);
+            builder.lower_closure(ClosureId(id))?;
+            let lowered = builder.finish();
+            self.program.functions.push(lowered);
+        }
+
+        Ok(())
+    }
+
+    /// Get the symbol name of an instance of a generic function, queueing it the first time
+    fn instance(&mut self, func: &hir::Function, type_args: Vec<Type>) -> String {
+        // e.g. `math::max<int>`
+        let name = format!(
+            "{}<{}>",
+            func.name,
+            type_args
+                .iter()
+                .map(|ty| ty.to_string())
+                .collect::<Vec<String>>()
+                .join(",")
+        );
+
+        if self.instances.insert(name.clone()) {
+            self.queue.insert(
+                0,
+                Instance {
+                    name: name.clone(),
+                    func: func.name.clone(),
+                    type_args: func.type_params.iter().cloned().zip(type_args).collect(),
+                },
+            );
+        }
+
+        name
+    }
+}
+
+/// Name of the code of a closure, e.g. `main::{closure#0}`
+fn closure_name(function: &str, id: ClosureId) -> String {
+    format!("{}::{{closure#{}}}", function, id.0)
+}
+
+/// How a binding is accessed
+#[derive(Debug, Clone, Copy)]
+enum Local {
+    /// An immutable binding, which is just its value
+    Value(Value),
+    Var(VarId),
+    /// A variable in a heap cell at this address, as it is captured by reference
+    Pointer(Value),
+}
+
+/// Builds the MIR of a function, or of one of its closures
+struct FunctionBuilder<'l, 'h> {
+    program: &'l mut ProgramLowerer<'h>,
+    hir: &'h hir::Function,
+    /// Symbol name of the function, which its closures are named after
+    name: &'l str,
+    type_args: &'l HashMap<String, Type>,
+    func: Function,
+    /// The block instructions are added to
+    current: BlockId,
+    locals: HashMap<BindingId, Local>,
+    /// The block self tail calls jump back to, and the variable of each parameter
+    tail_loop: Option<(BlockId, Vec<VarId>)>,
+}
+
+impl<'l, 'h> FunctionBuilder<'l, 'h> {
+    fn new(
+        program: &'l mut ProgramLowerer<'h>,
+        hir: &'h hir::Function,
+        file: &str,
+        instance: &'l Instance,
+    ) -> Self {
+        let func = Function {
+            name: instance.name.clone(),
+            closure: false,
+            inline: hir.inline,
+            params: Vec::new(),
+            ret_ty: hir.ret_ty.substitute(&instance.type_args),
+            values: Vec::new(),
+            vars: Vec::new(),
+            blocks: vec![Block {
+                insts: Vec::new(),
+                terminator: Terminator::Unreachable,
+            }],
+            file: file.to_string(),
+            span: hir.span.clone(),
+        };
+
+        Self {
+            program,
+            hir,
+            name: &instance.name,
+            type_args: &instance.type_args,
+            func,
+            current: BlockId(0),
+            locals: HashMap::new(),
+            tail_loop: None,
+        }
+    }
+
+    fn finish(self) -> Function {
+        self.func
+    }
+
+    fn lower_function(&mut self) -> Result<()> {
+        // self tail calls assign the parameters, so they need variables
+        let tail_calls = !hir::self_tail_calls(self.hir).is_empty();
+        let mut param_vars = Vec::new();
+        for (i, param) in self.hir.params.iter().enumerate() {
+            let ty = self.binding_type(*param);
+            self.func.params.push(ty.clone());
+            let binding = self.hir.binding(*param);
+            let span = binding.span.clone();
+            let value = self.ins(InstKind::Param(i), ty.clone(), &span);
+            if tail_calls {
+                let var = self.new_var(&binding.name, ty, binding.addressed);
+                self.effect(InstKind::Write(var, value), &span);
+                self.locals.insert(*param, Local::Var(var));
+                param_vars.push(var);
+            } else {
+                self.declare(*param, value, &span);
+            }
+        }
+
+        if tail_calls {
+            let body = self.new_block();
+            self.terminate(Terminator::Jump(body));
+            self.current = body;
+            self.tail_loop = Some((body, param_vars));
+        }
+
+        // analysis guarantees every path returns, so the end is unreachable
+        self.lower_block(&self.hir.body)
+    }
+
+    /// Lower the body of a closure into a function taking its closure object
+    fn lower_closure(&mut self, id: ClosureId) -> Result<()> {
+        let closure = self.hir.closure(id);
+        self.func.name = closure_name(self.name, id);
+        self.func.closure = true;
+        self.func.inline = None;
+        self.func.ret_ty = closure.ret_ty.substitute(self.type_args);
+        self.func.span = closure.span.clone();
+
+        for (i, capture) in closure.captures.iter().enumerate() {
+            let ty = self.binding_type(capture.binding);
+            if capture.by_ref {
+                let ty = Type::Reference(ReferenceType {
+                    inner: Box::new(ty),
+                    mutable: true,
+                    span: Default::default(),
+                });
+                let ptr = self.ins(InstKind::Capture(i), ty, &closure.span);
+                self.locals.insert(capture.binding, Local::Pointer(ptr));
+            } else {
+                let value = self.ins(InstKind::Capture(i), ty, &closure.span);
+                self.declare(capture.binding, value, &closure.span);
+            }
+        }
+
+        for (i, param) in closure.params.iter().enumerate() {
+            let ty = self.binding_type(*param);
+            self.func.params.push(ty.clone());
+            let span = self.hir.binding(*param).span.clone();
+            let value = self.ins(InstKind::Param(i), ty, &span);
+            self.declare(*param, value, &span);
+        }
+
+        self.lower_block(&closure.body)
+    }
+
+    /// The type of a binding, instantiated
+    fn binding_type(&self, id: BindingId) -> Type {
+        self.hir.binding(id).ty.substitute(self.type_args)
+    }
+
+    /// Add an instruction defining a value of type `ty`
+    fn ins(&mut self, kind: InstKind, ty: Type, span: &Span) -> Value {
+        let value = self.func.new_value(ty);
+        self.push(Some(value), kind, span);
+        value
+    }
+
+    /// Add an instruction defining no value
+    fn effect(&mut self, kind: InstKind, span: &Span) {
+        self.push(None, kind, span);
+    }
+
+    fn push(&mut self, result: Option<Value>, kind: InstKind, span: &Span) {
+        self.func.blocks[self.current.0].insts.push(Inst {
+            result,
+            kind,
+            span: span.clone(),
+        });
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.func.blocks.push(Block {
+            insts: Vec::new(),
+            terminator: Terminator::Unreachable,
+        });
+        BlockId(self.func.blocks.len() - 1)
+    }
+
+    /// End the current block
+    fn terminate(&mut self, terminator: Terminator) {
+        self.func.blocks[self.current.0].terminator = terminator;
+    }
+
+    /// Bind a binding to its initial value
+    /// Only bindings that change or whose address is taken need a variable,
+    /// unless every local is kept on the stack
+    fn declare(&mut self, id: BindingId, value: Value, span: &Span) {
+        let binding = self.hir.binding(id);
+        if binding.boxed {
+            let ptr = self.new_cell(id, span);
+            self.effect(InstKind::Store(ptr, value), span);
+            return;
+        }
+
+        if !binding.mutable && !binding.addressed && !self.program.stack_locals {
+            self.locals.insert(id, Local::Value(value));
+            return;
+        }
+
+        let var = self.new_var(&binding.name, self.binding_type(id), binding.addressed);
+        self.effect(InstKind::Write(var, value), span);
+        self.locals.insert(id, Local::Var(var));
+    }
+
+    /// Allocate the heap cell of a boxed binding, returning its address
+    fn new_cell(&mut self, id: BindingId, span: &Span) -> Value {
+        let ty = Type::Reference(ReferenceType {
+            inner: Box::new(self.binding_type(id)),
+            mutable: true,
+            span: Default::default(),
+        });
+        let ptr = self.ins(InstKind::Alloc, ty, span);
+        self.locals.insert(id, Local::Pointer(ptr));
+        ptr
+    }
+
+    fn new_var(&mut self, name: &str, ty: Type, addressed: bool) -> VarId {
+        self.func.vars.push(Var {
+            name: name.to_string(),
+            ty,
+            addressed,
+        });
+        VarId(self.func.vars.len() - 1)
+    }
+
+    fn local(&self, id: BindingId) -> Result<Local> {
+        self.locals.get(&id).copied().ok_or_else(|| {
+            anyhow!(
+                "Variable `{}` not found during lowering",
+                self.hir.binding(id).name
+            )
+        })
+    }
+
+    fn lower_block(&mut self, block: &hir::Block) -> Result<()> {
+        for statement in &block.statements {
+            self.lower_statement(statement)?;
+        }
+
+        Ok(())
+    }
+
+    fn lower_statement(&mut self, statement: &hir::Statement) -> Result<()> {
+        match statement {
+            hir::Statement::Expr(e) => {
+                self.lower_expr(e)?;
+            }
+            hir::Statement::Let(binding, Some(e)) => {
+                let value = self.lower_expr(e)?;
+                self.declare(*binding, value, &e.span);
+            }
+            // assigned later, so it needs a variable even when immutable
+            hir::Statement::Let(id, None) if self.hir.binding(*id).boxed => {
+                self.new_cell(*id, &self.hir.binding(*id).span);
+            }
+            hir::Statement::Let(id, None) => {
+                let binding = self.hir.binding(*id);
+                let var = self.new_var(&binding.name, self.binding_type(*id), binding.addressed);
+                self.locals.insert(*id, Local::Var(var));
+            }
+            hir::Statement::Assign(place, e) => {
+                let value = self.lower_expr(e)?;
+                self.lower_assignment(place, value)?;
+            }
+            hir::Statement::If(condition, then_body, else_body) => {
+                let condition = self.lower_expr(condition)?;
+                let then_block = self.new_block();
+                let else_block = else_body.as_ref().map(|_| self.new_block());
+                let merge_block = self.new_block();
+
+                self.terminate(Terminator::Branch(
+                    condition,
+                    then_block,
+                    else_block.unwrap_or(merge_block),
+                ));
+
+                self.current = then_block;
+                self.lower_block(then_body)?;
+                self.terminate(Terminator::Jump(merge_block));
+
+                if let (Some(block), Some(body)) = (else_block, else_body) {
+                    self.current = block;
+                    self.lower_block(body)?;
+                    self.terminate(Terminator::Jump(merge_block));
+                }
+
+                self.current = merge_block;
+            }
+            hir::Statement::Return(Some(Expr {
+                kind: ExprKind::Call(callee, args),
+                ty,
+                span,
+            })) if self.tail_loop.is_some() => {
+                let terminator = match self.lower_call(callee, args)? {
+                    InstKind::Call(name, values) if self.is_tail_loop(&name, &values) => {
+                        let (body, vars) = self.tail_loop.clone().unwrap();
+                        for (var, value) in vars.into_iter().zip(values) {
+                            self.effect(InstKind::Write(var, value), span);
+                        }
+                        Terminator::Jump(body)
+                    }
+                    call => {
+                        let ty = ty.substitute(self.type_args);
+                        Terminator::Return(Some(self.ins(call, ty, span)))
+                    }
+                };
+                self.terminate(terminator);
+                self.current = self.new_block();
+            }
+            hir::Statement::Return(e) => {
+                let value = e.as_ref().map(|e| self.lower_expr(e)).transpose()?;
+                self.terminate(Terminator::Return(value));
+
+                // anything after a return is unreachable, but still needs a block
+                self.current = self.new_block();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Can a call in tail position jump back to the start of the function?
+    /// Not if it passes a reference, which may point into the frame it reuses
+    fn is_tail_loop(&self, callee: &str, args: &[Value]) -> bool {
+        callee == self.func.name
+            && args.iter().all(|v| {
+                !matches!(
+                    self.func.value_type(*v),
+                    Type::Reference(_) | Type::Function(_)
+                )
+            })
+    }
+
+    fn lower_assignment(&mut self, place: &Expr, value: Value) -> Result<()> {
+        match &place.kind {
+            ExprKind::Local(id) => match self.local(*id)? {
+                Local::Var(var) => self.effect(InstKind::Write(var, value), &place.span),
+                Local::Pointer(ptr) => self.effect(InstKind::Store(ptr, value), &place.span),
+                Local::Value(_) => {
+                    return Err(anyhow!(
+                        "Cannot assign to immutable `{}`",
+                        self.hir.binding(*id).name
+                    ))
+                }
+            },
+            ExprKind::Static(_) | ExprKind::Deref(_) => {
+                let ptr = self.lower_address(place)?;
+                self.effect(InstKind::Store(ptr, value), &place.span);
+            }
+            other => return Err(anyhow!("Cannot assign to {:?}", other)),
+        }
+
+        Ok(())
+    }
+
+    fn lower_expr(&mut self, expr: &Expr) -> Result<Value> {
+        let ty = expr.ty.substitute(self.type_args);
+        let span = &expr.span;
+
+        let kind = match &expr.kind {
+            ExprKind::Literal(value) => InstKind::Const(*value),
+            ExprKind::Local(id) => match self.local(*id)? {
+                Local::Value(value) => return Ok(value),
+                Local::Var(var) => InstKind::Read(var),
+                Local::Pointer(ptr) => InstKind::Load(ptr),
+            },
+            ExprKind::Static(_) => InstKind::Load(self.lower_address(expr)?),
+            ExprKind::Function(name) => InstKind::FunctionValue(name.clone()),
+            ExprKind::Call(callee, args) => self.lower_call(callee, args)?,
+            ExprKind::Closure(id) => {
+                let captures = &self.hir.closure(*id).captures;
+                let mut values = Vec::with_capacity(captures.len());
+                for capture in captures {
+                    let local = Expr {
+                        kind: ExprKind::Local(capture.binding),
+                        ty: self.hir.binding(capture.binding).ty.clone(),
+                        span: span.clone(),
+                    };
+                    values.push(match capture.by_ref {
+                        true => self.lower_address(&local)?,
+                        false => self.lower_expr(&local)?,
+                    });
+                }
+                InstKind::Closure(closure_name(self.name, *id), values)
+            }
+            ExprKind::Unary(op, e) => InstKind::Unary(*op, self.lower_expr(e)?),
+            ExprKind::Binary(op @ (BinaryOperatorKind::And | BinaryOperatorKind::Or), lhs, rhs) => {
+                return self.lower_short_circuit(*op, lhs, rhs, ty, span)
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                let lhs = self.lower_expr(lhs)?;
+                let rhs = self.lower_expr(rhs)?;
+                InstKind::Binary(*op, lhs, rhs)
+            }
+            ExprKind::Ref(e) => return self.lower_address(e),
+            ExprKind::Deref(e) => InstKind::Load(self.lower_expr(e)?),
+            ExprKind::Cast(e) => InstKind::Cast(self.lower_expr(e)?),
+        };
+
+        Ok(self.ins(kind, ty, span))
+    }
+
+    /// `a && b` or `a || b`, only evaluating `b` when `a` doesn't decide the result
+    fn lower_short_circuit(
+        &mut self,
+        op: BinaryOperatorKind,
+        lhs: &Expr,
+        rhs: &Expr,
+        ty: Type,
+        span: &Span,
+    ) -> Result<Value> {
+        let var = self.new_var("", ty.clone(), false);
+        let lhs = self.lower_expr(lhs)?;
+        self.effect(InstKind::Write(var, lhs), span);
+
+        let rhs_block = self.new_block();
+        let merge_block = self.new_block();
+        let (then_block, else_block) = match op {
+            BinaryOperatorKind::And => (rhs_block, merge_block),
+            _ => (merge_block, rhs_block),
+        };
+        self.terminate(Terminator::Branch(lhs, then_block, else_block));
+
+        self.current = rhs_block;
+        let rhs = self.lower_expr(rhs)?;
+        self.effect(InstKind::Write(var, rhs), span);
+        self.terminate(Terminator::Jump(merge_block));
+
+        self.current = merge_block;
+        Ok(self.ins(InstKind::Read(var), ty, span))
+    }
+
+    fn lower_call(&mut self, callee: &Callee, args: &[Expr]) -> Result<InstKind> {
+        let mut values = Vec::with_capacity(args.len());
+        for arg in args {
+            values.push(self.lower_expr(arg)?);
+        }
+
+        Ok(match callee {
+            Callee::Function(name, type_args) => {
+                let (func, _) = *self
+                    .program
+                    .functions
+                    .get(name.as_str())
+                    .ok_or_else(|| anyhow!("Function `{}` not found during lowering", name))?;
+
+                if func.is_generic() {
+                    let type_args = type_args
+                        .iter()
+                        .map(|ty| ty.substitute(self.type_args))
+                        .collect();
+                    InstKind::Call(self.program.instance(func, type_args), values)
+                } else {
+                    InstKind::Call(name.clone(), values)
+                }
+            }
+            Callee::Method {
+                module,
+                trait_name,
+                method,
+                self_ty,
+            } => {
+                let self_ty = self_ty.substitute(self.type_args);
+                let name = impl_method_name(module.as_deref(), &self_ty, trait_name, method);
+                InstKind::Call(name, values)
+            }
+            Callee::Value(f) => InstKind::CallIndirect(self.lower_expr(f)?, values),
+        })
+    }
+
+    /// Get the address of a place
+    /// Temporaries are spilled to a fresh variable
+    fn lower_address(&mut self, expr: &Expr) -> Result<Value> {
+        let ty = Type::Reference(ReferenceType {
+            inner: Box::new(expr.ty.substitute(self.type_args)),
+            mutable: true,
+            span: Default::default(),
+        });
+
+        match &expr.kind {
+            ExprKind::Static(name) => {
+                Ok(self.ins(InstKind::StaticAddr(name.clone()), ty, &expr.span))
+            }
+            ExprKind::Local(id) => match self.local(*id)? {
+                Local::Var(var) if self.func.var(var).addressed => {
+                    Ok(self.ins(InstKind::VarAddr(var), ty, &expr.span))
+                }
+                Local::Pointer(ptr) => Ok(ptr),
+                _ => Err(anyhow!(
+                    "Variable `{}` is not addressable",
+                    self.hir.binding(*id).name
+                )),
+            },
+            ExprKind::Deref(e) => self.lower_expr(e),
+            _ => {
+                let value = self.lower_expr(expr)?;
+                let var = self.new_var("", expr.ty.substitute(self.type_args), true);
+                self.effect(InstKind::Write(var, value), &expr.span);
+                Ok(self.ins(InstKind::VarAddr(var), ty, &expr.span))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lower a single-file program, without the blocks left after returns
+    fn lower(src: &str) -> Program {
+        let mut program = crate::mir::tests::lower(src);
+
+        let mut passes = PassManager::new();
+        passes.add(RemoveUnreachableBlocks);
+        passes.run(&mut program).expect("Failed to run passes");
+
+        program
+    }
+
+    #[test]
+    fn golden() {
+        let src = r#"static mut COUNT: int = 0;
+
+        fn max<T>(a: T, b: T) -> T {
+            if a > b {
+                return a;
+            }
+            return b;
+        }
+
+        fn main() -> int {
+            let mut n: u8 = 1;
+            let bump = |by: u8| -> u8 {
+                n = n + by;
+                n
+            };
+            COUNT = COUNT + 1;
+            return max(bump(2), 2) as int;
+        }"#;
+
+        let expected = "static mut COUNT: int = 0
+
+fn main() -> int {
+bb0:
+    %0: u8 = const 1
+    %1: &mut u8 = alloc
+    store %1, %0
+    %2: fn(u8) -> u8 = closure main::{closure#0}(%1)
+    %3: &mut int = static_addr COUNT
+    %4: int = load %3
+    %5: int = const 1
+    %6: int = add %4, %5
+    %7: &mut int = static_addr COUNT
+    store %7, %6
+    %8: u8 = const 2
+    %9: u8 = call_indirect %2(%8)
+    %10: u8 = const 2
+    %11: u8 = call max<u8>(%9, %10)
+    %12: int = cast %11
+    return %12
+}
+
+closure fn main::{closure#0}(u8) -> u8 {
+bb0:
+    %0: &mut u8 = capture 0
+    %1: u8 = param 0
+    %2: u8 = load %0
+    %3: u8 = add %2, %1
+    store %0, %3
+    %4: u8 = load %0
+    return %4
+}
+
+fn max<u8>(u8, u8) -> u8 {
+bb0:
+    %0: u8 = param 0
+    %1: u8 = param 1
+    %2: bool = gt %0, %1
+    branch %2, bb1, bb2
+
+bb1:
+    return %0
+
+bb2:
+    return %1
+}
+";
+        assert_eq!(lower(src).to_string(), expected);
+    }
+
+    #[test]
+    fn unreachable_blocks() {
+        let src = r#"fn main() -> int {
+            if true {
+                return 1;
+            } else {
+                return 2;
+            }
+        }"#;
+        let program = lower(src);
+        let main = &program.functions[0];
+
+        // the blocks after each return, and the merge block, are gone
+        assert_eq!(main.blocks.len(), 3);
+        assert!(matches!(
+            main.blocks[0].terminator,
+            Terminator::Branch(_, BlockId(1), BlockId(2))
+        ));
+        assert!(main.blocks[1..]
+            .iter()
+            .all(|b| matches!(b.terminator, Terminator::Return(Some(_)))));
+    }
+
+    #[test]
+    fn stack_locals() {
+        let src = r#"fn main() -> int {
+            let a: int = 1;
+            return a;
+        }"#;
+        let module = crate::hir::tests::lower(src);
+        let program = lower_program(&[module], true).unwrap();
+
+        let expected = "fn main() -> int {
+    var _0 a: int
+
+bb0:
+    %0: int = const 1
+    write _0, %0
+    %1: int = read _0
+    return %1
+
+bb1:
+    unreachable
+}
+";
+        assert_eq!(program.to_string(), expected);
+    }
+
+    #[test]
+    fn self_tail_calls() {
+        let src = r#"fn gcd(a: int, b: int) -> int {
+            if b == 0 {
+                return a;
+            }
+            return gcd(b, a % b);
+        }
+
+        fn main() -> int {
+            return gcd(12, 18);
+        }"#;
+        let program = lower(src);
+
+        let expected = "fn gcd(int, int) -> int {
+    var _0 a: int
+    var _1 b: int
+
+bb0:
+    %0: int = param 0
+    write _0, %0
+    %1: int = param 1
+    write _1, %1
+    jump bb1
+
+bb1:
+    %2: int = read _1
+    %3: int = const 0
+    %4: bool = eq %2, %3
+    branch %4, bb2, bb3
+
+bb2:
+    %5: int = read _0
+    return %5
+
+bb3:
+    %6: int = read _1
+    %7: int = read _0
+    %8: int = read _1
+    %9: int = rem %7, %8
+    write _0, %6
+    write _1, %9
+    jump bb1
+}";
+        assert_eq!(program.functions[0].to_string(), expected);
+
+        // a call from another function is still a call
+        assert!(matches!(
+            program.functions[1].blocks[0].insts[2].kind,
+            InstKind::Call(ref name, _) if name == "gcd"
+        ));
+    }
+}
@@ -0,0 +1,426 @@
+//! Declares the MIR, a control-flow graph of typed SSA values between the
+//! HIR and codegen.
+//!
+//! The whole program is lowered into one [`Program`] after every module is
+//! lowered into HIR. Generic functions are instantiated and closures become
+//! functions of their own, so every type is concrete and every call names
+//! the function it calls.
+//!
+//! Each instruction defines at most one [`Value`], which never changes.
+//! Variables that are assigned to or whose address is taken are [`Var`]s,
+//! read and written with instructions, and turned into SSA values by codegen.
+//!
+//! Optimisations are [`Pass`]es run over the program by a [`PassManager`].
+//! The `Display` format is stable, and printed by `--emit=mir`.
+
+mod dce;
+mod fold;
+mod inline;
+mod lower;
+mod pass;
+mod simplify;
+
+pub use self::dce::*;
+pub use self::fold::*;
+pub use self::inline::*;
+pub use self::lower::*;
+pub use self::pass::*;
+pub use self::simplify::*;
+
+use std::fmt;
+
+use crate::ast::{BinaryOperatorKind, Inline, Type};
+use crate::hir::{Static, UnaryOp};
+use crate::semantic_analysis::ConstValue;
+use crate::token::Span;
+
+/// Every function and static of the program
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    pub functions: Vec<Function>,
+    pub statics: Vec<Static>,
+}
+
+/// A function, an instance of a generic function, or the code of a closure
+#[derive(Debug, Clone)]
+pub struct Function {
+    /// Symbol name, e.g. `math::add`, `max<int>` or `main::{closure#0}`
+    pub name: String,
+    /// The code of a closure, passed its closure object before the parameters
+    pub closure: bool,
+    /// Its `#[inline]` attribute, if any. Closures have none
+    pub inline: Option<Inline>,
+    pub params: Vec<Type>,
+    pub ret_ty: Type,
+    /// Type of each value, by [`Value`]
+    pub values: Vec<Type>,
+    pub vars: Vec<Var>,
+    /// The entry block is first
+    pub blocks: Vec<Block>,
+    /// The file it was declared in, for panic locations
+    pub file: String,
+    pub span: Span,
+}
+
+/// A value defined by an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Value(pub usize);
+
+/// Index of a block in [`Function::blocks`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub usize);
+
+/// Index of a variable in [`Function::vars`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VarId(pub usize);
+
+/// A mutable or addressed variable
+#[derive(Debug, Clone)]
+pub struct Var {
+    /// Name in the source, empty for temporaries
+    pub name: String,
+    pub ty: Type,
+    /// Its address is taken, so it must live in memory
+    pub addressed: bool,
+}
+
+/// A straight-line sequence of instructions, ended by a terminator
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub insts: Vec<Inst>,
+    pub terminator: Terminator,
+}
+
+#[derive(Debug, Clone)]
+pub struct Inst {
+    /// The value defined, if any
+    pub result: Option<Value>,
+    pub kind: InstKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstKind {
+    /// The parameter at an index
+    Param(usize),
+    /// The captured variable at an index of a closure's object.
+    /// Variables captured by reference are their address
+    Capture(usize),
+    Const(ConstValue),
+    Read(VarId),
+    Write(VarId, Value),
+    /// The address of an addressed variable
+    VarAddr(VarId),
+    StaticAddr(String),
+    Load(Value),
+    /// `Store(address, value)`
+    Store(Value, Value),
+    Unary(UnaryOp, Value),
+    Binary(BinaryOperatorKind, Value, Value),
+    /// Conversion to the result's type
+    Cast(Value),
+    /// Call a function by name
+    Call(String, Vec<Value>),
+    /// Call a function value through its closure object
+    CallIndirect(Value, Vec<Value>),
+    /// The closure object of a named function
+    FunctionValue(String),
+    /// Allocate a closure object for the code of a closure and its captures
+    Closure(String, Vec<Value>),
+    /// Allocate an uninitialised heap cell for a value of the type the result points to
+    Alloc,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(BlockId),
+    /// `Branch(condition, then, else)`
+    Branch(Value, BlockId, BlockId),
+    Return(Option<Value>),
+    /// Never reached, e.g. after the last `return` of a function
+    Unreachable,
+}
+
+impl Function {
+    pub fn block(&self, id: BlockId) -> &Block {
+        &self.blocks[id.0]
+    }
+
+    pub fn var(&self, id: VarId) -> &Var {
+        &self.vars[id.0]
+    }
+
+    pub fn value_type(&self, value: Value) -> &Type {
+        &self.values[value.0]
+    }
+
+    /// Add a value of a type, to be defined by an instruction
+    pub fn new_value(&mut self, ty: Type) -> Value {
+        self.values.push(ty);
+        Value(self.values.len() - 1)
+    }
+}
+
+impl InstKind {
+    /// Values used by the instruction
+    pub fn operands(&self) -> Vec<Value> {
+        match self {
+            InstKind::Param(_)
+            | InstKind::Capture(_)
+            | InstKind::Const(_)
+            | InstKind::Read(_)
+            | InstKind::VarAddr(_)
+            | InstKind::StaticAddr(_)
+            | InstKind::FunctionValue(_)
+            | InstKind::Alloc => vec![],
+            InstKind::Write(_, v)
+            | InstKind::Load(v)
+            | InstKind::Unary(_, v)
+            | InstKind::Cast(v) => vec![*v],
+            InstKind::Store(a, b) | InstKind::Binary(_, a, b) => vec![*a, *b],
+            InstKind::Call(_, args) | InstKind::Closure(_, args) => args.clone(),
+            InstKind::CallIndirect(f, args) => std::iter::once(*f).chain(args.clone()).collect(),
+        }
+    }
+
+    /// Replace each value used by the instruction
+    pub fn map_operands(&mut self, mut f: impl FnMut(Value) -> Value) {
+        match self {
+            InstKind::Param(_)
+            | InstKind::Capture(_)
+            | InstKind::Const(_)
+            | InstKind::Read(_)
+            | InstKind::VarAddr(_)
+            | InstKind::StaticAddr(_)
+            | InstKind::FunctionValue(_)
+            | InstKind::Alloc => {}
+            InstKind::Write(_, v)
+            | InstKind::Load(v)
+            | InstKind::Unary(_, v)
+            | InstKind::Cast(v) => *v = f(*v),
+            InstKind::Store(a, b) | InstKind::Binary(_, a, b) => {
+                *a = f(*a);
+                *b = f(*b);
+            }
+            InstKind::Call(_, args) | InstKind::Closure(_, args) => {
+                args.iter_mut().for_each(|v| *v = f(*v))
+            }
+            InstKind::CallIndirect(func, args) => {
+                *func = f(*func);
+                args.iter_mut().for_each(|v| *v = f(*v));
+            }
+        }
+    }
+
+    /// Could the instruction do anything beyond defining its value?
+    pub fn has_effects(&self) -> bool {
+        matches!(
+            self,
+            InstKind::Write(..)
+                | InstKind::Store(..)
+                | InstKind::Call(..)
+                | InstKind::CallIndirect(..)
+        )
+    }
+}
+
+impl Terminator {
+    /// Blocks control may continue to
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(b) => vec![*b],
+            Terminator::Branch(_, then, els) => vec![*then, *els],
+            Terminator::Return(_) | Terminator::Unreachable => vec![],
+        }
+    }
+
+    /// Replace the condition or returned value
+    pub fn map_operand(&mut self, f: impl FnOnce(Value) -> Value) {
+        if let Terminator::Branch(v, _, _) | Terminator::Return(Some(v)) = self {
+            *v = f(*v);
+        }
+    }
+
+    /// Replace each block control may continue to
+    pub fn map_successors(&mut self, mut f: impl FnMut(BlockId) -> BlockId) {
+        match self {
+            Terminator::Jump(b) => *b = f(*b),
+            Terminator::Branch(_, then, els) => {
+                *then = f(*then);
+                *els = f(*els);
+            }
+            Terminator::Return(_) | Terminator::Unreachable => {}
+        }
+    }
+}
+
+/// Mnemonic of a binary operator
+fn binary_name(op: BinaryOperatorKind) -> &'static str {
+    match op {
+        BinaryOperatorKind::Add => "add",
+        BinaryOperatorKind::Subtract => "sub",
+        BinaryOperatorKind::Multiply => "mul",
+        BinaryOperatorKind::Divide => "div",
+        BinaryOperatorKind::Modulus => "rem",
+        BinaryOperatorKind::Equal => "eq",
+        BinaryOperatorKind::NotEqual => "ne",
+        BinaryOperatorKind::LessThan => "lt",
+        BinaryOperatorKind::GreaterThan => "gt",
+        BinaryOperatorKind::LessThanOrEqual => "le",
+        BinaryOperatorKind::GreaterThanOrEqual => "ge",
+        BinaryOperatorKind::And => "and",
+        BinaryOperatorKind::Or => "or",
+    }
+}
+
+/// Join values as `%0, %1`
+fn values(values: &[Value]) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl fmt::Display for VarId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "_{}", self.0)
+    }
+}
+
+impl fmt::Display for InstKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstKind::Param(i) => write!(f, "param {}", i),
+            InstKind::Capture(i) => write!(f, "capture {}", i),
+            InstKind::Const(c) => write!(f, "const {}", c),
+            InstKind::Read(var) => write!(f, "read {}", var),
+            InstKind::Write(var, v) => write!(f, "write {}, {}", var, v),
+            InstKind::VarAddr(var) => write!(f, "var_addr {}", var),
+            InstKind::StaticAddr(name) => write!(f, "static_addr {}", name),
+            InstKind::Load(ptr) => write!(f, "load {}", ptr),
+            InstKind::Store(ptr, v) => write!(f, "store {}, {}", ptr, v),
+            InstKind::Unary(UnaryOp::Neg, v) => write!(f, "neg {}", v),
+            InstKind::Unary(UnaryOp::Not, v) => write!(f, "not {}", v),
+            InstKind::Binary(op, a, b) => write!(f, "{} {}, {}", binary_name(*op), a, b),
+            InstKind::Cast(v) => write!(f, "cast {}", v),
+            InstKind::Call(name, args) => write!(f, "call {}({})", name, values(args)),
+            InstKind::CallIndirect(func, args) => {
+                write!(f, "call_indirect {}({})", func, values(args))
+            }
+            InstKind::FunctionValue(name) => write!(f, "fn_value {}", name),
+            InstKind::Closure(name, captures) => {
+                write!(f, "closure {}({})", name, values(captures))
+            }
+            InstKind::Alloc => write!(f, "alloc"),
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Terminator::Jump(b) => write!(f, "jump {}", b),
+            Terminator::Branch(c, then, els) => write!(f, "branch {}, {}, {}", c, then, els),
+            Terminator::Return(Some(v)) => write!(f, "return {}", v),
+            Terminator::Return(None) => write!(f, "return"),
+            Terminator::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
+
+// format:
+// fn name(params) -> ty {
+//     var _0 name: ty
+//
+// bb0:
+//     %0: ty = inst
+//     terminator
+// }
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{}fn {}({}) -> {} {{",
+            if self.closure { "closure " } else { "" },
+            self.name,
+            self.params
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<String>>()
+                .join(", "),
+            self.ret_ty
+        )?;
+
+        for (i, var) in self.vars.iter().enumerate() {
+            write!(f, "    var {} {}: {}", VarId(i), var.name, var.ty)?;
+            if var.addressed {
+                write!(f, " addressed")?;
+            }
+            writeln!(f)?;
+        }
+
+        for (i, block) in self.blocks.iter().enumerate() {
+            if i > 0 || !self.vars.is_empty() {
+                writeln!(f)?;
+            }
+            writeln!(f, "{}:", BlockId(i))?;
+            for inst in &block.insts {
+                match inst.result {
+                    Some(v) => writeln!(f, "    {}: {} = {}", v, self.value_type(v), inst.kind)?,
+                    None => writeln!(f, "    {}", inst.kind)?,
+                }
+            }
+            writeln!(f, "    {}", block.terminator)?;
+        }
+
+        write!(f, "}}")
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for s in &self.statics {
+            writeln!(
+                f,
+                "static {}{}: {} = {}",
+                if s.mutable { "mut " } else { "" },
+                s.name,
+                s.ty,
+                s.value
+            )?;
+        }
+
+        for (i, func) in self.functions.iter().enumerate() {
+            if i > 0 || !self.statics.is_empty() {
+                writeln!(f)?;
+            }
+            writeln!(f, "{}", func)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Parse, analyse and lower a single-file program, without running any passes
+    pub fn lower(src: &str) -> Program {
+        let module = crate::hir::tests::lower(src);
+        lower_program(&[module], false).expect("Failed to lower to MIR")
+    }
+}
@@ -0,0 +1,46 @@
+//! Runs passes over the MIR of a program.
+
+use anyhow::Result;
+use log::debug;
+
+use super::Program;
+
+/// A transformation of the MIR, such as an optimisation
+pub trait Pass {
+    /// Name shown when the pass runs
+    fn name(&self) -> &'static str;
+
+    /// Transform the program in place
+    fn run(&self, program: &mut Program) -> Result<()>;
+}
+
+/// Runs a sequence of passes, in the order they were added
+#[derive(Default)]
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add<P: Pass + 'static>(&mut self, pass: P) {
+        self.passes.push(Box::new(pass));
+    }
+
+    /// Names of the passes, in the order they run
+    pub fn passes(&self) -> Vec<&'static str> {
+        self.passes.iter().map(|p| p.name()).collect()
+    }
+
+    pub fn run(&self, program: &mut Program) -> Result<()> {
+        for pass in &self.passes {
+            debug!("Running pass: {}", pass.name());
+            pass.run(program)?;
+            debug!("MIR after {}:\n{}", pass.name(), program);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+//! Removes blocks control never reaches.
+//!
+//! Lowering starts a new block after every `return`, so code following it
+//! has somewhere to go. Those blocks are dropped before codegen.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use super::*;
+
+/// Drops blocks unreachable from the entry block
+pub struct RemoveUnreachableBlocks;
+
+impl Pass for RemoveUnreachableBlocks {
+    fn name(&self) -> &'static str {
+        "remove-unreachable-blocks"
+    }
+
+    fn run(&self, program: &mut Program) -> Result<()> {
+        for func in &mut program.functions {
+            remove_unreachable_blocks(func);
+        }
+
+        Ok(())
+    }
+}
+
+/// Blocks reachable from the entry block, in the order they were reached
+pub fn reachable_blocks(func: &Function) -> Vec<BlockId> {
+    let mut reached = vec![BlockId(0)];
+    let mut i = 0;
+    while i < reached.len() {
+        for succ in func.block(reached[i]).terminator.successors() {
+            if !reached.contains(&succ) {
+                reached.push(succ);
+            }
+        }
+        i += 1;
+    }
+
+    reached
+}
+
+fn remove_unreachable_blocks(func: &mut Function) {
+    let mut reachable = reachable_blocks(func);
+    if reachable.len() == func.blocks.len() {
+        return;
+    }
+
+    // the remaining blocks keep their order
+    reachable.sort();
+    let renumbered: HashMap<BlockId, BlockId> = reachable
+        .iter()
+        .enumerate()
+        .map(|(i, old)| (*old, BlockId(i)))
+        .collect();
+
+    let blocks = std::mem::take(&mut func.blocks);
+    func.blocks = blocks
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| renumbered.contains_key(&BlockId(*i)))
+        .map(|(_, mut block)| {
+            block.terminator = match block.terminator {
+                Terminator::Jump(b) => Terminator::Jump(renumbered[&b]),
+                Terminator::Branch(c, then, els) => {
+                    Terminator::Branch(c, renumbered[&then], renumbered[&els])
+                }
+                t => t,
+            };
+            block
+        })
+        .collect();
+}
@@ -5,7 +5,7 @@ use crate::errors::LangError;
 use crate::token::{Span, Token, TokenKind};
 use anyhow::{anyhow, Result};
 use log::debug;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 
 /// Main parser struct
 /// Parses a program into an AST
@@ -17,11 +17,42 @@ pub struct Parser {
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
         Self {
-            tokens,
+            tokens: Self::drop_stray_doc_comments(tokens),
             pos: RefCell::new(0),
         }
     }
 
+    /// Drop `///` comments that aren't followed by an item, such as ones in a
+    /// function body or at the end of the file, so they act as plain comments
+    fn drop_stray_doc_comments(tokens: Vec<Token>) -> Vec<Token> {
+        let mut kept = Vec::with_capacity(tokens.len());
+        // walk backwards, so each doc comment knows the token after it
+        let mut documents_item = false;
+        for token in tokens.into_iter().rev() {
+            match token.kind {
+                TokenKind::DocComment(_) if !documents_item => continue,
+                TokenKind::DocComment(_) => {}
+                _ => {
+                    documents_item = matches!(
+                        token.kind,
+                        TokenKind::Hash
+                            | TokenKind::Pub
+                            | TokenKind::Fn
+                            | TokenKind::Const
+                            | TokenKind::Static
+                            | TokenKind::Mod
+                            | TokenKind::Trait
+                            | TokenKind::Impl
+                    )
+                }
+            }
+            kept.push(token);
+        }
+
+        kept.reverse();
+        kept
+    }
+
     /// Get position
     fn pos(&self) -> usize {
         *self.pos.borrow()
@@ -33,6 +64,11 @@ impl Parser {
         self.tokens.get(self.pos())
     }
 
+    /// Get the token after the current one
+    fn next(&self) -> Option<&Token> {
+        self.tokens.get(self.pos() + 1)
+    }
+
     /// Get current token
     /// Raises an error if at EOF
     fn current_or_eof(&self) -> Result<&Token> {
@@ -52,6 +88,28 @@ impl Parser {
         *self.pos.borrow_mut() += 1;
     }
 
+    /// Split a current `&&` into two `&` tokens, where it can only be
+    /// two references, as in `&&int` or `&&x`
+    fn split_ampersands(&mut self) {
+        let pos = self.pos();
+        let span = match self.tokens.get(pos) {
+            Some(token) if token.kind == TokenKind::AmpersandAmpersand => token.span.clone(),
+            _ => return,
+        };
+
+        let ampersand = |start, end| Token {
+            kind: TokenKind::Ampersand,
+            span: Span { start, end },
+        };
+        self.tokens.splice(
+            pos..=pos,
+            [
+                ampersand(span.start, span.start + 1),
+                ampersand(span.start + 1, span.end),
+            ],
+        );
+    }
+
     // ====================
     // Parsing helpers
     // ====================
@@ -89,13 +147,70 @@ impl Parser {
 
     /// Parse a single item
     fn item(&mut self) -> Result<Item> {
+        let doc = self.doc_comments()?;
+        let attribute = self.attribute()?;
         let current = self.current_or_eof()?;
 
+        // `const fn` is a function, any other `const` is a global
+        let is_function = match current.kind {
+            TokenKind::Pub | TokenKind::Fn => true,
+            TokenKind::Const => self.next().map(|t| &t.kind) == Some(&TokenKind::Fn),
+            _ => false,
+        };
+        let inline = match attribute {
+            Some((_, span)) if !is_function => {
+                return Err(anyhow!(LangError::MisplacedAttribute(span)))
+            }
+            attribute => attribute.map(|(inline, _)| inline),
+        };
+
         let item = match current.kind {
-            TokenKind::Fn => Item::FunctionDecl(self.function()?),
+            TokenKind::Pub => {
+                self.advance();
+                Item::FunctionDecl(FunctionDecl {
+                    doc,
+                    public: true,
+                    inline,
+                    ..self.function()?
+                })
+            }
+            TokenKind::Fn => Item::FunctionDecl(FunctionDecl {
+                doc,
+                inline,
+                ..self.function()?
+            }),
+            TokenKind::Const if is_function => Item::FunctionDecl(FunctionDecl {
+                doc,
+                inline,
+                ..self.function()?
+            }),
+            TokenKind::Mod => Item::ModDecl(ModDecl {
+                doc,
+                ..self.mod_decl()?
+            }),
+            TokenKind::Const | TokenKind::Static => Item::GlobalDecl(GlobalDecl {
+                doc,
+                ..self.global_decl()?
+            }),
+            TokenKind::Trait => Item::TraitDecl(TraitDecl {
+                doc,
+                ..self.trait_decl()?
+            }),
+            TokenKind::Impl => Item::ImplDecl(ImplDecl {
+                doc,
+                ..self.impl_decl()?
+            }),
             _ => {
                 return Err(anyhow!(LangError::ExpectedAnyToken {
-                    expected: vec![TokenKind::Fn,],
+                    expected: vec![
+                        TokenKind::Fn,
+                        TokenKind::Mod,
+                        TokenKind::Pub,
+                        TokenKind::Const,
+                        TokenKind::Static,
+                        TokenKind::Trait,
+                        TokenKind::Impl,
+                    ],
                     found: current.kind.clone(),
                     span: current.span.clone(),
                 }))
@@ -105,18 +220,144 @@ impl Parser {
         Ok(item)
     }
 
+    /// Collect consecutive `///` lines, joined by newlines
+    fn doc_comments(&mut self) -> Result<Option<String>> {
+        let mut lines = Vec::new();
+        while let TokenKind::DocComment(line) = &self.current_or_eof()?.kind {
+            lines.push(line.clone());
+            self.advance();
+        }
+
+        Ok((!lines.is_empty()).then(|| lines.join("\n")))
+    }
+
+    /// `"#" "[" "inline" ["(" "never" ")"] "]"`, and its span
+    fn attribute(&mut self) -> Result<Option<(Inline, Span)>> {
+        if self.current_or_eof()?.kind != TokenKind::Hash {
+            return Ok(None);
+        }
+        let start = self.expect(TokenKind::Hash)?.span.clone();
+        self.expect(TokenKind::LBracket)?;
+
+        let name = self.ident()?;
+        if name.ident != "inline" {
+            return Err(anyhow!(LangError::UnknownAttribute(name.ident, name.span)));
+        }
+
+        let inline = if self.current_or_eof()?.kind == TokenKind::LParen {
+            self.advance();
+            let arg = self.ident()?;
+            if arg.ident != "never" {
+                return Err(anyhow!(LangError::UnknownAttribute(
+                    format!("inline({})", arg.ident),
+                    Span::combine(&name.span, &arg.span)
+                )));
+            }
+            self.expect(TokenKind::RParen)?;
+            Inline::Never
+        } else {
+            Inline::Always
+        };
+
+        let end = self.expect(TokenKind::RBracket)?.span.clone();
+        Ok(Some((inline, Span::combine(&start, &end))))
+    }
+
     fn function(&mut self) -> Result<FunctionDecl> {
         debug!("Parsing function");
+        // ["const"]
+        let constant = self.current_or_eof()?.kind == TokenKind::Const;
+        if constant {
+            self.advance();
+        }
+
         // "fn"
         self.expect(TokenKind::Fn)?;
 
         // IDENTIFIER
         let ident = self.ident()?;
 
+        // ["<" type parameters ">"]
+        let type_params = if self.current_or_eof()?.kind == TokenKind::Less {
+            self.type_params()?
+        } else {
+            vec![]
+        };
+
+        // "(" parameter list ")"
+        let params = self.parameter_list()?;
+
+        // "->"
+        self.expect(TokenKind::Arrow)?;
+
+        // type
+        let ty = self.type_()?;
+
+        // block
+        let block = self.block()?;
+
+        let span = Span::combine(&ident.span, &block.span);
+
+        let func = FunctionDecl {
+            doc: None,
+            public: false,
+            constant,
+            inline: None,
+            ident,
+            type_params,
+            parameters: params,
+            ty,
+            block,
+            span,
+        };
+
+        debug!("Parsed function: {:#?}", func);
+
+        Ok(func)
+    }
+
+    /// `< type_param {, type_param} >`
+    fn type_params(&mut self) -> Result<Vec<TypeParam>> {
+        debug!("Parsing type parameters");
+
+        // "<"
+        self.expect(TokenKind::Less)?;
+
+        let mut params = vec![self.type_param()?];
+        while self.current_or_eof()?.kind == TokenKind::Comma {
+            self.advance();
+            params.push(self.type_param()?);
+        }
+
+        // ">"
+        self.expect(TokenKind::Greater)?;
+
+        Ok(params)
+    }
+
+    /// `IDENTIFIER [: IDENTIFIER {+ IDENTIFIER}]`
+    fn type_param(&mut self) -> Result<TypeParam> {
+        let ident = self.ident()?;
+
+        let mut bounds = Vec::new();
+        if self.current_or_eof()?.kind == TokenKind::Colon {
+            self.advance();
+            bounds.push(self.ident()?);
+
+            while self.current_or_eof()?.kind == TokenKind::Plus {
+                self.advance();
+                bounds.push(self.ident()?);
+            }
+        }
+
+        Ok(TypeParam { ident, bounds })
+    }
+
+    /// `( [parameter {, parameter}] )`
+    fn parameter_list(&mut self) -> Result<Vec<Parameter>> {
         // "("
         self.expect(TokenKind::LParen)?;
 
-        // parameter list
         let mut params: Vec<Parameter> = Vec::new();
         while self.current_or_eof()?.kind != TokenKind::RParen {
             let param = self.parameter()?;
@@ -130,28 +371,152 @@ impl Parser {
         // ")"
         self.expect(TokenKind::RParen)?;
 
-        // "->"
-        self.expect(TokenKind::Arrow)?;
+        Ok(params)
+    }
 
-        // type
-        let ty = self.type_()?;
+    /// `trait IDENTIFIER { {fn IDENTIFIER parameter_list -> type ;} }`
+    fn trait_decl(&mut self) -> Result<TraitDecl> {
+        debug!("Parsing trait declaration");
+        let start = self.expect(TokenKind::Trait)?.span.clone();
+        let ident = self.ident()?;
 
-        // block
-        let block = self.block()?;
+        // "{"
+        self.expect(TokenKind::LBrace)?;
 
-        let span = Span::combine(&ident.span, &block.span);
+        let mut methods = Vec::new();
+        while self.current_or_eof()?.kind != TokenKind::RBrace {
+            let fn_span = self.expect(TokenKind::Fn)?.span.clone();
+            let ident = self.ident()?;
+            let parameters = self.parameter_list()?;
+            self.expect(TokenKind::Arrow)?;
+            let ty = self.type_()?;
+            let end = self.expect(TokenKind::Semicolon)?.span.clone();
+
+            methods.push(MethodDecl {
+                ident,
+                parameters,
+                ty,
+                span: Span::combine(&fn_span, &end),
+            });
+        }
 
-        let func = FunctionDecl {
+        // "}"
+        let end = self.expect(TokenKind::RBrace)?.span.clone();
+
+        Ok(TraitDecl {
+            doc: None,
             ident,
-            parameters: params,
+            methods,
+            span: Span::combine(&start, &end),
+        })
+    }
+
+    /// `impl IDENTIFIER for type { {function} }`
+    fn impl_decl(&mut self) -> Result<ImplDecl> {
+        debug!("Parsing impl");
+        let start = self.expect(TokenKind::Impl)?.span.clone();
+        let trait_ident = self.ident()?;
+        self.expect(TokenKind::For)?;
+        let ty = self.type_()?;
+
+        // "{"
+        self.expect(TokenKind::LBrace)?;
+
+        let mut methods = Vec::new();
+        while self.current_or_eof()?.kind != TokenKind::RBrace {
+            let doc = self.doc_comments()?;
+            let inline = self.attribute()?.map(|(inline, _)| inline);
+            methods.push(FunctionDecl {
+                doc,
+                inline,
+                ..self.function()?
+            });
+        }
+
+        // "}"
+        let end = self.expect(TokenKind::RBrace)?.span.clone();
+
+        Ok(ImplDecl {
+            doc: None,
+            trait_ident,
             ty,
-            block,
-            span,
+            methods,
+            span: Span::combine(&start, &end),
+        })
+    }
+
+    /// `mod IDENTIFIER ;`
+    fn mod_decl(&mut self) -> Result<ModDecl> {
+        debug!("Parsing module declaration");
+        let start = self.expect(TokenKind::Mod)?.span.clone();
+        let ident = self.ident()?;
+        let end = self.expect(TokenKind::Semicolon)?.span.clone();
+
+        Ok(ModDecl {
+            doc: None,
+            ident,
+            span: Span::combine(&start, &end),
+        })
+    }
+
+    /// `"const" IDENTIFIER ":" type "=" expression ";"`
+    /// or `"static" ["mut"] IDENTIFIER ":" type "=" expression ";"`
+    fn global_decl(&mut self) -> Result<GlobalDecl> {
+        debug!("Parsing global declaration");
+        let start = self.current_or_eof()?.span.clone();
+
+        // "const" or "static"
+        let kind = if self.current_or_eof()?.kind == TokenKind::Const {
+            self.advance();
+            GlobalKind::Const
+        } else {
+            self.expect(TokenKind::Static)?;
+            GlobalKind::Static
         };
 
-        debug!("Parsed function: {:#?}", func);
+        // only statics may be mutable
+        let mutable = kind == GlobalKind::Static && self.current_or_eof()?.kind == TokenKind::Mut;
+        if mutable {
+            self.advance();
+        }
 
-        Ok(func)
+        let ident = self.ident()?;
+
+        // the type is always written out
+        self.expect(TokenKind::Colon)?;
+        let ty = self.type_()?;
+
+        self.expect(TokenKind::Equals)?;
+        let expression = self.expression()?;
+        let end = self.expect(TokenKind::Semicolon)?.span.clone();
+
+        Ok(GlobalDecl {
+            doc: None,
+            kind,
+            mutable,
+            ident,
+            ty,
+            expression,
+            value: Cell::new(None),
+            span: Span::combine(&start, &end),
+        })
+    }
+
+    /// `IDENTIFIER ("::" IDENTIFIER)*`
+    /// A qualified path is kept as a single ident, e.g. `math::add`
+    fn path(&mut self) -> Result<Ident> {
+        let mut path = self.ident()?;
+
+        while self.current_or_eof()?.kind == TokenKind::ColonColon {
+            self.advance();
+            let segment = self.ident()?;
+            path = Ident::new(
+                format!("{}::{}", path.ident, segment.ident),
+                Span::combine(&path.span, &segment.span),
+            );
+        }
+
+        Ok(path)
     }
 
     fn ident(&mut self) -> Result<Ident> {
@@ -163,10 +528,7 @@ impl Parser {
             TokenKind::Ident(ref ident) => {
                 let ident = ident.clone();
                 self.advance();
-                Ok(Ident {
-                    ident,
-                    span: current.span.clone(),
-                })
+                Ok(Ident::new(ident, current.span.clone()))
             }
             _ => Err(anyhow!(LangError::ExpectedToken {
                 expected: TokenKind::Ident("".to_string()),
@@ -200,19 +562,56 @@ impl Parser {
     fn type_(&mut self) -> Result<Type> {
         debug!("Parsing type (no-end)");
 
+        self.split_ampersands();
         let current = self.current_or_eof()?;
 
-        let rv = match current.kind {
-            TokenKind::Int => Ok(Type::Primitive(PrimitiveType {
-                kind: PrimitiveKind::Int,
+        if current.kind == TokenKind::Fn {
+            return self.function_type();
+        }
+
+        // "&" {"mut"} type
+        if current.kind == TokenKind::Ampersand {
+            let start_span = current.span.clone();
+            self.advance();
+
+            let mutable = self.current_or_eof()?.kind == TokenKind::Mut;
+            if mutable {
+                self.advance();
+            }
+
+            let inner = self.type_()?;
+            let span = Span::combine(&start_span, &inner.span());
+
+            return Ok(Type::Reference(ReferenceType {
+                inner: Box::new(inner),
+                mutable,
+                span,
+            }));
+        }
+
+        // a type parameter, checked during analysis
+        if let TokenKind::Ident(ref name) = current.kind {
+            let name = name.clone();
+            self.advance();
+            return Ok(Type::Param(ParamType {
+                name,
                 span: current.span.clone(),
-            })),
-            TokenKind::Bool => Ok(Type::Primitive(PrimitiveType {
-                kind: PrimitiveKind::Bool,
+            }));
+        }
+
+        let rv = match current.kind.as_primitive_kind() {
+            Some(kind) => Ok(Type::Primitive(PrimitiveType {
+                kind,
                 span: current.span.clone(),
             })),
-            _ => Err(anyhow!(LangError::ExpectedAnyToken {
-                expected: vec![TokenKind::Int, TokenKind::Bool,],
+            None => Err(anyhow!(LangError::ExpectedAnyToken {
+                expected: vec![
+                    TokenKind::Int,
+                    TokenKind::Bool,
+                    TokenKind::Float,
+                    TokenKind::Ampersand,
+                    TokenKind::Fn,
+                ],
                 found: current.kind.clone(),
                 span: current.span.clone(),
             })),
@@ -222,6 +621,43 @@ impl Parser {
         rv
     }
 
+    fn function_type(&mut self) -> Result<Type> {
+        debug!("Parsing function type");
+
+        // "fn"
+        let start_span = self.expect(TokenKind::Fn)?.span.clone();
+
+        // "("
+        self.expect(TokenKind::LParen)?;
+
+        // parameter types
+        let mut params = Vec::new();
+        while self.current_or_eof()?.kind != TokenKind::RParen {
+            params.push(self.type_()?);
+
+            if self.current_or_eof()?.kind == TokenKind::Comma {
+                self.advance();
+            }
+        }
+
+        // ")"
+        self.expect(TokenKind::RParen)?;
+
+        // "->"
+        self.expect(TokenKind::Arrow)?;
+
+        // type
+        let ret_ty = self.type_()?;
+
+        let span = Span::combine(&start_span, &ret_ty.span());
+
+        Ok(Type::Function(FunctionType {
+            params,
+            ret_ty: Box::new(ret_ty),
+            span,
+        }))
+    }
+
     fn block(&mut self) -> Result<Block> {
         debug!("Parsing block");
 
@@ -257,40 +693,94 @@ impl Parser {
             TokenKind::Let => self.variable_decl(),
             TokenKind::If => self.flow_statement(),
             TokenKind::Return => self.return_statement(),
-            _ => Err(anyhow!(LangError::ExpectedAnyToken {
-                expected: vec![TokenKind::Let,],
-                found: self.current_or_eof()?.kind.clone(),
-                span: self.current_or_eof()?.span.clone(),
-            })),
+            _ => self.expression_statement(),
         }
     }
 
+    /// Parse an expression statement, or an assignment
+    fn expression_statement(&mut self) -> Result<Statement> {
+        debug!("Parsing expression statement");
+
+        // expression
+        let expression = self.expression()?;
+
+        self.expression_statement_rest(expression)
+    }
+
+    /// Parse the rest of an expression statement, after its first expression
+    fn expression_statement_rest(&mut self, expression: Expression) -> Result<Statement> {
+        // may be an assignment: expression "=" expression
+        let stmt = if self.current_or_eof()?.kind == TokenKind::Equals {
+            self.advance();
+            let value = self.expression()?;
+            let span = Span::combine(&expression.span(), &value.span());
+
+            Statement::Assignment(Assignment {
+                target: expression,
+                expression: value,
+                span,
+            })
+        } else {
+            Statement::Expression(Box::new(expression))
+        };
+
+        // ";"
+        self.expect(TokenKind::Semicolon)?;
+
+        debug!("Parsed expression statement: {:#?}", stmt);
+
+        Ok(stmt)
+    }
+
     fn variable_decl(&mut self) -> Result<Statement> {
         debug!("Parsing variable decl");
 
         // "let"
         let start_span = self.expect(TokenKind::Let)?.span.clone();
 
+        // "mut" or not
+        let mutable = self.current_or_eof()?.kind == TokenKind::Mut;
+        if mutable {
+            self.advance();
+        }
+
         // IDENTIFIER
         let ident = self.ident()?;
 
-        // ":"
-        self.expect(TokenKind::Colon)?;
-
-        // type
-        let ty = self.type_()?;
-
-        // "="
-        self.expect(TokenKind::Equals)?;
+        // [":" type], inferred from the expression when omitted
+        let ty = if self.current_or_eof()?.kind == TokenKind::Colon {
+            self.advance();
+            Some(self.type_()?)
+        } else {
+            None
+        };
 
-        // expression
-        let expression = self.expression()?;
+        // ["=" expression], assigned later when omitted
+        let expression = if self.current_or_eof()?.kind == TokenKind::Equals {
+            self.advance();
+            Some(self.expression()?)
+        } else {
+            None
+        };
 
-        let span = Span::combine(&start_span, &expression.span());
+        let end_span = match (&expression, &ty) {
+            (Some(expression), _) => expression.span(),
+            (None, Some(ty)) => ty.span(),
+            (None, None) => {
+                // the type can't be inferred from later assignments
+                return Err(anyhow!(LangError::MissingTypeAnnotation(Span::combine(
+                    &start_span,
+                    &ident.span
+                ))));
+            }
+        };
+        let span = Span::combine(&start_span, &end_span);
 
         let var_decl = Statement::VariableDecl(VariableDecl {
             ident,
+            mutable,
             ty,
+            inferred_ty: RefCell::new(None),
             expression,
             span,
         });
@@ -369,80 +859,119 @@ impl Parser {
     // Should support:
     // - Primary expressions (literals, identifiers, function calls)
     // - Unary expressions (unary operators)
+    // - Casts (`expr as type`)
     // - Binary expressions (binary operators)
     // ====================
 
     fn expression(&mut self) -> Result<Expression> {
         debug!("Parsing expression");
-        let mut expr = self.primary()?;
+        let expr = self.binary_expression(0)?;
+
+        debug!("Parsed expression: {:#?}", expr);
+
+        Ok(expr)
+    }
+
+    /// Parse a binary expression by precedence climbing
+    /// Only consumes operators binding tighter than `min_precedence`
+    fn binary_expression(&mut self, min_precedence: u8) -> Result<Expression> {
+        let mut expr = self.cast()?;
 
         while let Some(op) = self.current_or_eof()?.as_bin_op() {
             let op_precedence = op.precedence();
-
-            // Check if the operator precedence is higher than the previous operator
-            if let Some(prev_op) = expr.binary_operator() {
-                if op_precedence <= prev_op.precedence() {
-                    break; // Break the loop if the current operator has lower precedence
-                }
+            if op_precedence <= min_precedence {
+                break;
             }
 
             // Consume the operator token
             self.advance();
 
-            // Parse the right-hand side expression
-            let rhs = self.primary()?;
-
-            // Handle unary operators
-            while let Some(unary_op) = self.current_or_eof()?.as_un_op() {
-                // Consume the operator token
-                self.advance();
-
-                // Parse the right-hand side expression
-                let rhs = self.primary()?;
-
-                // let unary_expression = match unary_op {
-                //     UnaryOperator::Negate => UnaryExpression::Negation(Box::new(rhs)),
-                //     UnaryOperator::Not => UnaryExpression::Not(Box::new(rhs)),
-                // };
-
-                let unary_span = Span::combine(&unary_op.span, &rhs.span());
-
-                let unary_expression = UnaryExpression {
-                    kind: match unary_op.kind {
-                        UnaryOperatorKind::Negate => UnaryExpressionKind::Negation(Box::new(rhs)),
-                        UnaryOperatorKind::Not => UnaryExpressionKind::Not(Box::new(rhs)),
-                    },
-                    span: unary_span,
-                };
-
-                expr = Expression::Unary(unary_expression)
-            }
+            // Operators are left-associative, so the right-hand side
+            // only takes operators binding tighter than this one
+            let rhs = self.binary_expression(op_precedence)?;
 
             let span = Span::combine(&expr.span(), &rhs.span());
 
-            // Parse binary expression
             expr = Expression::Binary(BinaryExpression {
                 lhs: Box::new(expr),
-                op: op,
+                op,
                 rhs: Box::new(rhs),
                 span,
             });
         }
 
-        debug!("Parsed expression: {:#?}", expr);
+        Ok(expr)
+    }
+
+    /// Parse a cast, which binds tighter than binary operators but looser
+    /// than prefix operators: `-x as u8` is `(-x) as u8`
+    fn cast(&mut self) -> Result<Expression> {
+        let mut expr = self.unary()?;
+
+        while self.current_or_eof()?.kind == TokenKind::As {
+            debug!("Parsing cast");
+            self.advance();
+
+            let ty = self.type_()?;
+            let span = Span::combine(&expr.span(), &ty.span());
+
+            expr = Expression::Cast(CastExpression {
+                expr: Box::new(expr),
+                ty,
+                span,
+            });
+        }
 
         Ok(expr)
     }
 
+    /// Parse a prefix unary expression, or a primary expression
+    fn unary(&mut self) -> Result<Expression> {
+        self.split_ampersands();
+        let unary_op = match self.current_or_eof()?.as_un_op() {
+            Some(op) => op,
+            None => return self.primary(),
+        };
+
+        debug!("Parsing unary expression");
+
+        // Consume the operator token
+        self.advance();
+
+        // "&" may be followed by "mut"
+        let mutable = matches!(unary_op.kind, UnaryOperatorKind::Reference)
+            && self.current_or_eof()?.kind == TokenKind::Mut;
+        if mutable {
+            self.advance();
+        }
+
+        let operand = Box::new(self.unary()?);
+        let span = Span::combine(&unary_op.span, &operand.span());
+
+        let kind = match unary_op.kind {
+            UnaryOperatorKind::Negate => UnaryExpressionKind::Negation(operand),
+            UnaryOperatorKind::Not => UnaryExpressionKind::Not(operand),
+            UnaryOperatorKind::Reference if mutable => {
+                UnaryExpressionKind::MutableReference(operand)
+            }
+            UnaryOperatorKind::Reference => UnaryExpressionKind::Reference(operand),
+            UnaryOperatorKind::Dereference => UnaryExpressionKind::Dereference(operand),
+        };
+
+        Ok(Expression::Unary(UnaryExpression { kind, span }))
+    }
+
     fn primary(&mut self) -> Result<Expression> {
         debug!("Parsing primary expression (no-end)");
 
         match self.current_or_eof()?.kind {
-            TokenKind::IntLiteral(_) | TokenKind::BoolLiteral(_) => {
-                self.literal().map(Expression::Primary)
-            }
+            TokenKind::IntLiteral(..)
+            | TokenKind::FloatLiteral(_)
+            | TokenKind::BoolLiteral(_)
+            | TokenKind::True
+            | TokenKind::False => self.literal().map(Expression::Primary),
             TokenKind::Ident(_) => {
-                let ident = self.ident()?;
+                let ident = self.path()?;
 
                 // if the next token is a "(", then it's a function call
                 if self.current_or_eof()?.kind == TokenKind::LParen {
@@ -465,12 +994,17 @@ impl Parser {
                     Box::new(expr),
                 )))
             }
+            TokenKind::Pipe | TokenKind::PipePipe => self
+                .closure()
+                .map(|c| Expression::Primary(PrimaryExpression::Closure(c))),
             _ => Err(anyhow!(LangError::ExpectedAnyToken {
                 expected: vec![
-                    TokenKind::IntLiteral(0),
+                    TokenKind::IntLiteral(0, None),
+                    TokenKind::FloatLiteral(0.0),
                     TokenKind::BoolLiteral(false),
                     TokenKind::Ident("".to_string()),
                     TokenKind::LParen,
+                    TokenKind::Pipe,
                 ],
                 found: self.current_or_eof()?.kind.clone(),
                 span: self.current_or_eof()?.span.clone(),
@@ -478,32 +1012,115 @@ impl Parser {
         }
     }
 
+    /// `| [parameter {, parameter}] | -> type closure_block`
+    /// Without parameters, the pipes are lexed as `||`
+    fn closure(&mut self) -> Result<Closure> {
+        debug!("Parsing closure");
+
+        let start_span = self.current_or_eof()?.span.clone();
+
+        let mut parameters = Vec::new();
+        if self.current_or_eof()?.kind == TokenKind::PipePipe {
+            self.advance();
+        } else {
+            self.expect(TokenKind::Pipe)?;
+            while self.current_or_eof()?.kind != TokenKind::Pipe {
+                parameters.push(self.parameter()?);
+
+                if self.current_or_eof()?.kind == TokenKind::Comma {
+                    self.advance();
+                }
+            }
+            self.expect(TokenKind::Pipe)?;
+        }
+
+        // "->" type
+        self.expect(TokenKind::Arrow)?;
+        let ty = self.type_()?;
+
+        let block = self.closure_block()?;
+        let span = Span::combine(&start_span, &block.span);
+
+        Ok(Closure {
+            parameters,
+            ty,
+            block,
+            captures: RefCell::new(Vec::new()),
+            span,
+        })
+    }
+
+    /// `{ statement* [expression] }`
+    /// A trailing expression without `;` is returned, as in `|x: int| -> int { x + 1 }`
+    fn closure_block(&mut self) -> Result<Block> {
+        let start_span = self.current_or_eof()?.span.clone();
+
+        // "{"
+        self.expect(TokenKind::LBrace)?;
+
+        let mut statements = Vec::new();
+        while self.current_or_eof()?.kind != TokenKind::RBrace {
+            let statement = match self.current_or_eof()?.kind {
+                TokenKind::Let | TokenKind::If | TokenKind::Return => self.statement()?,
+                _ => {
+                    let expression = self.expression()?;
+                    if self.current_or_eof()?.kind == TokenKind::RBrace {
+                        Statement::Return(Some(Box::new(expression)))
+                    } else {
+                        self.expression_statement_rest(expression)?
+                    }
+                }
+            };
+            statements.push(statement);
+        }
+
+        let end_span = self.current_or_eof()?.span.clone();
+
+        // "}"
+        self.expect(TokenKind::RBrace)?;
+
+        Ok(Block {
+            statements,
+            span: Span::combine(&start_span, &end_span),
+        })
+    }
+
     fn literal(&mut self) -> Result<PrimaryExpression> {
         debug!("Parsing literal (no-end)");
 
         let current = self.current_or_eof()?;
 
-        match current.kind {
-            TokenKind::IntLiteral(value) => {
-                self.advance();
-                Ok(PrimaryExpression::Literal(Literal {
-                    kind: LiteralKind::Int(value),
-                    span: current.span.clone(),
-                }))
-            }
-            TokenKind::BoolLiteral(value) => {
-                self.advance();
-                Ok(PrimaryExpression::Literal(Literal {
-                    kind: LiteralKind::Bool(value),
-                    span: current.span.clone(),
+        let kind = match current.kind {
+            TokenKind::IntLiteral(value, _) => LiteralKind::Int(value),
+            TokenKind::FloatLiteral(value) => LiteralKind::Float(value),
+            TokenKind::BoolLiteral(value) => LiteralKind::Bool(value),
+            TokenKind::True => LiteralKind::Bool(true),
+            TokenKind::False => LiteralKind::Bool(false),
+            _ => {
+                return Err(anyhow!(LangError::ExpectedAnyToken {
+                    expected: vec![
+                        TokenKind::IntLiteral(0, None),
+                        TokenKind::FloatLiteral(0.0),
+                        TokenKind::BoolLiteral(false),
+                    ],
+                    found: self.current_or_eof()?.kind.clone(),
+                    span: self.current_or_eof()?.span.clone(),
                 }))
             }
-            _ => Err(anyhow!(LangError::ExpectedAnyToken {
-                expected: vec![TokenKind::IntLiteral(0), TokenKind::BoolLiteral(false),],
-                found: self.current_or_eof()?.kind.clone(),
-                span: self.current_or_eof()?.span.clone(),
-            })),
-        }
+        };
+
+        let suffix = match current.kind {
+            TokenKind::IntLiteral(_, suffix) => suffix,
+            _ => None,
+        };
+
+        self.advance();
+        Ok(PrimaryExpression::Literal(Literal {
+            kind,
+            suffix,
+            contextual_ty: Cell::new(None),
+            span: current.span.clone(),
+        }))
     }
 
     fn expression_list(&mut self) -> Result<Vec<Expression>> {
@@ -532,3 +1149,357 @@ impl Parser {
         Ok(AST { program, file_id })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{consume_lexer, Lexer};
+
+    fn parse(src: &str) -> Result<AST> {
+        let (tokens, errors) = consume_lexer(Lexer::new(src));
+        assert!(errors.is_empty());
+
+        Parser::new(tokens).parse("test".to_string())
+    }
+
+    #[test]
+    fn doc_comments() {
+        let ast = parse(
+            "/// Adds two numbers.
+            /// Really.
+            fn add(a: int, b: int) -> int { return a + b; }
+
+            // not documented
+            fn main() -> int { return add(1, 2); }",
+        )
+        .unwrap();
+
+        let docs: Vec<Option<String>> = ast
+            .program
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::FunctionDecl(f) => f.doc.clone(),
+                Item::ModDecl(m) => m.doc.clone(),
+                Item::GlobalDecl(g) => g.doc.clone(),
+                Item::TraitDecl(t) => t.doc.clone(),
+                Item::ImplDecl(i) => i.doc.clone(),
+            })
+            .collect();
+
+        assert_eq!(
+            docs,
+            vec![Some("Adds two numbers.\nReally.".to_string()), None]
+        );
+    }
+
+    #[test]
+    fn stray_doc_comments() {
+        // outside item position `///` is an ordinary comment
+        let ast = parse(
+            "fn main() -> int {
+                /// not an item
+                let x = 1 /// mid-expression
+                    + 2;
+                return x;
+                /// before the brace
+            }
+            /// nothing follows",
+        )
+        .unwrap();
+
+        let Item::FunctionDecl(main) = &ast.program.items[0] else {
+            panic!("expected a function");
+        };
+        assert_eq!(ast.program.items.len(), 1);
+        assert_eq!(main.doc, None);
+        assert_eq!(main.block.statements.len(), 2);
+    }
+
+    #[test]
+    fn inline_attributes() {
+        let ast = parse(
+            "/// Cheap enough to inline
+            #[inline]
+            fn add(a: int, b: int) -> int { return a + b; }
+            #[inline(never)]
+            pub fn slow() -> int { return 0; }
+            fn main() -> int { return add(1, 2); }",
+        )
+        .unwrap();
+
+        let inline: Vec<Option<Inline>> = ast
+            .program
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::FunctionDecl(f) => f.inline,
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(inline, [Some(Inline::Always), Some(Inline::Never), None]);
+
+        assert!(parse("#[inline(always)] fn f() -> int { return 0; }").is_err());
+        assert!(parse("#[cold] fn f() -> int { return 0; }").is_err());
+        assert!(parse("#[inline] const X: int = 1;").is_err());
+    }
+
+    #[test]
+    fn deferred_initialization() {
+        let ast = parse("fn main() -> int { let x: int; x = 1; return x; }").unwrap();
+        let func = match &ast.program.items[..] {
+            [Item::FunctionDecl(f)] => f,
+            other => panic!("expected a function, found {other:?}"),
+        };
+        match &func.block.statements[0] {
+            Statement::VariableDecl(v) => {
+                assert!(v.expression.is_none());
+                assert_eq!(v.span, Span { start: 19, end: 29 });
+            }
+            s => panic!("expected a variable declaration, found {s:?}"),
+        }
+
+        // the type can't be inferred without a value
+        assert!(parse("fn main() -> int { let x; x = 1; return x; }").is_err());
+    }
+
+    #[test]
+    fn modules() {
+        let ast = parse(
+            "/// Arithmetic helpers
+            mod math;
+            fn main() -> int { return math::add(1, 2); }",
+        )
+        .unwrap();
+
+        match &ast.program.items[0] {
+            Item::ModDecl(m) => {
+                assert_eq!(m.ident.ident, "math");
+                assert_eq!(m.doc.as_deref(), Some("Arithmetic helpers"));
+            }
+            other => panic!("expected a module, found {other:?}"),
+        }
+
+        let Item::FunctionDecl(main) = &ast.program.items[1] else {
+            panic!("expected a function");
+        };
+        let path = match &main.block.statements[0] {
+            Statement::Return(Some(e)) => match e.as_ref() {
+                Expression::Primary(PrimaryExpression::FunctionCall(path, _)) => path,
+                other => panic!("expected a call, found {other:?}"),
+            },
+            other => panic!("expected a return, found {other:?}"),
+        };
+        assert_eq!(path.ident, "math::add");
+        assert_eq!(path.split_path(), Some(("math", "add")));
+
+        assert!(parse("fn main() -> int { return math::(1); }").is_err());
+    }
+
+    #[test]
+    fn globals() {
+        let ast = parse(
+            "const LIMIT: u8 = 200;
+            static mut COUNT: int = LIMIT as int * 2;
+            fn main() -> int { return COUNT; }",
+        )
+        .unwrap();
+
+        let globals: Vec<(GlobalKind, bool, &str)> = ast
+            .program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::GlobalDecl(g) => Some((g.kind, g.mutable, g.ident.ident.as_str())),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(
+            globals,
+            vec![
+                (GlobalKind::Const, false, "LIMIT"),
+                (GlobalKind::Static, true, "COUNT")
+            ]
+        );
+
+        let ast =
+            parse("pub const fn one() -> int { return 1; }\nconst ONE: int = one();").unwrap();
+        match &ast.program.items[..] {
+            [Item::FunctionDecl(f), Item::GlobalDecl(g)] => {
+                assert!(f.public && f.constant);
+                assert_eq!(g.kind, GlobalKind::Const);
+            }
+            other => panic!("expected a const fn and a const, found {other:?}"),
+        }
+
+        // the type is required, and consts are never mutable
+        assert!(parse("const LIMIT = 200;").is_err());
+        assert!(parse("const mut LIMIT: int = 200;").is_err());
+    }
+
+    #[test]
+    fn generics() {
+        let ast = parse("fn pick<T, U>(a: T, b: &U, f: fn(T) -> U) -> T { return a; }").unwrap();
+        let func = match &ast.program.items[..] {
+            [Item::FunctionDecl(f)] => f,
+            other => panic!("expected a function, found {other:?}"),
+        };
+
+        let names: Vec<&str> = func
+            .type_params
+            .iter()
+            .map(|p| p.ident.ident.as_str())
+            .collect();
+        assert_eq!(names, vec!["T", "U"]);
+        assert!(matches!(&func.ty, Type::Param(p) if p.name == "T"));
+        assert_eq!(func.parameters[2].ty.to_string(), "fn(T) -> U");
+
+        // at least one parameter, and the list must be closed
+        assert!(parse("fn none<>() -> int { return 0; }").is_err());
+        assert!(parse("fn open<T(a: T) -> T { return a; }").is_err());
+    }
+
+    #[test]
+    fn double_references() {
+        let src = "fn get(p: &&int, q: &&mut int) -> bool {
+            let r = &&x;
+            let m = &&mut x;
+            return x && y;
+        }";
+        let ast = parse(src).unwrap();
+        let func = match &ast.program.items[..] {
+            [Item::FunctionDecl(f)] => f,
+            other => panic!("expected a function, found {other:?}"),
+        };
+
+        // `&&` is two references, except between operands
+        assert_eq!(func.parameters[0].ty.to_string(), "&&int");
+        assert_eq!(func.parameters[1].ty.to_string(), "&&mut int");
+        let referenced = |i: usize| match &func.block.statements[i] {
+            Statement::VariableDecl(VariableDecl {
+                expression:
+                    Some(Expression::Unary(UnaryExpression {
+                        kind: UnaryExpressionKind::Reference(inner),
+                        ..
+                    })),
+                ..
+            }) => inner.as_ref(),
+            s => panic!("expected a let of a reference, found {s:?}"),
+        };
+        assert!(matches!(
+            referenced(0),
+            Expression::Unary(UnaryExpression {
+                kind: UnaryExpressionKind::Reference(_),
+                ..
+            })
+        ));
+        assert!(matches!(
+            referenced(1),
+            Expression::Unary(UnaryExpression {
+                kind: UnaryExpressionKind::MutableReference(_),
+                ..
+            })
+        ));
+        match &func.block.statements[2] {
+            Statement::Return(Some(e)) => {
+                assert!(
+                    matches!(e.strip_parens(), Expression::Binary(b) if b.op.kind == BinaryOperatorKind::And)
+                )
+            }
+            s => panic!("expected a return, found {s:?}"),
+        }
+    }
+
+    #[test]
+    fn traits() {
+        let src = r#"trait Shape {
+            fn area(s: Self) -> int;
+            fn scale(s: Self, by: int) -> Self;
+        }
+        impl Shape for u8 {
+            fn area(s: u8) -> int { return s as int; }
+            fn scale(s: u8, by: int) -> u8 { return s; }
+        }
+        fn total<T: Shape + Ord, U>(a: T, b: U) -> int { return Shape::area(a); }"#;
+        let ast = parse(src).unwrap();
+        let (trait_decl, impl_decl, func) = match &ast.program.items[..] {
+            [Item::TraitDecl(t), Item::ImplDecl(i), Item::FunctionDecl(f)] => (t, i, f),
+            other => panic!("expected a trait, an impl and a function, found {other:?}"),
+        };
+
+        assert_eq!(trait_decl.ident.ident, "Shape");
+        assert_eq!(trait_decl.methods.len(), 2);
+        assert_eq!(
+            trait_decl.methods[1].signature().to_string(),
+            "fn(Self, int) -> Self"
+        );
+
+        assert_eq!(impl_decl.trait_ident.ident, "Shape");
+        assert_eq!(impl_decl.ty.to_string(), "u8");
+        assert_eq!(impl_decl.methods.len(), 2);
+
+        let bounds: Vec<&str> = func.type_params[0]
+            .bounds
+            .iter()
+            .map(|b| b.ident.as_str())
+            .collect();
+        assert_eq!(bounds, vec!["Shape", "Ord"]);
+        assert!(func.type_params[1].bounds.is_empty());
+
+        // trait methods have no body, and impls need a type
+        assert!(parse("trait A { fn f(s: Self) -> int { return 0; } }").is_err());
+        assert!(parse("impl A for { }").is_err());
+        assert!(parse("fn f<T: >(a: T) -> T { return a; }").is_err());
+    }
+
+    #[test]
+    fn closures() {
+        let ast = parse(
+            "fn main() -> int {
+                let add = |a: int, b: int| -> int { a + b };
+                let five = || -> int { let x = 5; x };
+                let mut n = 0;
+                let bump = |by: int| -> int {
+                    n = n + by;
+                    return n;
+                };
+                return add(1, 2);
+            }",
+        )
+        .unwrap();
+        let func = match &ast.program.items[..] {
+            [Item::FunctionDecl(f)] => f,
+            other => panic!("expected a function, found {other:?}"),
+        };
+
+        let closures: Vec<&Closure> = func
+            .block
+            .statements
+            .iter()
+            .filter_map(|s| match s {
+                Statement::VariableDecl(VariableDecl {
+                    expression: Some(Expression::Primary(PrimaryExpression::Closure(c))),
+                    ..
+                }) => Some(c),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(closures.len(), 3);
+        assert_eq!(closures[0].get_type().to_string(), "fn(int, int) -> int");
+        assert!(closures[1].parameters.is_empty());
+
+        // a trailing expression is returned, anything else needs a `;`
+        assert!(matches!(
+            closures[1].block.statements[..],
+            [Statement::VariableDecl(_), Statement::Return(Some(_))]
+        ));
+        assert!(matches!(
+            closures[2].block.statements[..],
+            [Statement::Assignment(_), Statement::Return(Some(_))]
+        ));
+
+        assert!(parse("fn f() -> int { let c = |x: int| { x }; return 0; }").is_err());
+        assert!(parse("fn f() -> int { let c = |x: int| -> int { x 1 }; return 0; }").is_err());
+    }
+}
@@ -3,29 +3,50 @@
 //! Errors:
 //! - Scope checking                Idents must be declared before use
 //! - Type checking                 Return types, etc.
-//! - Variable redeclaration         Variables cannot be redeclared
+//! - Parameter redeclaration       Parameters cannot be redeclared, though `let` may shadow anything
 //! - Function redeclaration        Functions cannot be redeclared
 //! - Control flow checks           Return statements, etc. breaks cannot be outside loops
 //! - Missing main function
+//! - Non-constant initializers of `const` and `static` items
+//! - Overflow and division by zero while evaluating constants
+//! - Missing, extra or mismatched methods in trait impls
+//! - Unsatisfied trait bounds of generic functions
 //!
 //! Warnings:
 //! - Dead code (unused anything)
 //! - Unreachable code
 
-use super::symbols::SymbolTable;
+use std::collections::HashMap;
+
+use super::symbols::{ModuleSymbol, ScopeId, SymbolTable};
 use super::traits::Analysis;
 use crate::ast::*;
 use crate::errors::SemanticError;
 use crate::token::Span;
 use anyhow::{anyhow, Error};
 
-pub fn analyse<'a>(ast: &'a AST) -> Vec<Error> {
+/// Analyse a single-file program
+pub fn analyse(ast: &AST) -> Vec<Error> {
+    analyse_module(ast, true, &HashMap::new()).1
+}
+
+/// Analyse one module of a program.
+/// `modules` holds every module it may declare with `mod`, by name,
+/// and only the main module needs a `main` function
+///
+/// Returns the module's symbol table, with the scope of every body
+/// analysed, and the errors found
+pub fn analyse_module<'a>(
+    ast: &'a AST,
+    is_main: bool,
+    modules: &HashMap<String, ModuleSymbol>,
+) -> (SymbolTable<'a>, Vec<Error>) {
     let program = &ast.program;
 
     let mut global_table = SymbolTable::new();
     let mut errors = Vec::new();
 
-    // recognise all functions
+    // recognise all functions, modules and traits
     let mut main_node: Option<&FunctionDecl> = None;
 
     for item in &program.items {
@@ -39,27 +60,82 @@ pub fn analyse<'a>(ast: &'a AST) -> Vec<Error> {
                     errors.push(e);
                 }
             }
+            Item::ModDecl(m) => match modules.get(&m.ident.ident) {
+                Some(module) => {
+                    if let Err(e) = global_table.add_module(m, module) {
+                        errors.push(e);
+                    }
+                }
+                None => errors.push(anyhow!(SemanticError::ModuleNotFound(
+                    m.ident.clone(),
+                    format!("{}.pyl", m.ident),
+                    m.span.clone()
+                ))),
+            },
+            Item::TraitDecl(t) => {
+                if let Err(e) = global_table.add_trait(t) {
+                    errors.push(e);
+                }
+            }
+            Item::GlobalDecl(_) | Item::ImplDecl(_) => {}
         }
     }
 
-    if main_node.is_none() {
-        errors.push(anyhow!(SemanticError::MissingMainFunction(Span::default())));
-    } else {
-        // ensure return type is int
-        let ret_ty = main_node.unwrap().ty.clone();
-        match ret_ty {
-            Type::Primitive(ty) => {
-                if ty.kind != PrimitiveKind::Int {
-                    errors.push(anyhow!(SemanticError::MainMustReturnInt(ty.span.clone())));
-                }
-            },
+    // impls need every trait, and bounds are checked against every impl
+    for item in &program.items {
+        if let Item::ImplDecl(i) = item {
+            if let Err(e) = global_table.add_impl(i) {
+                errors.push(e);
+            }
+        }
+    }
+
+    // globals and `const fn`s are analysed in order, before other functions,
+    // so constants may only use earlier constants and `const fn`s
+    for item in &program.items {
+        match item {
+            Item::GlobalDecl(g) => errors.extend(g.analyze(&mut global_table)),
+            Item::FunctionDecl(f) if f.constant => {
+                errors.extend(f.analyze(&mut global_table));
+                global_table.add_const_fn(f);
+            }
+            _ => {}
         }
     }
 
+    // `main` is only the entry point in the main module
+    if is_main {
+        errors.extend(check_main(main_node));
+    }
+
     // let AST analyse itself
     errors.extend(ast.analyze(&mut global_table));
+    debug_assert_eq!(global_table.current(), ScopeId::ROOT);
+
+    (global_table, errors)
+}
+
+/// The main module must declare `main`, returning an integer
+fn check_main(main_node: Option<&FunctionDecl>) -> Option<Error> {
+    let main_node = match main_node {
+        Some(main_node) => main_node,
+        None => return Some(anyhow!(SemanticError::MissingMainFunction(Span::default()))),
+    };
+
+    // ensure return type is int
+    let ret_ty = main_node.ty.clone();
+    match ret_ty {
+        Type::Primitive(ty) => {
+            if ty.kind != PrimitiveKind::I64 {
+                return Some(anyhow!(SemanticError::MainMustReturnInt(ty.span.clone())));
+            }
+        },
+        ty => {
+            return Some(anyhow!(SemanticError::MainMustReturnInt(ty.span())));
+        },
+    }
 
-    errors
+    None
 }
 
 /// These tests are inexact
@@ -67,6 +143,7 @@ pub fn analyse<'a>(ast: &'a AST) -> Vec<Error> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::semantic_analysis::{Cfg, ConstValue, VarKind};
     use test_log::test;
 
     fn quick_parse(input: &str) -> AST {
@@ -97,7 +174,7 @@ mod tests {
 
         let mut reporter = crate::errors::ErrorReporter::new(&mut files);
         for err in errors {
-            reporter.report("test".to_string(), &err).unwrap();
+            reporter.report("test".to_string(), err).unwrap();
         }
     }
 
@@ -138,7 +215,7 @@ mod tests {
 
     #[test]
     fn test_duplicate_var() {
-        let src = "fn main() -> int { let x: int = 5; let x: int = 10; return y; }";
+        let src = "fn main(x: int, x: bool) -> int { return 0; }";
         let ast = quick_parse(src);
         let errors = filter_warnings(analyse(&ast));
 
@@ -149,6 +226,34 @@ mod tests {
         assert_eq!(errors[0].to_string(), "Variable `x` already declared");
     }
 
+    #[test]
+    fn test_shadowing() {
+        // shadowing in the same block, with another type, and in a nested block
+        let src = "fn main(a: int) -> int { let x: int = a; let mut x: bool = x > 1; x = !x; if x { let x = 3; return x; } return 0; }";
+        let ast = quick_parse(src);
+        let errors = analyse(&ast);
+
+        quick_errors(&errors, src);
+
+        assert_eq!(errors.len(), 0);
+    }
+
+    #[test]
+    fn test_shadowed_unused() {
+        // `y` is only used in a nested block, and the first `x` is never used
+        let src = "fn main(a: int) -> int { let x = 5; let x = 6; let y = 1; if a > 0 { return y; } return x; }";
+        let ast = quick_parse(src);
+        let errors = analyse(&ast);
+
+        quick_errors(&errors, src);
+
+        assert_eq!(errors.len(), 1);
+
+        assert_eq!(errors[0].to_string(), "Unused variable");
+        let span = errors[0].downcast_ref::<crate::errors::Warning>().unwrap().first_span("test");
+        assert_eq!(span.start, src.find("x = 5").unwrap());
+    }
+
     #[test]
     fn test_no_return() {
         let src = "fn main() -> int {}";
@@ -170,7 +275,7 @@ mod tests {
 
         quick_errors(&errors, src);
 
-        assert!(errors.len() > 0);
+        assert!(!errors.is_empty());
     }
 
     #[test]
@@ -431,4 +536,1176 @@ mod tests {
 
         assert_eq!(errors.len(), 0);
     }
+
+    #[test]
+    fn references() {
+        let src = r#"fn main() -> int {
+            let mut x: int = 5;
+            let r: &mut int = &mut x;
+            *r = *r + 1;
+            return read(&x);
+        }
+
+        fn read(p: &int) -> int {
+            return *p;
+        }"#;
+        let ast = quick_parse(src);
+        let errors = analyse(&ast);
+
+        quick_errors(&errors, src);
+
+        assert_eq!(errors.len(), 0);
+    }
+
+    #[test]
+    fn double_references() {
+        let src = r#"fn get(p: &&int) -> int {
+            return **p;
+        }
+
+        fn main() -> int {
+            let a = 1;
+            let r = &a;
+            let rr: &&int = &&a;
+            return get(&r) + get(rr);
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn returned_borrows() {
+        let src = r#"static mut TOTAL: int = 0;
+        const LIMIT: int = 10;
+        fn local() -> &int {
+            let x = 1;
+            return &x;
+        }
+        fn param(p: int) -> &int {
+            return (&p);
+        }
+        fn temporary() -> &int {
+            return &(1 + 2);
+        }
+        fn constant() -> &int {
+            return &LIMIT;
+        }
+        fn reborrow(p: &int) -> &int {
+            return &*p;
+        }
+        fn global() -> &mut int {
+            return &mut TOTAL;
+        }
+        fn main() -> int {
+            return *reborrow(&TOTAL) + *global();
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "Cannot return a reference to local variable `x`",
+                "Cannot return a reference to local variable `p`",
+                "Cannot return a reference to a temporary value",
+                "Cannot return a reference to a temporary value",
+            ]
+        );
+    }
+
+    #[test]
+    fn mutable_borrow_of_immutable() {
+        let src = r#"fn main() -> int {
+            let x: int = 5;
+            let r: &mut int = &mut x;
+            return *r;
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        assert_eq!(errors.len(), 1);
+
+        assert_eq!(errors[0].to_string(), "Cannot mutate immutable variable `x`");
+    }
+
+    #[test]
+    fn assign_to_immutable() {
+        let src = r#"fn main() -> int {
+            let x: int = 5;
+            x = 6;
+            return x;
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        assert_eq!(errors.len(), 1);
+
+        assert_eq!(errors[0].to_string(), "Cannot mutate immutable variable `x`");
+    }
+
+    #[test]
+    fn mutation_through_shared_reference() {
+        let src = r#"fn main() -> int {
+            let mut x: int = 5;
+            let r: &int = &x;
+            *r = 6;
+            return x;
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        assert_eq!(errors.len(), 1);
+
+        assert_eq!(errors[0].to_string(), "Cannot mutate through a shared reference");
+    }
+
+    #[test]
+    fn dereference_non_reference() {
+        let src = r#"fn main() -> int {
+            let x: int = 5;
+            return *x;
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        assert!(!errors.is_empty());
+
+        assert_eq!(errors[0].to_string(), "Unsupported unary operation");
+    }
+
+    #[test]
+    fn reference_type_mismatch() {
+        let src = r#"fn main() -> int {
+            let mut x: int = 5;
+            let r: &mut int = &x;
+            return *r;
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        assert_eq!(errors.len(), 1);
+
+        assert_eq!(errors[0].to_string(), "Types do not match");
+    }
+
+    #[test]
+    fn function_pointers() {
+        let src = r#"fn main() -> int {
+            let f: fn(int, int) -> int = add;
+            return apply(f, 1, 2) + f(3, 4);
+        }
+
+        fn add(a: int, b: int) -> int {
+            return a + b;
+        }
+
+        fn apply(f: fn(int, int) -> int, a: int, b: int) -> int {
+            return f(a, b);
+        }"#;
+        let ast = quick_parse(src);
+        let errors = analyse(&ast);
+
+        quick_errors(&errors, src);
+
+        assert_eq!(errors.len(), 0);
+    }
+
+    #[test]
+    fn function_pointer_signature_mismatch() {
+        let src = r#"fn main() -> int {
+            return apply(negate, 1);
+        }
+
+        fn negate(a: bool) -> bool {
+            return !a;
+        }
+
+        fn apply(f: fn(int) -> int, a: int) -> int {
+            return f(a);
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        assert_eq!(errors.len(), 1);
+
+        assert_eq!(errors[0].to_string(), "Types do not match");
+    }
+
+    #[test]
+    fn call_non_function() {
+        let src = r#"fn main() -> int {
+            let x: int = 5;
+            return x(1);
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        assert_eq!(errors.len(), 1);
+
+        assert_eq!(errors[0].to_string(), "`x` is not a function");
+    }
+
+    #[test]
+    fn inferred_types() {
+        let src = r#"fn main() -> int {
+            let x = 5;
+            let r = &x;
+            let f = add;
+            return f(*r, 1);
+        }
+
+        fn add(a: int, b: int) -> int {
+            return a + b;
+        }"#;
+        let ast = quick_parse(src);
+        let errors = analyse(&ast);
+
+        quick_errors(&errors, src);
+
+        assert_eq!(errors.len(), 0);
+
+        let printed = ast.to_string();
+        assert!(printed.contains("VariableDecl x:int (inferred)"));
+        assert!(printed.contains("VariableDecl r:&int (inferred)"));
+        assert!(printed.contains("VariableDecl f:fn(int, int) -> int (inferred)"));
+    }
+
+    #[test]
+    fn inferred_type_mismatch() {
+        let src = r#"fn main() -> int {
+            let x = 1 < 2;
+            let y: int = x;
+            return y;
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        assert_eq!(errors.len(), 1);
+
+        assert_eq!(errors[0].to_string(), "Types do not match");
+    }
+
+    #[test]
+    fn sized_integers() {
+        let src = r#"fn main() -> int {
+            let a: u8 = 1;
+            let b = a + 2;
+            let c: i32 = -5;
+            return takes_u16(7) as int + (b as int) + (c as int);
+        }
+
+        fn takes_u16(v: u16) -> u16 {
+            return v * 2;
+        }"#;
+        let ast = quick_parse(src);
+        let errors = analyse(&ast);
+
+        quick_errors(&errors, src);
+
+        assert_eq!(errors.len(), 0);
+    }
+
+    #[test]
+    fn implicit_integer_conversion() {
+        let src = r#"fn main() -> int {
+            let a: u8 = 1;
+            let b: i32 = 2;
+            return a + b;
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        assert!(!errors.is_empty());
+
+        assert_eq!(
+            errors[0].to_string(),
+            "Implicit conversion from `i32` to `u8`"
+        );
+    }
+
+    #[test]
+    fn literal_out_of_range() {
+        let src = r#"fn main() -> int {
+            let a: u8 = 256;
+            let b: i8 = -129;
+            let c: i8 = -128;
+            return 0;
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        assert_eq!(errors.len(), 2);
+
+        assert_eq!(errors[0].to_string(), "Literal out of range for `u8`");
+        assert_eq!(errors[1].to_string(), "Literal out of range for `i8`");
+    }
+
+    #[test]
+    fn invalid_cast() {
+        let src = r#"fn main() -> int {
+            let a = 5;
+            return &a as int;
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        assert_eq!(errors.len(), 1);
+
+        assert_eq!(errors[0].to_string(), "Cannot cast `&int` as `int`");
+    }
+
+    #[test]
+    fn negate_unsigned() {
+        let src = r#"fn main() -> int {
+            let a: u32 = 5;
+            return (-a) as int;
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        assert_eq!(errors.len(), 1);
+
+        assert_eq!(errors[0].to_string(), "Unsupported unary operation");
+    }
+
+    #[test]
+    fn qualified_calls() {
+        let math = quick_parse("pub fn add(a: int, b: int) -> int { return a + b; }");
+        let modules = HashMap::from([("math".to_string(), ModuleSymbol::new(&math))]);
+
+        // modules other than the main one need no `main`
+        assert_eq!(filter_warnings(analyse_module(&math, false, &HashMap::new()).1).len(), 0);
+
+        let src = r#"mod math;
+        fn main() -> int {
+            let f = math::add;
+            return math::add(1, 2) + f(3, 4);
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse_module(&ast, true, &modules).1);
+
+        quick_errors(&errors, src);
+
+        assert_eq!(errors.len(), 0);
+
+        let src = r#"mod math;
+        fn main() -> int {
+            return math::add(1) + math::sub(2, 1) + geo::area(1);
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse_module(&ast, true, &modules).1);
+
+        quick_errors(&errors, src);
+
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "Argument count mismatch",
+                "Function `math::sub` has not been declared yet",
+                "Module `geo` has not been declared",
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_module() {
+        let src = "mod math;\nfn main() -> int { return 0; }";
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        assert_eq!(errors.len(), 1);
+
+        assert_eq!(errors[0].to_string(), "Module `math` not found");
+    }
+
+    #[test]
+    fn private_functions() {
+        let math_src = "pub fn add(a: int, b: int) -> int { return secret(a) + b; }
+            fn secret(a: int) -> int { return a; }";
+        let math = quick_parse(math_src);
+        let modules = HashMap::from([("math".to_string(), ModuleSymbol::new(&math))]);
+
+        // private functions are usable inside their own module
+        assert_eq!(filter_warnings(analyse_module(&math, false, &modules).1).len(), 0);
+
+        let src = r#"mod math;
+        fn main() -> int {
+            let f = math::secret;
+            return math::add(1, 2) + math::secret(3);
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse_module(&ast, true, &modules).1);
+
+        assert_eq!(errors.len(), 2);
+
+        for err in &errors {
+            assert_eq!(err.to_string(), "`math::secret` is private");
+        }
+
+        // the declaration is in the module's own file
+        match errors[1].downcast_ref::<SemanticError>() {
+            Some(SemanticError::PrivateItem {
+                span,
+                decl_file,
+                decl_span,
+                ..
+            }) => {
+                assert_eq!(&src[span.start..span.end], "math::secret");
+                assert_eq!(decl_file, "test");
+                assert_eq!(&math_src[decl_span.start..decl_span.end], "secret");
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn globals() {
+        let src = r#"const WIDTH: u8 = 250;
+        const AREA: u8 = WIDTH / 2 + 3;
+        const SIGNED: i8 = WIDTH as i8;
+        const HALF: float = 3 as float / 2.0;
+        static mut COUNT: int = -(AREA as int);
+        fn main() -> int {
+            COUNT = COUNT + 1;
+            return COUNT;
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        assert_eq!(errors.len(), 0);
+
+        let values: Vec<Option<ConstValue>> = ast
+            .program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::GlobalDecl(g) => Some(g.value.get()),
+                _ => None,
+            })
+            .collect();
+
+        // casts wrap, as they do at runtime
+        assert_eq!(
+            values,
+            vec![
+                Some(ConstValue::Int(250, PrimitiveKind::U8)),
+                Some(ConstValue::Int(128, PrimitiveKind::U8)),
+                Some(ConstValue::Int(-6, PrimitiveKind::I8)),
+                Some(ConstValue::Float(1.5)),
+                Some(ConstValue::Int(-128, PrimitiveKind::I64)),
+            ]
+        );
+    }
+
+    #[test]
+    fn const_fns() {
+        let src = r#"const fn fib(n: u32) -> u32 {
+            if n < 2 {
+                return n;
+            }
+            let mut a = fib(n - 1);
+            a = a + fib(n - 2);
+            return a;
+        }
+        const FIB: u32 = fib(20);
+        const fn forever(n: int) -> int { return forever(n + 1); }
+        const DEEP: int = forever(0);
+        fn runtime() -> u32 { return 1; }
+        const fn calls_runtime() -> u32 { return runtime(); }
+        const RUNTIME: u32 = calls_runtime();
+        fn main() -> int { return fib(3) as int; }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Constant evaluation exceeded the recursion limit",
+                "Expression is not constant",
+            ]
+        );
+
+        let fib = ast.program.items.iter().find_map(|item| match item {
+            Item::GlobalDecl(g) if g.ident.ident == "FIB" => g.value.get(),
+            _ => None,
+        });
+        assert_eq!(fib, Some(ConstValue::Int(6765, PrimitiveKind::U32)));
+    }
+
+    #[test]
+    fn const_overflow() {
+        let src = r#"const BIG: u8 = 200 + 100;
+        const NEG: u8 = 1 - 2;
+        const MIN: i8 = -128i8 / -1;
+        const ZERO: int = 5 % (2 - 2);
+        const fn half(x: int) -> int { return x / 0; }
+        const HALF: int = half(4);
+        fn main() -> int { return 0; }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Constant evaluation overflowed",
+                "Constant evaluation overflowed",
+                "Constant evaluation overflowed",
+                "Constant evaluation divided by zero",
+                "Constant evaluation divided by zero",
+            ]
+        );
+
+        // reported at the operation, even inside a `const fn`
+        match errors[4].downcast_ref::<SemanticError>() {
+            Some(SemanticError::ConstDivisionByZero { verb, span }) => {
+                assert_eq!(verb, "divide");
+                assert_eq!(&src[span.start..span.end], "x / 0");
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn constant_conditions() {
+        let src = r#"const DEBUG: bool = false;
+        const LIMIT: int = 10;
+        const fn is_even(n: int) -> bool { return n % 2 == 0; }
+        fn main() -> int {
+            let x = 3;
+            if DEBUG { return 1; }
+            if is_even(LIMIT) && LIMIT > 5 { return 2; }
+            if x > LIMIT { return 3; }
+            if true { return 4; }
+            if 1 / 0 == 0 { return 5; }
+            return 0;
+        }"#;
+        let ast = quick_parse(src);
+        let errors = analyse(&ast);
+
+        quick_errors(&errors, src);
+
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Condition is always `false`",
+                "Condition is always `true`",
+                "Constant evaluation divided by zero",
+            ]
+        );
+    }
+
+    #[test]
+    fn non_constant_globals() {
+        let src = r#"static START: int = 1;
+        const NEXT: int = START + 1;
+        const CALLED: int = one();
+        const WRONG: bool = 1;
+        static one: int = 1;
+        fn one() -> int { return 1; }
+        fn main() -> int {
+            NEXT = 3;
+            return NEXT;
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Expression is not constant",
+                "Expression is not constant",
+                "Types do not match",
+                "`one` is already declared as a function",
+                "Cannot mutate immutable variable `NEXT`",
+            ]
+        );
+    }
+
+    #[test]
+    fn logical_operators() {
+        let src = r#"const BOTH: bool = false && 1 / 0 == 0;
+        fn main() -> int {
+            let a = true;
+            let b = false;
+            let c = (a || b) && !BOTH;
+            let d = 6 && 1;
+            let e = 1.5 || 2.5;
+            if a / b {
+                return 1;
+            }
+            let f = a + b;
+            return 0;
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        // `&&` and `||` only take `bool`s, and `bool`s don't take arithmetic
+        let operators: Vec<String> = errors
+            .iter()
+            .filter_map(|e| match e.downcast_ref::<SemanticError>() {
+                Some(SemanticError::UnsupportedBinaryOperation { operator, .. }) => {
+                    Some(operator.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(operators, ["&&", "&&", "||", "||", "/", "/", "+", "+"]);
+        assert_eq!(errors.len(), operators.len());
+    }
+
+    #[test]
+    fn immutable_variable_kinds() {
+        let src = r#"static COUNT: int = 0;
+        const LIMIT: int = 10;
+        fn bump(n: int) -> int {
+            let total: int = n;
+            COUNT = 1;
+            LIMIT = 2;
+            n = 3;
+            total = 4;
+            return total;
+        }
+        fn main() -> int { return bump(1); }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        // the suggested fix depends on how the variable was declared
+        let kinds: Vec<(String, VarKind)> = errors
+            .iter()
+            .filter_map(|e| match e.downcast_ref::<SemanticError>() {
+                Some(SemanticError::ImmutableVariable(ident, kind, _, _)) => {
+                    Some((ident.ident.clone(), *kind))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                ("COUNT".to_string(), VarKind::Static),
+                ("LIMIT".to_string(), VarKind::Const),
+                ("n".to_string(), VarKind::Param),
+                ("total".to_string(), VarKind::Let),
+            ]
+        );
+    }
+
+    #[test]
+    fn generics() {
+        let src = r#"fn max<T>(a: T, b: T) -> T {
+            if a > b {
+                return a;
+            }
+            return b;
+        }
+        fn first<A, B>(a: A, b: B) -> A { return a; }
+        fn twice<T>(x: T, f: fn(T) -> T) -> T {
+            let once: T = f(x);
+            return f(once);
+        }
+        fn inc(x: u8) -> u8 { return x + 1; }
+        fn main() -> int {
+            let small: u8 = 3;
+            let big: u8 = max(small, 200);
+            let f = max(1.5, 2.5);
+            let b = first(true, f);
+            return max(1, 2) + twice(big, inc) as int;
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn generic_errors() {
+        let src = r#"fn max<T>(a: T, b: T) -> T { return a; }
+        fn make<T>() -> T { return make(); }
+        fn twice<T, T>(x: T) -> T { return x; }
+        fn neg<T>(x: T) -> T { return -x; }
+        fn add<T>(a: T, b: T) -> T { return a + b; }
+        fn same<T>(a: T, b: T) -> bool { return a == b && !(a != b); }
+        fn wrong(x: U) -> int { return 0; }
+        fn main() -> int {
+            let x = 1;
+            let f = max;
+            let a = max(1, 2.0);
+            let b = max(&x, &x);
+            let c = max(300u16, 300);
+            return max(1u8, 256) as int;
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Cannot infer type parameter `T` of `make`",
+                "Type parameter `T` is already declared",
+                "Unsupported unary operation",
+                "Unsupported binary operation",
+                "Unsupported binary operation",
+                "Type `U` is not declared",
+                "Generic function `max` cannot be used as a value",
+                "Implicit conversion from `int` to `float`",
+                "Type parameter `T` cannot be `&int`",
+                "Literal out of range for `u8`",
+            ]
+        );
+    }
+
+    #[test]
+    fn traits() {
+        let src = r#"trait Area {
+            fn area(s: Self) -> int;
+            fn grow(s: Self, by: Self) -> Self;
+        }
+        impl Area for int {
+            fn area(s: int) -> int { return s * s; }
+            fn grow(s: int, by: int) -> int { return s + by; }
+        }
+        impl Area for u8 {
+            fn area(s: u8) -> int { return s as int; }
+            fn grow(s: u8, by: u8) -> u8 { return s + by; }
+        }
+        fn total<T: Area>(a: T, b: T) -> int {
+            return Area::area(Area::grow(a, b));
+        }
+        fn main() -> int {
+            let small: u8 = 3;
+            return total(2, 3) + Area::area(small) + Area::area(Area::grow(small, 1));
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn trait_errors() {
+        let src = r#"trait Area {
+            fn area(s: Self) -> int;
+            fn grow(s: Self, by: Self) -> Self;
+        }
+        impl Area for int {
+            fn area(s: int) -> int { return s; }
+        }
+        impl Area for u8 {
+            fn area(s: u8) -> u8 { return s; }
+            fn grow(s: u8, by: u8) -> u8 { return s; }
+            fn shrink(s: u8) -> u8 { return s; }
+        }
+        impl Area for int {
+            fn area(s: int) -> int { return s; }
+            fn grow(s: int, by: int) -> int { return s; }
+        }
+        impl Volume for int {}
+        fn total<T: Area>(a: T) -> int { return Area::area(a); }
+        fn bad<T: Volume>(a: T) -> int { return 0; }
+        fn main() -> int {
+            let b = Area::perimeter(1);
+            return total(true);
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Trait `Area` is already implemented for `int`",
+                "Trait `Volume` is not declared",
+                "Method `grow` of `Area` is not implemented for `int`",
+                "Method `area` does not match its declaration in the trait",
+                "Method `shrink` is not a member of trait `Area`",
+                "Trait `Volume` is not declared",
+                "Method `perimeter` is not a member of trait `Area`",
+                "Trait `Area` is not implemented for `bool`",
+            ]
+        );
+    }
+
+    /// Captures of the closures declared by `main`, in order
+    fn closure_captures(ast: &AST) -> Vec<Vec<(String, bool)>> {
+        let main = ast
+            .program
+            .items
+            .iter()
+            .find_map(|item| match item {
+                Item::FunctionDecl(f) if f.ident.ident == "main" => Some(f),
+                _ => None,
+            })
+            .unwrap();
+
+        main.block
+            .statements
+            .iter()
+            .filter_map(|s| match s {
+                Statement::VariableDecl(VariableDecl {
+                    expression: Some(Expression::Primary(PrimaryExpression::Closure(c))),
+                    ..
+                }) => Some(
+                    c.captures
+                        .borrow()
+                        .iter()
+                        .map(|c| (c.ident.ident.clone(), c.by_ref))
+                        .collect(),
+                ),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn closures() {
+        let src = r#"static LIMIT: int = 10;
+        fn main() -> int {
+            let n = 2;
+            let mut total = 0;
+            let add = |x: int| -> int { x + n + LIMIT };
+            let bump = |by: int| -> int {
+                total = total + by;
+                total
+            };
+            let nested = |x: int| -> int {
+                let local = x * 2;
+                let inner = || -> int { local + n };
+                return inner();
+            };
+            let borrow = || -> int {
+                let r = &mut total;
+                *r = 1;
+                return n;
+            };
+            return add(1) + bump(2) + nested(3) + borrow();
+        }"#;
+        let ast = quick_parse(src);
+        let errors = analyse(&ast);
+
+        quick_errors(&errors, src);
+        assert!(errors.is_empty());
+
+        let captures = closure_captures(&ast);
+        let owned = |c: &[(&str, bool)]| -> Vec<(String, bool)> {
+            c.iter().map(|(n, r)| (n.to_string(), *r)).collect()
+        };
+        assert_eq!(
+            captures,
+            vec![
+                owned(&[("n", false)]),
+                owned(&[("total", true)]),
+                owned(&[("n", false)]),
+                owned(&[("total", true), ("n", false)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn closure_errors() {
+        let src = r#"fn main() -> int {
+            let n = 2;
+            let assign = |x: int| -> int {
+                n = x;
+                return x;
+            };
+            let wrong = |x: int| -> bool { x };
+            let missing = |x: int| -> int {
+                if x > 0 {
+                    return 1;
+                }
+            };
+            let f: fn(int) -> int = |x: int| -> int { x };
+            return f(true);
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+
+        quick_errors(&errors, src);
+
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Cannot mutate immutable variable `n`",
+                "Incompatible return type",
+                "Return not guaranteed in all branches",
+                "Types do not match",
+            ]
+        );
+    }
+
+    #[test]
+    fn control_flow_graph() {
+        use crate::semantic_analysis::cfg::{BlockId, Terminator};
+
+        let src = r#"fn main() -> int {
+            let a: int = 1;
+            if a > 0 {
+                return 1;
+            } else {
+                let b: int = 2;
+            }
+            return a;
+        }"#;
+        let ast = quick_parse(src);
+        let func = match &ast.program.items[0] {
+            Item::FunctionDecl(f) => f,
+            _ => unreachable!(),
+        };
+        let cfg = Cfg::build(&func.block);
+
+        // entry, then, after the first return, else, join, after the last return
+        assert_eq!(cfg.blocks.len(), 6);
+        assert_eq!(cfg.block(BlockId(0)).statements.len(), 2);
+        assert!(matches!(cfg.block(BlockId(0)).terminator, Terminator::Branch(_, BlockId(1), BlockId(3))));
+        assert!(matches!(cfg.block(BlockId(2)).terminator, Terminator::Goto(BlockId(4))));
+        assert!(matches!(cfg.block(BlockId(3)).terminator, Terminator::Goto(BlockId(4))));
+        assert_eq!(cfg.reachable(), [true, true, false, true, true, false]);
+        assert!(!cfg.falls_through());
+        assert!(cfg.unreachable_code().is_empty());
+    }
+
+    #[test]
+    fn unreachable_code() {
+        let src = r#"fn main() -> int {
+            if 1 > 0 {
+                return 1;
+                let dead: int = 2;
+            } else {
+                return 2;
+            }
+            let x: int = 3;
+            return x;
+        }
+
+        fn branches(a: int) -> int {
+            if a > 0 {
+                return 1;
+            } else {
+                return 2;
+            }
+        }"#;
+        let ast = quick_parse(src);
+        let warnings: Vec<Span> = analyse(&ast)
+            .iter()
+            .filter_map(|e| match e.downcast_ref::<crate::errors::Warning>() {
+                Some(crate::errors::Warning::UnreachableCode(span)) => Some(span.clone()),
+                _ => None,
+            })
+            .collect();
+
+        // nothing follows the `if` in `branches`, so nothing is unreachable
+        let start = src.find("let x").unwrap();
+        let end = src.find("return x;").unwrap() + "return x".len();
+        assert_eq!(
+            warnings,
+            [
+                Span { start: src.find("let dead").unwrap(), end: src.find("2;\n").unwrap() + 1 },
+                Span { start, end },
+            ]
+        );
+    }
+
+    #[test]
+    fn definite_assignment() {
+        let src = r#"fn main() -> int {
+            let a: int;
+            let b: int;
+            if a > 0 {
+                a = 1;
+                b = 1;
+            } else {
+                b = 2;
+            }
+            let c: int;
+            c = 1;
+            c = b;
+            let a: int = 3;
+            let mut d: int;
+            d = a;
+            d = d + 1;
+            return b + d;
+        }
+
+        fn closure() -> int {
+            let x: int;
+            let f = || -> int { return x; };
+            x = 1;
+            return f();
+        }"#;
+        let ast = quick_parse(src);
+        let errors = analyse(&ast);
+        let errors: Vec<(String, usize)> = errors
+            .iter()
+            .filter_map(|e| match e.downcast_ref::<SemanticError>() {
+                Some(e @ SemanticError::PossiblyUninitialized(_, span, _))
+                | Some(e @ SemanticError::ImmutableVariable(_, _, span, _)) => {
+                    Some((e.to_string(), span.start))
+                }
+                _ => None,
+            })
+            .collect();
+
+        // the shadowing `a` is initialized, and `b` is assigned on every path
+        assert_eq!(
+            errors,
+            [
+                ("Use of possibly-uninitialized variable `a`".to_string(), src.find("a > 0").unwrap()),
+                ("Cannot mutate immutable variable `c`".to_string(), src.find("c = b").unwrap()),
+                ("Use of possibly-uninitialized variable `x`".to_string(), src.find("x; }").unwrap()),
+            ]
+        );
+    }
+
+    #[test]
+    fn scope_tree() {
+        use crate::semantic_analysis::ScopeKind;
+
+        let src = r#"fn add(a: int, b: int) -> int {
+            let c: int = a + b;
+            if c > 0 {
+                let c: bool = true;
+            }
+            let f = |x: int| -> int { return x + c; };
+            return f(c);
+        }
+
+        fn main() -> int {
+            return add(1, 2);
+        }"#;
+        let ast = quick_parse(src);
+        let (table, errors) = analyse_module(&ast, true, &HashMap::new());
+        assert!(filter_warnings(errors).is_empty());
+        assert_eq!(table.current(), ScopeId::ROOT);
+
+        // each function has a scope for its parameters, holding one for its body
+        let functions = &table.scope(ScopeId::ROOT).children;
+        assert_eq!(functions.len(), 2);
+        let add = table.scope(functions[0]);
+        assert!(matches!(&add.kind, ScopeKind::Function(ty) if ty.to_string() == "int"));
+        let body = add.children[0];
+        let (if_block, closure) = match table.scope(body).children[..] {
+            [if_block, closure] => (if_block, closure),
+            ref children => panic!("expected an if and a closure, found {children:?}"),
+        };
+        assert_eq!(table.scope(if_block).kind, ScopeKind::Block);
+        assert!(matches!(table.scope(closure).kind, ScopeKind::Closure(_)));
+
+        // lookups return the scope declaring the variable, even after analysis
+        let ident = |name: &str| Ident::new(name.to_string(), Span::default());
+        let (scope, c) = table.lookup_var_from(if_block, &ident("c")).unwrap();
+        assert_eq!((scope, table.symbol(c).ty.to_string()), (if_block, "bool".to_string()));
+        let closure_body = table.scope(closure).children[0];
+        let (scope, c) = table.lookup_var_from(closure_body, &ident("c")).unwrap();
+        assert_eq!((scope, table.symbol(c).ty.to_string()), (body, "int".to_string()));
+        assert_eq!(table.lookup_var_from(closure_body, &ident("a")).unwrap().0, functions[0]);
+        assert!(table.lookup_var(&ident("a")).is_none());
+
+        // each ident records the variable it declares or uses
+        let statements = match &ast.program.items[0] {
+            Item::FunctionDecl(f) => &f.block.statements,
+            _ => panic!("expected `add`"),
+        };
+        let declared = |statement: &Statement| match statement {
+            Statement::VariableDecl(v) => v.ident.symbol.get().copied(),
+            s => panic!("expected a let, found {s:?}"),
+        };
+        let (_, outer_c) = table.lookup_var_from(body, &ident("c")).unwrap();
+        assert_eq!(declared(&statements[0]), Some(outer_c));
+        match &statements[1] {
+            Statement::Flow(f) => assert_ne!(declared(&f.if_block.statements[0]), Some(outer_c)),
+            s => panic!("expected an if, found {s:?}"),
+        }
+        let (_, f) = table.lookup_var_from(body, &ident("f")).unwrap();
+        let call = match &statements[3] {
+            Statement::Return(Some(e)) => e.strip_parens(),
+            s => panic!("expected a return, found {s:?}"),
+        };
+        match call {
+            Expression::Primary(PrimaryExpression::FunctionCall(callee, args)) => {
+                assert_eq!(callee.symbol.get(), Some(&f));
+                assert_eq!(args[0].idents_used()[0].symbol.get(), Some(&outer_c));
+            }
+            e => panic!("expected a call, found {e:?}"),
+        }
+    }
+
+    #[test]
+    fn return_type_uses_parameters() {
+        let src = r#"fn negate(b: bool) -> int {
+            return b;
+        }
+
+        fn main() -> int {
+            let x: int = 1;
+            if x > 0 {
+                let x: bool = true;
+                return x;
+            }
+            return x;
+        }"#;
+        let ast = quick_parse(src);
+        let errors = filter_warnings(analyse(&ast));
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+
+        // the variables in scope at each `return` give its type
+        assert_eq!(messages, ["Incompatible return type", "Incompatible return type"]);
+    }
 }
@@ -0,0 +1,183 @@
+//! Builds the control-flow graph of a function or closure body from its AST.
+//!
+//! Each basic block holds the statements run in order, the last of which
+//! may be the `if` or `return` ending it. A statement following a `return`
+//! starts a block with no predecessors, so code that can never run is kept
+//! and can be reported.
+//!
+//! Flow checks are computed from the graph once per body: whether control
+//! can reach the end of the body without returning, and which statements are
+//! unreachable.
+
+use std::ptr;
+
+use crate::ast::*;
+use crate::token::Span;
+
+/// The control-flow graph of a body
+#[derive(Debug)]
+pub struct Cfg<'a> {
+    /// The entry block is first
+    pub blocks: Vec<BasicBlock<'a>>,
+    body: &'a Block,
+}
+
+/// Index of a block in [`Cfg::blocks`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockId(pub usize);
+
+#[derive(Debug)]
+pub struct BasicBlock<'a> {
+    pub statements: Vec<&'a Statement>,
+    pub terminator: Terminator<'a>,
+}
+
+#[derive(Debug)]
+pub enum Terminator<'a> {
+    Goto(BlockId),
+    /// `Branch(condition, then, else)`
+    Branch(&'a Expression, BlockId, BlockId),
+    Return(Option<&'a Expression>),
+    /// The end of the body, reached without returning
+    FallThrough,
+}
+
+impl<'a> Cfg<'a> {
+    pub fn build(body: &'a Block) -> Self {
+        let mut cfg = Cfg {
+            blocks: Vec::new(),
+            body,
+        };
+        let entry = cfg.new_block();
+        cfg.add_block(entry, body);
+        cfg
+    }
+
+    pub fn block(&self, id: BlockId) -> &BasicBlock<'a> {
+        &self.blocks[id.0]
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push(BasicBlock {
+            statements: Vec::new(),
+            terminator: Terminator::FallThrough,
+        });
+        BlockId(self.blocks.len() - 1)
+    }
+
+    /// Add the statements of a block, starting in `current`.
+    /// Returns the block control leaves them from
+    fn add_block(&mut self, mut current: BlockId, block: &'a Block) -> BlockId {
+        for statement in &block.statements {
+            self.blocks[current.0].statements.push(statement);
+
+            match statement {
+                Statement::Return(e) => {
+                    self.blocks[current.0].terminator = Terminator::Return(e.as_deref());
+                    // anything after a return is unreachable, but still needs a block
+                    current = self.new_block();
+                }
+                Statement::Flow(flow) => {
+                    let then_block = self.new_block();
+                    let then_end = self.add_block(then_block, &flow.if_block);
+
+                    let (else_block, else_end) = match &flow.else_block {
+                        Some(block) => {
+                            let else_block = self.new_block();
+                            (Some(else_block), Some(self.add_block(else_block, block)))
+                        }
+                        None => (None, None),
+                    };
+
+                    let join = self.new_block();
+                    self.blocks[current.0].terminator =
+                        Terminator::Branch(&flow.condition, then_block, else_block.unwrap_or(join));
+                    for end in std::iter::once(then_end).chain(else_end) {
+                        self.blocks[end.0].terminator = Terminator::Goto(join);
+                    }
+                    current = join;
+                }
+                _ => {}
+            }
+        }
+
+        current
+    }
+
+    /// Blocks control may continue to from a block
+    pub fn successors(&self, id: BlockId) -> Vec<BlockId> {
+        match self.block(id).terminator {
+            Terminator::Goto(b) => vec![b],
+            Terminator::Branch(_, then, els) => vec![then, els],
+            Terminator::Return(_) | Terminator::FallThrough => vec![],
+        }
+    }
+
+    /// Whether each block is reachable from the entry block, by [`BlockId`]
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut queue = vec![BlockId(0)];
+        while let Some(id) = queue.pop() {
+            if !reachable[id.0] {
+                reachable[id.0] = true;
+                queue.extend(self.successors(id));
+            }
+        }
+        reachable
+    }
+
+    /// Can control reach the end of the body without returning?
+    pub fn falls_through(&self) -> bool {
+        let reachable = self.reachable();
+        self.blocks
+            .iter()
+            .enumerate()
+            .any(|(i, b)| reachable[i] && matches!(b.terminator, Terminator::FallThrough))
+    }
+
+    /// Does the body have any `return`, reachable or not?
+    pub fn has_return(&self) -> bool {
+        self.blocks
+            .iter()
+            .any(|b| matches!(b.terminator, Terminator::Return(_)))
+    }
+
+    /// Spans of unreachable code, each from the first statement never run in
+    /// a block to the end of the block
+    pub fn unreachable_code(&self) -> Vec<Span> {
+        let reachable = self.reachable();
+        let is_reachable = |statement: &Statement| {
+            self.blocks
+                .iter()
+                .enumerate()
+                .any(|(i, b)| reachable[i] && b.statements.iter().any(|s| ptr::eq(*s, statement)))
+        };
+
+        let mut spans = Vec::new();
+        unreachable_in(self.body, &is_reachable, &mut spans);
+        spans
+    }
+}
+
+/// Only the first unreachable statement of each block is reported, so the
+/// blocks nested in it are not searched
+fn unreachable_in(
+    block: &Block,
+    is_reachable: &impl Fn(&Statement) -> bool,
+    spans: &mut Vec<Span>,
+) {
+    for statement in &block.statements {
+        if !is_reachable(statement) {
+            let last = block.statements.last().unwrap_or(statement);
+            spans.push(Span::combine(&statement.span(), &last.span()));
+            return;
+        }
+
+        if let Statement::Flow(flow) = statement {
+            unreachable_in(&flow.if_block, is_reachable, spans);
+            if let Some(else_block) = &flow.else_block {
+                unreachable_in(else_block, is_reachable, spans);
+            }
+        }
+    }
+}
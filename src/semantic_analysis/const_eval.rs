@@ -0,0 +1,390 @@
+//! Evaluates constant expressions at compile time.
+//!
+//! Used for the initializers of `const` and `static` items, and to find
+//! conditions that are always true or false. Expressions are evaluated
+//! after analysis, so they are already known to be well typed.
+//!
+//! Calls to `const fn`s are evaluated by interpreting their body.
+//! Overflow and division by zero are errors, rather than wrapping or
+//! panicking as they would at runtime.
+
+use std::collections::HashMap;
+
+use super::symbols::SymbolTable;
+use crate::ast::*;
+use crate::errors::SemanticError;
+use crate::token::Span;
+use anyhow::{anyhow, Error, Result};
+use log::debug;
+
+/// Deepest chain of `const fn` calls before evaluation gives up
+pub const MAX_CALL_DEPTH: usize = 128;
+
+/// A value known at compile time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConstValue {
+    /// An integer, always in range of its type
+    Int(i128, PrimitiveKind),
+    Float(f64),
+    Bool(bool),
+}
+
+impl ConstValue {
+    /// Convert to another primitive type, following the rules of `as`
+    pub fn cast(self, to: PrimitiveKind) -> ConstValue {
+        match (self, to) {
+            (ConstValue::Int(v, _), PrimitiveKind::Bool) => ConstValue::Bool(v != 0),
+            (ConstValue::Int(v, _), PrimitiveKind::Float) => ConstValue::Float(v as f32 as f64),
+            (ConstValue::Int(v, _), to) => ConstValue::Int(to.wrap(v), to),
+            (ConstValue::Float(f), PrimitiveKind::Bool) => ConstValue::Bool(f != 0.0),
+            (ConstValue::Float(f), PrimitiveKind::Float) => ConstValue::Float(f),
+            // saturating, and NaN is zero
+            (ConstValue::Float(f), to) => ConstValue::Int(to.saturate(f), to),
+            (ConstValue::Bool(b), PrimitiveKind::Float) => ConstValue::Float(b as u8 as f64),
+            (ConstValue::Bool(b), PrimitiveKind::Bool) => ConstValue::Bool(b),
+            (ConstValue::Bool(b), to) => ConstValue::Int(b as i128, to),
+        }
+    }
+}
+
+impl std::fmt::Display for ConstValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConstValue::Int(v, _) => write!(f, "{}", v),
+            ConstValue::Float(v) => write!(f, "{}", v),
+            ConstValue::Bool(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// Evaluate an analysed expression
+pub fn eval(expr: &Expression, table: &SymbolTable) -> Result<ConstValue> {
+    ConstEvaluator::new(table).eval(expr)
+}
+
+/// Did evaluation fail only because the expression is not constant?
+/// Other errors mean it would always fail at runtime
+pub fn is_not_constant(err: &Error) -> bool {
+    matches!(
+        err.downcast_ref::<SemanticError>(),
+        Some(SemanticError::NotConstant(_))
+    )
+}
+
+/// Evaluates expressions in a scope, including calls to `const fn`s
+pub struct ConstEvaluator<'t, 'a> {
+    table: &'t SymbolTable<'a>,
+    /// Locals of each `const fn` call being evaluated, innermost call last.
+    /// Every call has a stack of block scopes, holding `None` for locals
+    /// declared without a value until they are assigned
+    frames: Vec<Vec<HashMap<String, Option<ConstValue>>>>,
+}
+
+/// How a statement of a `const fn` finished
+enum Flow {
+    Next,
+    Return(ConstValue),
+}
+
+impl<'t, 'a> ConstEvaluator<'t, 'a> {
+    /// Evaluate expressions using the constants visible in `table`
+    pub fn new(table: &'t SymbolTable<'a>) -> Self {
+        Self {
+            table,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn eval(&mut self, expr: &Expression) -> Result<ConstValue> {
+        debug!("Evaluating constant expression: {:?}", expr);
+
+        match expr {
+            Expression::Primary(p) => self.eval_primary(p),
+            Expression::Unary(u) => self.eval_unary(u),
+            Expression::Binary(b) => self.eval_binary(b),
+            Expression::Cast(c) => match &c.ty {
+                Type::Primitive(p) => Ok(self.eval(&c.expr)?.cast(p.kind)),
+                _ => Err(not_constant(&c.span)),
+            },
+        }
+    }
+
+    fn eval_primary(&mut self, expr: &PrimaryExpression) -> Result<ConstValue> {
+        match expr {
+            PrimaryExpression::Literal(l) => Ok(match l.kind {
+                LiteralKind::Int(i) => match l.get_type() {
+                    Type::Primitive(p) => ConstValue::Int(i as i128, p.kind),
+                    _ => unreachable!("integer literals have a primitive type"),
+                },
+                LiteralKind::Float(f) => ConstValue::Float(f),
+                LiteralKind::Bool(b) => ConstValue::Bool(b),
+            }),
+            PrimaryExpression::Ident(i) => self.lookup(i),
+            PrimaryExpression::Parenthesized(e) => self.eval(e),
+            PrimaryExpression::FunctionCall(i, args) => self.call(i, args),
+            // function values only exist at run time
+            PrimaryExpression::Closure(c) => Err(not_constant(&c.span)),
+        }
+    }
+
+    /// Get the value of a local of the current `const fn`, or a `const`
+    /// Variables and `static`s have no value at compile time
+    fn lookup(&self, ident: &Ident) -> Result<ConstValue> {
+        let var = match self.frames.last() {
+            Some(scopes) => {
+                // locals not yet assigned are rejected by analysis
+                if let Some(local) = scopes.iter().rev().find_map(|s| s.get(&ident.ident)) {
+                    return local.ok_or_else(|| not_constant(&ident.span));
+                }
+
+                // a `const fn` can't see its caller's variables
+                self.table.get_global(ident)
+            }
+            None => self.table.get_var(ident),
+        };
+
+        var.and_then(|var| var.value)
+            .ok_or_else(|| not_constant(&ident.span))
+    }
+
+    /// Evaluate a call by interpreting the body of a `const fn`
+    fn call(&mut self, ident: &Ident, args: &[Expression]) -> Result<ConstValue> {
+        let func = match self.table.get_const_fn(ident) {
+            Some(func) => func,
+            None => return Err(not_constant(&ident.span)),
+        };
+
+        let mut params = HashMap::new();
+        for (param, arg) in func.parameters.iter().zip(args) {
+            params.insert(param.ident.ident.clone(), Some(self.eval(arg)?));
+        }
+
+        if self.frames.len() >= MAX_CALL_DEPTH {
+            return Err(anyhow!(SemanticError::ConstRecursionLimit(
+                ident.span.clone()
+            )));
+        }
+
+        debug!("Evaluating call to const fn {}", ident);
+        self.frames.push(vec![params]);
+        let flow = self.exec_block(&func.block);
+        self.frames.pop();
+
+        match flow? {
+            Flow::Return(value) => Ok(value),
+            // analysis guarantees a return
+            Flow::Next => Err(not_constant(&func.span)),
+        }
+    }
+
+    fn exec_block(&mut self, block: &Block) -> Result<Flow> {
+        self.scopes().push(HashMap::new());
+
+        let mut flow = Ok(Flow::Next);
+        for statement in &block.statements {
+            flow = self.exec(statement);
+            if !matches!(flow, Ok(Flow::Next)) {
+                break;
+            }
+        }
+
+        self.scopes().pop();
+        flow
+    }
+
+    fn exec(&mut self, statement: &Statement) -> Result<Flow> {
+        match statement {
+            Statement::Expression(e) => {
+                self.eval(e)?;
+            }
+            Statement::VariableDecl(v) => {
+                let value = v.expression.as_ref().map(|e| self.eval(e)).transpose()?;
+                let scope = self.scopes().last_mut().unwrap();
+                scope.insert(v.ident.ident.clone(), value);
+            }
+            Statement::Assignment(a) => {
+                let value = self.eval(&a.expression)?;
+                let ident = match a.target.strip_parens() {
+                    Expression::Primary(PrimaryExpression::Ident(i)) => i,
+                    _ => return Err(not_constant(&a.target.span())),
+                };
+
+                match self
+                    .scopes()
+                    .iter_mut()
+                    .rev()
+                    .find_map(|s| s.get_mut(&ident.ident))
+                {
+                    Some(local) => *local = Some(value),
+                    None => return Err(not_constant(&ident.span)),
+                }
+            }
+            Statement::Flow(f) => {
+                let branch = match self.eval(&f.condition)? {
+                    ConstValue::Bool(true) => Some(&f.if_block),
+                    ConstValue::Bool(false) => f.else_block.as_ref(),
+                    _ => return Err(not_constant(&f.condition.span())),
+                };
+
+                if let Some(block) = branch {
+                    return self.exec_block(block);
+                }
+            }
+            Statement::Return(Some(e)) => return Ok(Flow::Return(self.eval(e)?)),
+            Statement::Return(None) => return Err(not_constant(&statement.span())),
+        }
+
+        Ok(Flow::Next)
+    }
+
+    /// Block scopes of the `const fn` being evaluated
+    fn scopes(&mut self) -> &mut Vec<HashMap<String, Option<ConstValue>>> {
+        self.frames
+            .last_mut()
+            .expect("statements are only evaluated in a const fn")
+    }
+
+    fn eval_unary(&mut self, expr: &UnaryExpression) -> Result<ConstValue> {
+        let value = match &expr.kind {
+            UnaryExpressionKind::Negation(e) | UnaryExpressionKind::Not(e) => self.eval(e)?,
+            _ => return Err(not_constant(&expr.span)),
+        };
+
+        match (&expr.kind, value) {
+            (UnaryExpressionKind::Negation(_), value) => negate(value, &expr.span),
+            (UnaryExpressionKind::Not(_), ConstValue::Bool(b)) => Ok(ConstValue::Bool(!b)),
+            _ => Err(not_constant(&expr.span)),
+        }
+    }
+
+    fn eval_binary(&mut self, expr: &BinaryExpression) -> Result<ConstValue> {
+        let lhs = self.eval(&expr.lhs)?;
+
+        // the right-hand side of `&&` and `||` is only evaluated if needed
+        match (&expr.op.kind, lhs) {
+            (BinaryOperatorKind::And, ConstValue::Bool(false))
+            | (BinaryOperatorKind::Or, ConstValue::Bool(true)) => return Ok(lhs),
+            _ => (),
+        }
+
+        let rhs = self.eval(&expr.rhs)?;
+
+        binary(&expr.op.kind, lhs, rhs, &expr.span)
+    }
+}
+
+/// `-value`, an overflow error for the minimum of a signed type
+pub fn negate(value: ConstValue, span: &Span) -> Result<ConstValue> {
+    match value {
+        ConstValue::Int(v, kind) => checked(v.checked_neg(), kind, "negate", span),
+        ConstValue::Float(f) => Ok(ConstValue::Float(-f)),
+        ConstValue::Bool(_) => Err(not_constant(span)),
+    }
+}
+
+/// Apply a binary operator to two values of the same type
+/// Overflow and division by zero are errors, located at `span`
+pub fn binary(
+    op: &BinaryOperatorKind,
+    lhs: ConstValue,
+    rhs: ConstValue,
+    span: &Span,
+) -> Result<ConstValue> {
+    match (lhs, rhs) {
+        (ConstValue::Int(a, kind), ConstValue::Int(b, _)) => eval_int(op, a, b, kind, span),
+        // evaluated at the precision of `float`
+        (ConstValue::Float(a), ConstValue::Float(b)) => {
+            let (a, b) = (a as f32, b as f32);
+            let value = match op {
+                BinaryOperatorKind::Add => ConstValue::Float((a + b) as f64),
+                BinaryOperatorKind::Subtract => ConstValue::Float((a - b) as f64),
+                BinaryOperatorKind::Multiply => ConstValue::Float((a * b) as f64),
+                BinaryOperatorKind::Divide => ConstValue::Float((a / b) as f64),
+                // as codegen computes it
+                BinaryOperatorKind::Modulus => ConstValue::Float((a - (a / b).trunc() * b) as f64),
+                op if op.is_comparison() => match a.partial_cmp(&b) {
+                    Some(ordering) => ConstValue::Bool(compare(op, ordering)),
+                    // NaN is unequal to everything
+                    None => ConstValue::Bool(matches!(op, BinaryOperatorKind::NotEqual)),
+                },
+                _ => return Err(not_constant(span)),
+            };
+            Ok(value)
+        }
+        (ConstValue::Bool(a), ConstValue::Bool(b)) => match op {
+            BinaryOperatorKind::And => Ok(ConstValue::Bool(a && b)),
+            BinaryOperatorKind::Or => Ok(ConstValue::Bool(a || b)),
+            op if op.is_comparison() => Ok(ConstValue::Bool(compare(op, a.cmp(&b)))),
+            _ => Err(not_constant(span)),
+        },
+        _ => Err(not_constant(span)),
+    }
+}
+
+/// Integer arithmetic and comparisons, which must stay in range of the type
+fn eval_int(
+    op: &BinaryOperatorKind,
+    a: i128,
+    b: i128,
+    kind: PrimitiveKind,
+    span: &Span,
+) -> Result<ConstValue> {
+    let (value, verb) = match op {
+        BinaryOperatorKind::Add => (a.checked_add(b), "add"),
+        BinaryOperatorKind::Subtract => (a.checked_sub(b), "subtract"),
+        BinaryOperatorKind::Multiply => (a.checked_mul(b), "multiply"),
+        BinaryOperatorKind::Divide => (a.checked_div(b), "divide"),
+        BinaryOperatorKind::Modulus => (a.checked_rem(b), "calculate the remainder"),
+        BinaryOperatorKind::And | BinaryOperatorKind::Or => return Err(not_constant(span)),
+        op => return Ok(ConstValue::Bool(compare(op, a.cmp(&b)))),
+    };
+
+    if b == 0 && value.is_none() {
+        return Err(anyhow!(SemanticError::ConstDivisionByZero {
+            verb: verb.to_string(),
+            span: span.clone(),
+        }));
+    }
+
+    checked(value, kind, verb, span)
+}
+
+/// An integer result, or an overflow error if it is out of range of its type
+/// `None` is a result too large for any type
+fn checked(
+    value: Option<i128>,
+    kind: PrimitiveKind,
+    verb: &str,
+    span: &Span,
+) -> Result<ConstValue> {
+    match value {
+        Some(value) if kind.fits(value) => Ok(ConstValue::Int(value, kind)),
+        _ => {
+            debug!("Constant {:?} overflows {:?}", value, kind);
+            Err(anyhow!(SemanticError::ConstOverflow {
+                verb: verb.to_string(),
+                ty: Type::primitive(kind),
+                span: span.clone(),
+            }))
+        }
+    }
+}
+
+/// Does the ordering of two operands satisfy a comparison operator?
+fn compare(op: &BinaryOperatorKind, ordering: std::cmp::Ordering) -> bool {
+    use std::cmp::Ordering::*;
+
+    match op {
+        BinaryOperatorKind::Equal => ordering == Equal,
+        BinaryOperatorKind::NotEqual => ordering != Equal,
+        BinaryOperatorKind::LessThan => ordering == Less,
+        BinaryOperatorKind::LessThanOrEqual => ordering != Greater,
+        BinaryOperatorKind::GreaterThan => ordering == Greater,
+        BinaryOperatorKind::GreaterThanOrEqual => ordering != Less,
+        op => unreachable!("not a comparison: {:?}", op),
+    }
+}
+
+fn not_constant(span: &Span) -> Error {
+    anyhow!(SemanticError::NotConstant(span.clone()))
+}
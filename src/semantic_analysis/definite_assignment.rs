@@ -0,0 +1,197 @@
+//! Checks variables declared without a value, `let x: T;`, are assigned
+//! before they are read.
+//!
+//! A forward dataflow pass over the [`Cfg`] of a body finds the variables
+//! assigned on every path to each block, and those assigned on some path.
+//! A read of a variable not assigned on every path is reported, as is a
+//! second assignment to an immutable one: it may be assigned only once.
+//!
+//! Variables are told apart by the symbol analysis recorded on each ident
+//! rather than by name, so a `let` shadowing another is its own variable.
+//! Reads inside a closure happen when it is created, as that is when it
+//! captures the variable, so the body must have been analysed to know the
+//! captures.
+
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Error};
+use log::{debug, warn};
+
+use super::cfg::{BlockId, Cfg};
+use super::{SymbolId, SymbolTable, VarKind};
+use crate::ast::*;
+use crate::errors::SemanticError;
+
+/// Report reads of variables that may not be assigned yet, and assignments
+/// to immutable variables that may already be
+pub fn check_definite_assignment(body: &Block, table: &SymbolTable) -> Vec<Error> {
+    let mut decls = Declarations {
+        table,
+        decls: HashSet::new(),
+    };
+    decls.collect(body);
+    if decls.decls.is_empty() {
+        return Vec::new();
+    }
+
+    let cfg = Cfg::build(body);
+    let states = decls.solve(&cfg);
+    let mut errors = Vec::new();
+    for (i, block) in cfg.blocks.iter().enumerate() {
+        // unreachable blocks are reported as such
+        if let Some(state) = &states[i] {
+            let mut state = state.clone();
+            for statement in &block.statements {
+                decls.transfer(statement, &mut state, &mut errors);
+            }
+        }
+    }
+
+    debug!("Definite assignment errors: {:?}", errors);
+
+    errors
+}
+
+/// The variables of a body declared without a value
+struct Declarations<'t> {
+    table: &'t SymbolTable<'t>,
+    decls: HashSet<SymbolId>,
+}
+
+/// Which variables are assigned on entry to a block
+#[derive(Debug, Clone, PartialEq, Default)]
+struct State {
+    /// On every path
+    assigned: HashSet<SymbolId>,
+    /// On at least one path
+    maybe_assigned: HashSet<SymbolId>,
+}
+
+impl State {
+    fn join(&mut self, other: &State) {
+        self.assigned.retain(|d| other.assigned.contains(d));
+        self.maybe_assigned.extend(&other.maybe_assigned);
+    }
+}
+
+impl Declarations<'_> {
+    /// Find the variables declared without a value, in nested blocks too.
+    /// Those of closures are checked with the closure's body
+    fn collect(&mut self, block: &Block) {
+        for statement in &block.statements {
+            match statement {
+                Statement::VariableDecl(v) if v.expression.is_none() => {
+                    self.decls.extend(v.ident.symbol.get());
+                }
+                Statement::Flow(flow) => {
+                    self.collect(&flow.if_block);
+                    if let Some(else_block) = &flow.else_block {
+                        self.collect(else_block);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// The variable used by an identifier, if it is one to check
+    fn decl(&self, ident: &Ident) -> Option<SymbolId> {
+        ident
+            .symbol
+            .get()
+            .copied()
+            .filter(|id| self.decls.contains(id))
+    }
+
+    /// The state on entry to each block, `None` for blocks never reached
+    fn solve(&self, cfg: &Cfg) -> Vec<Option<State>> {
+        let mut states = vec![None; cfg.blocks.len()];
+        states[0] = Some(State::default());
+
+        let mut queue = vec![BlockId(0)];
+        while let Some(id) = queue.pop() {
+            let mut state = match &states[id.0] {
+                Some(state) => state.clone(),
+                None => continue,
+            };
+            for statement in &cfg.block(id).statements {
+                self.transfer(statement, &mut state, &mut Vec::new());
+            }
+
+            for succ in cfg.successors(id) {
+                let joined = match &states[succ.0] {
+                    Some(existing) => {
+                        let mut joined = existing.clone();
+                        joined.join(&state);
+                        joined
+                    }
+                    None => state.clone(),
+                };
+                if states[succ.0].as_ref() != Some(&joined) {
+                    states[succ.0] = Some(joined);
+                    queue.push(succ);
+                }
+            }
+        }
+
+        states
+    }
+
+    /// Update the state for a statement run, reporting any invalid use.
+    /// A branch's blocks are separate, so only its condition is run here
+    fn transfer(&self, statement: &Statement, state: &mut State, errors: &mut Vec<Error>) {
+        match statement {
+            Statement::VariableDecl(v) => {
+                if let Some(e) = &v.expression {
+                    self.read(e, state, errors);
+                }
+            }
+            Statement::Assignment(a) => {
+                self.read(&a.expression, state, errors);
+
+                let target = match a.target.strip_parens() {
+                    Expression::Primary(PrimaryExpression::Ident(i)) => i,
+                    // assigning through a reference reads it
+                    target => return self.read(target, state, errors),
+                };
+                if let Some(decl) = self.decl(target) {
+                    let var = self.table.symbol(decl);
+                    if !var.mutable && state.maybe_assigned.contains(&decl) {
+                        warn!("Immutable variable may be assigned twice: {}", target);
+                        errors.push(anyhow!(SemanticError::ImmutableVariable(
+                            target.clone(),
+                            VarKind::Let,
+                            target.span.clone(),
+                            var.span.clone()
+                        )));
+                    }
+                    state.assigned.insert(decl);
+                    state.maybe_assigned.insert(decl);
+                }
+            }
+            Statement::Expression(e) => self.read(e, state, errors),
+            Statement::Return(e) => {
+                if let Some(e) = e {
+                    self.read(e, state, errors);
+                }
+            }
+            Statement::Flow(flow) => self.read(&flow.condition, state, errors),
+        }
+    }
+
+    fn read(&self, expr: &Expression, state: &State, errors: &mut Vec<Error>) {
+        for ident in expr.idents_used() {
+            if let Some(decl) = self.decl(&ident) {
+                if !state.assigned.contains(&decl) {
+                    warn!("Variable may be read before it is assigned: {}", ident);
+                    let span = ident.span.clone();
+                    errors.push(anyhow!(SemanticError::PossiblyUninitialized(
+                        ident,
+                        span,
+                        self.table.symbol(decl).span.clone()
+                    )));
+                }
+            }
+        }
+    }
+}
@@ -1,7 +1,12 @@
 pub mod analysis;
+pub mod cfg;
+pub mod const_eval;
+pub mod definite_assignment;
 pub mod symbols;
 pub mod traits;
 
 pub use analysis::*;
+pub use cfg::Cfg;
+pub use const_eval::ConstValue;
 pub use symbols::*;
 pub use traits::*;
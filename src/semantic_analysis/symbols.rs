@@ -1,7 +1,17 @@
 //! Defines the symbol table and its related functions.
+//!
+//! The table of a module is a tree of scopes stored in an arena, the
+//! module's own scope first. Analysis enters a scope for each function,
+//! closure and block it checks and declares symbols in the current one;
+//! lookups start there and go outwards. Scopes and variables are referred
+//! to by [`ScopeId`] and [`SymbolId`], which stay valid once analysis is
+//! done, so the finished table is kept for lowering. Each ident declaring
+//! or using a variable records its [`SymbolId`], so later passes needn't
+//! resolve names again.
 
 use crate::ast::*;
 use crate::errors::SemanticError;
+use crate::semantic_analysis::ConstValue;
 use crate::token::Span;
 
 use anyhow::{anyhow, Result};
@@ -12,12 +22,75 @@ use std::collections::HashMap;
 /// Represents a symbol table
 #[derive(Debug)]
 pub struct SymbolTable<'a> {
-    /// Table for variables
-    pub variables: HashMap<Ident, VarSymbol>,
+    /// Every scope, the module's first
+    scopes: Vec<Scope<'a>>,
+    /// Every variable declared, including those since shadowed
+    symbols: Vec<VarSymbol>,
+    /// The scope being analysed
+    current: ScopeId,
+}
+
+/// Index of a scope in the table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ScopeId(pub usize);
+
+impl ScopeId {
+    /// The module's scope, holding its items
+    pub const ROOT: ScopeId = ScopeId(0);
+}
+
+/// Index of a variable in the table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SymbolId(pub usize);
+
+/// What a scope belongs to
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScopeKind {
+    /// The module's items
+    Module,
+    /// Type parameters and parameters of a function, and its return type
+    Function(Type),
+    /// Parameters of a closure, and its return type
+    Closure(Type),
+    /// `Self` in the signatures of a trait's methods
+    Trait,
+    /// Variables declared by `let` in a block
+    Block,
+}
+
+/// Represents a scope
+#[derive(Debug)]
+pub struct Scope<'a> {
+    pub kind: ScopeKind,
+    /// The enclosing scope, `None` for the module's
+    pub parent: Option<ScopeId>,
+    /// Scopes nested in this one, in the order they were entered
+    pub children: Vec<ScopeId>,
+    /// Full span of what the scope belongs to
+    pub span: Span,
+    /// Table for variables, holding the latest of each name
+    pub variables: HashMap<Ident, SymbolId>,
     /// Table for functions
     pub functions: HashMap<Ident, FuncSymbol>,
-    /// Parent
-    pub parent: Option<Box<&'a SymbolTable<'a>>>,
+    /// Table for modules declared with `mod`
+    pub modules: HashMap<String, ModuleSymbol>,
+    /// Bodies of `const fn`s, for evaluating calls at compile time
+    pub const_fns: HashMap<Ident, &'a FunctionDecl>,
+    /// Table for traits
+    pub traits: HashMap<String, TraitSymbol>,
+    /// Trait implementations
+    pub impls: Vec<ImplSymbol>,
+    /// Type parameters of the generic function being analysed
+    pub type_params: HashMap<String, TypeParamSymbol>,
+}
+
+/// How a variable was declared
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VarKind {
+    Let,
+    Param,
+    Const,
+    Static,
 }
 
 /// Represents a variable symbol
@@ -25,12 +98,21 @@ pub struct SymbolTable<'a> {
 pub struct VarSymbol {
     /// The type of the variable
     pub ty: Type,
+    /// How it was declared
+    pub kind: VarKind,
+    /// Declared with `mut`?
+    pub mutable: bool,
+    /// Value of a `const`, known at compile time
+    pub value: Option<ConstValue>,
+    /// Declared by `let x: T;`, to be assigned later.
+    /// Even an immutable one may be assigned once
+    pub deferred: bool,
     /// Full span
     pub span: Span,
 }
 
 /// Represents a function symbol
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FuncSymbol {
     /// Parameter types
     pub params: Vec<Type>,
@@ -42,117 +124,658 @@ pub struct FuncSymbol {
     pub ident_span: Span,
     /// Signature span
     pub sig_span: Span,
+    /// Declared with `pub`?
+    pub public: bool,
+    /// Type parameters and their bounds, empty unless generic
+    pub type_params: Vec<TypeParam>,
 }
 
-impl<'a> SymbolTable<'a> {
-    /// Creates a new symbol table
-    pub fn new() -> Self {
-        SymbolTable {
+/// Represents a trait symbol
+#[derive(Debug, Clone)]
+pub struct TraitSymbol {
+    /// Method signatures in declaration order, by name
+    /// Each is generic over `Self`, bounded by the trait
+    pub methods: Vec<(String, FuncSymbol)>,
+    /// Full span
+    pub span: Span,
+}
+
+/// Represents an `impl Trait for type`
+#[derive(Debug, Clone)]
+pub struct ImplSymbol {
+    /// Name of the implemented trait
+    pub trait_name: String,
+    /// The implementing type
+    pub ty: Type,
+    /// Full span
+    pub span: Span,
+}
+
+/// Represents a type parameter in scope
+#[derive(Debug)]
+pub struct TypeParamSymbol {
+    /// Traits the type must implement
+    pub bounds: Vec<String>,
+    /// Span of the declaration
+    pub span: Span,
+}
+
+/// Represents a module symbol
+#[derive(Debug, Clone)]
+pub struct ModuleSymbol {
+    /// Functions in the module, by unqualified name
+    pub functions: HashMap<String, FuncSymbol>,
+    /// Span of the `mod` item
+    pub span: Span,
+    /// The module's file
+    pub file: String,
+    /// Where each function is declared, in the module's own file
+    pub decl_spans: HashMap<String, Span>,
+    /// Trait implementations in the module, which bound its generic functions
+    pub impls: Vec<ImplSymbol>,
+}
+
+impl ModuleSymbol {
+    /// Collect the functions declared by a module
+    /// Redeclarations are reported when the module itself is analysed
+    pub fn new(ast: &AST) -> Self {
+        let mut table = SymbolTable::new();
+        for item in &ast.program.items {
+            match item {
+                Item::FunctionDecl(f) => {
+                    let _ = table.add_fn(f);
+                }
+                Item::ImplDecl(i) => table.scope_mut().impls.push(ImplSymbol::new(i)),
+                _ => {}
+            }
+        }
+
+        // only the module's scope was used
+        let root = table.scopes.swap_remove(ScopeId::ROOT.0);
+        let decl_spans = root
+            .functions
+            .iter()
+            .map(|(ident, func)| (ident.ident.clone(), func.ident_span.clone()))
+            .collect();
+
+        ModuleSymbol {
+            functions: root
+                .functions
+                .into_iter()
+                .map(|(ident, func)| (ident.ident, func))
+                .collect(),
+            span: Span::default(),
+            file: ast.file_id.clone(),
+            decl_spans,
+            impls: root.impls,
+        }
+    }
+
+    /// Does the module implement a trait for a type?
+    pub fn implements(&self, ty: &Type, trait_name: &str) -> bool {
+        self.impls
+            .iter()
+            .any(|i| i.trait_name == trait_name && i.ty == *ty)
+    }
+
+    /// The module as seen from a `mod` item
+    /// Spans in the module's file are meaningless in the declaring file,
+    /// so everything points at the `mod` item instead
+    fn declared_at(&self, span: &Span) -> Self {
+        let functions = self
+            .functions
+            .iter()
+            .map(|(name, func)| {
+                let func = FuncSymbol {
+                    params: func.params.iter().map(|p| p.with_span(span)).collect(),
+                    ret_ty: func.ret_ty.with_span(span),
+                    span: span.clone(),
+                    ident_span: span.clone(),
+                    sig_span: span.clone(),
+                    public: func.public,
+                    type_params: func.type_params.clone(),
+                };
+                (name.clone(), func)
+            })
+            .collect();
+
+        ModuleSymbol {
+            functions,
+            span: span.clone(),
+            file: self.file.clone(),
+            decl_spans: self.decl_spans.clone(),
+            impls: self.impls.clone(),
+        }
+    }
+}
+
+impl ImplSymbol {
+    fn new(decl: &ImplDecl) -> Self {
+        ImplSymbol {
+            trait_name: decl.trait_ident.ident.clone(),
+            ty: decl.ty.clone(),
+            span: decl.span.clone(),
+        }
+    }
+}
+
+impl TraitSymbol {
+    /// Looks up a method signature by name
+    pub fn method(&self, name: &str) -> Option<&FuncSymbol> {
+        self.methods
+            .iter()
+            .find(|(method, _)| method == name)
+            .map(|(_, func)| func)
+    }
+}
+
+impl FuncSymbol {
+    /// Does the function have type parameters?
+    pub fn is_generic(&self) -> bool {
+        !self.type_params.is_empty()
+    }
+
+    /// Get the function's type, for using it as a value
+    /// The types of a generic function still mention its type parameters
+    pub fn ty(&self) -> Type {
+        Type::Function(FunctionType {
+            params: self.params.clone(),
+            ret_ty: Box::new(self.ret_ty.clone()),
+            span: self.sig_span.clone(),
+        })
+    }
+}
+
+impl Default for SymbolTable<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scope<'_> {
+    fn new(kind: ScopeKind, parent: Option<ScopeId>, span: Span) -> Self {
+        Scope {
+            kind,
+            parent,
+            children: Vec::new(),
+            span,
             variables: HashMap::new(),
             functions: HashMap::new(),
-            parent: None,
+            modules: HashMap::new(),
+            const_fns: HashMap::new(),
+            traits: HashMap::new(),
+            impls: Vec::new(),
+            type_params: HashMap::new(),
         }
     }
+}
 
-    /// Creates a new symbol table with a parent
-    pub fn child(parent: &'a SymbolTable) -> Self {
+impl<'a> SymbolTable<'a> {
+    /// Creates a new symbol table, in the module's scope
+    pub fn new() -> Self {
         SymbolTable {
-            variables: HashMap::new(),
-            functions: HashMap::new(),
-            parent: Some(Box::new(parent)),
+            scopes: vec![Scope::new(ScopeKind::Module, None, Span::default())],
+            symbols: Vec::new(),
+            current: ScopeId::ROOT,
         }
     }
 
+    /// Enter a new scope nested in the current one
+    /// Symbols are declared in it until [`SymbolTable::exit`]
+    pub fn enter(&mut self, kind: ScopeKind, span: Span) -> ScopeId {
+        let id = ScopeId(self.scopes.len());
+        debug!("Entering scope {:?}: {:?}", id, kind);
+        self.scopes.push(Scope::new(kind, Some(self.current), span));
+        self.scope_mut().children.push(id);
+        self.current = id;
+        id
+    }
+
+    /// Go back to the scope enclosing the current one
+    /// The scope and its symbols are kept
+    pub fn exit(&mut self) {
+        debug!("Exiting scope {:?}", self.current);
+        self.current = self
+            .scope(self.current)
+            .parent
+            .expect("The module's scope is never exited");
+    }
+
+    /// The scope being analysed
+    pub fn current(&self) -> ScopeId {
+        self.current
+    }
+
+    pub fn scope(&self, id: ScopeId) -> &Scope<'a> {
+        &self.scopes[id.0]
+    }
+
+    pub fn symbol(&self, id: SymbolId) -> &VarSymbol {
+        &self.symbols[id.0]
+    }
+
+    /// The current scope
+    fn scope_mut(&mut self) -> &mut Scope<'a> {
+        &mut self.scopes[self.current.0]
+    }
+
+    /// The current scope, then each enclosing one out to the module's
+    fn ancestors(&self) -> impl Iterator<Item = (ScopeId, &Scope<'a>)> {
+        self.ancestors_of(self.current)
+    }
+
+    fn ancestors_of(&self, scope: ScopeId) -> impl Iterator<Item = (ScopeId, &Scope<'a>)> {
+        std::iter::successors(Some(scope), |id| self.scope(*id).parent)
+            .map(|id| (id, self.scope(id)))
+    }
+
+    /// Declare a variable in the current scope, shadowing any of the same name,
+    /// and record it on the ident declaring it
+    fn insert_var(&mut self, ident: &Ident, symbol: VarSymbol) -> SymbolId {
+        let id = SymbolId(self.symbols.len());
+        self.symbols.push(symbol);
+        let _ = ident.symbol.set(id);
+
+        // removed first, as inserting keeps the old key and its span
+        let scope = self.scope_mut();
+        scope.variables.remove(ident);
+        scope.variables.insert(ident.clone(), id);
+
+        id
+    }
+
+    /// The return type of the function or closure being analysed
+    pub fn return_type(&self) -> Option<&Type> {
+        self.ancestors().find_map(|(_, scope)| match &scope.kind {
+            ScopeKind::Function(ty) | ScopeKind::Closure(ty) => Some(ty),
+            _ => None,
+        })
+    }
+
     /// Inserts a variable symbol into the table
-    pub fn add_var(&mut self, var: &VariableDecl) -> Result<()> {
-        if let Some(existing) = self.variables.get(&var.ident) {
-            return Err(anyhow!(SemanticError::VariableAlreadyDeclared(
-                var.ident.clone(),
-                var.span.clone(),
-                existing.span.clone()
-            )));
-        } else {
-            self.variables.insert(
-                var.ident.clone(),
-                VarSymbol {
-                    ty: var.ty.clone(),
-                    span: var.span.clone(),
-                },
+    ///
+    /// Variables without an annotation take the type of their initializer
+    ///
+    /// Like in Rust, a `let` shadows any variable of the same name, whether
+    /// declared in an enclosing block or earlier in the same one, and may
+    /// give it a different type or mutability. The shadowed variable is
+    /// still used by the initializer, and again once an inner block ends.
+    /// Parameters and globals can't be redeclared, see `add_param` and `add_global`
+    pub fn add_var(&mut self, var: &VariableDecl) -> Result<SymbolId> {
+        let ty = match (var.var_type(), &var.expression) {
+            (Some(ty), _) => ty,
+            (None, Some(expression)) => expression.get_type(self)?,
+            (None, None) => unreachable!("The parser requires a type without an initializer"),
+        };
+
+        if let Some(existing) = self.scope(self.current).variables.get(&var.ident) {
+            debug!(
+                "Variable {} shadows the one declared at {:?}",
+                var.ident.ident,
+                self.symbol(*existing).span
             );
         }
 
-        Ok(())
+        Ok(self.insert_var(
+            &var.ident,
+            VarSymbol {
+                ty,
+                kind: VarKind::Let,
+                mutable: var.mutable,
+                value: None,
+                deferred: var.expression.is_none(),
+                span: var.span.clone(),
+            },
+        ))
     }
 
     /// Insert a parameter symbol into the table
-    pub fn add_param(&mut self, param: &Parameter) -> Result<()> {
-        if let Some(existing) = self.variables.get(&param.ident) {
+    pub fn add_param(&mut self, param: &Parameter) -> Result<SymbolId> {
+        if let Some(existing) = self.scope(self.current).variables.get(&param.ident) {
             return Err(anyhow!(SemanticError::VariableAlreadyDeclared(
                 param.ident.clone(),
                 param.span.clone(),
-                existing.span.clone()
+                self.symbol(*existing).span.clone()
             )));
-        } else {
-            self.variables.insert(
-                param.ident.clone(),
-                VarSymbol {
-                    ty: param.ty.clone(),
-                    span: param.span.clone(),
-                },
-            );
         }
 
-        Ok(())
+        Ok(self.insert_var(
+            &param.ident,
+            VarSymbol {
+                ty: param.ty.clone(),
+                kind: VarKind::Param,
+                mutable: false,
+                value: None,
+                deferred: false,
+                span: param.span.clone(),
+            },
+        ))
+    }
+
+    /// Insert a `const` or `static` symbol into the table
+    ///
+    /// Globals share their names with functions, as both are used as values
+    pub fn add_global(&mut self, global: &GlobalDecl) -> Result<SymbolId> {
+        let scope = self.scope(self.current);
+        if let Some(existing) = scope.variables.get(&global.ident) {
+            return Err(anyhow!(SemanticError::VariableAlreadyDeclared(
+                global.ident.clone(),
+                global.span.clone(),
+                self.symbol(*existing).span.clone()
+            )));
+        }
+
+        if let Some(func) = scope.functions.get(&global.ident) {
+            warn!("Global shares its name with a function: {}", global.ident);
+            return Err(anyhow!(SemanticError::NameAlreadyDeclared(
+                global.ident.clone(),
+                global.span.clone(),
+                func.span.clone()
+            )));
+        }
+
+        debug!("Adding global: {}", global.ident.ident);
+        let (kind, value) = match global.kind {
+            GlobalKind::Const => (VarKind::Const, global.value.get()),
+            GlobalKind::Static => (VarKind::Static, None),
+        };
+        Ok(self.insert_var(
+            &global.ident,
+            VarSymbol {
+                ty: global.ty.clone(),
+                kind,
+                mutable: global.mutable,
+                value,
+                deferred: false,
+                span: global.span.clone(),
+            },
+        ))
     }
 
     /// Inserts a function symbol into the table
+    // idents are hashed by name alone, so the symbol they record can't change a key
+    #[allow(clippy::mutable_key_type)]
     pub fn add_fn(&mut self, func: &FunctionDecl) -> Result<()> {
         let params = func.parameters.iter().map(|p| p.ty.clone()).collect();
         let ret_ty = func.ty.clone();
 
-        if let Some(existing) = self.functions.get(&func.ident) {
+        let functions = &mut self.scope_mut().functions;
+        if let Some(existing) = functions.get(&func.ident) {
             warn!("Function already declared: {}", func.ident.ident);
             return Err(anyhow!(SemanticError::FunctionAlreadyDeclared(
                 func.ident.clone(),
                 func.span.clone(),
                 existing.span.clone()
             )));
-        } else {
-            debug!("Adding function: {}", func.ident.ident);
-            self.functions.insert(
-                func.ident.clone(),
-                FuncSymbol {
-                    params,
-                    ret_ty,
-                    span: func.span.clone(),
-                    ident_span: func.ident.span.clone(),
-                    sig_span: Span::combine(&func.ident.span, &func.ty.span()),
-                },
-            );
         }
 
+        debug!("Adding function: {}", func.ident.ident);
+        functions.insert(
+            func.ident.clone(),
+            FuncSymbol {
+                params,
+                ret_ty,
+                span: func.span.clone(),
+                ident_span: func.ident.span.clone(),
+                sig_span: Span::combine(&func.ident.span, &func.ty.span()),
+                public: func.public,
+                type_params: func.type_params.clone(),
+            },
+        );
+
         Ok(())
     }
 
-    /// Looks up a variable symbol in the table
-    pub fn get_var(&self, name: &Ident) -> Option<&VarSymbol> {
-        match self.variables.get(name) {
-            Some(v) => Some(v),
-            None => match &self.parent {
-                Some(p) => p.get_var(name),
-                None => None,
+    /// Make a `const fn`'s body available to the constant evaluator
+    /// It must already be declared with [`SymbolTable::add_fn`]
+    pub fn add_const_fn(&mut self, func: &'a FunctionDecl) {
+        debug!("Adding const fn: {}", func.ident.ident);
+        self.scope_mut()
+            .const_fns
+            .entry(func.ident.clone())
+            .or_insert(func);
+    }
+
+    /// Declare a type parameter of the function being analysed
+    pub fn add_type_param(&mut self, param: &TypeParam) -> Result<()> {
+        let ident = &param.ident;
+        let type_params = &mut self.scope_mut().type_params;
+        if let Some(existing) = type_params.get(&ident.ident) {
+            warn!("Type parameter already declared: {}", ident);
+            return Err(anyhow!(SemanticError::TypeParamAlreadyDeclared(
+                ident.clone(),
+                ident.span.clone(),
+                existing.span.clone()
+            )));
+        }
+
+        debug!("Adding type parameter: {}", ident);
+        type_params.insert(
+            ident.ident.clone(),
+            TypeParamSymbol {
+                bounds: param.bounds.iter().map(|b| b.ident.clone()).collect(),
+                span: ident.span.clone(),
             },
+        );
+
+        Ok(())
+    }
+
+    /// Inserts a trait symbol into the table
+    /// Each method becomes generic over `Self`, bounded by the trait
+    pub fn add_trait(&mut self, decl: &TraitDecl) -> Result<()> {
+        if let Some(existing) = self.scope_mut().traits.get(&decl.ident.ident) {
+            warn!("Trait already declared: {}", decl.ident);
+            return Err(anyhow!(SemanticError::TraitAlreadyDeclared(
+                decl.ident.clone(),
+                decl.span.clone(),
+                existing.span.clone()
+            )));
+        }
+
+        let self_param = TypeParam {
+            ident: Ident::new(SELF_TYPE.to_string(), decl.ident.span.clone()),
+            bounds: vec![decl.ident.clone()],
+        };
+        let methods = decl
+            .methods
+            .iter()
+            .map(|m| {
+                let func = FuncSymbol {
+                    params: m.parameters.iter().map(|p| p.ty.clone()).collect(),
+                    ret_ty: m.ty.clone(),
+                    span: m.span.clone(),
+                    ident_span: m.ident.span.clone(),
+                    sig_span: m.span.clone(),
+                    public: true,
+                    type_params: vec![self_param.clone()],
+                };
+                (m.ident.ident.clone(), func)
+            })
+            .collect();
+
+        debug!("Adding trait: {}", decl.ident.ident);
+        self.scope_mut().traits.insert(
+            decl.ident.ident.clone(),
+            TraitSymbol {
+                methods,
+                span: decl.span.clone(),
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Inserts a trait implementation into the table
+    /// Its methods are checked against the trait when the impl is analysed
+    pub fn add_impl(&mut self, decl: &ImplDecl) -> Result<()> {
+        if self.get_trait(&decl.trait_ident.ident).is_none() {
+            warn!("Trait not declared: {}", decl.trait_ident);
+            return Err(anyhow!(SemanticError::TraitNotDeclared(
+                decl.trait_ident.clone(),
+                decl.trait_ident.span.clone()
+            )));
+        }
+
+        self.check_type(&decl.ty)?;
+
+        // like type arguments, `Self` only stands for primitives
+        if !matches!(decl.ty, Type::Primitive(_)) {
+            warn!("Trait implemented for non-primitive: {}", decl.ty);
+            return Err(anyhow!(SemanticError::InvalidTypeArgument {
+                param: SELF_TYPE.to_string(),
+                ty: decl.ty.clone(),
+                span: decl.ty.span(),
+            }));
+        }
+
+        if let Some(existing) = self
+            .scope_mut()
+            .impls
+            .iter()
+            .find(|i| i.trait_name == decl.trait_ident.ident && i.ty == decl.ty)
+        {
+            warn!("Conflicting impl of {} for {}", decl.trait_ident, decl.ty);
+            return Err(anyhow!(SemanticError::ConflictingImpl {
+                trait_ident: decl.trait_ident.clone(),
+                ty: decl.ty.clone(),
+                span: decl.span.clone(),
+                existing: existing.span.clone(),
+            }));
+        }
+
+        debug!("Adding impl of {} for {}", decl.trait_ident, decl.ty);
+        self.scope_mut().impls.push(ImplSymbol::new(decl));
+
+        Ok(())
+    }
+
+    /// Check that every type parameter a type mentions is in scope
+    pub fn check_type(&self, ty: &Type) -> Result<()> {
+        for param in ty.params() {
+            if self.get_type_param(&param.name).is_none() {
+                warn!("Type not declared: {}", param.name);
+                return Err(anyhow!(SemanticError::TypeNotDeclared(
+                    param.name.clone(),
+                    param.span.clone()
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Looks up a type parameter in scope
+    fn get_type_param(&self, name: &str) -> Option<&TypeParamSymbol> {
+        self.ancestors()
+            .find_map(|(_, scope)| scope.type_params.get(name))
+    }
+
+    /// Inserts a module symbol into the table
+    pub fn add_module(&mut self, decl: &ModDecl, module: &ModuleSymbol) -> Result<()> {
+        let modules = &mut self.scope_mut().modules;
+        if let Some(existing) = modules.get(&decl.ident.ident) {
+            warn!("Module already declared: {}", decl.ident.ident);
+            return Err(anyhow!(SemanticError::ModuleAlreadyDeclared(
+                decl.ident.clone(),
+                decl.span.clone(),
+                existing.span.clone()
+            )));
+        }
+
+        debug!("Adding module: {}", decl.ident.ident);
+        modules.insert(decl.ident.ident.clone(), module.declared_at(&decl.span));
+
+        Ok(())
+    }
+
+    /// Looks up a variable in the table
+    /// Returns the scope declaring it, and the variable
+    pub fn lookup_var(&self, name: &Ident) -> Option<(ScopeId, SymbolId)> {
+        self.lookup_var_from(self.current, name)
+    }
+
+    /// Looks up a variable as seen from a scope, which may have been exited.
+    /// Only the latest variable of each name declared in the scope is seen
+    pub fn lookup_var_from(&self, scope: ScopeId, name: &Ident) -> Option<(ScopeId, SymbolId)> {
+        self.ancestors_of(scope)
+            .find_map(|(id, scope)| scope.variables.get(name).map(|symbol| (id, *symbol)))
+    }
+
+    /// Record the variable an ident refers to in the current scope, if any,
+    /// so lowering needn't resolve names again
+    pub fn resolve(&self, ident: &Ident) {
+        if let Some((_, id)) = self.lookup_var(ident) {
+            let _ = ident.symbol.set(id);
         }
     }
 
+    /// Looks up a variable symbol in the table
+    pub fn get_var(&self, name: &Ident) -> Option<&VarSymbol> {
+        self.lookup_var(name).map(|(_, id)| self.symbol(id))
+    }
+
+    /// Looks up a `const` or `static` of the module, ignoring any variable in
+    /// scope of the same name
+    pub fn get_global(&self, name: &Ident) -> Option<&VarSymbol> {
+        let id = self.scope(ScopeId::ROOT).variables.get(name)?;
+        Some(self.symbol(*id))
+    }
+
+    /// Is the variable declared in a function, rather than a global of the module?
+    /// Only these can be captured by closures
+    pub fn is_local(&self, name: &Ident) -> bool {
+        self.lookup_var(name)
+            .is_some_and(|(scope, _)| scope != ScopeId::ROOT)
+    }
+
     /// Looks up a function symbol in the table
+    /// Qualified paths like `math::add` look in the module,
+    /// and paths like `Shape::area` in the trait
     pub fn get_fn(&self, name: &Ident) -> Option<&FuncSymbol> {
-        match self.functions.get(name) {
-            Some(f) => Some(f),
-            None => match &self.parent {
-                Some(p) => p.get_fn(name),
-                None => None,
-            },
+        if let Some((prefix, ident)) = name.split_path() {
+            if let Some(t) = self.get_trait(prefix) {
+                return t.method(ident);
+            }
+            return self.get_module(prefix)?.functions.get(ident);
         }
+
+        self.ancestors()
+            .find_map(|(_, scope)| scope.functions.get(name))
+    }
+
+    /// Looks up the body of a `const fn` in the table
+    pub fn get_const_fn(&self, name: &Ident) -> Option<&'a FunctionDecl> {
+        self.ancestors()
+            .find_map(|(_, scope)| scope.const_fns.get(name).copied())
+    }
+
+    /// Looks up a trait symbol in the table
+    pub fn get_trait(&self, name: &str) -> Option<&TraitSymbol> {
+        self.ancestors()
+            .find_map(|(_, scope)| scope.traits.get(name))
+    }
+
+    /// Does a type implement a trait?
+    /// A type parameter does if the trait is one of its bounds
+    pub fn implements(&self, ty: &Type, trait_name: &str) -> bool {
+        if let Type::Param(p) = ty {
+            return self
+                .get_type_param(&p.name)
+                .is_some_and(|param| param.bounds.iter().any(|b| b == trait_name));
+        }
+
+        self.ancestors().any(|(_, scope)| {
+            scope
+                .impls
+                .iter()
+                .any(|i| i.trait_name == trait_name && i.ty == *ty)
+        })
+    }
+
+    /// Looks up a module symbol in the table
+    pub fn get_module(&self, name: &str) -> Option<&ModuleSymbol> {
+        self.ancestors()
+            .find_map(|(_, scope)| scope.modules.get(name))
     }
 }
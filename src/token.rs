@@ -2,7 +2,9 @@
 
 use std::hash::{Hash, Hasher};
 
-use crate::ast::{BinaryOperator, BinaryOperatorKind, UnaryOperator, UnaryOperatorKind};
+use crate::ast::{
+    BinaryOperator, BinaryOperatorKind, PrimitiveKind, UnaryOperator, UnaryOperatorKind,
+};
 
 #[derive(Debug, Clone, PartialEq, Hash)]
 pub struct Token {
@@ -10,7 +12,7 @@ pub struct Token {
     pub span: Span,
 }
 
-#[derive(Debug, Clone, PartialEq, Hash)]
+#[derive(Debug, Clone, PartialEq, Hash, Default)]
 pub struct Span {
     pub start: usize,
     pub end: usize,
@@ -26,8 +28,16 @@ pub enum TokenKind {
     For,
     Return,
     Let,
+    Mut,
+    As,
     True,
     False,
+    Mod,
+    Pub,
+    Const,
+    Static,
+    Trait,
+    Impl,
 
     // single-character tokens
     Plus,      // +
@@ -48,29 +58,49 @@ pub enum TokenKind {
     RParen,    // )
     LBrace,    // {
     RBrace,    // }
+    LBracket,  // [
+    RBracket,  // ]
+    Hash,      // #
     Quote,     // "
+    Ampersand, // &
+    Pipe,      // |
 
     // double-character tokens
-    PlusEquals,    // +=
-    MinusEquals,   // -=
-    StarEquals,    // *=
-    SlashEquals,   // /=
-    PercentEquals, // %=
-    CaretEquals,   // ^=
-    BangEquals,    // !=
-    LessEquals,    // <=
-    GreaterEquals, // >=
-    EqualsEquals,  // ==
-    Arrow,         // ->
+    PlusEquals,         // +=
+    MinusEquals,        // -=
+    StarEquals,         // *=
+    SlashEquals,        // /=
+    PercentEquals,      // %=
+    CaretEquals,        // ^=
+    BangEquals,         // !=
+    LessEquals,         // <=
+    GreaterEquals,      // >=
+    EqualsEquals,       // ==
+    Arrow,              // ->
+    AmpersandAmpersand, // &&
+    PipePipe,           // ||
+    ColonColon,         // ::
+
+    /// `/// text`, attached to the next item
+    DocComment(String),
 
     // literals
     Ident(String),
-    IntLiteral(i32),
-    FloatLiteral(f32),
+    /// Value, and the type suffix if any (`5u8`)
+    IntLiteral(u64, Option<PrimitiveKind>),
+    FloatLiteral(f64),
     BoolLiteral(bool),
 
     // Data types
     Int,
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
     Bool,
     Float,
 
@@ -158,6 +188,20 @@ impl Token {
                 kind: BinaryOperatorKind::GreaterThanOrEqual,
                 span: span.clone(),
             }),
+            Token {
+                kind: TokenKind::AmpersandAmpersand,
+                span,
+            } => Some(BinaryOperator {
+                kind: BinaryOperatorKind::And,
+                span: span.clone(),
+            }),
+            Token {
+                kind: TokenKind::PipePipe,
+                span,
+            } => Some(BinaryOperator {
+                kind: BinaryOperatorKind::Or,
+                span: span.clone(),
+            }),
             _ => None,
         }
     }
@@ -171,6 +215,27 @@ impl Token {
                 kind: UnaryOperatorKind::Not,
                 span: span.clone(),
             }),
+            Token {
+                kind: TokenKind::Minus,
+                span,
+            } => Some(UnaryOperator {
+                kind: UnaryOperatorKind::Negate,
+                span: span.clone(),
+            }),
+            Token {
+                kind: TokenKind::Ampersand,
+                span,
+            } => Some(UnaryOperator {
+                kind: UnaryOperatorKind::Reference,
+                span: span.clone(),
+            }),
+            Token {
+                kind: TokenKind::Star,
+                span,
+            } => Some(UnaryOperator {
+                kind: UnaryOperatorKind::Dereference,
+                span: span.clone(),
+            }),
             _ => None,
         }
     }
@@ -205,10 +270,35 @@ impl TokenKind {
             ">=" => TokenKind::GreaterEquals,
             "==" => TokenKind::EqualsEquals,
             "->" => TokenKind::Arrow,
+            "&" => TokenKind::Ampersand,
+            "|" => TokenKind::Pipe,
+            "&&" => TokenKind::AmpersandAmpersand,
+            "||" => TokenKind::PipePipe,
+            "::" => TokenKind::ColonColon,
             _ => panic!("Unknown operator: {}", op),
         }
     }
 
+    /// Is the string a two-character operator?
+    pub fn is_compound_operator(op: &str) -> bool {
+        matches!(
+            op,
+            "+=" | "-="
+                | "*="
+                | "/="
+                | "%="
+                | "^="
+                | "!="
+                | "<="
+                | ">="
+                | "=="
+                | "->"
+                | "&&"
+                | "||"
+                | "::"
+        )
+    }
+
     /// Get keyword from a string
     pub fn keyword_from(keyword: &str) -> TokenKind {
         match keyword {
@@ -219,9 +309,25 @@ impl TokenKind {
             "for" => TokenKind::For,
             "return" => TokenKind::Return,
             "let" => TokenKind::Let,
+            "mut" => TokenKind::Mut,
+            "as" => TokenKind::As,
             "true" => TokenKind::True,
             "false" => TokenKind::False,
+            "mod" => TokenKind::Mod,
+            "pub" => TokenKind::Pub,
+            "const" => TokenKind::Const,
+            "static" => TokenKind::Static,
+            "trait" => TokenKind::Trait,
+            "impl" => TokenKind::Impl,
             "int" => TokenKind::Int,
+            "i8" => TokenKind::I8,
+            "i16" => TokenKind::I16,
+            "i32" => TokenKind::I32,
+            "i64" => TokenKind::I64,
+            "u8" => TokenKind::U8,
+            "u16" => TokenKind::U16,
+            "u32" => TokenKind::U32,
+            "u64" => TokenKind::U64,
             "bool" => TokenKind::Bool,
             "float" => TokenKind::Float,
             _ => TokenKind::Ident(keyword.to_string()),
@@ -230,70 +336,92 @@ impl TokenKind {
 
     /// Is literal?
     pub fn is_literal(&self) -> bool {
-        match self {
-            TokenKind::IntLiteral(_) | TokenKind::FloatLiteral(_) | TokenKind::BoolLiteral(_) => {
-                true
-            }
-            _ => false,
-        }
+        matches!(
+            self,
+            TokenKind::IntLiteral(..) | TokenKind::FloatLiteral(_) | TokenKind::BoolLiteral(_)
+        )
     }
 
     /// Is operator?
     pub fn is_operator(&self) -> bool {
-        match self {
+        matches!(
+            self,
             TokenKind::Plus
-            | TokenKind::Minus
-            | TokenKind::Star
-            | TokenKind::Slash
-            | TokenKind::Percent
-            | TokenKind::Caret
-            | TokenKind::Bang
-            | TokenKind::Colon
-            | TokenKind::Semicolon
-            | TokenKind::Comma
-            | TokenKind::Dot
-            | TokenKind::Equals
-            | TokenKind::Less
-            | TokenKind::Greater
-            | TokenKind::PlusEquals
-            | TokenKind::MinusEquals
-            | TokenKind::StarEquals
-            | TokenKind::SlashEquals
-            | TokenKind::PercentEquals
-            | TokenKind::CaretEquals
-            | TokenKind::BangEquals
-            | TokenKind::LessEquals
-            | TokenKind::GreaterEquals
-            | TokenKind::EqualsEquals
-            | TokenKind::Arrow => true,
-            _ => false,
-        }
+                | TokenKind::Minus
+                | TokenKind::Star
+                | TokenKind::Slash
+                | TokenKind::Percent
+                | TokenKind::Caret
+                | TokenKind::Bang
+                | TokenKind::Colon
+                | TokenKind::Semicolon
+                | TokenKind::Comma
+                | TokenKind::Dot
+                | TokenKind::Equals
+                | TokenKind::Less
+                | TokenKind::Greater
+                | TokenKind::PlusEquals
+                | TokenKind::MinusEquals
+                | TokenKind::StarEquals
+                | TokenKind::SlashEquals
+                | TokenKind::PercentEquals
+                | TokenKind::CaretEquals
+                | TokenKind::BangEquals
+                | TokenKind::LessEquals
+                | TokenKind::GreaterEquals
+                | TokenKind::EqualsEquals
+                | TokenKind::Arrow
+                | TokenKind::Ampersand
+                | TokenKind::Pipe
+                | TokenKind::AmpersandAmpersand
+                | TokenKind::PipePipe
+                | TokenKind::ColonColon
+        )
     }
 
     /// Is keyword?
     pub fn is_keyword(&self) -> bool {
-        match self {
+        matches!(
+            self,
             TokenKind::Fn
-            | TokenKind::If
-            | TokenKind::Else
-            | TokenKind::While
-            | TokenKind::For
-            | TokenKind::Return
-            | TokenKind::Let
-            | TokenKind::True
-            | TokenKind::False
-            | TokenKind::Int
-            | TokenKind::Bool
-            | TokenKind::Float => true,
-            _ => false,
-        }
+                | TokenKind::If
+                | TokenKind::Else
+                | TokenKind::While
+                | TokenKind::For
+                | TokenKind::Return
+                | TokenKind::Let
+                | TokenKind::Mut
+                | TokenKind::As
+                | TokenKind::True
+                | TokenKind::False
+                | TokenKind::Mod
+                | TokenKind::Pub
+                | TokenKind::Const
+                | TokenKind::Static
+                | TokenKind::Trait
+                | TokenKind::Impl
+        ) || self.is_data_type()
     }
 
     /// Is data type?
     pub fn is_data_type(&self) -> bool {
+        self.as_primitive_kind().is_some()
+    }
+
+    /// The primitive type named by a data type keyword
+    pub fn as_primitive_kind(&self) -> Option<PrimitiveKind> {
         match self {
-            TokenKind::Int | TokenKind::Bool | TokenKind::Float => true,
-            _ => false,
+            TokenKind::Int | TokenKind::I64 => Some(PrimitiveKind::I64),
+            TokenKind::I8 => Some(PrimitiveKind::I8),
+            TokenKind::I16 => Some(PrimitiveKind::I16),
+            TokenKind::I32 => Some(PrimitiveKind::I32),
+            TokenKind::U8 => Some(PrimitiveKind::U8),
+            TokenKind::U16 => Some(PrimitiveKind::U16),
+            TokenKind::U32 => Some(PrimitiveKind::U32),
+            TokenKind::U64 => Some(PrimitiveKind::U64),
+            TokenKind::Bool => Some(PrimitiveKind::Bool),
+            TokenKind::Float => Some(PrimitiveKind::Float),
+            _ => None,
         }
     }
 }
@@ -308,8 +436,16 @@ impl std::fmt::Display for TokenKind {
             TokenKind::For => write!(f, "for"),
             TokenKind::Return => write!(f, "return"),
             TokenKind::Let => write!(f, "let"),
+            TokenKind::Mut => write!(f, "mut"),
+            TokenKind::As => write!(f, "as"),
             TokenKind::True => write!(f, "true"),
             TokenKind::False => write!(f, "false"),
+            TokenKind::Mod => write!(f, "mod"),
+            TokenKind::Pub => write!(f, "pub"),
+            TokenKind::Const => write!(f, "const"),
+            TokenKind::Static => write!(f, "static"),
+            TokenKind::Trait => write!(f, "trait"),
+            TokenKind::Impl => write!(f, "impl"),
             TokenKind::Plus => write!(f, "+"),
             TokenKind::Minus => write!(f, "-"),
             TokenKind::Star => write!(f, "*"),
@@ -328,7 +464,12 @@ impl std::fmt::Display for TokenKind {
             TokenKind::RParen => write!(f, ")"),
             TokenKind::LBrace => write!(f, "{{"),
             TokenKind::RBrace => write!(f, "}}"),
+            TokenKind::LBracket => write!(f, "["),
+            TokenKind::RBracket => write!(f, "]"),
+            TokenKind::Hash => write!(f, "#"),
             TokenKind::Quote => write!(f, "\""),
+            TokenKind::Ampersand => write!(f, "&"),
+            TokenKind::Pipe => write!(f, "|"),
             TokenKind::PlusEquals => write!(f, "+="),
             TokenKind::MinusEquals => write!(f, "-="),
             TokenKind::StarEquals => write!(f, "*="),
@@ -340,12 +481,24 @@ impl std::fmt::Display for TokenKind {
             TokenKind::GreaterEquals => write!(f, ">="),
             TokenKind::EqualsEquals => write!(f, "=="),
             TokenKind::Arrow => write!(f, "->"),
+            TokenKind::AmpersandAmpersand => write!(f, "&&"),
+            TokenKind::PipePipe => write!(f, "||"),
+            TokenKind::ColonColon => write!(f, "::"),
             TokenKind::Int => write!(f, "int"),
+            TokenKind::I8 => write!(f, "i8"),
+            TokenKind::I16 => write!(f, "i16"),
+            TokenKind::I32 => write!(f, "i32"),
+            TokenKind::I64 => write!(f, "i64"),
+            TokenKind::U8 => write!(f, "u8"),
+            TokenKind::U16 => write!(f, "u16"),
+            TokenKind::U32 => write!(f, "u32"),
+            TokenKind::U64 => write!(f, "u64"),
             TokenKind::Bool => write!(f, "bool"),
             TokenKind::Float => write!(f, "float"),
             TokenKind::Eof => write!(f, "EOF"),
             TokenKind::Ident(_) => write!(f, "Ident"),
-            TokenKind::IntLiteral(_) => write!(f, "IntegerLiteral"),
+            TokenKind::DocComment(_) => write!(f, "DocComment"),
+            TokenKind::IntLiteral(..) => write!(f, "IntegerLiteral"),
             TokenKind::FloatLiteral(_) => write!(f, "FloatLiteral"),
             TokenKind::BoolLiteral(_) => write!(f, "BooleanLiteral"),
         }
@@ -369,10 +522,19 @@ impl Hash for TokenKind {
             TokenKind::For => "For".hash(state),
             TokenKind::Return => "Return".hash(state),
             TokenKind::Let => "Let".hash(state),
+            TokenKind::Mut => "Mut".hash(state),
+            TokenKind::As => "As".hash(state),
             TokenKind::True => "True".hash(state),
             TokenKind::False => "False".hash(state),
+            TokenKind::Mod => "Mod".hash(state),
+            TokenKind::Pub => "Pub".hash(state),
+            TokenKind::Const => "Const".hash(state),
+            TokenKind::Static => "Static".hash(state),
+            TokenKind::Trait => "Trait".hash(state),
+            TokenKind::Impl => "Impl".hash(state),
             TokenKind::Ident(identifier) => identifier.hash(state),
-            TokenKind::IntLiteral(integer) => integer.hash(state),
+            TokenKind::DocComment(text) => text.hash(state),
+            TokenKind::IntLiteral(integer, _) => integer.hash(state),
             TokenKind::FloatLiteral(float) => {
                 // Hash the float value as a bit pattern
                 let bytes = float.to_ne_bytes();
@@ -438,9 +600,3 @@ impl Span {
         }
     }
 }
-
-impl Default for Span {
-    fn default() -> Self {
-        Span { start: 0, end: 0 }
-    }
-}